@@ -1,7 +1,38 @@
 use std::process::Command;
 
 fn main() {
-    let shaders = ["triangle.vert", "triangle.frag"];
+    let shaders = [
+        "triangle.vert",
+        "triangle.frag",
+        "mesh.vert",
+        "mesh_instanced.vert",
+        "mesh_indirect.vert",
+        "mesh.frag",
+        "mesh_bindless.frag",
+        "shadow.vert",
+        "shadow.frag",
+        "shadow_debug.vert",
+        "shadow_debug.frag",
+        "skybox.vert",
+        "skybox.frag",
+        "equirect_to_cube.comp",
+        "irradiance.comp",
+        "prefilter.comp",
+        "brdf_lut.comp",
+        "cull.comp",
+        "post.vert",
+        "bloom_downsample.frag",
+        "bloom_upsample.frag",
+        "tonemap.frag",
+        "post_output.frag",
+    ];
+
+    // Included by several of the shaders above
+    println!("cargo::rerun-if-changed=shaders/frame_uniforms.glsl");
+    println!("cargo::rerun-if-changed=shaders/cube.glsl");
+    println!("cargo::rerun-if-changed=shaders/post.glsl");
+    println!("cargo::rerun-if-changed=shaders/draw_object.glsl");
+    println!("cargo::rerun-if-changed=shaders/mesh_shading.glsl");
 
     for s in shaders.iter() {
         println!("cargo::rerun-if-changed=shaders/{}", s);
@@ -0,0 +1,11 @@
+use vulkan_tutorial_rust::Application;
+
+// The smallest program on the renderer: no model is loaded, so the built-in triangle is drawn.
+
+struct Triangle;
+
+impl Application for Triangle {}
+
+fn main() {
+    vulkan_tutorial_rust::run(Triangle);
+}
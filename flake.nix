@@ -30,11 +30,19 @@
           vulkan-loader
           vulkan-headers
         ];
+        # Loaded at runtime by the winit backend
+        windowLibs = with pkgs; [
+          wayland
+          libxkbcommon
+          xorg.libX11
+          xorg.libXcursor
+          xorg.libXi
+        ];
       in
         with pkgs; {
           devShells.default = mkShell {
             RUST_BACKTRACE = 1;
-            LD_LIBRARY_PATH = lib.makeLibraryPath vulkanLibs;
+            LD_LIBRARY_PATH = lib.makeLibraryPath (vulkanLibs ++ windowLibs);
             packages = [
               pkg-config
               cmake
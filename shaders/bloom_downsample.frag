@@ -0,0 +1,48 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "post.glsl"
+
+// params: x threshold, y knee
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+// Keeps what is brighter than the threshold, fading in quadratically over the knee below it
+vec3 threshold(vec3 color) {
+  float brightness = max(color.r, max(color.g, color.b));
+  float knee = max(post.params.y, 1e-4);
+  float soft = clamp(brightness - post.params.x + knee, 0.0, 2.0 * knee);
+  soft = soft * soft / (4.0 * knee);
+  return color * max(soft, brightness - post.params.x) / max(brightness, 1e-4);
+}
+
+// 13 bilinear taps forming overlapping 2x2 boxes weighted towards the center, which keeps small
+// bright spots from flickering as they move
+void main() {
+  vec2 texel = 1.0 / vec2(textureSize(source, 0));
+  vec3 a = texture(source, fragUv + texel * vec2(-2.0, -2.0)).rgb;
+  vec3 b = texture(source, fragUv + texel * vec2(0.0, -2.0)).rgb;
+  vec3 c = texture(source, fragUv + texel * vec2(2.0, -2.0)).rgb;
+  vec3 d = texture(source, fragUv + texel * vec2(-1.0, -1.0)).rgb;
+  vec3 e = texture(source, fragUv + texel * vec2(1.0, -1.0)).rgb;
+  vec3 f = texture(source, fragUv + texel * vec2(-2.0, 0.0)).rgb;
+  vec3 g = texture(source, fragUv).rgb;
+  vec3 h = texture(source, fragUv + texel * vec2(2.0, 0.0)).rgb;
+  vec3 i = texture(source, fragUv + texel * vec2(-1.0, 1.0)).rgb;
+  vec3 j = texture(source, fragUv + texel * vec2(1.0, 1.0)).rgb;
+  vec3 k = texture(source, fragUv + texel * vec2(-2.0, 2.0)).rgb;
+  vec3 l = texture(source, fragUv + texel * vec2(0.0, 2.0)).rgb;
+  vec3 m = texture(source, fragUv + texel * vec2(2.0, 2.0)).rgb;
+
+  vec3 color = g * 0.125 + (d + e + i + j) * 0.125 + (b + f + h + l) * 0.0625
+               + (a + c + k + m) * 0.03125;
+  if ((post.flags & FLAG_THRESHOLD) != 0u) {
+    // Half float overflow would spread as infinity through the whole chain
+    color = threshold(min(color, vec3(65000.0)));
+  }
+  outColor = vec4(color, 1.0);
+}
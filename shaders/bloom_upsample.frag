@@ -0,0 +1,28 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "post.glsl"
+
+// This level of the downsampled chain, and the next smaller level already blurred back up
+layout(set = 0, binding = 0) uniform sampler2D source;
+layout(set = 0, binding = 1) uniform sampler2D lower;
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+// 3x3 tent over the smaller level, added to this level's detail
+void main() {
+  vec2 texel = 1.0 / vec2(textureSize(lower, 0));
+  vec3 blurred = texture(lower, fragUv).rgb * 4.0;
+  blurred += (texture(lower, fragUv + texel * vec2(0.0, -1.0)).rgb
+              + texture(lower, fragUv + texel * vec2(-1.0, 0.0)).rgb
+              + texture(lower, fragUv + texel * vec2(1.0, 0.0)).rgb
+              + texture(lower, fragUv + texel * vec2(0.0, 1.0)).rgb) * 2.0;
+  blurred += texture(lower, fragUv + texel * vec2(-1.0, -1.0)).rgb
+             + texture(lower, fragUv + texel * vec2(1.0, -1.0)).rgb
+             + texture(lower, fragUv + texel * vec2(-1.0, 1.0)).rgb
+             + texture(lower, fragUv + texel * vec2(1.0, 1.0)).rgb;
+
+  outColor = vec4(texture(source, fragUv).rgb + blurred / 16.0, 1.0);
+}
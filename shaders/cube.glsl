@@ -0,0 +1,38 @@
+// Shared by the environment bake shaders
+
+const float PI = 3.14159265359;
+
+// Direction through texel `id` of a cube face, in Vulkan's face order and orientation
+vec3 cubeDirection(uvec3 id, uint size) {
+  vec2 st = (vec2(id.xy) + 0.5) / float(size) * 2.0 - 1.0;
+  vec3 direction;
+  switch (id.z) {
+    case 0: direction = vec3(1.0, -st.y, -st.x); break;
+    case 1: direction = vec3(-1.0, -st.y, st.x); break;
+    case 2: direction = vec3(st.x, 1.0, st.y); break;
+    case 3: direction = vec3(st.x, -1.0, -st.y); break;
+    case 4: direction = vec3(st.x, -st.y, 1.0); break;
+    default: direction = vec3(-st.x, -st.y, -1.0); break;
+  }
+  return normalize(direction);
+}
+
+// Low-discrepancy point i of n in [0, 1)^2
+vec2 hammersley(uint i, uint n) {
+  uint bits = bitfieldReverse(i);
+  return vec2(float(i) / float(n), float(bits) * 2.3283064365386963e-10);
+}
+
+// GGX-distributed half vector around n
+vec3 importanceSampleGGX(vec2 xi, vec3 n, float roughness) {
+  float a = roughness * roughness;
+  float phi = 2.0 * PI * xi.x;
+  float cosTheta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+  float sinTheta = sqrt(1.0 - cosTheta * cosTheta);
+  vec3 h = vec3(cos(phi) * sinTheta, sin(phi) * sinTheta, cosTheta);
+
+  vec3 up = abs(n.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+  vec3 tangent = normalize(cross(up, n));
+  vec3 bitangent = cross(n, tangent);
+  return normalize(tangent * h.x + bitangent * h.y + n * h.z);
+}
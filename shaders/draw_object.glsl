@@ -0,0 +1,15 @@
+// Mirrors indirect::DrawObject: one primitive of a scene node, as the culling shader and the
+// indirect mesh pipelines see it
+struct DrawObject {
+  mat4 model;
+  // Object-space bounding sphere, xyz center and w radius
+  vec4 sphere;
+  uint indexCount;
+  uint firstIndex;
+  int vertexOffset;
+  // Which draw count the object adds to, and where that batch's commands start
+  uint batch;
+  uint firstCommand;
+  // Index into the bindless materials
+  uint material;
+};
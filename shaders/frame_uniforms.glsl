@@ -0,0 +1,33 @@
+// Set 0, shared by every pass that draws the scene. Mirrors FrameUniforms in descriptor.rs.
+
+#define SHADOW_CASCADES 3
+#define MAX_POINT_LIGHTS 4
+
+layout(set = 0, binding = 0) uniform FrameUniforms {
+  mat4 view;
+  mat4 proj;
+  mat4 view_proj;
+  mat4 inv_view_proj;
+  vec4 camera_pos;
+  vec4 sun_direction;
+  vec4 sun_color;
+  vec4 spot_position;
+  vec4 spot_direction;
+  vec4 spot_color;
+  vec4 light_counts;
+  vec4 point_positions[MAX_POINT_LIGHTS];
+  vec4 point_colors[MAX_POINT_LIGHTS];
+  mat4 cascade_view_proj[SHADOW_CASCADES];
+  mat4 spot_view_proj;
+  vec4 cascade_splits;
+  vec4 shadow_tiles[SHADOW_CASCADES + 1];
+  vec4 shadow_params;
+  vec4 environment_params;
+} frame;
+
+layout(set = 0, binding = 1) uniform sampler2DShadow shadowAtlas;
+layout(set = 0, binding = 2) uniform sampler2D shadowAtlasDepth;
+layout(set = 0, binding = 3) uniform samplerCube irradianceMap;
+layout(set = 0, binding = 4) uniform samplerCube prefilteredMap;
+layout(set = 0, binding = 5) uniform sampler2D brdfLut;
+layout(set = 0, binding = 6) uniform samplerCube skybox;
@@ -0,0 +1,27 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "frame_uniforms.glsl"
+
+// Mirrors MaterialUniforms in material.rs
+layout(set = 1, binding = 0) uniform MaterialUniforms {
+  vec4 base_color_factor;
+  vec4 emissive_factor;
+  vec4 params;
+} material;
+
+// Texture slots in material::TextureSlot order
+layout(set = 1, binding = 1) uniform sampler2D baseColorTexture;
+layout(set = 1, binding = 2) uniform sampler2D normalTexture;
+layout(set = 1, binding = 3) uniform sampler2D metallicRoughnessTexture;
+layout(set = 1, binding = 4) uniform sampler2D occlusionTexture;
+layout(set = 1, binding = 5) uniform sampler2D emissiveTexture;
+
+#include "mesh_shading.glsl"
+
+void main(){
+  shade(texture(baseColorTexture, fragTexCoord), texture(normalTexture, fragTexCoord).xyz,
+        texture(metallicRoughnessTexture, fragTexCoord),
+        texture(occlusionTexture, fragTexCoord).r, texture(emissiveTexture, fragTexCoord).rgb,
+        material.base_color_factor, material.emissive_factor, material.params);
+}
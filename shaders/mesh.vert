@@ -0,0 +1,36 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "frame_uniforms.glsl"
+
+layout(push_constant) uniform PushConstants {
+  mat4 model;
+  uint material;
+} push;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inNormal;
+layout(location = 2) in vec2 inTexCoord;
+layout(location = 3) in vec3 inColor;
+layout(location = 4) in vec4 inTangent;
+
+layout(location = 0) out vec3 fragNormal;
+layout(location = 1) out vec2 fragTexCoord;
+layout(location = 2) out vec3 fragColor;
+layout(location = 3) out vec3 fragWorldPos;
+layout(location = 4) out float fragViewDepth;
+layout(location = 5) out vec4 fragTangent;
+// Only mesh_bindless.frag reads it
+layout(location = 6) flat out uint fragMaterial;
+
+void main(){
+  vec4 worldPos = push.model * vec4(inPosition, 1.0);
+  gl_Position = frame.view_proj * worldPos;
+  fragNormal = mat3(transpose(inverse(push.model))) * inNormal;
+  fragTangent = vec4(mat3(push.model) * inTangent.xyz, inTangent.w);
+  fragTexCoord = inTexCoord;
+  fragColor = inColor;
+  fragWorldPos = worldPos.xyz;
+  fragViewDepth = -(frame.view * worldPos).z;
+  fragMaterial = push.material;
+}
@@ -0,0 +1,42 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+#extension GL_EXT_nonuniform_qualifier : require
+
+#include "frame_uniforms.glsl"
+
+// mesh.frag with the material looked up by ID in the bindless set, see bindless.rs
+
+// Mirrors BindlessMaterial in material.rs: MaterialUniforms, then a texture and a sampler
+// handle per material::TextureSlot
+struct MaterialRecord {
+  vec4 base_color_factor;
+  vec4 emissive_factor;
+  vec4 params;
+  uint textures[5];
+  uint samplers[5];
+};
+
+layout(set = 1, binding = 0) uniform texture2D textures[];
+layout(set = 1, binding = 1) uniform sampler samplers[];
+layout(std430, set = 1, binding = 2) readonly buffer Materials {
+  MaterialRecord materials[];
+};
+
+layout(location = 6) flat in uint fragMaterial;
+
+#include "mesh_shading.glsl"
+
+// Indirect draws mix materials within a call, so the handles can differ between invocations
+vec4 sampleSlot(uint slot) {
+  uint textureIndex = materials[fragMaterial].textures[slot];
+  uint samplerIndex = materials[fragMaterial].samplers[slot];
+  return texture(sampler2D(textures[nonuniformEXT(textureIndex)],
+                           samplers[nonuniformEXT(samplerIndex)]),
+                 fragTexCoord);
+}
+
+void main(){
+  MaterialRecord material = materials[fragMaterial];
+  shade(sampleSlot(0), sampleSlot(1).xyz, sampleSlot(2), sampleSlot(3).r, sampleSlot(4).rgb,
+        material.base_color_factor, material.emissive_factor, material.params);
+}
@@ -0,0 +1,38 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "frame_uniforms.glsl"
+#include "draw_object.glsl"
+
+// mesh.vert for indirect draws, whose commands carry the object index as their first instance
+layout(std430, set = 2, binding = 0) readonly buffer DrawObjects {
+  DrawObject objects[];
+};
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inNormal;
+layout(location = 2) in vec2 inTexCoord;
+layout(location = 3) in vec3 inColor;
+layout(location = 4) in vec4 inTangent;
+
+layout(location = 0) out vec3 fragNormal;
+layout(location = 1) out vec2 fragTexCoord;
+layout(location = 2) out vec3 fragColor;
+layout(location = 3) out vec3 fragWorldPos;
+layout(location = 4) out float fragViewDepth;
+layout(location = 5) out vec4 fragTangent;
+// Only mesh_bindless.frag reads it
+layout(location = 6) flat out uint fragMaterial;
+
+void main(){
+  mat4 model = objects[gl_InstanceIndex].model;
+  vec4 worldPos = model * vec4(inPosition, 1.0);
+  gl_Position = frame.view_proj * worldPos;
+  fragNormal = mat3(transpose(inverse(model))) * inNormal;
+  fragTangent = vec4(mat3(model) * inTangent.xyz, inTangent.w);
+  fragTexCoord = inTexCoord;
+  fragColor = inColor;
+  fragWorldPos = worldPos.xyz;
+  fragViewDepth = -(frame.view * worldPos).z;
+  fragMaterial = objects[gl_InstanceIndex].material;
+}
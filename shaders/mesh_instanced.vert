@@ -0,0 +1,40 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "frame_uniforms.glsl"
+
+// mesh.vert with the transform and a tint per instance, see instancing::InstanceData
+
+// model::MeshPushConstants without the transform
+layout(push_constant) uniform PushConstants {
+  layout(offset = 64) uint material;
+} push;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inNormal;
+layout(location = 2) in vec2 inTexCoord;
+layout(location = 3) in vec3 inColor;
+layout(location = 4) in vec4 inTangent;
+layout(location = 5) in mat4 instanceModel;
+layout(location = 9) in vec4 instanceColor;
+
+layout(location = 0) out vec3 fragNormal;
+layout(location = 1) out vec2 fragTexCoord;
+layout(location = 2) out vec3 fragColor;
+layout(location = 3) out vec3 fragWorldPos;
+layout(location = 4) out float fragViewDepth;
+layout(location = 5) out vec4 fragTangent;
+// Only mesh_bindless.frag reads it
+layout(location = 6) flat out uint fragMaterial;
+
+void main(){
+  vec4 worldPos = instanceModel * vec4(inPosition, 1.0);
+  gl_Position = frame.view_proj * worldPos;
+  fragNormal = mat3(transpose(inverse(instanceModel))) * inNormal;
+  fragTangent = vec4(mat3(instanceModel) * inTangent.xyz, inTangent.w);
+  fragTexCoord = inTexCoord;
+  fragColor = inColor * instanceColor.rgb;
+  fragWorldPos = worldPos.xyz;
+  fragViewDepth = -(frame.view * worldPos).z;
+  fragMaterial = push.material;
+}
@@ -0,0 +1,200 @@
+// Lighting shared by mesh.frag and mesh_bindless.frag, which differ only in where the material
+// comes from. Expects frame_uniforms.glsl included first.
+
+layout(location = 0) in vec3 fragNormal;
+layout(location = 1) in vec2 fragTexCoord;
+layout(location = 2) in vec3 fragColor;
+layout(location = 3) in vec3 fragWorldPos;
+layout(location = 4) in float fragViewDepth;
+layout(location = 5) in vec4 fragTangent;
+
+layout(location = 0) out vec4 out_color;
+
+const float PI = 3.14159265359;
+const vec3 cascadeTints[4] = vec3[](
+  vec3(1.0, 0.4, 0.4), vec3(0.4, 1.0, 0.4), vec3(0.4, 0.4, 1.0), vec3(1.0, 1.0, 0.4)
+);
+
+// Fraction of (2r+1)^2 comparison taps that are lit, each of them already a bilinear 2x2 PCF
+// when the sampler filters linearly. Taps are clamped to the tile so they never read a
+// neighbouring shadow map.
+float sampleShadow(mat4 lightViewProj, vec4 tile, vec3 worldPos) {
+  vec4 clip = lightViewProj * vec4(worldPos, 1.0);
+  vec3 ndc = clip.xyz / clip.w;
+  if (ndc.z >= 1.0) {
+    return 1.0;
+  }
+
+  float texel = frame.shadow_params.x;
+  int radius = int(frame.shadow_params.y);
+  vec2 uv = tile.xy + (ndc.xy * 0.5 + 0.5) * tile.zw;
+  vec2 lo = tile.xy + texel * (float(radius) + 0.5);
+  vec2 hi = tile.xy + tile.zw - texel * (float(radius) + 0.5);
+
+  float lit = 0.0;
+  for (int y = -radius; y <= radius; y++) {
+    for (int x = -radius; x <= radius; x++) {
+      vec2 tap = clamp(uv + vec2(x, y) * texel, lo, hi);
+      lit += texture(shadowAtlas, vec3(tap, ndc.z));
+    }
+  }
+  float taps = float((2 * radius + 1) * (2 * radius + 1));
+  return lit / taps;
+}
+
+int cascadeIndex() {
+  for (int i = 0; i < SHADOW_CASCADES - 1; i++) {
+    if (fragViewDepth < frame.cascade_splits[i]) {
+      return i;
+    }
+  }
+  return SHADOW_CASCADES - 1;
+}
+
+// Smooth falloff shaped like inverse square but measured in units of the range, so intensities
+// don't depend on the scale of the scene, reaching zero at the range
+float attenuation(float distance, float range) {
+  float x = distance / range;
+  float window = clamp(1.0 - x * x * x * x, 0.0, 1.0);
+  return window * window / (1.0 + 25.0 * x * x);
+}
+
+// GGX / Trowbridge-Reitz normal distribution
+float distributionGGX(float nDotH, float roughness) {
+  float a2 = roughness * roughness * roughness * roughness;
+  float d = nDotH * nDotH * (a2 - 1.0) + 1.0;
+  return a2 / (PI * d * d);
+}
+
+// Smith's height-correlated visibility term, the geometry term divided by 4 n.l n.v
+float visibilitySmithGGX(float nDotV, float nDotL, float roughness) {
+  float a2 = roughness * roughness * roughness * roughness;
+  float ggxV = nDotL * sqrt(nDotV * nDotV * (1.0 - a2) + a2);
+  float ggxL = nDotV * sqrt(nDotL * nDotL * (1.0 - a2) + a2);
+  return 0.5 / max(ggxV + ggxL, 1e-5);
+}
+
+vec3 fresnelSchlick(float cosTheta, vec3 f0) {
+  return f0 + (1.0 - f0) * pow(1.0 - cosTheta, 5.0);
+}
+
+// Rough surfaces reflect less at grazing angles than Schlick alone predicts
+vec3 fresnelSchlickRoughness(float cosTheta, vec3 f0, float roughness) {
+  return f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(1.0 - cosTheta, 5.0);
+}
+
+// Ambient light from the environment maps: irradiance for diffuse, the prefiltered mip matching
+// the roughness and the split-sum lookup table for specular
+vec3 imageBasedLighting(vec3 n, vec3 v, vec3 albedo, float metallic, float roughness) {
+  float nDotV = max(dot(n, v), 1e-4);
+  vec3 f0 = mix(vec3(0.04), albedo, metallic);
+  vec3 f = fresnelSchlickRoughness(nDotV, f0, roughness);
+
+  vec3 diffuse = texture(irradianceMap, n).rgb * albedo * (1.0 - f) * (1.0 - metallic);
+  vec3 r = reflect(-v, n);
+  vec3 prefiltered = textureLod(prefilteredMap, r, roughness * frame.environment_params.x).rgb;
+  vec2 scaleBias = texture(brdfLut, vec2(nDotV, roughness)).rg;
+  vec3 specular = prefiltered * (f0 * scaleBias.x + scaleBias.y);
+
+  return (diffuse + specular) * frame.environment_params.y;
+}
+
+// Outgoing radiance towards the eye from light arriving along `toLight` with `radiance`
+vec3 brdf(vec3 n, vec3 v, vec3 toLight, vec3 radiance, vec3 albedo, float metallic,
+          float roughness) {
+  vec3 h = normalize(v + toLight);
+  float nDotL = max(dot(n, toLight), 0.0);
+  float nDotV = max(dot(n, v), 1e-4);
+  float nDotH = max(dot(n, h), 0.0);
+  if (nDotL <= 0.0) {
+    return vec3(0.0);
+  }
+
+  vec3 f0 = mix(vec3(0.04), albedo, metallic);
+  vec3 f = fresnelSchlick(max(dot(h, v), 0.0), f0);
+  vec3 specular = distributionGGX(nDotH, roughness) * visibilitySmithGGX(nDotV, nDotL, roughness)
+                  * f;
+  vec3 diffuse = (1.0 - f) * (1.0 - metallic) * albedo / PI;
+  return (diffuse + specular) * radiance * nDotL;
+}
+
+// Tangent-space normal from a normal map texel, in world space
+vec3 shadingNormal(vec3 normalTexel, float normalScale) {
+  vec3 n = normalize(fragNormal);
+  vec3 t = fragTangent.xyz - n * dot(n, fragTangent.xyz);
+  if (dot(t, t) > 1e-8) {
+    t = normalize(t);
+    vec3 b = cross(n, t) * fragTangent.w;
+    vec3 mapped = normalTexel * 2.0 - 1.0;
+    mapped.xy *= normalScale;
+    n = normalize(mat3(t, b, n) * mapped);
+  }
+  // Double-sided materials light their back faces as seen from behind
+  return gl_FrontFacing ? n : -n;
+}
+
+// Lights the fragment with the texels of the material's slots and its factors, laid out like
+// MaterialUniforms, and writes the result
+void shade(vec4 baseColorTexel, vec3 normalTexel, vec4 metallicRoughnessTexel,
+           float occlusionTexel, vec3 emissiveTexel, vec4 baseColorFactor, vec4 emissiveFactor,
+           vec4 params) {
+  vec4 baseColor = baseColorTexel * baseColorFactor;
+  float alphaCutoff = emissiveFactor.w;
+  if (baseColor.a < alphaCutoff) {
+    discard;
+  }
+  vec3 albedo = baseColor.rgb * fragColor;
+
+  // glTF packs roughness into green and metalness into blue
+  float metallic = clamp(params.x * metallicRoughnessTexel.b, 0.0, 1.0);
+  float roughness = clamp(params.y * metallicRoughnessTexel.g, 0.04, 1.0);
+  float occlusion = mix(1.0, occlusionTexel, params.w);
+  vec3 emissive = emissiveTexel * emissiveFactor.rgb;
+
+  vec3 n = shadingNormal(normalTexel, params.z);
+  vec3 v = normalize(frame.camera_pos.xyz - fragWorldPos);
+  int shadowFlags = int(frame.shadow_params.w);
+  int cascade = cascadeIndex();
+
+  vec3 toSun = normalize(frame.sun_direction.xyz);
+  float sunShadow = 1.0;
+  if ((shadowFlags & 1) != 0) {
+    sunShadow = sampleShadow(frame.cascade_view_proj[cascade], frame.shadow_tiles[cascade],
+                             fragWorldPos);
+  }
+  vec3 color = brdf(n, v, toSun, frame.sun_color.rgb * sunShadow, albedo, metallic, roughness);
+
+  float spotRange = frame.spot_position.w;
+  if (spotRange > 0.0) {
+    vec3 toSpot = frame.spot_position.xyz - fragWorldPos;
+    float spotDistance = length(toSpot);
+    toSpot /= spotDistance;
+    float cone = smoothstep(frame.spot_direction.w, frame.spot_color.w,
+                            dot(-toSpot, normalize(frame.spot_direction.xyz)));
+    float spotShadow = 1.0;
+    if ((shadowFlags & 2) != 0 && cone > 0.0) {
+      spotShadow = sampleShadow(frame.spot_view_proj, frame.shadow_tiles[SHADOW_CASCADES],
+                                fragWorldPos);
+    }
+    vec3 radiance = frame.spot_color.rgb * cone * attenuation(spotDistance, spotRange)
+                    * spotShadow;
+    color += brdf(n, v, toSpot, radiance, albedo, metallic, roughness);
+  }
+
+  int pointCount = int(frame.light_counts.x);
+  for (int i = 0; i < pointCount; i++) {
+    vec3 toPoint = frame.point_positions[i].xyz - fragWorldPos;
+    float pointDistance = length(toPoint);
+    vec3 radiance = frame.point_colors[i].rgb
+                    * attenuation(pointDistance, frame.point_positions[i].w);
+    color += brdf(n, v, toPoint / pointDistance, radiance, albedo, metallic, roughness);
+  }
+
+  color += imageBasedLighting(n, v, albedo, metallic, roughness) * occlusion;
+  color += emissive;
+  // Debug view 1 tints by cascade
+  if (int(frame.shadow_params.z) == 1) {
+    color *= cascadeTints[cascade];
+  }
+  out_color = vec4(color, baseColor.a);
+}
@@ -0,0 +1,22 @@
+// Shared by the full-screen post-processing passes
+
+// Mirrors PostPushConstants in post_process.rs; what params hold depends on the pass
+layout(push_constant) uniform PostPushConstants {
+  vec4 params;
+  uint flags;
+} post;
+
+// Match the FLAG_ constants in post_process.rs
+const uint FLAG_THRESHOLD = 1u;
+const uint FLAG_BLOOM = 2u;
+const uint FLAG_COLOR_GRADING = 4u;
+const uint FLAG_FXAA = 8u;
+
+vec3 linearToSrgb(vec3 color) {
+  return mix(color * 12.92, 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055,
+             step(vec3(0.0031308), color));
+}
+
+vec3 srgbToLinear(vec3 color) {
+  return mix(color / 12.92, pow((color + 0.055) / 1.055, vec3(2.4)), step(vec3(0.04045), color));
+}
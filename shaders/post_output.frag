@@ -0,0 +1,186 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "post.glsl"
+
+// params: x paper white in nits, y headroom the source is scaled by, z encoding in
+// OutputEncoding order
+
+// The tonemapped image, gamma encoded with luma in alpha
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+const int ENCODING_SRGB_FORMAT = 0;
+const int ENCODING_SRGB_SHADER = 1;
+const int ENCODING_PQ = 2;
+const int ENCODING_SCRGB_LINEAR = 3;
+
+const float EDGE_THRESHOLD_MIN = 0.0312;
+const float EDGE_THRESHOLD_MAX = 0.125;
+const float SUBPIXEL_QUALITY = 0.75;
+const int SEARCH_STEPS = 12;
+// How far each step of the edge search moves, in texels
+const float SEARCH_STEP_SIZES[SEARCH_STEPS] =
+    float[](1.0, 1.0, 1.0, 1.0, 1.0, 1.5, 2.0, 2.0, 2.0, 2.0, 4.0, 8.0);
+
+float luma(vec2 uv) {
+  return textureLod(source, uv, 0.0).a;
+}
+
+// FXAA 3.11 quality: find high-contrast texels, tell horizontal from vertical edges, search
+// along the edge for its ends and shift the sample across it by how close the nearer end is,
+// with extra blending for features smaller than a texel
+vec3 fxaa(vec2 uv) {
+  vec2 texel = 1.0 / vec2(textureSize(source, 0));
+  vec3 colorCenter = textureLod(source, uv, 0.0).rgb;
+  float lumaCenter = luma(uv);
+  float lumaDown = luma(uv + vec2(0.0, -texel.y));
+  float lumaUp = luma(uv + vec2(0.0, texel.y));
+  float lumaLeft = luma(uv + vec2(-texel.x, 0.0));
+  float lumaRight = luma(uv + vec2(texel.x, 0.0));
+
+  float lumaMin = min(lumaCenter, min(min(lumaDown, lumaUp), min(lumaLeft, lumaRight)));
+  float lumaMax = max(lumaCenter, max(max(lumaDown, lumaUp), max(lumaLeft, lumaRight)));
+  float lumaRange = lumaMax - lumaMin;
+  if (lumaRange < max(EDGE_THRESHOLD_MIN, lumaMax * EDGE_THRESHOLD_MAX)) {
+    return colorCenter;
+  }
+
+  float lumaDownLeft = luma(uv + vec2(-texel.x, -texel.y));
+  float lumaUpRight = luma(uv + vec2(texel.x, texel.y));
+  float lumaUpLeft = luma(uv + vec2(-texel.x, texel.y));
+  float lumaDownRight = luma(uv + vec2(texel.x, -texel.y));
+
+  float lumaDownUp = lumaDown + lumaUp;
+  float lumaLeftRight = lumaLeft + lumaRight;
+  float lumaLeftCorners = lumaDownLeft + lumaUpLeft;
+  float lumaDownCorners = lumaDownLeft + lumaDownRight;
+  float lumaRightCorners = lumaDownRight + lumaUpRight;
+  float lumaUpCorners = lumaUpRight + lumaUpLeft;
+
+  float edgeHorizontal = abs(-2.0 * lumaLeft + lumaLeftCorners)
+                         + abs(-2.0 * lumaCenter + lumaDownUp) * 2.0
+                         + abs(-2.0 * lumaRight + lumaRightCorners);
+  float edgeVertical = abs(-2.0 * lumaUp + lumaUpCorners)
+                       + abs(-2.0 * lumaCenter + lumaLeftRight) * 2.0
+                       + abs(-2.0 * lumaDown + lumaDownCorners);
+  bool isHorizontal = edgeHorizontal >= edgeVertical;
+
+  // Which side of the edge is steeper, and half a texel towards it
+  float luma1 = isHorizontal ? lumaDown : lumaLeft;
+  float luma2 = isHorizontal ? lumaUp : lumaRight;
+  float gradient1 = luma1 - lumaCenter;
+  float gradient2 = luma2 - lumaCenter;
+  bool is1Steepest = abs(gradient1) >= abs(gradient2);
+  float gradientScaled = 0.25 * max(abs(gradient1), abs(gradient2));
+  float stepLength = isHorizontal ? texel.y : texel.x;
+  float lumaLocalAverage;
+  if (is1Steepest) {
+    stepLength = -stepLength;
+    lumaLocalAverage = 0.5 * (luma1 + lumaCenter);
+  } else {
+    lumaLocalAverage = 0.5 * (luma2 + lumaCenter);
+  }
+  vec2 edgeUv = uv;
+  if (isHorizontal) {
+    edgeUv.y += stepLength * 0.5;
+  } else {
+    edgeUv.x += stepLength * 0.5;
+  }
+
+  // Walk both ways along the edge until the luma differs enough from its average
+  vec2 offset = isHorizontal ? vec2(texel.x, 0.0) : vec2(0.0, texel.y);
+  vec2 uv1 = edgeUv - offset;
+  vec2 uv2 = edgeUv + offset;
+  float lumaEnd1 = luma(uv1) - lumaLocalAverage;
+  float lumaEnd2 = luma(uv2) - lumaLocalAverage;
+  bool reached1 = abs(lumaEnd1) >= gradientScaled;
+  bool reached2 = abs(lumaEnd2) >= gradientScaled;
+  if (!reached1) {
+    uv1 -= offset;
+  }
+  if (!reached2) {
+    uv2 += offset;
+  }
+  for (int i = 2; i < SEARCH_STEPS && !(reached1 && reached2); i++) {
+    if (!reached1) {
+      lumaEnd1 = luma(uv1) - lumaLocalAverage;
+      reached1 = abs(lumaEnd1) >= gradientScaled;
+    }
+    if (!reached2) {
+      lumaEnd2 = luma(uv2) - lumaLocalAverage;
+      reached2 = abs(lumaEnd2) >= gradientScaled;
+    }
+    if (!reached1) {
+      uv1 -= offset * SEARCH_STEP_SIZES[i];
+    }
+    if (!reached2) {
+      uv2 += offset * SEARCH_STEP_SIZES[i];
+    }
+  }
+
+  float distance1 = isHorizontal ? uv.x - uv1.x : uv.y - uv1.y;
+  float distance2 = isHorizontal ? uv2.x - uv.x : uv2.y - uv.y;
+  bool isDirection1 = distance1 < distance2;
+  float distanceFinal = min(distance1, distance2);
+  float edgeLength = distance1 + distance2;
+  float pixelOffset = -distanceFinal / edgeLength + 0.5;
+  // Only shift when the nearer end varies the same way the center does
+  bool isLumaCenterSmaller = lumaCenter < lumaLocalAverage;
+  bool correctVariation = ((isDirection1 ? lumaEnd1 : lumaEnd2) < 0.0) != isLumaCenterSmaller;
+  float finalOffset = correctVariation ? pixelOffset : 0.0;
+
+  float lumaAverage = (1.0 / 12.0) * (2.0 * (lumaDownUp + lumaLeftRight) + lumaLeftCorners
+                                      + lumaRightCorners);
+  float subPixelOffset1 = clamp(abs(lumaAverage - lumaCenter) / lumaRange, 0.0, 1.0);
+  float subPixelOffset2 = (-2.0 * subPixelOffset1 + 3.0) * subPixelOffset1 * subPixelOffset1;
+  finalOffset = max(finalOffset, subPixelOffset2 * subPixelOffset2 * SUBPIXEL_QUALITY);
+
+  vec2 finalUv = uv;
+  if (isHorizontal) {
+    finalUv.y += finalOffset * stepLength;
+  } else {
+    finalUv.x += finalOffset * stepLength;
+  }
+  return textureLod(source, finalUv, 0.0).rgb;
+}
+
+// SMPTE ST 2084 inverse EOTF, from nits to the 0..1 signal
+vec3 pqEncode(vec3 nits) {
+  const float m1 = 0.1593017578125, m2 = 78.84375;
+  const float c1 = 0.8359375, c2 = 18.8515625, c3 = 18.6875;
+  vec3 y = pow(clamp(nits / 10000.0, 0.0, 1.0), vec3(m1));
+  return pow((c1 + c2 * y) / (1.0 + c3 * y), vec3(m2));
+}
+
+// Linear BT.709 primaries to BT.2020 ones, BT.2087 section 2
+const mat3 BT709_TO_BT2020 = mat3(
+  0.6274, 0.0691, 0.0164,
+  0.3293, 0.9195, 0.0880,
+  0.0433, 0.0114, 0.8956
+);
+
+void main() {
+  vec3 color = (post.flags & FLAG_FXAA) != 0u ? fxaa(fragUv) : textureLod(source, fragUv, 0.0).rgb;
+
+  // Linear, relative to paper white
+  vec3 linear = srgbToLinear(color) * post.params.y;
+  switch (int(post.params.z)) {
+  case ENCODING_SRGB_FORMAT:
+    color = linear;
+    break;
+  case ENCODING_PQ:
+    color = pqEncode(BT709_TO_BT2020 * linear * post.params.x);
+    break;
+  case ENCODING_SCRGB_LINEAR:
+    // 1.0 is 80 nits
+    color = linear * post.params.x / 80.0;
+    break;
+  default:
+    break;
+  }
+  outColor = vec4(color, 1.0);
+}
@@ -0,0 +1,5 @@
+#version 450
+
+// Depth only; the pipeline has no color attachments
+void main(){
+}
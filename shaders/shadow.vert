@@ -0,0 +1,12 @@
+#version 450
+
+layout(push_constant) uniform PushConstants {
+  mat4 light_view_proj;
+  mat4 model;
+} push;
+
+layout(location = 0) in vec3 inPosition;
+
+void main(){
+  gl_Position = push.light_view_proj * push.model * vec4(inPosition, 1.0);
+}
@@ -0,0 +1,13 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "frame_uniforms.glsl"
+
+layout(location = 0) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 out_color;
+
+void main(){
+  float depth = texture(shadowAtlasDepth, fragTexCoord).r;
+  out_color = vec4(vec3(depth), 1.0);
+}
@@ -0,0 +1,19 @@
+#version 450
+
+// Two triangles covering a rectangle given in normalized device coordinates
+layout(push_constant) uniform PushConstants {
+  vec4 rect;
+} push;
+
+layout(location = 0) out vec2 fragTexCoord;
+
+const vec2 corners[6] = vec2[](
+  vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(1.0, 1.0),
+  vec2(1.0, 1.0), vec2(0.0, 1.0), vec2(0.0, 0.0)
+);
+
+void main(){
+  vec2 corner = corners[gl_VertexIndex];
+  fragTexCoord = corner;
+  gl_Position = vec4(mix(push.rect.xy, push.rect.zw, corner), 0.0, 1.0);
+}
@@ -0,0 +1,18 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "frame_uniforms.glsl"
+
+layout(location = 0) in vec2 fragNdc;
+
+layout(location = 0) out vec4 out_color;
+
+void main() {
+  // The view ray through this pixel, from unprojecting it onto the near and far planes
+  float farDepth = frame.environment_params.w;
+  vec4 near = frame.inv_view_proj * vec4(fragNdc, 1.0 - farDepth, 1.0);
+  vec4 far = frame.inv_view_proj * vec4(fragNdc, farDepth, 1.0);
+  vec3 direction = normalize(far.xyz / far.w - near.xyz / near.w);
+
+  out_color = vec4(textureLod(skybox, direction, 0.0).rgb * frame.environment_params.y, 1.0);
+}
@@ -0,0 +1,13 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "frame_uniforms.glsl"
+
+layout(location = 0) out vec2 fragNdc;
+
+// One triangle covering the screen, placed on the far plane
+void main() {
+  vec2 ndc = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0 - 1.0;
+  fragNdc = ndc;
+  gl_Position = vec4(ndc, frame.environment_params.w, 1.0);
+}
@@ -0,0 +1,69 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "post.glsl"
+
+// params: x exposure scale, y bloom intensity, z tonemapper in post_process::Tonemapper order,
+// w how far above paper white the display goes, 1 for SDR
+
+layout(set = 0, binding = 0) uniform sampler2D sceneColor;
+layout(set = 0, binding = 1) uniform sampler2D bloom;
+layout(set = 0, binding = 2) uniform sampler3D colorLut;
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+vec3 reinhard(vec3 color) {
+  return color / (1.0 + color);
+}
+
+// Narkowicz's fit of the ACES filmic curve
+vec3 acesFilmic(vec3 color) {
+  return clamp(color * (2.51 * color + 0.03) / (color * (2.43 * color + 0.59) + 0.14), 0.0, 1.0);
+}
+
+// Hable's filmic curve from Uncharted 2
+vec3 hable(vec3 x) {
+  const float a = 0.15, b = 0.50, c = 0.10, d = 0.20, e = 0.02, f = 0.30;
+  return (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f;
+}
+
+vec3 uncharted2(vec3 color) {
+  const float whitePoint = 11.2;
+  return hable(color * 2.0) / hable(vec3(whitePoint));
+}
+
+void main() {
+  vec3 color = texture(sceneColor, fragUv).rgb;
+  if ((post.flags & FLAG_BLOOM) != 0u) {
+    color += texture(bloom, fragUv).rgb * post.params.y;
+  }
+  // The curves map to 0..1, which is stretched over the whole range of HDR displays
+  float headroom = post.params.w;
+  color *= post.params.x / headroom;
+
+  switch (int(post.params.z)) {
+  case 1:
+    color = reinhard(color);
+    break;
+  case 2:
+    color = acesFilmic(color);
+    break;
+  case 3:
+    color = uncharted2(color);
+    break;
+  default:
+    break;
+  }
+
+  // Lookup tables are authored for gamma encoded colors; the half texel remap samples texel
+  // centers at the ends of the range. For HDR output 1 stands for the display's peak.
+  vec3 encoded = linearToSrgb(clamp(color, 0.0, 1.0));
+  if ((post.flags & FLAG_COLOR_GRADING) != 0u) {
+    float size = float(textureSize(colorLut, 0).x);
+    encoded = texture(colorLut, encoded * ((size - 1.0) / size) + 0.5 / size).rgb;
+  }
+
+  outColor = vec4(encoded, dot(encoded, vec3(0.299, 0.587, 0.114)));
+}
@@ -0,0 +1,27 @@
+use super::window::WindowEvent;
+use super::Renderer;
+
+// What a program built on the renderer implements. The renderer owns the window, the device and
+// the main loop and calls back into the application at fixed points of each frame; everything it
+// draws is reached through the `Renderer` handed to each hook.
+
+pub trait Application {
+    // Once, after the renderer is created and before the first frame
+    fn init(&mut self, _renderer: &mut Renderer) {}
+
+    // Every frame after input and the camera are updated, with the seconds since the last frame
+    fn update(&mut self, _renderer: &mut Renderer, _dt: f32) {}
+
+    // Every frame right before it is recorded, after any swapchain recreation
+    fn render(&mut self, _renderer: &mut Renderer) {}
+
+    // Every window event, after the renderer has seen it
+    fn on_event(&mut self, _renderer: &mut Renderer, _event: &WindowEvent) {}
+}
+
+// Opens the window and runs `application` until it is closed
+pub fn run(mut application: impl Application) {
+    let mut renderer = Renderer::new();
+    application.init(&mut renderer);
+    renderer.main_loop(&mut application);
+}
@@ -0,0 +1,310 @@
+use ash::vk;
+use std::ffi::c_void;
+use std::ptr;
+
+use super::descriptor;
+use super::resources::{Device, Owned};
+use crate::util::constants::PREFER_BINDLESS;
+
+// Every texture and sampler in one descriptor set that stays bound for the whole frame, instead
+// of a set per material. Uploads register their image views and samplers here and get back
+// stable indices, which the materials storage buffer at binding 2 stores per texture slot;
+// mesh_bindless.frag looks a draw's material up by an ID from push constants or the indirect
+// draw object. The arrays are update-after-bind and partially bound, so slots can be filled and
+// released while frames that don't use them are in flight, and empty slots are never read.
+
+pub const TEXTURE_BINDING: u32 = 0;
+pub const SAMPLER_BINDING: u32 = 1;
+pub const MATERIAL_BINDING: u32 = 2;
+
+// Array sizes, lowered to what the device allows
+const MAX_TEXTURES: u32 = 4096;
+const MAX_SAMPLERS: u32 = 64;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TextureHandle(pub u32);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SamplerHandle(pub u32);
+
+// Hands out array indices, reusing released ones first so handles stay small
+struct Slots {
+    next: u32,
+    free: Vec<u32>,
+    capacity: u32,
+}
+
+impl Slots {
+    fn new(capacity: u32) -> Slots {
+        Slots {
+            next: 0,
+            free: vec![],
+            capacity,
+        }
+    }
+
+    fn allocate(&mut self) -> Option<u32> {
+        if let Some(slot) = self.free.pop() {
+            return Some(slot);
+        }
+        if self.next == self.capacity {
+            return None;
+        }
+        self.next += 1;
+        Some(self.next - 1)
+    }
+
+    fn release(&mut self, slot: u32) {
+        self.free.push(slot);
+    }
+}
+
+pub struct BindlessTextures {
+    _descriptor_pool: Owned<vk::DescriptorPool>,
+    set_layout: Owned<vk::DescriptorSetLayout>,
+    set: vk::DescriptorSet,
+    textures: Slots,
+    samplers: Slots,
+}
+
+// Needs Vulkan 1.2's descriptor indexing: runtime-sized arrays indexed non-uniformly, and
+// binding sampled images partially and updating them after binding
+pub fn choose_bindless(instance: &ash::Instance, physical_device: &vk::PhysicalDevice) -> bool {
+    let properties = unsafe { instance.get_physical_device_properties(*physical_device) };
+    let bindless = PREFER_BINDLESS && properties.api_version >= vk::API_VERSION_1_2 && {
+        let mut vulkan_12_features = vk::PhysicalDeviceVulkan12Features {
+            s_type: vk::StructureType::PHYSICAL_DEVICE_VULKAN_1_2_FEATURES,
+            ..Default::default()
+        };
+        let mut features = vk::PhysicalDeviceFeatures2 {
+            s_type: vk::StructureType::PHYSICAL_DEVICE_FEATURES_2,
+            p_next: &mut vulkan_12_features as *mut _ as *mut c_void,
+            ..Default::default()
+        };
+        unsafe { instance.get_physical_device_features2(*physical_device, &mut features) };
+
+        [
+            vulkan_12_features.runtime_descriptor_array,
+            vulkan_12_features.shader_sampled_image_array_non_uniform_indexing,
+            vulkan_12_features.descriptor_binding_partially_bound,
+            vulkan_12_features.descriptor_binding_sampled_image_update_after_bind,
+            vulkan_12_features.descriptor_binding_update_unused_while_pending,
+        ]
+        .iter()
+        .all(|&feature| feature == vk::TRUE)
+    };
+    println!("Bindless textures: {}", if bindless { "on" } else { "off" });
+
+    bindless
+}
+
+impl BindlessTextures {
+    // `reserved_samplers` of the fragment stage's sampler and sampled image limits are left to
+    // the other sets of the pipelines using this one
+    pub fn new(
+        instance: &ash::Instance,
+        physical_device: &vk::PhysicalDevice,
+        device: &Device,
+        reserved_samplers: u32,
+    ) -> BindlessTextures {
+        let mut vulkan_12_properties = vk::PhysicalDeviceVulkan12Properties {
+            s_type: vk::StructureType::PHYSICAL_DEVICE_VULKAN_1_2_PROPERTIES,
+            ..Default::default()
+        };
+        let mut properties = vk::PhysicalDeviceProperties2 {
+            s_type: vk::StructureType::PHYSICAL_DEVICE_PROPERTIES_2,
+            p_next: &mut vulkan_12_properties as *mut _ as *mut c_void,
+            ..Default::default()
+        };
+        unsafe { instance.get_physical_device_properties2(*physical_device, &mut properties) };
+        let texture_capacity = MAX_TEXTURES
+            .min(vulkan_12_properties.max_descriptor_set_update_after_bind_sampled_images)
+            .min(
+                vulkan_12_properties
+                    .max_per_stage_descriptor_update_after_bind_sampled_images
+                    .saturating_sub(reserved_samplers),
+            );
+        let sampler_capacity = MAX_SAMPLERS
+            .min(vulkan_12_properties.max_descriptor_set_update_after_bind_samplers)
+            .min(
+                vulkan_12_properties
+                    .max_per_stage_descriptor_update_after_bind_samplers
+                    .saturating_sub(reserved_samplers),
+            );
+
+        let bindings = [
+            vk::DescriptorSetLayoutBinding {
+                binding: TEXTURE_BINDING,
+                descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                descriptor_count: texture_capacity,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: SAMPLER_BINDING,
+                descriptor_type: vk::DescriptorType::SAMPLER,
+                descriptor_count: sampler_capacity,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: MATERIAL_BINDING,
+                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+        ];
+        let array_flags = vk::DescriptorBindingFlags::PARTIALLY_BOUND
+            | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+            | vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING;
+        let binding_flags = [
+            array_flags,
+            array_flags,
+            vk::DescriptorBindingFlags::empty(),
+        ];
+        let binding_flags_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo {
+            s_type: vk::StructureType::DESCRIPTOR_SET_LAYOUT_BINDING_FLAGS_CREATE_INFO,
+            binding_count: binding_flags.len() as u32,
+            p_binding_flags: binding_flags.as_ptr(),
+            ..Default::default()
+        };
+        let layout_info = vk::DescriptorSetLayoutCreateInfo {
+            s_type: vk::StructureType::DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+            p_next: &binding_flags_info as *const _ as *const c_void,
+            flags: vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL,
+            binding_count: bindings.len() as u32,
+            p_bindings: bindings.as_ptr(),
+            ..Default::default()
+        };
+        let set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(&layout_info, None)
+                .expect("Failed to create bindless descriptor set layout")
+        };
+
+        let pool_sizes = bindings.map(|binding| vk::DescriptorPoolSize {
+            ty: binding.descriptor_type,
+            descriptor_count: binding.descriptor_count,
+        });
+        let pool_info = vk::DescriptorPoolCreateInfo {
+            s_type: vk::StructureType::DESCRIPTOR_POOL_CREATE_INFO,
+            flags: vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND,
+            pool_size_count: pool_sizes.len() as u32,
+            p_pool_sizes: pool_sizes.as_ptr(),
+            max_sets: 1,
+            ..Default::default()
+        };
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(&pool_info, None)
+                .expect("Failed to create bindless descriptor pool")
+        };
+        let set = descriptor::allocate_descriptor_sets(device, descriptor_pool, set_layout, 1)[0];
+
+        BindlessTextures {
+            _descriptor_pool: device.own(descriptor_pool, "bindless pool"),
+            set_layout: device.own(set_layout, "bindless set layout"),
+            set,
+            textures: Slots::new(texture_capacity),
+            samplers: Slots::new(sampler_capacity),
+        }
+    }
+
+    // Set 1 of the mesh pipelines in place of the material set
+    pub fn set_layout(&self) -> vk::DescriptorSetLayout {
+        *self.set_layout
+    }
+
+    pub fn set(&self) -> vk::DescriptorSet {
+        self.set
+    }
+
+    // The view must stay alive until the handle is released
+    pub fn register_texture(
+        &mut self,
+        device: &Device,
+        view: vk::ImageView,
+    ) -> Result<TextureHandle, String> {
+        let slot = self
+            .textures
+            .allocate()
+            .ok_or_else(|| format!("all {} bindless textures in use", self.textures.capacity))?;
+        let image_info = [vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view: view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }];
+        self.write(
+            device,
+            TEXTURE_BINDING,
+            slot,
+            vk::DescriptorType::SAMPLED_IMAGE,
+            &image_info,
+        );
+
+        Ok(TextureHandle(slot))
+    }
+
+    pub fn register_sampler(
+        &mut self,
+        device: &Device,
+        sampler: vk::Sampler,
+    ) -> Result<SamplerHandle, String> {
+        let slot = self
+            .samplers
+            .allocate()
+            .ok_or_else(|| format!("all {} bindless samplers in use", self.samplers.capacity))?;
+        let image_info = [vk::DescriptorImageInfo {
+            sampler,
+            image_view: vk::ImageView::null(),
+            image_layout: vk::ImageLayout::UNDEFINED,
+        }];
+        self.write(
+            device,
+            SAMPLER_BINDING,
+            slot,
+            vk::DescriptorType::SAMPLER,
+            &image_info,
+        );
+
+        Ok(SamplerHandle(slot))
+    }
+
+    // Frames still reading the slot must have finished before it's reused
+    pub fn release_texture(&mut self, handle: TextureHandle) {
+        self.textures.release(handle.0);
+    }
+
+    pub fn release_sampler(&mut self, handle: SamplerHandle) {
+        self.samplers.release(handle.0);
+    }
+
+    // The material records mesh_bindless.frag indexes by material ID. Only one buffer can be
+    // bound, and no frame that reads the old one may be in flight.
+    pub fn write_materials(&self, device: &Device, buffer: vk::Buffer) {
+        descriptor::write_storage_buffer(device, self.set, MATERIAL_BINDING, buffer);
+    }
+
+    fn write(
+        &self,
+        device: &Device,
+        binding: u32,
+        slot: u32,
+        descriptor_type: vk::DescriptorType,
+        image_info: &[vk::DescriptorImageInfo],
+    ) {
+        let write = vk::WriteDescriptorSet {
+            s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
+            p_next: ptr::null(),
+            dst_set: self.set,
+            dst_binding: binding,
+            dst_array_element: slot,
+            descriptor_count: image_info.len() as u32,
+            descriptor_type,
+            p_image_info: image_info.as_ptr(),
+            ..Default::default()
+        };
+        unsafe { device.update_descriptor_sets(&[write], &[]) };
+    }
+}
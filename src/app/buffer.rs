@@ -0,0 +1,149 @@
+use ash::vk;
+
+use super::commands;
+use super::resources::Device;
+use crate::util::structures::BufferStuff;
+
+pub fn find_memory_type(
+    memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    type_filter: u32,
+    properties: vk::MemoryPropertyFlags,
+) -> u32 {
+    for (i, memory_type) in memory_properties.memory_types
+        [..memory_properties.memory_type_count as usize]
+        .iter()
+        .enumerate()
+    {
+        if (type_filter & (1 << i)) != 0 && memory_type.property_flags.contains(properties) {
+            return i as u32;
+        }
+    }
+
+    panic!("Failed to find suitable memory type")
+}
+
+pub fn create_buffer(
+    device: &Device,
+    memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    size: vk::DeviceSize,
+    usage: vk::BufferUsageFlags,
+    properties: vk::MemoryPropertyFlags,
+    name: &str,
+) -> BufferStuff {
+    let buffer_info = vk::BufferCreateInfo {
+        s_type: vk::StructureType::BUFFER_CREATE_INFO,
+        size,
+        usage,
+        sharing_mode: vk::SharingMode::EXCLUSIVE,
+        ..Default::default()
+    };
+
+    let buffer = unsafe {
+        device
+            .create_buffer(&buffer_info, None)
+            .expect("Failed to create buffer")
+    };
+
+    let requirements = unsafe { device.get_buffer_memory_requirements(buffer) };
+    let alloc_info = vk::MemoryAllocateInfo {
+        s_type: vk::StructureType::MEMORY_ALLOCATE_INFO,
+        allocation_size: requirements.size,
+        memory_type_index: find_memory_type(
+            memory_properties,
+            requirements.memory_type_bits,
+            properties,
+        ),
+        ..Default::default()
+    };
+
+    let memory = unsafe {
+        device
+            .allocate_memory(&alloc_info, None)
+            .expect("Failed to allocate buffer memory")
+    };
+
+    unsafe {
+        device
+            .bind_buffer_memory(buffer, memory, 0)
+            .expect("Failed to bind buffer memory")
+    };
+
+    BufferStuff {
+        buffer: device.own(buffer, name),
+        memory: device.own(memory, &format!("{} memory", name)),
+        size,
+    }
+}
+
+// Copies `data` through a staging buffer into a new DEVICE_LOCAL buffer
+pub fn create_device_local_buffer(
+    device: &Device,
+    memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    command_pool: vk::CommandPool,
+    queue: vk::Queue,
+    usage: vk::BufferUsageFlags,
+    data: &[u8],
+    name: &str,
+) -> BufferStuff {
+    let size = data.len() as vk::DeviceSize;
+    let staging = create_staging_buffer(device, memory_properties, data);
+
+    let buffer_stuff = create_buffer(
+        device,
+        memory_properties,
+        size,
+        usage | vk::BufferUsageFlags::TRANSFER_DST,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        name,
+    );
+
+    let command_buffer = commands::begin_single_time_commands(device, command_pool);
+    let region = vk::BufferCopy {
+        src_offset: 0,
+        dst_offset: 0,
+        size,
+    };
+    unsafe {
+        device.cmd_copy_buffer(
+            command_buffer,
+            *staging.buffer,
+            *buffer_stuff.buffer,
+            &[region],
+        )
+    };
+    commands::end_single_time_commands(device, command_pool, queue, command_buffer);
+
+    drop(staging);
+
+    buffer_stuff
+}
+
+pub fn create_staging_buffer(
+    device: &Device,
+    memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    data: &[u8],
+) -> BufferStuff {
+    let staging = create_buffer(
+        device,
+        memory_properties,
+        data.len() as vk::DeviceSize,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        "staging",
+    );
+
+    unsafe {
+        let mapped = device
+            .map_memory(
+                *staging.memory,
+                0,
+                staging.size,
+                vk::MemoryMapFlags::empty(),
+            )
+            .expect("Failed to map staging buffer memory");
+        std::ptr::copy_nonoverlapping(data.as_ptr(), mapped as *mut u8, data.len());
+        device.unmap_memory(*staging.memory);
+    }
+
+    staging
+}
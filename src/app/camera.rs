@@ -0,0 +1,297 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Vector3, Vector4};
+use std::f32::consts::FRAC_PI_2;
+
+use super::descriptor::FrameUniforms;
+use super::input::Input;
+
+// Radians per second at full stick deflection
+const GAMEPAD_LOOK_SPEED: f32 = 2.5_f32;
+
+// Cameras are right-handed, looking down -Z in view space. Projections target Vulkan clip space
+// directly: y points down and depth goes 0..1, or 1..0 with reversed-Z, where the far plane
+// lands on 0 for better float depth precision. Reversed-Z needs a GREATER depth test and a
+// depth clear of 0.
+
+#[derive(Clone, Copy, Debug)]
+pub enum Projection {
+    Perspective {
+        fov_y_degrees: f32,
+        near: f32,
+        far: f32,
+    },
+    // `height` is the visible view-space height; width follows the aspect ratio
+    Orthographic {
+        height: f32,
+        near: f32,
+        far: f32,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CameraMode {
+    Fly,
+    Orbit,
+}
+
+pub struct Camera {
+    pub position: Point3<f32>,
+    // Radians; yaw 0 looks down -Z, positive pitch looks up
+    pub yaw: f32,
+    pub pitch: f32,
+    pub projection: Projection,
+    pub reversed_z: bool,
+}
+
+impl Camera {
+    pub fn forward(&self) -> Vector3<f32> {
+        Vector3::new(
+            self.yaw.sin() * self.pitch.cos(),
+            self.pitch.sin(),
+            -self.yaw.cos() * self.pitch.cos(),
+        )
+    }
+
+    pub fn right(&self) -> Vector3<f32> {
+        Vector3::new(self.yaw.cos(), 0.0_f32, self.yaw.sin())
+    }
+
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        Matrix4::look_to_rh(self.position, self.forward(), Vector3::unit_y())
+    }
+
+    pub fn projection_matrix(&self, aspect: f32) -> Matrix4<f32> {
+        self.projection.matrix(aspect, self.reversed_z)
+    }
+
+    pub fn frame_uniforms(&self, aspect: f32) -> FrameUniforms {
+        let view = self.view_matrix();
+        let proj = self.projection_matrix(aspect);
+        let view_proj = proj * view;
+
+        FrameUniforms {
+            view: view.into(),
+            proj: proj.into(),
+            view_proj: view_proj.into(),
+            inv_view_proj: view_proj.invert().unwrap_or(Matrix4::identity()).into(),
+            camera_pos: [self.position.x, self.position.y, self.position.z, 1.0_f32],
+            ..Default::default()
+        }
+    }
+}
+
+impl Projection {
+    pub fn matrix(&self, aspect: f32, reversed_z: bool) -> Matrix4<f32> {
+        match *self {
+            Projection::Perspective {
+                fov_y_degrees,
+                near,
+                far,
+            } => {
+                let f = 1.0_f32 / (fov_y_degrees.to_radians() * 0.5_f32).tan();
+                let (z_scale, z_offset) = if reversed_z {
+                    (near / (far - near), near * far / (far - near))
+                } else {
+                    (far / (near - far), near * far / (near - far))
+                };
+                Matrix4::from_cols(
+                    Vector4::new(f / aspect, 0.0_f32, 0.0_f32, 0.0_f32),
+                    Vector4::new(0.0_f32, -f, 0.0_f32, 0.0_f32),
+                    Vector4::new(0.0_f32, 0.0_f32, z_scale, -1.0_f32),
+                    Vector4::new(0.0_f32, 0.0_f32, z_offset, 0.0_f32),
+                )
+            }
+            Projection::Orthographic { height, near, far } => {
+                let (z_scale, z_offset) = if reversed_z {
+                    (1.0_f32 / (far - near), far / (far - near))
+                } else {
+                    (-1.0_f32 / (far - near), -near / (far - near))
+                };
+                Matrix4::from_cols(
+                    Vector4::new(2.0_f32 / (height * aspect), 0.0_f32, 0.0_f32, 0.0_f32),
+                    Vector4::new(0.0_f32, -2.0_f32 / height, 0.0_f32, 0.0_f32),
+                    Vector4::new(0.0_f32, 0.0_f32, z_scale, 0.0_f32),
+                    Vector4::new(0.0_f32, 0.0_f32, z_offset, 1.0_f32),
+                )
+            }
+        }
+    }
+
+    pub fn near_far(&self) -> (f32, f32) {
+        match *self {
+            Projection::Perspective { near, far, .. }
+            | Projection::Orthographic { near, far, .. } => (near, far),
+        }
+    }
+}
+
+// Driven by the actions in config/input.cfg. Fly mode moves along the view direction and looks
+// around while "look" is held; orbit mode rotates around a target while "orbit" is held and zooms
+// towards it.
+pub struct CameraController {
+    pub camera: Camera,
+    pub mode: CameraMode,
+    pub orbit_target: Point3<f32>,
+    pub orbit_distance: f32,
+    pub move_speed: f32,
+    pub look_sensitivity: f32,
+}
+
+impl CameraController {
+    pub fn new(reversed_z: bool) -> CameraController {
+        let mut controller = CameraController {
+            camera: Camera {
+                position: Point3::new(0.0_f32, 0.0_f32, 3.0_f32),
+                yaw: 0.0_f32,
+                pitch: 0.0_f32,
+                projection: Projection::Perspective {
+                    fov_y_degrees: 45.0_f32,
+                    near: 0.05_f32,
+                    far: 100.0_f32,
+                },
+                reversed_z,
+            },
+            mode: CameraMode::Orbit,
+            orbit_target: Point3::origin(),
+            orbit_distance: 3.0_f32,
+            move_speed: 2.0_f32,
+            look_sensitivity: 0.005_f32,
+        };
+        controller.apply_orbit();
+
+        controller
+    }
+
+    // Looks at a bounding sphere from slightly above and scales speeds and clip planes to it
+    pub fn frame_bounds(&mut self, center: Point3<f32>, radius: f32) {
+        let radius = radius.max(0.001_f32);
+        self.orbit_target = center;
+        self.orbit_distance = radius * 3.0_f32;
+        self.camera.yaw = 0.0_f32;
+        self.camera.pitch = -0.35_f32;
+        self.move_speed = radius * 2.0_f32;
+        self.set_clip_planes(radius * 0.01_f32, radius * 50.0_f32);
+        self.apply_orbit();
+    }
+
+    fn set_clip_planes(&mut self, near_plane: f32, far_plane: f32) {
+        match &mut self.camera.projection {
+            Projection::Perspective { near, far, .. }
+            | Projection::Orthographic { near, far, .. } => {
+                *near = near_plane;
+                *far = far_plane;
+            }
+        }
+    }
+
+    pub fn toggle_mode(&mut self) {
+        self.mode = match self.mode {
+            CameraMode::Fly => {
+                // Orbit whatever is in front of the camera at the current distance
+                self.orbit_target =
+                    self.camera.position + self.camera.forward() * self.orbit_distance;
+                CameraMode::Orbit
+            }
+            CameraMode::Orbit => CameraMode::Fly,
+        };
+        println!("Camera mode: {:?}", self.mode);
+    }
+
+    pub fn toggle_projection(&mut self) {
+        self.camera.projection = match self.camera.projection {
+            Projection::Perspective {
+                fov_y_degrees,
+                near,
+                far,
+            } => Projection::Orthographic {
+                // Keeps the orbit target about the same size on screen
+                height: 2.0_f32
+                    * self.orbit_distance
+                    * (fov_y_degrees.to_radians() * 0.5_f32).tan(),
+                near,
+                far,
+            },
+            Projection::Orthographic { near, far, .. } => Projection::Perspective {
+                fov_y_degrees: 45.0_f32,
+                near,
+                far,
+            },
+        };
+        println!("Camera projection: {:?}", self.camera.projection);
+    }
+
+    fn rotate(&mut self, dx: f32, dy: f32) {
+        // Dragging in orbit mode moves the camera around the target, the opposite of looking
+        let sign = match self.mode {
+            CameraMode::Fly => 1.0_f32,
+            CameraMode::Orbit => -1.0_f32,
+        };
+        let pitch_limit = FRAC_PI_2 - 0.01_f32;
+        self.camera.yaw += sign * dx * self.look_sensitivity;
+        self.camera.pitch = (self.camera.pitch - sign * dy * self.look_sensitivity)
+            .clamp(-pitch_limit, pitch_limit);
+
+        if self.mode == CameraMode::Orbit {
+            self.apply_orbit();
+        }
+    }
+
+    fn apply_orbit(&mut self) {
+        self.camera.position = self.orbit_target - self.camera.forward() * self.orbit_distance;
+    }
+
+    pub fn update(&mut self, input: &Input, dt: f32) {
+        if input.pressed("camera_mode") {
+            self.toggle_mode();
+        }
+        if input.pressed("camera_projection") {
+            self.toggle_projection();
+        }
+
+        let (cursor_dx, cursor_dy) = input.cursor_delta();
+        let drag_action = match self.mode {
+            CameraMode::Fly => "look",
+            CameraMode::Orbit => "orbit",
+        };
+        if input.held(drag_action) {
+            self.rotate(cursor_dx as f32, cursor_dy as f32);
+        }
+        // Sticks turn at a rate instead of by a distance
+        let stick_scale = GAMEPAD_LOOK_SPEED * dt / self.look_sensitivity;
+        let (stick_x, stick_y) = (
+            input.axis("look_left", "look_right"),
+            input.axis("look_up", "look_down"),
+        );
+        if stick_x != 0.0_f32 || stick_y != 0.0_f32 {
+            self.rotate(stick_x * stick_scale, stick_y * stick_scale);
+        }
+
+        match self.mode {
+            CameraMode::Fly => {
+                let direction = self.camera.forward() * input.axis("move_back", "move_forward")
+                    + self.camera.right() * input.axis("move_left", "move_right")
+                    + Vector3::unit_y() * input.axis("move_down", "move_up");
+                // Analog sticks move slower than full tilt, but never faster
+                let length = direction.magnitude();
+                if length > 0.0_f32 {
+                    let boost = if input.held("move_fast") {
+                        4.0_f32
+                    } else {
+                        1.0_f32
+                    };
+                    self.camera.position +=
+                        direction / length * length.min(1.0_f32) * self.move_speed * boost * dt;
+                }
+            }
+            CameraMode::Orbit => {
+                let zoom = input.axis("zoom_out", "zoom_in");
+                if zoom != 0.0_f32 {
+                    self.orbit_distance *= 0.9_f32.powf(zoom);
+                    if let Projection::Orthographic { height, .. } = &mut self.camera.projection {
+                        *height *= 0.9_f32.powf(zoom);
+                    }
+                    self.apply_orbit();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use ash::vk;
+
+use crate::util::structures::{ColorSpacePolicy, OutputEncoding};
+
+// Picks the swapchain's surface format and color space from what the surface offers, trying the
+// configured policy first and then the ones that degrade most gracefully from it
+
+#[derive(Clone, Copy)]
+pub struct SurfaceFormatChoice {
+    pub surface_format: vk::SurfaceFormatKHR,
+    pub encoding: OutputEncoding,
+}
+
+pub fn check_swapchain_colorspace_support(entry: &ash::Entry) -> bool {
+    let extensions = unsafe {
+        entry
+            .enumerate_instance_extension_properties(None)
+            .expect("Failed to enumerate instance extensions")
+    };
+
+    extensions.iter().any(|extension| {
+        extension.extension_name_as_c_str() == Ok(ash::ext::swapchain_colorspace::NAME)
+    })
+}
+
+// Prints what was chosen, and why when it isn't what `policy` asked for
+pub fn choose_surface_format(
+    available_formats: &[vk::SurfaceFormatKHR],
+    policy: ColorSpacePolicy,
+    colorspace_extension: bool,
+) -> SurfaceFormatChoice {
+    for (i, &candidate) in fallbacks(policy).iter().enumerate() {
+        // The extended color spaces mustn't be used without the extension enabled
+        if encoding(candidate).is_hdr() && !colorspace_extension {
+            continue;
+        }
+        let found = available_formats
+            .iter()
+            .find(|format| satisfies(candidate, format));
+        if let Some(&surface_format) = found {
+            if i > 0 {
+                println!(
+                    "Color space policy {:?} can't be satisfied: {}; using {:?}",
+                    policy,
+                    unavailable_reason(policy, colorspace_extension),
+                    candidate
+                );
+            }
+            println!(
+                "Swapchain: {:?} in {:?}",
+                surface_format.format, surface_format.color_space
+            );
+            return SurfaceFormatChoice {
+                surface_format,
+                encoding: encoding(candidate),
+            };
+        }
+    }
+
+    // Nothing recognizable; whatever the surface prefers is treated as SDR
+    let surface_format = available_formats[0];
+    let encoding = if is_srgb_format(surface_format.format) {
+        OutputEncoding::SrgbFormat
+    } else {
+        OutputEncoding::SrgbShader
+    };
+    println!(
+        "Color space policy {:?} can't be satisfied: {}; using {:?} in {:?} as {:?}",
+        policy,
+        unavailable_reason(policy, colorspace_extension),
+        surface_format.format,
+        surface_format.color_space,
+        encoding
+    );
+    SurfaceFormatChoice {
+        surface_format,
+        encoding,
+    }
+}
+
+// HDR policies try the other HDR one before giving up on HDR
+fn fallbacks(policy: ColorSpacePolicy) -> &'static [ColorSpacePolicy] {
+    use ColorSpacePolicy::*;
+    match policy {
+        SdrSrgb => &[SdrSrgb, SdrUnorm],
+        SdrUnorm => &[SdrUnorm, SdrSrgb],
+        Hdr10 => &[Hdr10, ScRgb, SdrSrgb, SdrUnorm],
+        ScRgb => &[ScRgb, Hdr10, SdrSrgb, SdrUnorm],
+    }
+}
+
+fn satisfies(policy: ColorSpacePolicy, surface_format: &vk::SurfaceFormatKHR) -> bool {
+    let (formats, color_space): (&[vk::Format], _) = match policy {
+        ColorSpacePolicy::SdrSrgb => (
+            &[
+                vk::Format::B8G8R8A8_SRGB,
+                vk::Format::R8G8B8A8_SRGB,
+                vk::Format::A8B8G8R8_SRGB_PACK32,
+            ],
+            vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        ),
+        ColorSpacePolicy::SdrUnorm => (
+            &[
+                vk::Format::B8G8R8A8_UNORM,
+                vk::Format::R8G8B8A8_UNORM,
+                vk::Format::A8B8G8R8_UNORM_PACK32,
+                vk::Format::A2B10G10R10_UNORM_PACK32,
+                vk::Format::A2R10G10B10_UNORM_PACK32,
+            ],
+            vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        ),
+        ColorSpacePolicy::Hdr10 => (
+            &[
+                vk::Format::A2B10G10R10_UNORM_PACK32,
+                vk::Format::A2R10G10B10_UNORM_PACK32,
+            ],
+            vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+        ),
+        ColorSpacePolicy::ScRgb => (
+            &[vk::Format::R16G16B16A16_SFLOAT],
+            vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+        ),
+    };
+
+    surface_format.color_space == color_space && formats.contains(&surface_format.format)
+}
+
+fn encoding(policy: ColorSpacePolicy) -> OutputEncoding {
+    match policy {
+        ColorSpacePolicy::SdrSrgb => OutputEncoding::SrgbFormat,
+        ColorSpacePolicy::SdrUnorm => OutputEncoding::SrgbShader,
+        ColorSpacePolicy::Hdr10 => OutputEncoding::Pq,
+        ColorSpacePolicy::ScRgb => OutputEncoding::ScRgbLinear,
+    }
+}
+
+fn unavailable_reason(policy: ColorSpacePolicy, colorspace_extension: bool) -> String {
+    match policy {
+        ColorSpacePolicy::Hdr10 | ColorSpacePolicy::ScRgb if !colorspace_extension => format!(
+            "{} isn't available",
+            ash::ext::swapchain_colorspace::NAME.to_string_lossy()
+        ),
+        _ => "the surface offers no matching format".to_string(),
+    }
+}
+
+fn is_srgb_format(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::R8G8B8A8_SRGB | vk::Format::B8G8R8A8_SRGB | vk::Format::A8B8G8R8_SRGB_PACK32
+    )
+}
@@ -0,0 +1,70 @@
+use ash::vk;
+
+use super::resources::Device;
+
+// One-off command buffers for uploads at load time. Submission waits for the queue to go idle,
+// so these must stay out of the per-frame path.
+
+pub fn begin_single_time_commands(
+    device: &Device,
+    command_pool: vk::CommandPool,
+) -> vk::CommandBuffer {
+    let alloc_info = vk::CommandBufferAllocateInfo {
+        s_type: vk::StructureType::COMMAND_BUFFER_ALLOCATE_INFO,
+        command_pool,
+        level: vk::CommandBufferLevel::PRIMARY,
+        command_buffer_count: 1,
+        ..Default::default()
+    };
+
+    let command_buffer = unsafe {
+        device
+            .allocate_command_buffers(&alloc_info)
+            .expect("Failed to allocate command buffer")[0]
+    };
+
+    let begin_info = vk::CommandBufferBeginInfo {
+        s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
+        flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+        ..Default::default()
+    };
+
+    unsafe {
+        device
+            .begin_command_buffer(command_buffer, &begin_info)
+            .expect("Failed to begin single time command buffer")
+    };
+
+    command_buffer
+}
+
+pub fn end_single_time_commands(
+    device: &Device,
+    command_pool: vk::CommandPool,
+    queue: vk::Queue,
+    command_buffer: vk::CommandBuffer,
+) {
+    let submit_info = vk::SubmitInfo {
+        s_type: vk::StructureType::SUBMIT_INFO,
+        command_buffer_count: 1,
+        p_command_buffers: &command_buffer,
+        ..Default::default()
+    };
+
+    unsafe {
+        device
+            .end_command_buffer(command_buffer)
+            .expect("Failed to end single time command buffer");
+        device
+            .debug()
+            .begin_queue_label(queue, "single time commands");
+        device
+            .queue_submit(queue, &[submit_info], vk::Fence::null())
+            .expect("Failed to submit single time command buffer");
+        device.debug().end_queue_label(queue);
+        device
+            .queue_wait_idle(queue)
+            .expect("Failed to wait for queue idle");
+        device.free_command_buffers(command_pool, &[command_buffer]);
+    }
+}
@@ -0,0 +1,58 @@
+use ash::vk::{self, PipelineCache};
+use std::{ffi::CString, path::Path};
+
+use super::graphics_pipeline::{create_shader_module, read_shader};
+use super::resources::{Device, Scoped};
+use crate::util::structures::ComputePipelineStuff;
+
+pub fn create_compute_pipeline(
+    device: &Device,
+    shader: &str,
+    set_layouts: &[vk::DescriptorSetLayout],
+    push_constant_ranges: &[vk::PushConstantRange],
+) -> ComputePipelineStuff {
+    let code = read_shader(Path::new(&format!("shaders/spv/{}.spv", shader)));
+    let shader_module = Scoped::new(device, create_shader_module(device, &code, shader));
+
+    let entry_point = CString::new("main").unwrap();
+    let stage = vk::PipelineShaderStageCreateInfo {
+        s_type: vk::StructureType::PIPELINE_SHADER_STAGE_CREATE_INFO,
+        stage: vk::ShaderStageFlags::COMPUTE,
+        module: *shader_module,
+        p_name: entry_point.as_ptr(),
+        ..Default::default()
+    };
+
+    let pipeline_layout_info = vk::PipelineLayoutCreateInfo {
+        s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
+        set_layout_count: set_layouts.len() as u32,
+        p_set_layouts: set_layouts.as_ptr(),
+        push_constant_range_count: push_constant_ranges.len() as u32,
+        p_push_constant_ranges: push_constant_ranges.as_ptr(),
+        ..Default::default()
+    };
+
+    let pipeline_layout = unsafe {
+        device
+            .create_pipeline_layout(&pipeline_layout_info, None)
+            .expect("Failed to create pipeline layout")
+    };
+
+    let pipeline_info = vk::ComputePipelineCreateInfo {
+        s_type: vk::StructureType::COMPUTE_PIPELINE_CREATE_INFO,
+        stage,
+        layout: pipeline_layout,
+        ..Default::default()
+    };
+
+    let compute_pipeline = unsafe {
+        device
+            .create_compute_pipelines(PipelineCache::null(), &[pipeline_info], None)
+            .expect("Failed to create compute pipeline")[0]
+    };
+
+    ComputePipelineStuff {
+        compute_pipeline: device.own(compute_pipeline, shader),
+        pipeline_layout: device.own(pipeline_layout, shader),
+    }
+}
@@ -0,0 +1,81 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix, Matrix4, Point3, Transform, Vector3, Vector4};
+
+use crate::assets::mesh::Bounds;
+
+// View frustum as six inward-facing planes (xyz normal, w distance), extracted from a
+// view-projection matrix in Vulkan clip space: -w <= x, y <= w and 0 <= z <= w. Reversed-Z only
+// swaps which of the two depth planes is near, so it needs no special casing.
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    pub fn from_matrix(view_proj: &Matrix4<f32>) -> Frustum {
+        let [r0, r1, r2, r3] = [0, 1, 2, 3].map(|i| view_proj.row(i));
+        let planes = [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r2, r3 - r2].map(|plane| {
+            let length = plane.truncate().magnitude();
+            if length > f32::EPSILON {
+                plane / length
+            } else {
+                plane
+            }
+        });
+
+        Frustum { planes }
+    }
+
+    pub fn planes(&self) -> &[Vector4<f32>; 6] {
+        &self.planes
+    }
+
+    pub fn intersects_sphere(&self, center: Point3<f32>, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.truncate().dot(center.to_vec()) + plane.w >= -radius)
+    }
+
+    // Box given by its center and half extents along the world axes
+    pub fn intersects_box(&self, center: Point3<f32>, extents: Vector3<f32>) -> bool {
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            let reach = normal.x.abs() * extents.x
+                + normal.y.abs() * extents.y
+                + normal.z.abs() * extents.z;
+            normal.dot(center.to_vec()) + plane.w >= -reach
+        })
+    }
+
+    // Object-space bounds placed by `transform`. The sphere test rejects most objects cheaply;
+    // the box then rejects objects whose sphere only grazes the frustum.
+    pub fn is_visible(&self, bounds: &Bounds, transform: &Matrix4<f32>) -> bool {
+        if bounds.is_empty() {
+            return false;
+        }
+
+        let axes = [
+            transform.x.truncate(),
+            transform.y.truncate(),
+            transform.z.truncate(),
+        ];
+        let max_scale = axes
+            .iter()
+            .map(|axis| axis.magnitude())
+            .fold(0.0_f32, f32::max);
+        let center = transform.transform_point(Point3::from(bounds.center));
+        if !self.intersects_sphere(center, bounds.radius * max_scale) {
+            return false;
+        }
+
+        // World-space box around the transformed object-space box
+        let half = [0, 1, 2].map(|axis| (bounds.max[axis] - bounds.min[axis]) * 0.5_f32);
+        let box_center = transform.transform_point(Point3::new(
+            (bounds.min[0] + bounds.max[0]) * 0.5_f32,
+            (bounds.min[1] + bounds.max[1]) * 0.5_f32,
+            (bounds.min[2] + bounds.max[2]) * 0.5_f32,
+        ));
+        let abs = |v: Vector3<f32>| Vector3::new(v.x.abs(), v.y.abs(), v.z.abs());
+        let extents = abs(axes[0]) * half[0] + abs(axes[1]) * half[1] + abs(axes[2]) * half[2];
+
+        self.intersects_box(box_center, extents)
+    }
+}
@@ -0,0 +1,338 @@
+use ash::vk;
+use std::ptr;
+
+use super::buffer;
+use super::material::TEXTURE_SLOT_COUNT;
+use super::resources::{Device, Owned};
+use crate::util::constants::{MAX_FRAMES_IN_FLIGHT, MAX_POINT_LIGHTS, SHADOW_CASCADES};
+use crate::util::structures::FrameUniformStuff;
+
+// Set 0 holds per-frame data shared by every draw, set 1 the material being drawn, or every
+// material when bindless (see bindless.rs)
+
+// Matches the std140 block in the shaders, so everything is made of vec4s and mat4s
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct FrameUniforms {
+    pub view: [[f32; 4]; 4],
+    pub proj: [[f32; 4]; 4],
+    pub view_proj: [[f32; 4]; 4],
+    pub inv_view_proj: [[f32; 4]; 4],
+    pub camera_pos: [f32; 4],
+    // xyz points towards the light
+    pub sun_direction: [f32; 4],
+    // Color times intensity
+    pub sun_color: [f32; 4],
+    // w is the range
+    pub spot_position: [f32; 4],
+    // xyz is where the light points, w the cosine of the outer cone angle
+    pub spot_direction: [f32; 4],
+    // w is the cosine of the inner cone angle
+    pub spot_color: [f32; 4],
+    // x is the number of point lights in use
+    pub light_counts: [f32; 4],
+    // w is the range
+    pub point_positions: [[f32; 4]; MAX_POINT_LIGHTS],
+    pub point_colors: [[f32; 4]; MAX_POINT_LIGHTS],
+    pub cascade_view_proj: [[[f32; 4]; 4]; SHADOW_CASCADES],
+    pub spot_view_proj: [[f32; 4]; 4],
+    // View-space distance at which each cascade ends
+    pub cascade_splits: [f32; 4],
+    // Atlas uv offset (xy) and scale (zw) of every cascade, then the spot light
+    pub shadow_tiles: [[f32; 4]; SHADOW_CASCADES + 1],
+    // x: texel size in uv, y: PCF radius in texels, z: debug view, w: 1 sun / 2 spot shadow bits
+    pub shadow_params: [f32; 4],
+    // x: mip level of the roughest prefiltered specular, y: intensity, w: depth of the far plane
+    pub environment_params: [f32; 4],
+}
+
+// Number of image bindings in the frame set after the uniform buffer at binding 0
+pub const FRAME_SET_IMAGES: u32 = 6;
+
+// Binding 1 samples the shadow atlas with depth comparison, binding 2 reads raw depth for the
+// debug view. Bindings 3 to 6 are the environment: irradiance, prefiltered specular, the BRDF
+// lookup table and the sky itself.
+pub fn create_frame_set_layout(device: &Device) -> Owned<vk::DescriptorSetLayout> {
+    let mut bindings = vec![vk::DescriptorSetLayoutBinding {
+        binding: 0,
+        descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+        descriptor_count: 1,
+        stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+        ..Default::default()
+    }];
+    for binding in 1..=FRAME_SET_IMAGES {
+        bindings.push(vk::DescriptorSetLayoutBinding {
+            binding,
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: 1,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            ..Default::default()
+        });
+    }
+
+    create_set_layout(device, &bindings, "frame set layout")
+}
+
+// Binding 0 holds the material's factors, the rest its textures in material::TextureSlot order
+pub fn create_material_set_layout(device: &Device) -> Owned<vk::DescriptorSetLayout> {
+    let mut bindings = vec![vk::DescriptorSetLayoutBinding {
+        binding: 0,
+        descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+        descriptor_count: 1,
+        stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        ..Default::default()
+    }];
+    for slot in 0..TEXTURE_SLOT_COUNT {
+        bindings.push(vk::DescriptorSetLayoutBinding {
+            binding: 1 + slot as u32,
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: 1,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            ..Default::default()
+        });
+    }
+
+    create_set_layout(device, &bindings, "material set layout")
+}
+
+pub fn create_set_layout(
+    device: &Device,
+    bindings: &[vk::DescriptorSetLayoutBinding],
+    name: &str,
+) -> Owned<vk::DescriptorSetLayout> {
+    let layout_info = vk::DescriptorSetLayoutCreateInfo {
+        s_type: vk::StructureType::DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+        binding_count: bindings.len() as u32,
+        p_bindings: bindings.as_ptr(),
+        ..Default::default()
+    };
+
+    let layout = unsafe {
+        device
+            .create_descriptor_set_layout(&layout_info, None)
+            .expect("Failed to create descriptor set layout")
+    };
+
+    device.own(layout, name)
+}
+
+pub fn create_descriptor_pool(
+    device: &Device,
+    pool_sizes: &[vk::DescriptorPoolSize],
+    max_sets: u32,
+    name: &str,
+) -> Owned<vk::DescriptorPool> {
+    let pool_info = vk::DescriptorPoolCreateInfo {
+        s_type: vk::StructureType::DESCRIPTOR_POOL_CREATE_INFO,
+        pool_size_count: pool_sizes.len() as u32,
+        p_pool_sizes: pool_sizes.as_ptr(),
+        max_sets,
+        ..Default::default()
+    };
+
+    let pool = unsafe {
+        device
+            .create_descriptor_pool(&pool_info, None)
+            .expect("Failed to create descriptor pool")
+    };
+
+    device.own(pool, name)
+}
+
+pub fn allocate_descriptor_sets(
+    device: &Device,
+    descriptor_pool: vk::DescriptorPool,
+    layout: vk::DescriptorSetLayout,
+    count: usize,
+) -> Vec<vk::DescriptorSet> {
+    let layouts = vec![layout; count];
+    let alloc_info = vk::DescriptorSetAllocateInfo {
+        s_type: vk::StructureType::DESCRIPTOR_SET_ALLOCATE_INFO,
+        descriptor_pool,
+        descriptor_set_count: layouts.len() as u32,
+        p_set_layouts: layouts.as_ptr(),
+        ..Default::default()
+    };
+
+    unsafe {
+        device
+            .allocate_descriptor_sets(&alloc_info)
+            .expect("Failed to allocate descriptor sets")
+    }
+}
+
+// One persistently mapped uniform buffer per frame in flight, written after that frame's fence
+pub fn create_frame_uniforms(
+    device: &Device,
+    memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    descriptor_pool: vk::DescriptorPool,
+    layout: vk::DescriptorSetLayout,
+) -> FrameUniformStuff {
+    let size = std::mem::size_of::<FrameUniforms>() as vk::DeviceSize;
+    let buffers: Vec<_> = (0..MAX_FRAMES_IN_FLIGHT)
+        .map(|frame| {
+            buffer::create_buffer(
+                device,
+                memory_properties,
+                size,
+                vk::BufferUsageFlags::UNIFORM_BUFFER,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                &format!("frame uniforms {}", frame),
+            )
+        })
+        .collect();
+
+    let mapped = buffers
+        .iter()
+        .map(|buffer_stuff| unsafe {
+            device
+                .map_memory(*buffer_stuff.memory, 0, size, vk::MemoryMapFlags::empty())
+                .expect("Failed to map uniform buffer memory")
+        })
+        .collect();
+
+    let descriptor_sets = allocate_descriptor_sets(device, descriptor_pool, layout, buffers.len());
+    for (&descriptor_set, buffer_stuff) in descriptor_sets.iter().zip(buffers.iter()) {
+        let buffer_info = vk::DescriptorBufferInfo {
+            buffer: *buffer_stuff.buffer,
+            offset: 0,
+            range: size,
+        };
+        let write = vk::WriteDescriptorSet {
+            s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
+            dst_set: descriptor_set,
+            dst_binding: 0,
+            dst_array_element: 0,
+            descriptor_count: 1,
+            descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+            p_buffer_info: &buffer_info,
+            ..Default::default()
+        };
+        unsafe { device.update_descriptor_sets(&[write], &[]) };
+    }
+
+    FrameUniformStuff {
+        buffers,
+        mapped,
+        descriptor_sets,
+    }
+}
+
+pub fn update_frame_uniforms(
+    frame_uniforms: &FrameUniformStuff,
+    frame: usize,
+    uniforms: &FrameUniforms,
+) {
+    unsafe {
+        ptr::copy_nonoverlapping(
+            uniforms as *const FrameUniforms,
+            frame_uniforms.mapped[frame] as *mut FrameUniforms,
+            1,
+        )
+    };
+}
+
+pub fn write_combined_image_sampler(
+    device: &Device,
+    descriptor_set: vk::DescriptorSet,
+    binding: u32,
+    image_view: vk::ImageView,
+    sampler: vk::Sampler,
+) {
+    let image_info = vk::DescriptorImageInfo {
+        sampler,
+        image_view,
+        image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    };
+    let write = vk::WriteDescriptorSet {
+        s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
+        dst_set: descriptor_set,
+        dst_binding: binding,
+        dst_array_element: 0,
+        descriptor_count: 1,
+        descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        p_image_info: &image_info,
+        ..Default::default()
+    };
+
+    unsafe { device.update_descriptor_sets(&[write], &[]) };
+}
+
+pub fn write_uniform_buffer(
+    device: &Device,
+    descriptor_set: vk::DescriptorSet,
+    binding: u32,
+    buffer: vk::Buffer,
+    offset: vk::DeviceSize,
+    range: vk::DeviceSize,
+) {
+    let buffer_info = vk::DescriptorBufferInfo {
+        buffer,
+        offset,
+        range,
+    };
+    let write = vk::WriteDescriptorSet {
+        s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
+        dst_set: descriptor_set,
+        dst_binding: binding,
+        dst_array_element: 0,
+        descriptor_count: 1,
+        descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+        p_buffer_info: &buffer_info,
+        ..Default::default()
+    };
+
+    unsafe { device.update_descriptor_sets(&[write], &[]) };
+}
+
+// For compute shaders writing `image_view`, which has to be in GENERAL layout
+pub fn write_storage_image(
+    device: &Device,
+    descriptor_set: vk::DescriptorSet,
+    binding: u32,
+    image_view: vk::ImageView,
+) {
+    let image_info = vk::DescriptorImageInfo {
+        sampler: vk::Sampler::null(),
+        image_view,
+        image_layout: vk::ImageLayout::GENERAL,
+    };
+    let write = vk::WriteDescriptorSet {
+        s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
+        dst_set: descriptor_set,
+        dst_binding: binding,
+        dst_array_element: 0,
+        descriptor_count: 1,
+        descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
+        p_image_info: &image_info,
+        ..Default::default()
+    };
+
+    unsafe { device.update_descriptor_sets(&[write], &[]) };
+}
+
+// The whole of `buffer`
+pub fn write_storage_buffer(
+    device: &Device,
+    descriptor_set: vk::DescriptorSet,
+    binding: u32,
+    buffer: vk::Buffer,
+) {
+    let buffer_info = vk::DescriptorBufferInfo {
+        buffer,
+        offset: 0,
+        range: vk::WHOLE_SIZE,
+    };
+    let write = vk::WriteDescriptorSet {
+        s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
+        dst_set: descriptor_set,
+        dst_binding: binding,
+        dst_array_element: 0,
+        descriptor_count: 1,
+        descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+        p_buffer_info: &buffer_info,
+        ..Default::default()
+    };
+
+    unsafe { device.update_descriptor_sets(&[write], &[]) };
+}
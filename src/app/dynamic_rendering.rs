@@ -0,0 +1,140 @@
+use ash::vk;
+use std::ffi::c_void;
+
+use super::resources::Device;
+
+// Dynamic rendering (core in Vulkan 1.3) renders straight into image views without a
+// VkRenderPass/VkFramebuffer. The layout transitions a render pass would perform are recorded
+// by the render graph, with synchronization2 barriers on this path.
+
+pub fn check_dynamic_rendering_support(
+    instance: &ash::Instance,
+    physical_device: &vk::PhysicalDevice,
+) -> bool {
+    let properties = unsafe { instance.get_physical_device_properties(*physical_device) };
+    if properties.api_version < vk::API_VERSION_1_3 {
+        return false;
+    }
+
+    let mut vulkan_13_features = vk::PhysicalDeviceVulkan13Features {
+        s_type: vk::StructureType::PHYSICAL_DEVICE_VULKAN_1_3_FEATURES,
+        ..Default::default()
+    };
+    let mut features = vk::PhysicalDeviceFeatures2 {
+        s_type: vk::StructureType::PHYSICAL_DEVICE_FEATURES_2,
+        p_next: &mut vulkan_13_features as *mut _ as *mut c_void,
+        ..Default::default()
+    };
+    unsafe { instance.get_physical_device_features2(*physical_device, &mut features) };
+
+    vulkan_13_features.dynamic_rendering == vk::TRUE
+        && vulkan_13_features.synchronization2 == vk::TRUE
+}
+
+pub fn cmd_begin_rendering(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    image_view: vk::ImageView,
+    depth_view: vk::ImageView,
+    extent: vk::Extent2D,
+    clear_values: &[vk::ClearValue; 2],
+    flags: vk::RenderingFlags,
+) {
+    let color_attachment = vk::RenderingAttachmentInfo {
+        s_type: vk::StructureType::RENDERING_ATTACHMENT_INFO,
+        image_view,
+        image_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        load_op: vk::AttachmentLoadOp::CLEAR,
+        store_op: vk::AttachmentStoreOp::STORE,
+        clear_value: clear_values[0],
+        ..Default::default()
+    };
+    let depth_attachment = vk::RenderingAttachmentInfo {
+        s_type: vk::StructureType::RENDERING_ATTACHMENT_INFO,
+        image_view: depth_view,
+        image_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        load_op: vk::AttachmentLoadOp::CLEAR,
+        store_op: vk::AttachmentStoreOp::DONT_CARE,
+        clear_value: clear_values[1],
+        ..Default::default()
+    };
+
+    let rendering_info = vk::RenderingInfo {
+        s_type: vk::StructureType::RENDERING_INFO,
+        flags,
+        render_area: vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        },
+        layer_count: 1,
+        color_attachment_count: 1,
+        p_color_attachments: &color_attachment,
+        p_depth_attachment: &depth_attachment,
+        ..Default::default()
+    };
+
+    unsafe { device.cmd_begin_rendering(command_buffer, &rendering_info) };
+}
+
+// Depth-only rendering whose result is kept, e.g. for shadow maps
+pub fn cmd_begin_depth_rendering(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    depth_view: vk::ImageView,
+    extent: vk::Extent2D,
+    clear_value: vk::ClearValue,
+) {
+    let depth_attachment = vk::RenderingAttachmentInfo {
+        s_type: vk::StructureType::RENDERING_ATTACHMENT_INFO,
+        image_view: depth_view,
+        image_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        load_op: vk::AttachmentLoadOp::CLEAR,
+        store_op: vk::AttachmentStoreOp::STORE,
+        clear_value,
+        ..Default::default()
+    };
+
+    let rendering_info = vk::RenderingInfo {
+        s_type: vk::StructureType::RENDERING_INFO,
+        render_area: vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        },
+        layer_count: 1,
+        p_depth_attachment: &depth_attachment,
+        ..Default::default()
+    };
+
+    unsafe { device.cmd_begin_rendering(command_buffer, &rendering_info) };
+}
+
+// A single color attachment whose previous contents are overwritten, for full-screen passes
+pub fn cmd_begin_color_rendering(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    image_view: vk::ImageView,
+    extent: vk::Extent2D,
+) {
+    let color_attachment = vk::RenderingAttachmentInfo {
+        s_type: vk::StructureType::RENDERING_ATTACHMENT_INFO,
+        image_view,
+        image_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        load_op: vk::AttachmentLoadOp::DONT_CARE,
+        store_op: vk::AttachmentStoreOp::STORE,
+        ..Default::default()
+    };
+
+    let rendering_info = vk::RenderingInfo {
+        s_type: vk::StructureType::RENDERING_INFO,
+        render_area: vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        },
+        layer_count: 1,
+        color_attachment_count: 1,
+        p_color_attachments: &color_attachment,
+        ..Default::default()
+    };
+
+    unsafe { device.cmd_begin_rendering(command_buffer, &rendering_info) };
+}
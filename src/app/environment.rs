@@ -0,0 +1,701 @@
+use ash::vk;
+
+use super::buffer;
+use super::commands;
+use super::compute_pipeline;
+use super::descriptor::{self, FrameUniforms};
+use super::graphics_pipeline;
+use super::model::as_bytes;
+use super::resources::{Device, Owned};
+use super::texture;
+use crate::assets::environment::EnvironmentSource;
+use crate::assets::hdr::HdrImage;
+use crate::assets::material::SamplerDesc;
+use crate::util::constants::REVERSED_Z;
+use crate::util::structures::{
+    ComputePipelineStuff, GraphicsPipelineStuff, ImageStuff, PipelineDesc,
+};
+
+// The sky around the scene and the image-based lighting baked from it. Everything is computed
+// once at load time on the GPU: the source becomes a mipmapped cube map, which is then convolved
+// into diffuse irradiance and a specular cube whose mips hold increasingly rough reflections.
+// Together with a lookup table of the split-sum BRDF terms the lit shader gets ambient light that
+// matches the sky.
+
+const HDR_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+const MAX_SKYBOX_SIZE: u32 = 1024;
+const IRRADIANCE_SIZE: u32 = 32;
+const PREFILTERED_SIZE: u32 = 128;
+// Roughness 0 to 1 over the mips, the last one 4x4
+const PREFILTERED_MIPS: u32 = 6;
+const BRDF_LUT_SIZE: u32 = 256;
+// Matches local_size_x/y in the bake shaders
+const WORKGROUP_SIZE: u32 = 8;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct PrefilterPushConstants {
+    roughness: f32,
+    // Of the skybox's first mip, for picking the mip to sample from
+    source_size: f32,
+}
+
+pub struct Environment {
+    skybox: ImageStuff,
+    irradiance: ImageStuff,
+    prefiltered: ImageStuff,
+    brdf_lut: ImageStuff,
+    sampler: Owned<vk::Sampler>,
+    skybox_pipeline: GraphicsPipelineStuff,
+    // Scales the sky and all light taken from it
+    pub intensity: f32,
+}
+
+impl Environment {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        source: &EnvironmentSource,
+        frame_set_layout: vk::DescriptorSetLayout,
+        render_pass: vk::RenderPass,
+        color_format: vk::Format,
+        depth_format: vk::Format,
+    ) -> Environment {
+        let skybox_size = match source {
+            EnvironmentSource::Equirect(image) => (image.width / 4)
+                .next_power_of_two()
+                .clamp(64, MAX_SKYBOX_SIZE),
+            EnvironmentSource::Faces(faces) => faces[0].width,
+        };
+        let cube_usage = vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED;
+        let skybox = texture::create_cube_image(
+            device,
+            memory_properties,
+            skybox_size,
+            texture::mip_level_count(skybox_size, skybox_size),
+            HDR_FORMAT,
+            cube_usage | vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST,
+            "skybox",
+        );
+        let irradiance = texture::create_cube_image(
+            device,
+            memory_properties,
+            IRRADIANCE_SIZE,
+            1,
+            HDR_FORMAT,
+            cube_usage,
+            "irradiance",
+        );
+        let prefiltered = texture::create_cube_image(
+            device,
+            memory_properties,
+            PREFILTERED_SIZE,
+            PREFILTERED_MIPS,
+            HDR_FORMAT,
+            cube_usage,
+            "prefiltered environment",
+        );
+        let brdf_lut = texture::create_image(
+            device,
+            memory_properties,
+            vk::Extent2D {
+                width: BRDF_LUT_SIZE,
+                height: BRDF_LUT_SIZE,
+            },
+            1,
+            HDR_FORMAT,
+            cube_usage,
+            "BRDF LUT",
+        );
+        let sampler = texture::create_sampler(
+            device,
+            &SamplerDesc {
+                address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+                address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+                ..Default::default()
+            },
+            "environment sampler",
+        );
+
+        let environment = Environment {
+            skybox,
+            irradiance,
+            prefiltered,
+            brdf_lut,
+            sampler,
+            skybox_pipeline: graphics_pipeline::create_graphics_pipeline(
+                device,
+                &PipelineDesc {
+                    vert_shader: "skybox.vert",
+                    frag_shader: "skybox.frag",
+                    vertex_bindings: &[],
+                    vertex_attributes: &[],
+                    set_layouts: &[frame_set_layout],
+                    push_constant_ranges: &[],
+                    render_pass,
+                    color_format,
+                    depth_format,
+                    // The sky sits exactly on the cleared far plane, so it only passes where
+                    // nothing was drawn
+                    depth_test: true,
+                    depth_compare_op: if REVERSED_Z {
+                        vk::CompareOp::GREATER_OR_EQUAL
+                    } else {
+                        vk::CompareOp::LESS_OR_EQUAL
+                    },
+                    depth_bias: None,
+                    blend: false,
+                    cull_mode: vk::CullModeFlags::NONE,
+                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                },
+            ),
+            intensity: 1.0_f32,
+        };
+        environment.bake(device, memory_properties, command_pool, queue, source);
+
+        environment
+    }
+
+    fn bake(
+        &self,
+        device: &Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        source: &EnvironmentSource,
+    ) {
+        // Every bake shader samples binding 0 and writes binding 1
+        let set_layout = descriptor::create_set_layout(
+            device,
+            &[
+                vk::DescriptorSetLayoutBinding {
+                    binding: 0,
+                    descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    descriptor_count: 1,
+                    stage_flags: vk::ShaderStageFlags::COMPUTE,
+                    ..Default::default()
+                },
+                vk::DescriptorSetLayoutBinding {
+                    binding: 1,
+                    descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
+                    descriptor_count: 1,
+                    stage_flags: vk::ShaderStageFlags::COMPUTE,
+                    ..Default::default()
+                },
+            ],
+            "environment bake set layout",
+        );
+        // Equirect conversion, irradiance, BRDF LUT and one per prefiltered mip
+        let set_count = 3 + PREFILTERED_MIPS;
+        let descriptor_pool = descriptor::create_descriptor_pool(
+            device,
+            &[
+                vk::DescriptorPoolSize {
+                    ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    descriptor_count: set_count,
+                },
+                vk::DescriptorPoolSize {
+                    ty: vk::DescriptorType::STORAGE_IMAGE,
+                    descriptor_count: set_count,
+                },
+            ],
+            set_count,
+            "environment bake pool",
+        );
+        let mut sets = descriptor::allocate_descriptor_sets(
+            device,
+            *descriptor_pool,
+            *set_layout,
+            set_count as usize,
+        )
+        .into_iter();
+        let prefilter_push_range = vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+            offset: 0,
+            size: std::mem::size_of::<PrefilterPushConstants>() as u32,
+        };
+        let pipelines: Vec<ComputePipelineStuff> = [
+            ("equirect_to_cube.comp", None),
+            ("irradiance.comp", None),
+            ("prefilter.comp", Some(prefilter_push_range)),
+            ("brdf_lut.comp", None),
+        ]
+        .iter()
+        .map(|&(shader, push_range)| {
+            compute_pipeline::create_compute_pipeline(
+                device,
+                shader,
+                &[*set_layout],
+                push_range.as_slice(),
+            )
+        })
+        .collect();
+        let [equirect_to_cube, irradiance, prefilter, brdf_lut] = &pipelines[..] else {
+            unreachable!()
+        };
+        // Storage views of single mips, viewed as arrays of six layers for cubes
+        let mut views = vec![];
+        let mut storage_view = |image: &ImageStuff, mip: u32, layers: u32| {
+            let view_type = if layers == 6 {
+                vk::ImageViewType::TYPE_2D_ARRAY
+            } else {
+                vk::ImageViewType::TYPE_2D
+            };
+            let view = device.own(
+                texture::create_image_view(
+                    device,
+                    *image.image,
+                    view_type,
+                    image.format,
+                    mip..mip + 1,
+                    layers,
+                ),
+                "environment bake storage view",
+            );
+            let handle = *view;
+            views.push(view);
+            handle
+        };
+        let dispatch = |command_buffer: vk::CommandBuffer,
+                        pipeline: &ComputePipelineStuff,
+                        set: vk::DescriptorSet,
+                        size: u32,
+                        layers: u32,
+                        push_constants: &[u8]| unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                *pipeline.compute_pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                *pipeline.pipeline_layout,
+                0,
+                &[set],
+                &[],
+            );
+            if !push_constants.is_empty() {
+                device.cmd_push_constants(
+                    command_buffer,
+                    *pipeline.pipeline_layout,
+                    vk::ShaderStageFlags::COMPUTE,
+                    0,
+                    push_constants,
+                );
+            }
+            let groups = size.div_ceil(WORKGROUP_SIZE);
+            device.cmd_dispatch(command_buffer, groups, groups, layers);
+        };
+
+        let command_buffer = commands::begin_single_time_commands(device, command_pool);
+        let all_mips = 0..self.skybox.mip_levels;
+        let mut staging = vec![];
+        let mut equirect_image = None;
+        match source {
+            EnvironmentSource::Equirect(image) => {
+                let equirect = texture::create_image(
+                    device,
+                    memory_properties,
+                    vk::Extent2D {
+                        width: image.width,
+                        height: image.height,
+                    },
+                    1,
+                    HDR_FORMAT,
+                    vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+                    "equirect environment",
+                );
+                let equirect_staging =
+                    buffer::create_staging_buffer(device, memory_properties, &to_half_rgba(image));
+                cmd_barrier(
+                    device,
+                    command_buffer,
+                    *equirect.image,
+                    0..1,
+                    (
+                        vk::ImageLayout::UNDEFINED,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    ),
+                    (
+                        vk::PipelineStageFlags::TOP_OF_PIPE,
+                        vk::AccessFlags::empty(),
+                    ),
+                    (
+                        vk::PipelineStageFlags::TRANSFER,
+                        vk::AccessFlags::TRANSFER_WRITE,
+                    ),
+                );
+                cmd_copy_to_layers(
+                    device,
+                    command_buffer,
+                    *equirect_staging.buffer,
+                    &equirect,
+                    1,
+                );
+                cmd_barrier(
+                    device,
+                    command_buffer,
+                    *equirect.image,
+                    0..1,
+                    (
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    ),
+                    (
+                        vk::PipelineStageFlags::TRANSFER,
+                        vk::AccessFlags::TRANSFER_WRITE,
+                    ),
+                    (
+                        vk::PipelineStageFlags::COMPUTE_SHADER,
+                        vk::AccessFlags::SHADER_READ,
+                    ),
+                );
+
+                // Only the first mip is rendered, the rest are blitted from it below
+                cmd_barrier(
+                    device,
+                    command_buffer,
+                    *self.skybox.image,
+                    all_mips.clone(),
+                    (vk::ImageLayout::UNDEFINED, vk::ImageLayout::GENERAL),
+                    (
+                        vk::PipelineStageFlags::TOP_OF_PIPE,
+                        vk::AccessFlags::empty(),
+                    ),
+                    (
+                        vk::PipelineStageFlags::COMPUTE_SHADER,
+                        vk::AccessFlags::SHADER_WRITE,
+                    ),
+                );
+                let set = sets.next().unwrap();
+                let equirect_sampler = texture::create_sampler(
+                    device,
+                    &SamplerDesc {
+                        mipmaps: false,
+                        address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+                        ..Default::default()
+                    },
+                    "equirect sampler",
+                );
+                descriptor::write_combined_image_sampler(
+                    device,
+                    set,
+                    0,
+                    *equirect.view,
+                    *equirect_sampler,
+                );
+                descriptor::write_storage_image(device, set, 1, storage_view(&self.skybox, 0, 6));
+                dispatch(
+                    command_buffer,
+                    equirect_to_cube,
+                    set,
+                    self.skybox.extent.width,
+                    6,
+                    &[],
+                );
+                cmd_barrier(
+                    device,
+                    command_buffer,
+                    *self.skybox.image,
+                    all_mips.clone(),
+                    (
+                        vk::ImageLayout::GENERAL,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    ),
+                    (
+                        vk::PipelineStageFlags::COMPUTE_SHADER,
+                        vk::AccessFlags::SHADER_WRITE,
+                    ),
+                    (
+                        vk::PipelineStageFlags::TRANSFER,
+                        vk::AccessFlags::TRANSFER_WRITE,
+                    ),
+                );
+
+                staging.push(equirect_staging);
+                equirect_image = Some((equirect, equirect_sampler));
+            }
+            EnvironmentSource::Faces(faces) => {
+                let pixels: Vec<u8> = faces.iter().flat_map(to_half_rgba).collect();
+                let faces_staging =
+                    buffer::create_staging_buffer(device, memory_properties, &pixels);
+                cmd_barrier(
+                    device,
+                    command_buffer,
+                    *self.skybox.image,
+                    all_mips.clone(),
+                    (
+                        vk::ImageLayout::UNDEFINED,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    ),
+                    (
+                        vk::PipelineStageFlags::TOP_OF_PIPE,
+                        vk::AccessFlags::empty(),
+                    ),
+                    (
+                        vk::PipelineStageFlags::TRANSFER,
+                        vk::AccessFlags::TRANSFER_WRITE,
+                    ),
+                );
+                cmd_copy_to_layers(
+                    device,
+                    command_buffer,
+                    *faces_staging.buffer,
+                    &self.skybox,
+                    6,
+                );
+                staging.push(faces_staging);
+            }
+        }
+        texture::cmd_generate_mipmaps(device, command_buffer, &self.skybox, 6);
+
+        let to_general = |image: &ImageStuff| {
+            cmd_barrier(
+                device,
+                command_buffer,
+                *image.image,
+                0..image.mip_levels,
+                (vk::ImageLayout::UNDEFINED, vk::ImageLayout::GENERAL),
+                (
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    vk::AccessFlags::empty(),
+                ),
+                (
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::AccessFlags::SHADER_WRITE,
+                ),
+            )
+        };
+        let to_shader_read = |image: &ImageStuff| {
+            cmd_barrier(
+                device,
+                command_buffer,
+                *image.image,
+                0..image.mip_levels,
+                (
+                    vk::ImageLayout::GENERAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                ),
+                (
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::AccessFlags::SHADER_WRITE,
+                ),
+                (
+                    vk::PipelineStageFlags::FRAGMENT_SHADER,
+                    vk::AccessFlags::SHADER_READ,
+                ),
+            )
+        };
+
+        to_general(&self.irradiance);
+        let set = sets.next().unwrap();
+        descriptor::write_combined_image_sampler(device, set, 0, *self.skybox.view, *self.sampler);
+        descriptor::write_storage_image(device, set, 1, storage_view(&self.irradiance, 0, 6));
+        dispatch(command_buffer, irradiance, set, IRRADIANCE_SIZE, 6, &[]);
+        to_shader_read(&self.irradiance);
+
+        to_general(&self.prefiltered);
+        for mip in 0..PREFILTERED_MIPS {
+            let set = sets.next().unwrap();
+            descriptor::write_combined_image_sampler(
+                device,
+                set,
+                0,
+                *self.skybox.view,
+                *self.sampler,
+            );
+            descriptor::write_storage_image(
+                device,
+                set,
+                1,
+                storage_view(&self.prefiltered, mip, 6),
+            );
+            let push_constants = PrefilterPushConstants {
+                roughness: mip as f32 / (PREFILTERED_MIPS - 1) as f32,
+                source_size: self.skybox.extent.width as f32,
+            };
+            dispatch(
+                command_buffer,
+                prefilter,
+                set,
+                (PREFILTERED_SIZE >> mip).max(1),
+                6,
+                as_bytes(std::slice::from_ref(&push_constants)),
+            );
+        }
+        to_shader_read(&self.prefiltered);
+
+        to_general(&self.brdf_lut);
+        let set = sets.next().unwrap();
+        descriptor::write_storage_image(device, set, 1, storage_view(&self.brdf_lut, 0, 1));
+        dispatch(command_buffer, brdf_lut, set, BRDF_LUT_SIZE, 1, &[]);
+        to_shader_read(&self.brdf_lut);
+
+        commands::end_single_time_commands(device, command_pool, queue, command_buffer);
+
+        // The commands are done, so everything made for the bake can go
+        drop((staging, equirect_image, views, pipelines));
+    }
+
+    pub fn write_descriptors(&self, device: &Device, frame_sets: &[vk::DescriptorSet]) {
+        for &set in frame_sets.iter() {
+            for (binding, image) in [
+                (3, &self.irradiance),
+                (4, &self.prefiltered),
+                (5, &self.brdf_lut),
+                (6, &self.skybox),
+            ] {
+                descriptor::write_combined_image_sampler(
+                    device,
+                    set,
+                    binding,
+                    *image.view,
+                    *self.sampler,
+                );
+            }
+        }
+    }
+
+    pub fn write_uniforms(&self, uniforms: &mut FrameUniforms) {
+        uniforms.environment_params = [
+            (PREFILTERED_MIPS - 1) as f32,
+            self.intensity,
+            0.0_f32,
+            if REVERSED_Z { 0.0_f32 } else { 1.0_f32 },
+        ];
+    }
+
+    // Inside a pass that has already drawn the opaque geometry
+    pub fn record_skybox(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        frame_set: vk::DescriptorSet,
+    ) {
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                *self.skybox_pipeline.graphics_pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                *self.skybox_pipeline.pipeline_layout,
+                0,
+                &[frame_set],
+                &[],
+            );
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+    }
+}
+
+// Load-time barrier over `mips` of every layer
+fn cmd_barrier(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    mips: std::ops::Range<u32>,
+    (old_layout, new_layout): (vk::ImageLayout, vk::ImageLayout),
+    (src_stage, src_access_mask): (vk::PipelineStageFlags, vk::AccessFlags),
+    (dst_stage, dst_access_mask): (vk::PipelineStageFlags, vk::AccessFlags),
+) {
+    let barrier = vk::ImageMemoryBarrier {
+        s_type: vk::StructureType::IMAGE_MEMORY_BARRIER,
+        src_access_mask,
+        dst_access_mask,
+        old_layout,
+        new_layout,
+        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        image,
+        subresource_range: vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: mips.start,
+            level_count: mips.end - mips.start,
+            base_array_layer: 0,
+            layer_count: vk::REMAINING_ARRAY_LAYERS,
+        },
+        ..Default::default()
+    };
+
+    unsafe {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            src_stage,
+            dst_stage,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[barrier],
+        )
+    };
+}
+
+// Tightly packed layers one after the other into the first mip
+fn cmd_copy_to_layers(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    buffer: vk::Buffer,
+    image: &ImageStuff,
+    layer_count: u32,
+) {
+    let region = vk::BufferImageCopy {
+        buffer_offset: 0,
+        buffer_row_length: 0,
+        buffer_image_height: 0,
+        image_subresource: vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count,
+        },
+        image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+        image_extent: vk::Extent3D {
+            width: image.extent.width,
+            height: image.extent.height,
+            depth: 1,
+        },
+    };
+
+    unsafe {
+        device.cmd_copy_buffer_to_image(
+            command_buffer,
+            buffer,
+            *image.image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[region],
+        )
+    };
+}
+
+// RGBA16F texels with alpha 1, as HDR_FORMAT expects them
+fn to_half_rgba(image: &HdrImage) -> Vec<u8> {
+    let one = to_half(1.0_f32);
+    image
+        .pixels
+        .iter()
+        .flat_map(|&[r, g, b]| [to_half(r), to_half(g), to_half(b), one])
+        .flat_map(u16::to_ne_bytes)
+        .collect()
+}
+
+// Rounds to nearest, clamps to the largest finite half and flushes values too small for a
+// normal half to zero, which is plenty for radiance
+fn to_half(value: f32) -> u16 {
+    let sign = ((value.to_bits() >> 16) & 0x8000) as u16;
+    let magnitude = value.abs().min(65504.0_f32);
+    if magnitude < 6.103_515_6e-5_f32 {
+        return sign;
+    }
+
+    let bits = magnitude.to_bits() + 0x1000;
+    let exponent = ((bits >> 23) as i32 - 127 + 15) as u16;
+    let mantissa = ((bits >> 13) & 0x3ff) as u16;
+    sign | exponent << 10 | mantissa
+}
@@ -0,0 +1,61 @@
+use std::time::{Duration, Instant};
+
+// Rates the frame limit cycles through at runtime, after the configured one
+const PRESETS: [f64; 4] = [30.0_f64, 60.0_f64, 120.0_f64, 144.0_f64];
+// OS sleeps can overshoot by about a scheduler tick, so the last stretch before a deadline is
+// spun instead
+const SPIN_MARGIN: Duration = Duration::from_micros(1500);
+
+// Holds the main loop to a target frame rate. Deadlines advance by whole periods rather than
+// from when the wait ended, so an occasional late frame doesn't shift every later one; a loop
+// that falls more than a period behind starts over instead of rushing to catch up.
+pub struct FrameLimiter {
+    target_fps: Option<f64>,
+    next_deadline: Instant,
+}
+
+impl FrameLimiter {
+    pub fn new(target_fps: Option<f64>) -> FrameLimiter {
+        FrameLimiter {
+            target_fps: target_fps.filter(|&fps| fps > 0.0_f64),
+            next_deadline: Instant::now(),
+        }
+    }
+
+    pub fn target_fps(&self) -> Option<f64> {
+        self.target_fps
+    }
+
+    // Off, then each preset in turn, then off again
+    pub fn cycle_target(&mut self) {
+        self.target_fps = match self.target_fps {
+            None => Some(PRESETS[0]),
+            Some(fps) => PRESETS.iter().copied().find(|&preset| preset > fps),
+        };
+        self.next_deadline = Instant::now();
+    }
+
+    // Blocks until the next frame is due; returns immediately when unlimited
+    pub fn wait(&mut self) {
+        let Some(fps) = self.target_fps else {
+            return;
+        };
+        let period = Duration::from_secs_f64(1.0_f64 / fps);
+
+        let now = Instant::now();
+        if now > self.next_deadline + period {
+            self.next_deadline = now;
+            return;
+        }
+
+        if let Some(remaining) = self.next_deadline.checked_duration_since(now) {
+            if remaining > SPIN_MARGIN {
+                std::thread::sleep(remaining - SPIN_MARGIN);
+            }
+            while Instant::now() < self.next_deadline {
+                std::hint::spin_loop();
+            }
+        }
+        self.next_deadline += period;
+    }
+}
@@ -1,17 +1,27 @@
 use ash::vk::{self, PipelineCache};
-use std::{ffi::CString, path::Path, ptr};
+use std::{
+    ffi::{c_void, CString},
+    path::Path,
+    ptr,
+};
 
-use crate::util::structures::GraphicsPipelineStuff;
+use super::resources::{Device, Scoped};
+use crate::util::structures::{GraphicsPipelineStuff, PipelineDesc};
 
-pub fn create_graphics_pipeline(
-    device: &ash::Device,
-    render_pass: vk::RenderPass,
-) -> GraphicsPipelineStuff {
-    let vert_code = read_shader(Path::new("shaders/spv/triangle.vert.spv"));
-    let frag_code = read_shader(Path::new("shaders/spv/triangle.frag.spv"));
+// A null `desc.render_pass` creates the pipeline for dynamic rendering into `desc.color_format`
+// and `desc.depth_format`
+pub fn create_graphics_pipeline(device: &Device, desc: &PipelineDesc) -> GraphicsPipelineStuff {
+    let vert_code = read_shader(Path::new(&format!("shaders/spv/{}.spv", desc.vert_shader)));
+    let frag_code = read_shader(Path::new(&format!("shaders/spv/{}.spv", desc.frag_shader)));
 
-    let vert_shader_module = create_shader_module(device, &vert_code);
-    let frag_shader_module = create_shader_module(device, &frag_code);
+    let vert_shader_module = Scoped::new(
+        device,
+        create_shader_module(device, &vert_code, desc.vert_shader),
+    );
+    let frag_shader_module = Scoped::new(
+        device,
+        create_shader_module(device, &frag_code, desc.frag_shader),
+    );
 
     let entry_point = CString::new("main").unwrap();
     let vert_shader_stage = vk::PipelineShaderStageCreateInfo {
@@ -19,7 +29,7 @@ pub fn create_graphics_pipeline(
         p_next: ptr::null(),
         flags: vk::PipelineShaderStageCreateFlags::empty(),
         stage: vk::ShaderStageFlags::VERTEX,
-        module: vert_shader_module,
+        module: *vert_shader_module,
         p_name: entry_point.as_ptr(),
         ..Default::default()
     };
@@ -28,7 +38,7 @@ pub fn create_graphics_pipeline(
         p_next: ptr::null(),
         flags: vk::PipelineShaderStageCreateFlags::empty(),
         stage: vk::ShaderStageFlags::FRAGMENT,
-        module: frag_shader_module,
+        module: *frag_shader_module,
         p_name: entry_point.as_ptr(),
         ..Default::default()
     };
@@ -48,10 +58,10 @@ pub fn create_graphics_pipeline(
         s_type: vk::StructureType::PIPELINE_VERTEX_INPUT_STATE_CREATE_INFO,
         p_next: ptr::null(),
         flags: vk::PipelineVertexInputStateCreateFlags::empty(),
-        vertex_binding_description_count: 0,
-        p_vertex_binding_descriptions: ptr::null(),
-        vertex_attribute_description_count: 0,
-        p_vertex_attribute_descriptions: ptr::null(),
+        vertex_binding_description_count: desc.vertex_bindings.len() as u32,
+        p_vertex_binding_descriptions: desc.vertex_bindings.as_ptr(),
+        vertex_attribute_description_count: desc.vertex_attributes.len() as u32,
+        p_vertex_attribute_descriptions: desc.vertex_attributes.as_ptr(),
         _marker: std::marker::PhantomData,
     };
 
@@ -80,12 +90,12 @@ pub fn create_graphics_pipeline(
         depth_clamp_enable: vk::FALSE,
         rasterizer_discard_enable: vk::FALSE,
         polygon_mode: vk::PolygonMode::FILL,
-        cull_mode: vk::CullModeFlags::BACK,
-        front_face: vk::FrontFace::CLOCKWISE,
-        depth_bias_enable: vk::FALSE,
-        depth_bias_constant_factor: 0.0_f32,
-        depth_bias_clamp: 0.0_f32,
-        depth_bias_slope_factor: 0.0_f32,
+        cull_mode: desc.cull_mode,
+        front_face: desc.front_face,
+        depth_bias_enable: desc.depth_bias.is_some() as vk::Bool32,
+        depth_bias_constant_factor: desc.depth_bias.map_or(0.0_f32, |bias| bias.constant_factor),
+        depth_bias_clamp: desc.depth_bias.map_or(0.0_f32, |bias| bias.clamp),
+        depth_bias_slope_factor: desc.depth_bias.map_or(0.0_f32, |bias| bias.slope_factor),
         line_width: 1.0_f32,
         _marker: std::marker::PhantomData,
     };
@@ -103,22 +113,44 @@ pub fn create_graphics_pipeline(
         _marker: std::marker::PhantomData,
     };
 
+    let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo {
+        s_type: vk::StructureType::PIPELINE_DEPTH_STENCIL_STATE_CREATE_INFO,
+        depth_test_enable: desc.depth_test as vk::Bool32,
+        depth_write_enable: (desc.depth_test && !desc.blend) as vk::Bool32,
+        depth_compare_op: desc.depth_compare_op,
+        depth_bounds_test_enable: vk::FALSE,
+        stencil_test_enable: vk::FALSE,
+        min_depth_bounds: 0.0_f32,
+        max_depth_bounds: 1.0_f32,
+        ..Default::default()
+    };
+
     let color_blending_att = vk::PipelineColorBlendAttachmentState {
         color_write_mask: vk::ColorComponentFlags::RGBA,
-        blend_enable: vk::FALSE,
-        ..Default::default()
+        blend_enable: desc.blend as vk::Bool32,
+        src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
+        dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+        color_blend_op: vk::BlendOp::ADD,
+        src_alpha_blend_factor: vk::BlendFactor::ONE,
+        dst_alpha_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+        alpha_blend_op: vk::BlendOp::ADD,
     };
 
+    let color_attachment_count = (desc.color_format != vk::Format::UNDEFINED) as u32;
     let color_blending_info = vk::PipelineColorBlendStateCreateInfo {
         s_type: vk::StructureType::PIPELINE_COLOR_BLEND_STATE_CREATE_INFO,
         logic_op_enable: vk::FALSE,
-        attachment_count: 1,
+        attachment_count: color_attachment_count,
         p_attachments: &color_blending_att,
         ..Default::default()
     };
 
     let pipeline_layout_info = vk::PipelineLayoutCreateInfo {
         s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
+        set_layout_count: desc.set_layouts.len() as u32,
+        p_set_layouts: desc.set_layouts.as_ptr(),
+        push_constant_range_count: desc.push_constant_ranges.len() as u32,
+        p_push_constant_ranges: desc.push_constant_ranges.as_ptr(),
         ..Default::default()
     };
 
@@ -128,9 +160,21 @@ pub fn create_graphics_pipeline(
             .expect("Failed to create pipeline layout")
     };
 
+    let rendering_info = vk::PipelineRenderingCreateInfo {
+        s_type: vk::StructureType::PIPELINE_RENDERING_CREATE_INFO,
+        color_attachment_count,
+        p_color_attachment_formats: &desc.color_format,
+        depth_attachment_format: desc.depth_format,
+        ..Default::default()
+    };
+
     let pipeline_info = vk::GraphicsPipelineCreateInfo {
         s_type: vk::StructureType::GRAPHICS_PIPELINE_CREATE_INFO,
-        p_next: ptr::null(),
+        p_next: if desc.render_pass == vk::RenderPass::null() {
+            &rendering_info as *const _ as *const c_void
+        } else {
+            ptr::null()
+        },
         stage_count: 2,
         p_stages: shader_stages.as_ptr(),
         p_vertex_input_state: &vertex_input_info,
@@ -138,11 +182,11 @@ pub fn create_graphics_pipeline(
         p_viewport_state: &viewport_info,
         p_rasterization_state: &rasterizer_info,
         p_multisample_state: &multi_sampling_info,
-        p_depth_stencil_state: ptr::null(),
+        p_depth_stencil_state: &depth_stencil_info,
         p_color_blend_state: &color_blending_info,
         p_dynamic_state: &dynamic_state_info,
         layout: pipeline_layout,
-        render_pass,
+        render_pass: desc.render_pass,
         subpass: 0,
         ..Default::default()
     };
@@ -155,19 +199,20 @@ pub fn create_graphics_pipeline(
             .unwrap()
             .to_owned()
     };
-
-    unsafe {
-        device.destroy_shader_module(vert_shader_module, None);
-        device.destroy_shader_module(frag_shader_module, None);
-    };
+    // Pipelines are told apart by their shaders
+    let name = format!("{} + {}", desc.vert_shader, desc.frag_shader);
 
     GraphicsPipelineStuff {
-        graphics_pipeline,
-        pipeline_layout,
+        graphics_pipeline: device.own(graphics_pipeline, &name),
+        pipeline_layout: device.own(pipeline_layout, &name),
     }
 }
 
-pub fn creat_render_pass(device: &ash::Device, swapchain_format: vk::Format) -> vk::RenderPass {
+pub fn creat_render_pass(
+    device: &Device,
+    swapchain_format: vk::Format,
+    depth_format: vk::Format,
+) -> vk::RenderPass {
     let color_attachment = vk::AttachmentDescription {
         format: swapchain_format,
         samples: vk::SampleCountFlags::TYPE_1,
@@ -176,10 +221,86 @@ pub fn creat_render_pass(device: &ash::Device, swapchain_format: vk::Format) ->
         stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
         stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
         initial_layout: vk::ImageLayout::UNDEFINED,
-        final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+        // The render graph transitions the image for whatever comes next
+        final_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
         flags: vk::AttachmentDescriptionFlags::empty(),
     };
 
+    // Depth is cleared every frame and never read back
+    let depth_attachment = vk::AttachmentDescription {
+        format: depth_format,
+        samples: vk::SampleCountFlags::TYPE_1,
+        load_op: vk::AttachmentLoadOp::CLEAR,
+        store_op: vk::AttachmentStoreOp::DONT_CARE,
+        stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+        stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+        initial_layout: vk::ImageLayout::UNDEFINED,
+        final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        flags: vk::AttachmentDescriptionFlags::empty(),
+    };
+    let attachments = [color_attachment, depth_attachment];
+
+    let color_attachment_ref = vk::AttachmentReference {
+        attachment: 0,
+        layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+    };
+    let depth_attachment_ref = vk::AttachmentReference {
+        attachment: 1,
+        layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+    };
+
+    let subpass = vk::SubpassDescription {
+        pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
+        color_attachment_count: 1,
+        p_color_attachments: &color_attachment_ref,
+        p_depth_stencil_attachment: &depth_attachment_ref,
+        ..Default::default()
+    };
+
+    let dependency = vk::SubpassDependency {
+        src_subpass: vk::SUBPASS_EXTERNAL,
+        dst_subpass: 0,
+        src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+        src_access_mask: vk::AccessFlags::empty(),
+        dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+        dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        ..Default::default()
+    };
+
+    let render_pass_info = vk::RenderPassCreateInfo {
+        s_type: vk::StructureType::RENDER_PASS_CREATE_INFO,
+        attachment_count: attachments.len() as u32,
+        p_attachments: attachments.as_ptr(),
+        subpass_count: 1,
+        p_subpasses: &subpass,
+        dependency_count: 1,
+        p_dependencies: &dependency,
+        ..Default::default()
+    };
+
+    unsafe {
+        device
+            .create_render_pass(&render_pass_info, None)
+            .expect("Failed to create renderpass")
+    }
+}
+
+// A single color attachment that full-screen passes overwrite completely
+pub fn create_color_render_pass(device: &Device, color_format: vk::Format) -> vk::RenderPass {
+    let color_attachment = vk::AttachmentDescription {
+        format: color_format,
+        samples: vk::SampleCountFlags::TYPE_1,
+        load_op: vk::AttachmentLoadOp::DONT_CARE,
+        store_op: vk::AttachmentStoreOp::STORE,
+        stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+        stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+        initial_layout: vk::ImageLayout::UNDEFINED,
+        final_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        flags: vk::AttachmentDescriptionFlags::empty(),
+    };
     let color_attachment_ref = vk::AttachmentReference {
         attachment: 0,
         layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
@@ -216,11 +337,63 @@ pub fn creat_render_pass(device: &ash::Device, swapchain_format: vk::Format) ->
     unsafe {
         device
             .create_render_pass(&render_pass_info, None)
-            .expect("Failed to create renderpass")
+            .expect("Failed to create color-only renderpass")
+    }
+}
+
+// A single depth attachment that is stored for sampling later, for shadow maps
+pub fn create_depth_only_render_pass(device: &Device, depth_format: vk::Format) -> vk::RenderPass {
+    let depth_attachment = vk::AttachmentDescription {
+        format: depth_format,
+        samples: vk::SampleCountFlags::TYPE_1,
+        load_op: vk::AttachmentLoadOp::CLEAR,
+        store_op: vk::AttachmentStoreOp::STORE,
+        stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+        stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+        initial_layout: vk::ImageLayout::UNDEFINED,
+        final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        flags: vk::AttachmentDescriptionFlags::empty(),
+    };
+    let depth_attachment_ref = vk::AttachmentReference {
+        attachment: 0,
+        layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+    };
+
+    let subpass = vk::SubpassDescription {
+        pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
+        p_depth_stencil_attachment: &depth_attachment_ref,
+        ..Default::default()
+    };
+
+    let dependency = vk::SubpassDependency {
+        src_subpass: vk::SUBPASS_EXTERNAL,
+        dst_subpass: 0,
+        src_stage_mask: vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+        src_access_mask: vk::AccessFlags::empty(),
+        dst_stage_mask: vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+        dst_access_mask: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        ..Default::default()
+    };
+
+    let render_pass_info = vk::RenderPassCreateInfo {
+        s_type: vk::StructureType::RENDER_PASS_CREATE_INFO,
+        attachment_count: 1,
+        p_attachments: &depth_attachment,
+        subpass_count: 1,
+        p_subpasses: &subpass,
+        dependency_count: 1,
+        p_dependencies: &dependency,
+        ..Default::default()
+    };
+
+    unsafe {
+        device
+            .create_render_pass(&render_pass_info, None)
+            .expect("Failed to create depth-only renderpass")
     }
 }
 
-fn create_shader_module(device: &ash::Device, code: &Vec<u8>) -> vk::ShaderModule {
+pub fn create_shader_module(device: &Device, code: &[u8], name: &str) -> vk::ShaderModule {
     let create_info = vk::ShaderModuleCreateInfo {
         s_type: vk::StructureType::SHADER_MODULE_CREATE_INFO,
         p_next: ptr::null(),
@@ -230,14 +403,17 @@ fn create_shader_module(device: &ash::Device, code: &Vec<u8>) -> vk::ShaderModul
         _marker: std::marker::PhantomData,
     };
 
-    unsafe {
+    let shader_module = unsafe {
         device
             .create_shader_module(&create_info, None)
             .expect("Failed to create shader module")
-    }
+    };
+    device.debug().set_name(shader_module, name);
+
+    shader_module
 }
 
-fn read_shader(path: &Path) -> Vec<u8> {
+pub fn read_shader(path: &Path) -> Vec<u8> {
     use std::fs::File;
     use std::io::Read;
 
@@ -0,0 +1,523 @@
+use ash::vk;
+use std::ffi::c_void;
+use std::ptr;
+
+use super::buffer;
+use super::compute_pipeline;
+use super::culling::Frustum;
+use super::descriptor;
+use super::material::{MaterialPipelines, PipelineKey, TransformSource};
+use super::model::{as_bytes, Model};
+use super::render_graph::{Access, RenderGraph, ResourceId};
+use super::resources::{Device, Owned};
+use super::scene::DrawNode;
+use crate::util::constants::{MAX_FRAMES_IN_FLIGHT, PREFER_GPU_CULLING};
+use crate::util::structures::{BufferStuff, ComputePipelineStuff, IndirectPath};
+
+// Opaque scene geometry drawn with one indirect call per pipeline and material, however many
+// objects use them. Every opaque primitive of the scene becomes a draw object; objects are sorted
+// into batches and each batch owns a range of the command buffer as long as it has objects. On
+// the GPU-culled path a compute pass tests every object against the frustum and appends
+// commands for the visible ones, counting them per batch for vkCmdDrawIndexedIndirectCount.
+// Without drawIndirectCount the CPU culls and writes the commands itself. Either way a command's
+// first instance is its object's index, which is how mesh_indirect.vert finds the transform.
+// Blended primitives have to be sorted back to front and stay on the direct path.
+
+const CULL_WORKGROUP_SIZE: usize = 64;
+// Buffers start with room for this many objects and double from there
+const MIN_CAPACITY: usize = 256;
+
+// Mirrors DrawObject in draw_object.glsl, std430
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct DrawObject {
+    pub model: [[f32; 4]; 4],
+    pub sphere: [f32; 4],
+    pub index_count: u32,
+    pub first_index: u32,
+    pub vertex_offset: i32,
+    pub batch: u32,
+    pub first_command: u32,
+    pub material: u32,
+    _padding: [u32; 2],
+}
+
+// Mirrors the push constant block in cull.comp
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct CullPushConstants {
+    planes: [[f32; 4]; 6],
+    object_count: u32,
+}
+
+struct Batch {
+    key: PipelineKey,
+    material: usize,
+    first_command: u32,
+    // Objects in the batch, the most commands it can get
+    capacity: u32,
+}
+
+struct FrameBuffers {
+    // Host visible and persistently mapped
+    _objects: BufferStuff,
+    objects_mapped: *mut c_void,
+    // Device local on the GPU-culled path; mapped for the CPU to write otherwise
+    commands: BufferStuff,
+    commands_mapped: *mut c_void,
+    // A draw count per batch; only the GPU-culled path uses them
+    counts: BufferStuff,
+    capacity: usize,
+}
+
+pub struct IndirectDraws {
+    path: IndirectPath,
+    // The draw objects for vertex shaders, the commands and counts for the culling shader
+    _descriptor_pool: Owned<vk::DescriptorPool>,
+    set_layout: Owned<vk::DescriptorSetLayout>,
+    sets: Vec<vk::DescriptorSet>,
+    cull: Option<ComputePipelineStuff>,
+    frames: Vec<Option<FrameBuffers>>,
+    // Of the frame last prepared
+    batches: Vec<Batch>,
+    object_count: usize,
+}
+
+// Indirect draws need several draws per call and the object index in firstInstance; counting
+// on the GPU needs drawIndirectCount on top
+pub fn choose_indirect_path(
+    instance: &ash::Instance,
+    physical_device: &vk::PhysicalDevice,
+) -> IndirectPath {
+    let features = unsafe { instance.get_physical_device_features(*physical_device) };
+    let properties = unsafe { instance.get_physical_device_properties(*physical_device) };
+
+    let path = if features.multi_draw_indirect != vk::TRUE
+        || features.draw_indirect_first_instance != vk::TRUE
+    {
+        IndirectPath::Direct
+    } else if PREFER_GPU_CULLING && properties.api_version >= vk::API_VERSION_1_2 {
+        let mut vulkan_12_features = vk::PhysicalDeviceVulkan12Features {
+            s_type: vk::StructureType::PHYSICAL_DEVICE_VULKAN_1_2_FEATURES,
+            ..Default::default()
+        };
+        let mut features = vk::PhysicalDeviceFeatures2 {
+            s_type: vk::StructureType::PHYSICAL_DEVICE_FEATURES_2,
+            p_next: &mut vulkan_12_features as *mut _ as *mut c_void,
+            ..Default::default()
+        };
+        unsafe { instance.get_physical_device_features2(*physical_device, &mut features) };
+
+        if vulkan_12_features.draw_indirect_count == vk::TRUE {
+            IndirectPath::GpuCulled
+        } else {
+            IndirectPath::CpuBuilt
+        }
+    } else {
+        IndirectPath::CpuBuilt
+    };
+    println!("Indirect drawing: {:?}", path);
+
+    path
+}
+
+impl IndirectDraws {
+    pub fn new(device: &Device, path: IndirectPath) -> IndirectDraws {
+        let bindings: Vec<_> = (0..3)
+            .map(|binding| vk::DescriptorSetLayoutBinding {
+                binding,
+                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::COMPUTE,
+                ..Default::default()
+            })
+            .collect();
+        let set_layout = descriptor::create_set_layout(device, &bindings, "draw object set layout");
+        let descriptor_pool = descriptor::create_descriptor_pool(
+            device,
+            &[vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: MAX_FRAMES_IN_FLIGHT * bindings.len() as u32,
+            }],
+            MAX_FRAMES_IN_FLIGHT,
+            "draw object pool",
+        );
+        let sets = descriptor::allocate_descriptor_sets(
+            device,
+            *descriptor_pool,
+            *set_layout,
+            MAX_FRAMES_IN_FLIGHT as usize,
+        );
+
+        let cull = (path == IndirectPath::GpuCulled).then(|| {
+            compute_pipeline::create_compute_pipeline(
+                device,
+                "cull.comp",
+                &[*set_layout],
+                &[vk::PushConstantRange {
+                    stage_flags: vk::ShaderStageFlags::COMPUTE,
+                    offset: 0,
+                    size: std::mem::size_of::<CullPushConstants>() as u32,
+                }],
+            )
+        });
+
+        IndirectDraws {
+            path,
+            _descriptor_pool: descriptor_pool,
+            set_layout,
+            sets,
+            cull,
+            frames: (0..MAX_FRAMES_IN_FLIGHT).map(|_| None).collect(),
+            batches: vec![],
+            object_count: 0,
+        }
+    }
+
+    pub fn path(&self) -> IndirectPath {
+        self.path
+    }
+
+    // Set 2 of the mesh pipelines
+    pub fn set_layout(&self) -> vk::DescriptorSetLayout {
+        *self.set_layout
+    }
+
+    // Builds the draw objects of `frame` from the opaque primitives of `draws`, which on the
+    // CPU-built path have to be culled already. The frame's fence must have been waited on.
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        frame: usize,
+        model: &Model,
+        draws: &[DrawNode],
+    ) {
+        let mut entries = vec![];
+        for draw in draws.iter() {
+            if model.mesh_bounds(draw.mesh).is_empty() {
+                continue;
+            }
+            for primitive in model.mesh_primitives(draw.mesh) {
+                let material = draw.material.unwrap_or(primitive.material);
+                let key = model.material_key(material);
+                if !key.blend {
+                    let key = PipelineKey {
+                        transforms: TransformSource::DrawObject,
+                        ..key
+                    };
+                    entries.push((key, material, draw, primitive));
+                }
+            }
+        }
+        entries.sort_by_key(|&(key, material, _, _)| (key, material));
+
+        self.batches.clear();
+        let mut objects = Vec::with_capacity(entries.len());
+        for (key, material, draw, primitive) in entries {
+            let index = objects.len() as u32;
+            // Bindless materials are all in one set, so only pipelines split batches
+            match self.batches.last_mut() {
+                Some(batch)
+                    if batch.key == key && (model.bindless() || batch.material == material) =>
+                {
+                    batch.capacity += 1
+                }
+                _ => self.batches.push(Batch {
+                    key,
+                    material,
+                    first_command: index,
+                    capacity: 1,
+                }),
+            }
+            let bounds = model.mesh_bounds(draw.mesh);
+            let [x, y, z] = bounds.center;
+            objects.push(DrawObject {
+                model: (*draw.transform).into(),
+                sphere: [x, y, z, bounds.radius],
+                index_count: primitive.index_count,
+                first_index: primitive.first_index,
+                vertex_offset: primitive.vertex_offset,
+                batch: self.batches.len() as u32 - 1,
+                first_command: self.batches.last().unwrap().first_command,
+                material: material as u32,
+                _padding: [0; 2],
+            });
+        }
+        self.object_count = objects.len();
+        if objects.is_empty() {
+            return;
+        }
+
+        self.reserve(device, memory_properties, frame, objects.len());
+        let buffers = self.frames[frame].as_ref().unwrap();
+        unsafe {
+            ptr::copy_nonoverlapping(
+                objects.as_ptr(),
+                buffers.objects_mapped as *mut DrawObject,
+                objects.len(),
+            )
+        };
+
+        // Everything left is visible, so each object's command sits at its own index
+        if self.path == IndirectPath::CpuBuilt {
+            let commands: Vec<_> = objects
+                .iter()
+                .enumerate()
+                .map(|(i, object)| vk::DrawIndexedIndirectCommand {
+                    index_count: object.index_count,
+                    instance_count: 1,
+                    first_index: object.first_index,
+                    vertex_offset: object.vertex_offset,
+                    first_instance: i as u32,
+                })
+                .collect();
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    commands.as_ptr(),
+                    buffers.commands_mapped as *mut vk::DrawIndexedIndirectCommand,
+                    commands.len(),
+                )
+            };
+        }
+    }
+
+    // On the GPU-culled path, adds the passes that clear the counts and cull the objects of
+    // `frame`. Returns the commands and counts, for the pass drawing them to read.
+    pub fn add_cull_passes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        frame: usize,
+        frustum: &Frustum,
+    ) -> Option<[ResourceId; 2]> {
+        let cull = self.cull.as_ref()?;
+        if self.object_count == 0 {
+            return None;
+        }
+        let buffers = self.frames[frame].as_ref().unwrap();
+        let commands = graph.import_buffer("draw commands", *buffers.commands.buffer, None);
+        let counts = graph.import_buffer("draw counts", *buffers.counts.buffer, None);
+
+        let counts_buffer = *buffers.counts.buffer;
+        graph.add_pass(
+            "reset draw counts",
+            &[(counts, Access::TransferWrite)],
+            move |ctx| unsafe {
+                ctx.device
+                    .cmd_fill_buffer(ctx.command_buffer, counts_buffer, 0, vk::WHOLE_SIZE, 0)
+            },
+        );
+
+        let push_constants = CullPushConstants {
+            planes: frustum.planes().map(|plane| plane.into()),
+            object_count: self.object_count as u32,
+        };
+        let set = self.sets[frame];
+        let group_count = self.object_count.div_ceil(CULL_WORKGROUP_SIZE) as u32;
+        graph.add_pass(
+            "cull draws",
+            &[
+                (commands, Access::ComputeWrite),
+                (counts, Access::ComputeWrite),
+            ],
+            move |ctx| unsafe {
+                let device = ctx.device;
+                let command_buffer = ctx.command_buffer;
+                device.cmd_bind_pipeline(
+                    command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    *cull.compute_pipeline,
+                );
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    *cull.pipeline_layout,
+                    0,
+                    &[set],
+                    &[],
+                );
+                device.cmd_push_constants(
+                    command_buffer,
+                    *cull.pipeline_layout,
+                    vk::ShaderStageFlags::COMPUTE,
+                    0,
+                    as_bytes(std::slice::from_ref(&push_constants)),
+                );
+                device.cmd_dispatch(command_buffer, group_count, 1, 1);
+            },
+        );
+
+        Some([commands, counts])
+    }
+
+    // The opaque primitives prepared for `frame`, a call per batch. `model` has to be bound.
+    pub fn draw(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        pipelines: &MaterialPipelines,
+        frame_set: vk::DescriptorSet,
+        model: &Model,
+        frame: usize,
+    ) {
+        if self.object_count == 0 {
+            return;
+        }
+        let buffers = self.frames[frame].as_ref().unwrap();
+        let stride = std::mem::size_of::<vk::DrawIndexedIndirectCommand>();
+
+        let layout = pipelines.layout();
+        unsafe {
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                layout,
+                0,
+                &[frame_set],
+                &[],
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                layout,
+                2,
+                &[self.sets[frame]],
+                &[],
+            );
+        }
+
+        let mut bound_key = None;
+        for (i, batch) in self.batches.iter().enumerate() {
+            let offset = (batch.first_command as usize * stride) as vk::DeviceSize;
+            unsafe {
+                if bound_key != Some(batch.key) {
+                    device.cmd_bind_pipeline(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        pipelines.pipeline(batch.key),
+                    );
+                    bound_key = Some(batch.key);
+                }
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    layout,
+                    1,
+                    &[model.material_set(batch.material)],
+                    &[],
+                );
+                match self.path {
+                    IndirectPath::GpuCulled => device.cmd_draw_indexed_indirect_count(
+                        command_buffer,
+                        *buffers.commands.buffer,
+                        offset,
+                        *buffers.counts.buffer,
+                        (i * std::mem::size_of::<u32>()) as vk::DeviceSize,
+                        batch.capacity,
+                        stride as u32,
+                    ),
+                    _ => device.cmd_draw_indexed_indirect(
+                        command_buffer,
+                        *buffers.commands.buffer,
+                        offset,
+                        batch.capacity,
+                        stride as u32,
+                    ),
+                }
+            }
+        }
+    }
+
+    // Replaces the buffers of `frame` with bigger ones when `object_count` doesn't fit
+    fn reserve(
+        &mut self,
+        device: &Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        frame: usize,
+        object_count: usize,
+    ) {
+        if self.frames[frame]
+            .as_ref()
+            .is_some_and(|buffers| buffers.capacity >= object_count)
+        {
+            return;
+        }
+        self.frames[frame] = None;
+
+        let capacity = object_count.next_power_of_two().max(MIN_CAPACITY);
+        let host_visible =
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+        let create = |name: &str, size: usize, usage, properties| {
+            buffer::create_buffer(
+                device,
+                memory_properties,
+                (capacity * size) as vk::DeviceSize,
+                usage,
+                properties,
+                &format!("{} {}", name, frame),
+            )
+        };
+        let map = |buffer_stuff: &BufferStuff| unsafe {
+            device
+                .map_memory(
+                    *buffer_stuff.memory,
+                    0,
+                    buffer_stuff.size,
+                    vk::MemoryMapFlags::empty(),
+                )
+                .expect("Failed to map draw buffer memory")
+        };
+
+        let objects = create(
+            "draw objects",
+            std::mem::size_of::<DrawObject>(),
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            host_visible,
+        );
+        let commands_usage =
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER;
+        let (commands, commands_mapped) = match self.path {
+            IndirectPath::GpuCulled => (
+                create(
+                    "draw commands",
+                    std::mem::size_of::<vk::DrawIndexedIndirectCommand>(),
+                    commands_usage,
+                    vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                ),
+                ptr::null_mut(),
+            ),
+            _ => {
+                let commands = create(
+                    "draw commands",
+                    std::mem::size_of::<vk::DrawIndexedIndirectCommand>(),
+                    commands_usage,
+                    host_visible,
+                );
+                let mapped = map(&commands);
+                (commands, mapped)
+            }
+        };
+        let counts = create(
+            "draw counts",
+            std::mem::size_of::<u32>(),
+            vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::INDIRECT_BUFFER
+                | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        );
+
+        let set = self.sets[frame];
+        for (binding, buffer_stuff) in [&objects, &commands, &counts].into_iter().enumerate() {
+            descriptor::write_storage_buffer(device, set, binding as u32, *buffer_stuff.buffer);
+        }
+
+        self.frames[frame] = Some(FrameBuffers {
+            objects_mapped: map(&objects),
+            _objects: objects,
+            commands,
+            commands_mapped,
+            counts,
+            capacity,
+        });
+    }
+}
@@ -0,0 +1,463 @@
+use super::window::{
+    Action, GamepadAxis, GamepadButton, GamepadState, Key, MouseButton, WindowEvent,
+};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+// Raw window input is folded into per-frame device state, which named actions from a config file
+// (see config/input.cfg) are evaluated against once per frame. Everything past the main loop
+// asks for actions, cursor motion or scroll, never for window events.
+
+const DEFAULT_CONFIG: &str = include_str!("../../config/input.cfg");
+const GAMEPAD_DEADZONE: f32 = 0.15_f32;
+// Analog values at or above this count as the action being held
+const PRESS_THRESHOLD: f32 = 0.5_f32;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Binding {
+    Key(Key),
+    Mouse(MouseButton),
+    ScrollUp,
+    ScrollDown,
+    GamepadButton(GamepadButton),
+    // One half of an axis, reported as 0..1 in the direction of `sign`
+    GamepadAxis(GamepadAxis, f32),
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+struct ActionState {
+    value: f32,
+    held: bool,
+    was_held: bool,
+}
+
+pub struct Input {
+    bindings: HashMap<String, Vec<Binding>>,
+    actions: HashMap<String, ActionState>,
+    keys: HashSet<Key>,
+    mouse_buttons: HashSet<MouseButton>,
+    // Pressed since the last update, so taps shorter than a frame still register
+    tapped_keys: HashSet<Key>,
+    tapped_mouse_buttons: HashSet<MouseButton>,
+    gamepad_buttons: HashSet<GamepadButton>,
+    gamepad_axes: HashMap<GamepadAxis, f32>,
+    cursor_position: Option<(f64, f64)>,
+    // Accumulated from events until the next update, then exposed for one frame
+    pending_cursor_delta: (f64, f64),
+    pending_scroll: (f64, f64),
+    cursor_delta: (f64, f64),
+    scroll: (f64, f64),
+}
+
+impl Input {
+    // Falls back to the built-in bindings when the file is missing or invalid
+    pub fn load(path: &Path) -> Input {
+        let bindings = fs::read_to_string(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+            .and_then(|source| parse(&source).map_err(|e| format!("{}:{}", path.display(), e)))
+            .unwrap_or_else(|e| {
+                println!("Using default input bindings: {}", e);
+                parse(DEFAULT_CONFIG).expect("Invalid built-in input bindings")
+            });
+
+        Input::new(bindings)
+    }
+
+    pub fn new(bindings: HashMap<String, Vec<Binding>>) -> Input {
+        Input {
+            actions: bindings
+                .keys()
+                .map(|action| (action.clone(), ActionState::default()))
+                .collect(),
+            bindings,
+            keys: HashSet::new(),
+            mouse_buttons: HashSet::new(),
+            tapped_keys: HashSet::new(),
+            tapped_mouse_buttons: HashSet::new(),
+            gamepad_buttons: HashSet::new(),
+            gamepad_axes: HashMap::new(),
+            cursor_position: None,
+            pending_cursor_delta: (0.0_f64, 0.0_f64),
+            pending_scroll: (0.0_f64, 0.0_f64),
+            cursor_delta: (0.0_f64, 0.0_f64),
+            scroll: (0.0_f64, 0.0_f64),
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &WindowEvent) {
+        match *event {
+            WindowEvent::Key(key, Action::Press) => {
+                self.keys.insert(key);
+                self.tapped_keys.insert(key);
+            }
+            WindowEvent::Key(key, Action::Release) => {
+                self.keys.remove(&key);
+            }
+            WindowEvent::MouseButton(button, Action::Press) => {
+                self.mouse_buttons.insert(button);
+                self.tapped_mouse_buttons.insert(button);
+            }
+            WindowEvent::MouseButton(button, Action::Release) => {
+                self.mouse_buttons.remove(&button);
+            }
+            WindowEvent::CursorPos(x, y) => {
+                if let Some((last_x, last_y)) = self.cursor_position {
+                    self.pending_cursor_delta.0 += x - last_x;
+                    self.pending_cursor_delta.1 += y - last_y;
+                }
+                self.cursor_position = Some((x, y));
+            }
+            WindowEvent::Scroll(x, y) => {
+                self.pending_scroll.0 += x;
+                self.pending_scroll.1 += y;
+            }
+            // Releases while unfocused never arrive
+            WindowEvent::Focus(false) => {
+                self.keys.clear();
+                self.mouse_buttons.clear();
+            }
+            _ => {}
+        }
+    }
+
+    // Call once per frame after the frame's events have been handled
+    pub fn update(&mut self, gamepad: Option<GamepadState>) {
+        self.cursor_delta = std::mem::take(&mut self.pending_cursor_delta);
+        self.scroll = std::mem::take(&mut self.pending_scroll);
+        let gamepad = gamepad.unwrap_or_default();
+        self.gamepad_buttons = gamepad.buttons.into_iter().collect();
+        self.gamepad_axes = gamepad.axes.into_iter().collect();
+
+        for (action, bindings) in self.bindings.iter() {
+            let value = bindings
+                .iter()
+                .map(|&binding| self.binding_value(binding))
+                .fold(0.0_f32, f32::max);
+            let state = self.actions.get_mut(action).unwrap();
+            state.was_held = state.held;
+            state.held = value >= PRESS_THRESHOLD;
+            state.value = value;
+        }
+
+        self.tapped_keys.clear();
+        self.tapped_mouse_buttons.clear();
+    }
+
+    fn binding_value(&self, binding: Binding) -> f32 {
+        let pressed = |down: bool| if down { 1.0_f32 } else { 0.0_f32 };
+        match binding {
+            Binding::Key(key) => {
+                pressed(self.keys.contains(&key) || self.tapped_keys.contains(&key))
+            }
+            Binding::Mouse(button) => pressed(
+                self.mouse_buttons.contains(&button) || self.tapped_mouse_buttons.contains(&button),
+            ),
+            Binding::ScrollUp => self.scroll.1.max(0.0_f64) as f32,
+            Binding::ScrollDown => (-self.scroll.1).max(0.0_f64) as f32,
+            Binding::GamepadButton(button) => pressed(self.gamepad_buttons.contains(&button)),
+            Binding::GamepadAxis(axis, sign) => {
+                let value = self.gamepad_axes.get(&axis).copied().unwrap_or(0.0_f32) * sign;
+                // Rescaled so the output starts at 0 at the edge of the deadzone
+                ((value - GAMEPAD_DEADZONE) / (1.0_f32 - GAMEPAD_DEADZONE)).clamp(0.0_f32, 1.0_f32)
+            }
+        }
+    }
+
+    fn state(&self, action: &str) -> ActionState {
+        self.actions.get(action).copied().unwrap_or_default()
+    }
+
+    // Became held this frame
+    pub fn pressed(&self, action: &str) -> bool {
+        let state = self.state(action);
+        state.held && !state.was_held
+    }
+
+    pub fn held(&self, action: &str) -> bool {
+        self.state(action).held
+    }
+
+    // Stopped being held this frame
+    pub fn released(&self, action: &str) -> bool {
+        let state = self.state(action);
+        !state.held && state.was_held
+    }
+
+    // Strongest of the action's bindings: 0 or 1 for buttons, 0..1 for axes, scroll distance
+    // for scroll bindings
+    pub fn value(&self, action: &str) -> f32 {
+        self.state(action).value
+    }
+
+    pub fn axis(&self, negative: &str, positive: &str) -> f32 {
+        self.value(positive) - self.value(negative)
+    }
+
+    pub fn cursor_position(&self) -> Option<(f64, f64)> {
+        self.cursor_position
+    }
+
+    pub fn cursor_delta(&self) -> (f64, f64) {
+        self.cursor_delta
+    }
+
+    pub fn scroll(&self) -> (f64, f64) {
+        self.scroll
+    }
+}
+
+pub fn parse(source: &str) -> Result<HashMap<String, Vec<Binding>>, String> {
+    let mut bindings = HashMap::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let error = |message: String| format!("{}: {}", index + 1, message);
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let Some((action, inputs)) = line.split_once('=') else {
+            return Err(error(format!(
+                "expected '<action> = <bindings>', got '{}'",
+                line
+            )));
+        };
+        let action = action.trim();
+        if action.is_empty() || action.contains(char::is_whitespace) {
+            return Err(error(format!("invalid action name '{}'", action)));
+        }
+
+        let parsed = inputs
+            .split(',')
+            .map(|binding| parse_binding(binding.trim()).map_err(error))
+            .collect::<Result<Vec<_>, _>>()?;
+        if bindings.insert(action.to_string(), parsed).is_some() {
+            return Err(error(format!("action '{}' is bound twice", action)));
+        }
+    }
+
+    Ok(bindings)
+}
+
+fn parse_binding(binding: &str) -> Result<Binding, String> {
+    let Some((device, name)) = binding.split_once(':') else {
+        return Err(format!("expected '<device>:<input>', got '{}'", binding));
+    };
+    let unknown = || format!("unknown {} input '{}'", device, name);
+
+    match device {
+        "key" => parse_key(name).map(Binding::Key).ok_or_else(unknown),
+        "mouse" => match name {
+            "Left" => Ok(Binding::Mouse(MouseButton::Left)),
+            "Right" => Ok(Binding::Mouse(MouseButton::Right)),
+            "Middle" => Ok(Binding::Mouse(MouseButton::Middle)),
+            _ => name
+                .strip_prefix("Button")
+                .and_then(|n| n.parse::<u8>().ok())
+                .and_then(|n| match n {
+                    1 => Some(MouseButton::Left),
+                    2 => Some(MouseButton::Right),
+                    3 => Some(MouseButton::Middle),
+                    4..=8 => Some(MouseButton::Other(n)),
+                    _ => None,
+                })
+                .map(Binding::Mouse)
+                .ok_or_else(unknown),
+        },
+        "scroll" => match name {
+            "Up" => Ok(Binding::ScrollUp),
+            "Down" => Ok(Binding::ScrollDown),
+            _ => Err(unknown()),
+        },
+        "gamepad" => {
+            let button = match name {
+                "A" => GamepadButton::A,
+                "B" => GamepadButton::B,
+                "X" => GamepadButton::X,
+                "Y" => GamepadButton::Y,
+                "LeftBumper" => GamepadButton::LeftBumper,
+                "RightBumper" => GamepadButton::RightBumper,
+                "Back" => GamepadButton::Back,
+                "Start" => GamepadButton::Start,
+                "Guide" => GamepadButton::Guide,
+                "LeftThumb" => GamepadButton::LeftThumb,
+                "RightThumb" => GamepadButton::RightThumb,
+                "DpadUp" => GamepadButton::DpadUp,
+                "DpadRight" => GamepadButton::DpadRight,
+                "DpadDown" => GamepadButton::DpadDown,
+                "DpadLeft" => GamepadButton::DpadLeft,
+                _ => return Err(unknown()),
+            };
+            Ok(Binding::GamepadButton(button))
+        }
+        "gamepad_axis" => {
+            let (axis, sign) = match (name.strip_suffix('+'), name.strip_suffix('-')) {
+                (Some(axis), _) => (axis, 1.0_f32),
+                (_, Some(axis)) => (axis, -1.0_f32),
+                _ => return Err(format!("gamepad axis '{}' needs a + or - suffix", name)),
+            };
+            let axis = match axis {
+                "LeftX" => GamepadAxis::LeftX,
+                "LeftY" => GamepadAxis::LeftY,
+                "RightX" => GamepadAxis::RightX,
+                "RightY" => GamepadAxis::RightY,
+                "LeftTrigger" => GamepadAxis::LeftTrigger,
+                "RightTrigger" => GamepadAxis::RightTrigger,
+                _ => return Err(unknown()),
+            };
+            Ok(Binding::GamepadAxis(axis, sign))
+        }
+        _ => Err(format!("unknown input device '{}'", device)),
+    }
+}
+
+fn parse_key(name: &str) -> Option<Key> {
+    let mut chars = name.chars();
+    if let (Some(c @ 'A'..='Z'), None) = (chars.next(), chars.next()) {
+        return Some(Key::LETTERS[(c as u8 - b'A') as usize]);
+    }
+    if let Some(digit) = name
+        .strip_prefix("Num")
+        .and_then(|n| n.parse::<usize>().ok())
+    {
+        return Key::DIGITS.get(digit).copied();
+    }
+    if let Some(number) = name.strip_prefix('F').and_then(|n| n.parse::<usize>().ok()) {
+        return number
+            .checked_sub(1)
+            .and_then(|i| Key::FUNCTION_KEYS.get(i))
+            .copied();
+    }
+
+    let key = match name {
+        "Space" => Key::Space,
+        "Escape" => Key::Escape,
+        "Enter" => Key::Enter,
+        "Tab" => Key::Tab,
+        "Backspace" => Key::Backspace,
+        "Insert" => Key::Insert,
+        "Delete" => Key::Delete,
+        "Home" => Key::Home,
+        "End" => Key::End,
+        "PageUp" => Key::PageUp,
+        "PageDown" => Key::PageDown,
+        "Up" => Key::Up,
+        "Down" => Key::Down,
+        "Left" => Key::Left,
+        "Right" => Key::Right,
+        "LeftShift" => Key::LeftShift,
+        "RightShift" => Key::RightShift,
+        "LeftControl" => Key::LeftControl,
+        "RightControl" => Key::RightControl,
+        "LeftAlt" => Key::LeftAlt,
+        "RightAlt" => Key::RightAlt,
+        "Minus" => Key::Minus,
+        "Equal" => Key::Equal,
+        "Comma" => Key::Comma,
+        "Period" => Key::Period,
+        "Slash" => Key::Slash,
+        "GraveAccent" => Key::GraveAccent,
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_config_parses() {
+        let bindings = parse(DEFAULT_CONFIG).unwrap();
+
+        assert_eq!(
+            bindings["screenshot"],
+            vec![
+                Binding::Key(Key::F12),
+                Binding::GamepadButton(GamepadButton::Start)
+            ]
+        );
+    }
+
+    #[test]
+    fn bindings_name_a_device_and_an_input() {
+        assert_eq!(parse_binding("key:W"), Ok(Binding::Key(Key::W)));
+        assert_eq!(parse_binding("key:Num3"), Ok(Binding::Key(Key::DIGITS[3])));
+        assert_eq!(parse_binding("key:F12"), Ok(Binding::Key(Key::F12)));
+        assert_eq!(
+            parse_binding("mouse:Button5"),
+            Ok(Binding::Mouse(MouseButton::Other(5)))
+        );
+        assert_eq!(parse_binding("scroll:Down"), Ok(Binding::ScrollDown));
+        assert!(parse_binding("W").is_err());
+        assert!(parse_binding("joystick:A").is_err());
+    }
+
+    #[test]
+    fn unknown_inputs_are_rejected() {
+        assert_eq!(
+            parse_binding("key:Hyper"),
+            Err("unknown key input 'Hyper'".to_string())
+        );
+        assert!(parse_binding("key:F0").is_err());
+        assert!(parse_binding("key:Num10").is_err());
+        assert!(parse_binding("mouse:Button9").is_err());
+        assert!(parse_binding("gamepad:Z").is_err());
+        assert!(parse_binding("gamepad_axis:LeftZ+").is_err());
+    }
+
+    #[test]
+    fn axis_bindings_take_a_sign_suffix() {
+        assert_eq!(
+            parse_binding("gamepad_axis:LeftY-"),
+            Ok(Binding::GamepadAxis(GamepadAxis::LeftY, -1.0_f32))
+        );
+        assert_eq!(
+            parse_binding("gamepad_axis:RightTrigger+"),
+            Ok(Binding::GamepadAxis(GamepadAxis::RightTrigger, 1.0_f32))
+        );
+        for bad in ["LeftX", "LeftX*", "LeftXé", "", "+"] {
+            assert!(
+                parse_binding(&format!("gamepad_axis:{}", bad)).is_err(),
+                "accepted {}",
+                bad
+            );
+        }
+        assert_eq!(
+            parse_binding("gamepad_axis:LeftXé"),
+            Err("gamepad axis 'LeftXé' needs a + or - suffix".to_string())
+        );
+    }
+
+    #[test]
+    fn modifier_actions_hold_several_bindings() {
+        let bindings = parse("window_modifier = key:LeftAlt, key:RightAlt # either Alt\n").unwrap();
+
+        assert_eq!(
+            bindings["window_modifier"],
+            vec![Binding::Key(Key::LeftAlt), Binding::Key(Key::RightAlt)]
+        );
+    }
+
+    #[test]
+    fn config_errors_name_the_line() {
+        assert_eq!(
+            parse("# comment\n\nquit key:Escape\n"),
+            Err("3: expected '<action> = <bindings>', got 'quit key:Escape'".to_string())
+        );
+        assert_eq!(
+            parse("quit = key:Escape\nquit = key:Q\n"),
+            Err("2: action 'quit' is bound twice".to_string())
+        );
+        assert_eq!(
+            parse("two words = key:Q\n"),
+            Err("1: invalid action name 'two words'".to_string())
+        );
+        assert_eq!(
+            parse("quit = key:Escape, key:Hyper\n"),
+            Err("1: unknown key input 'Hyper'".to_string())
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use ash::vk;
+use std::ffi::c_void;
+use std::mem::offset_of;
+use std::ptr;
+
+use super::buffer;
+use super::resources::Device;
+use crate::util::constants::MAX_FRAMES_IN_FLIGHT;
+use crate::util::structures::BufferStuff;
+
+// Vertex binding the per-instance data is read from, after the mesh vertices at binding 0
+pub const INSTANCE_BINDING: u32 = 1;
+
+// Per-instance vertex attributes of mesh_instanced.vert
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct InstanceData {
+    pub model: [[f32; 4]; 4],
+    // Multiplies the vertex color
+    pub color: [f32; 4],
+}
+
+impl InstanceData {
+    pub fn binding_description() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription {
+            binding: INSTANCE_BINDING,
+            stride: std::mem::size_of::<InstanceData>() as u32,
+            input_rate: vk::VertexInputRate::INSTANCE,
+        }
+    }
+
+    // A mat4 attribute takes a location per column
+    pub fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 5] {
+        let column = |i: usize| vk::VertexInputAttributeDescription {
+            location: 5 + i as u32,
+            binding: INSTANCE_BINDING,
+            format: vk::Format::R32G32B32A32_SFLOAT,
+            offset: (offset_of!(InstanceData, model) + i * std::mem::size_of::<[f32; 4]>()) as u32,
+        };
+        [
+            column(0),
+            column(1),
+            column(2),
+            column(3),
+            vk::VertexInputAttributeDescription {
+                location: 9,
+                binding: INSTANCE_BINDING,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                offset: offset_of!(InstanceData, color) as u32,
+            },
+        ]
+    }
+}
+
+struct InstanceBuffer {
+    buffer_stuff: BufferStuff,
+    mapped: *mut c_void,
+    capacity: usize,
+}
+
+// Copies of one mesh, each with its own transform and color, drawn with one instanced call per
+// primitive. `instances` is copied each frame into that frame's persistently mapped vertex
+// buffer, which grows when it runs out of room; the frame's fence has been waited on by then, so
+// replacing it is safe.
+pub struct InstanceBatch {
+    pub mesh: usize,
+    // Overrides the materials of the mesh's primitives, like a scene node's
+    pub material: Option<usize>,
+    pub instances: Vec<InstanceData>,
+    buffers: Vec<Option<InstanceBuffer>>,
+}
+
+impl InstanceBatch {
+    pub fn new(mesh: usize, material: Option<usize>, instances: Vec<InstanceData>) -> Self {
+        InstanceBatch {
+            mesh,
+            material,
+            instances,
+            buffers: (0..MAX_FRAMES_IN_FLIGHT).map(|_| None).collect(),
+        }
+    }
+
+    // Once per frame before recording its draws
+    pub fn upload(
+        &mut self,
+        device: &Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        frame: usize,
+    ) {
+        let count = self.instances.len();
+        let slot = &mut self.buffers[frame];
+        if slot.as_ref().map_or(0, |buffer| buffer.capacity) < count {
+            *slot = None;
+            // Headroom so a slowly growing batch doesn't reallocate every frame
+            let capacity = count.next_power_of_two();
+            let size = (capacity * std::mem::size_of::<InstanceData>()) as vk::DeviceSize;
+            let buffer_stuff = buffer::create_buffer(
+                device,
+                memory_properties,
+                size,
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                &format!("instances {}", frame),
+            );
+            let mapped = unsafe {
+                device
+                    .map_memory(*buffer_stuff.memory, 0, size, vk::MemoryMapFlags::empty())
+                    .expect("Failed to map instance buffer memory")
+            };
+            *slot = Some(InstanceBuffer {
+                buffer_stuff,
+                mapped,
+                capacity,
+            });
+        }
+
+        if let Some(buffer) = slot {
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    self.instances.as_ptr(),
+                    buffer.mapped as *mut InstanceData,
+                    count,
+                )
+            };
+        }
+    }
+
+    // Null until the first upload for `frame`
+    pub fn buffer(&self, frame: usize) -> vk::Buffer {
+        self.buffers[frame]
+            .as_ref()
+            .map_or(vk::Buffer::null(), |buffer| *buffer.buffer_stuff.buffer)
+    }
+}
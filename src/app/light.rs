@@ -0,0 +1,99 @@
+use cgmath::{InnerSpace, Point3, Vector3};
+
+use super::descriptor::FrameUniforms;
+use crate::util::constants::MAX_POINT_LIGHTS;
+
+// Light sources in world space. The lit shader takes one directional light (the sun), optionally
+// one spot light and up to MAX_POINT_LIGHTS point lights. Colors are linear and get multiplied
+// by the intensity before upload.
+
+#[derive(Clone, Copy, Debug)]
+pub struct DirectionalLight {
+    // The way the light travels, i.e. from the light towards the scene
+    pub direction: Vector3<f32>,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub casts_shadows: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SpotLight {
+    pub position: Point3<f32>,
+    pub direction: Vector3<f32>,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    // Light falls off to nothing at this distance
+    pub range: f32,
+    // Half angles in radians; intensity fades out between the inner and the outer cone
+    pub inner_angle: f32,
+    pub outer_angle: f32,
+    pub casts_shadows: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PointLight {
+    pub position: Point3<f32>,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub range: f32,
+}
+
+pub struct Lights {
+    pub sun: DirectionalLight,
+    pub spot: Option<SpotLight>,
+    pub points: Vec<PointLight>,
+}
+
+impl Lights {
+    pub fn write_uniforms(&self, uniforms: &mut FrameUniforms) {
+        let to_sun = -self.sun.direction.normalize();
+        uniforms.sun_direction = [to_sun.x, to_sun.y, to_sun.z, 0.0_f32];
+        uniforms.sun_color = scaled_color(self.sun.color, self.sun.intensity);
+
+        match &self.spot {
+            Some(spot) => {
+                let direction = spot.direction.normalize();
+                uniforms.spot_position = [
+                    spot.position.x,
+                    spot.position.y,
+                    spot.position.z,
+                    spot.range,
+                ];
+                uniforms.spot_direction = [
+                    direction.x,
+                    direction.y,
+                    direction.z,
+                    spot.outer_angle.cos(),
+                ];
+                let color = scaled_color(spot.color, spot.intensity);
+                uniforms.spot_color = [color[0], color[1], color[2], spot.inner_angle.cos()];
+            }
+            // Zero range turns the spot light off in the shader
+            None => {
+                uniforms.spot_position = [0.0_f32; 4];
+                uniforms.spot_color = [0.0_f32; 4];
+            }
+        }
+
+        let points = &self.points[..self.points.len().min(MAX_POINT_LIGHTS)];
+        for (i, point) in points.iter().enumerate() {
+            uniforms.point_positions[i] = [
+                point.position.x,
+                point.position.y,
+                point.position.z,
+                point.range,
+            ];
+            uniforms.point_colors[i] = scaled_color(point.color, point.intensity);
+        }
+        uniforms.light_counts = [points.len() as f32, 0.0_f32, 0.0_f32, 0.0_f32];
+    }
+}
+
+fn scaled_color(color: [f32; 3], intensity: f32) -> [f32; 4] {
+    [
+        color[0] * intensity,
+        color[1] * intensity,
+        color[2] * intensity,
+        0.0_f32,
+    ]
+}
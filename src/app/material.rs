@@ -0,0 +1,249 @@
+use ash::vk;
+use std::collections::HashMap;
+
+use super::graphics_pipeline;
+use super::instancing::InstanceData;
+use super::model;
+use super::resources::Device;
+use crate::assets::material::{AlphaMode, Material};
+use crate::assets::mesh::Vertex;
+use crate::util::structures::{GraphicsPipelineStuff, PipelineDesc};
+
+// GPU side of the metallic-roughness materials: the factors every material uploads, the texture
+// slots of its descriptor set and the pipeline variants materials pick from
+
+// Binding order of the material textures, after the uniform buffer at binding 0
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TextureSlot {
+    BaseColor,
+    Normal,
+    MetallicRoughness,
+    Occlusion,
+    Emissive,
+}
+
+pub const TEXTURE_SLOT_COUNT: usize = 5;
+
+impl TextureSlot {
+    pub const ALL: [TextureSlot; TEXTURE_SLOT_COUNT] = [
+        TextureSlot::BaseColor,
+        TextureSlot::Normal,
+        TextureSlot::MetallicRoughness,
+        TextureSlot::Occlusion,
+        TextureSlot::Emissive,
+    ];
+
+    pub fn binding(self) -> u32 {
+        1 + self as u32
+    }
+
+    pub fn texture(self, material: &Material) -> Option<usize> {
+        match self {
+            TextureSlot::BaseColor => material.base_color_texture,
+            TextureSlot::Normal => material.normal_texture,
+            TextureSlot::MetallicRoughness => material.metallic_roughness_texture,
+            TextureSlot::Occlusion => material.occlusion_texture,
+            TextureSlot::Emissive => material.emissive_texture,
+        }
+    }
+}
+
+// Matches the std140 block in mesh.frag
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct MaterialUniforms {
+    pub base_color_factor: [f32; 4],
+    // w is the alpha cutoff, negative unless the material is masked
+    pub emissive_factor: [f32; 4],
+    // Metallic, roughness, normal scale and occlusion strength
+    pub params: [f32; 4],
+}
+
+impl MaterialUniforms {
+    pub fn new(material: &Material) -> MaterialUniforms {
+        let [r, g, b] = material.emissive_factor;
+        let alpha_cutoff = match material.alpha_mode {
+            AlphaMode::Mask => material.alpha_cutoff,
+            _ => -1.0_f32,
+        };
+
+        MaterialUniforms {
+            base_color_factor: material.base_color_factor,
+            emissive_factor: [r, g, b, alpha_cutoff],
+            params: [
+                material.metallic_factor,
+                material.roughness_factor,
+                material.normal_scale,
+                material.occlusion_strength,
+            ],
+        }
+    }
+}
+
+// A material in the bindless materials buffer, mirroring MaterialRecord in mesh_bindless.frag
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct BindlessMaterial {
+    pub uniforms: MaterialUniforms,
+    // bindless::TextureHandle and SamplerHandle indices in TextureSlot order
+    pub textures: [u32; TEXTURE_SLOT_COUNT],
+    pub samplers: [u32; TEXTURE_SLOT_COUNT],
+    // std430 rounds the struct up to its vec4 alignment
+    _padding: [u32; 2],
+}
+
+impl BindlessMaterial {
+    pub fn new(
+        uniforms: MaterialUniforms,
+        textures: [u32; TEXTURE_SLOT_COUNT],
+        samplers: [u32; TEXTURE_SLOT_COUNT],
+    ) -> BindlessMaterial {
+        BindlessMaterial {
+            uniforms,
+            textures,
+            samplers,
+            _padding: [0; 2],
+        }
+    }
+}
+
+// Every material's uniforms live in one buffer. Descriptor offsets have to be a multiple of
+// minUniformBufferOffsetAlignment, which the spec caps at 256.
+pub const MATERIAL_UNIFORM_STRIDE: usize = 256;
+
+// Where the mesh vertex shader gets the model matrix from
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum TransformSource {
+    PushConstant,
+    // A per-instance vertex buffer, see instancing::InstanceData
+    Instance,
+    // The draw object storage buffer at set 2, indexed by the instance, for indirect draws
+    DrawObject,
+}
+
+impl TransformSource {
+    const ALL: [TransformSource; 3] = [
+        TransformSource::PushConstant,
+        TransformSource::Instance,
+        TransformSource::DrawObject,
+    ];
+}
+
+// The fixed-function state a material needs. Opaque variants sort before blended ones, which
+// have to be drawn last.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct PipelineKey {
+    pub blend: bool,
+    pub double_sided: bool,
+    pub transforms: TransformSource,
+}
+
+impl PipelineKey {
+    pub fn new(material: &Material) -> PipelineKey {
+        PipelineKey {
+            blend: material.alpha_mode == AlphaMode::Blend,
+            double_sided: material.double_sided,
+            transforms: TransformSource::PushConstant,
+        }
+    }
+}
+
+// The lit mesh pipeline in every variant a material can ask for, however it's drawn. All of them
+// share the same set layouts and push constants, so descriptor sets stay bound when switching
+// between them. Bindless pipelines expect the bindless set at set 1 instead of a material set.
+pub struct MaterialPipelines {
+    pipelines: HashMap<PipelineKey, GraphicsPipelineStuff>,
+}
+
+impl MaterialPipelines {
+    pub fn new(
+        device: &Device,
+        set_layouts: &[vk::DescriptorSetLayout],
+        render_pass: vk::RenderPass,
+        color_format: vk::Format,
+        depth_format: vk::Format,
+        depth_compare_op: vk::CompareOp,
+        bindless: bool,
+    ) -> MaterialPipelines {
+        let frag_shader = if bindless {
+            "mesh_bindless.frag"
+        } else {
+            "mesh.frag"
+        };
+        // Instanced variants read the transform from a second, per-instance vertex binding
+        let bindings = Vertex::binding_descriptions();
+        let attributes = Vertex::attribute_descriptions();
+        let instanced_bindings = [bindings[0], InstanceData::binding_description()];
+        let instanced_attributes: Vec<_> = attributes
+            .iter()
+            .copied()
+            .chain(InstanceData::attribute_descriptions())
+            .collect();
+
+        let mut pipelines = HashMap::new();
+        for transforms in TransformSource::ALL {
+            let (vert_shader, vertex_bindings, vertex_attributes): (_, &[_], &[_]) =
+                match transforms {
+                    TransformSource::PushConstant => ("mesh.vert", &bindings, &attributes),
+                    TransformSource::Instance => (
+                        "mesh_instanced.vert",
+                        &instanced_bindings,
+                        &instanced_attributes,
+                    ),
+                    TransformSource::DrawObject => ("mesh_indirect.vert", &bindings, &attributes),
+                };
+            for blend in [false, true] {
+                for double_sided in [false, true] {
+                    let pipeline = graphics_pipeline::create_graphics_pipeline(
+                        device,
+                        &PipelineDesc {
+                            vert_shader,
+                            frag_shader,
+                            vertex_bindings,
+                            vertex_attributes,
+                            set_layouts,
+                            push_constant_ranges: &[model::push_constant_range()],
+                            render_pass,
+                            color_format,
+                            depth_format,
+                            depth_test: true,
+                            depth_compare_op,
+                            depth_bias: None,
+                            blend,
+                            cull_mode: if double_sided {
+                                vk::CullModeFlags::NONE
+                            } else {
+                                vk::CullModeFlags::BACK
+                            },
+                            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                        },
+                    );
+                    pipelines.insert(
+                        PipelineKey {
+                            blend,
+                            double_sided,
+                            transforms,
+                        },
+                        pipeline,
+                    );
+                }
+            }
+        }
+
+        MaterialPipelines { pipelines }
+    }
+
+    pub fn pipeline(&self, key: PipelineKey) -> vk::Pipeline {
+        *self.pipelines[&key].graphics_pipeline
+    }
+
+    // Compatible with every variant
+    pub fn layout(&self) -> vk::PipelineLayout {
+        *self.pipelines[&PipelineKey {
+            blend: false,
+            double_sided: false,
+            transforms: TransformSource::PushConstant,
+        }]
+            .pipeline_layout
+    }
+}
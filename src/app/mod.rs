@@ -1,106 +1,572 @@
+pub mod application;
+pub mod bindless;
+pub mod buffer;
+pub mod camera;
+pub mod color_space;
+pub mod commands;
+pub mod compute_pipeline;
+pub mod culling;
+pub mod descriptor;
+pub mod dynamic_rendering;
+pub mod environment;
+pub mod frame_limiter;
 pub mod graphics_pipeline;
-extern crate glfw;
-
+pub mod indirect;
+pub mod input;
+pub mod instancing;
+pub mod light;
+pub mod material;
+pub mod model;
+pub mod parallel_recording;
+pub mod post_process;
+pub mod present_settings;
+pub mod render_graph;
+pub mod resources;
+pub mod scene;
+pub mod screenshot;
+pub mod shadow;
+pub mod texture;
+pub mod window;
+
+use crate::assets::color_lut;
+use crate::assets::environment::{self as environment_source, EnvironmentSource};
+use crate::assets::gltf;
+use crate::assets::obj;
 use crate::util::constants::{
-    DEVICE_EXTENSIONS, MAX_FRAMES_IN_FLIGHT, VALIDATION, WINDOW_HEIGHT, WINDOW_WIDTH,
+    COLOR_LUT_PATH, COLOR_SPACE_POLICY, DEVICE_EXTENSIONS, ENVIRONMENT_PATH, INPUT_CONFIG_PATH,
+    MAX_FRAMES_IN_FLIGHT, PARALLEL_RECORDING, PREFER_DYNAMIC_RENDERING, PRESENT_SETTINGS_PATH,
+    RECORDING_BENCHMARK_DRAWS, RECORDING_BENCHMARK_RUNS, RECORDING_THREADS, RENDER_GRAPH_DUMP_PATH,
+    REVERSED_Z, STRESS_TEST_INSTANCES, VALIDATION, WINDOW_SETTINGS_PATH,
 };
 use crate::util::structures::{
-    AppWindow, GraphicsPipelineStuff, QueueFamilyIndices, SurfaceStuff, SwapChainStuff,
-    SwapChainSupportDetails, SyncObjects,
+    FrameStats, FrameUniformStuff, ImageStuff, IndirectPath, PipelineDesc, QueueFamilyIndices,
+    RenderingPath, SurfaceStuff, SwapChainStuff, SwapChainSupportDetails, SyncObjects, VsyncPolicy,
 };
 use crate::util::{debug, tools};
+use application::Application;
 use ash::vk::CommandBufferResetFlags;
 use ash::{vk, Entry};
+use bindless::BindlessTextures;
+use camera::CameraController;
+use cgmath::{InnerSpace, Matrix4, Point3, Rad, Vector3};
+use color_space::SurfaceFormatChoice;
 use core::panic;
-use glfw::{Action, ClientApiHint, Key, WindowHint};
+use culling::Frustum;
+use environment::Environment;
+use frame_limiter::FrameLimiter;
+use indirect::IndirectDraws;
+use input::Input;
+use instancing::{InstanceBatch, InstanceData};
+use light::{DirectionalLight, Lights, SpotLight};
+use material::MaterialPipelines;
+use model::Model;
+use parallel_recording::{ParallelRecorder, SecondaryTarget};
+use post_process::{PostProcess, SCENE_COLOR_FORMAT};
+use present_settings::PresentSettings;
+use render_graph::{Access, ImageDesc, PassContext, RenderGraph, ResourceId, TransientPool};
+use resources::{DeletionQueue, Device, Owned};
+use scene::{DrawNode, Scene};
+use screenshot::Screenshot;
+use shadow::{ShadowMaps, ShadowSettings};
 use std::collections::HashSet;
+use std::f32::consts::TAU;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
 use std::u32;
-use std::{ffi::CString, ptr};
+use std::{
+    ffi::{c_void, CString},
+    ptr,
+};
+use texture::TextureFormatSupport;
+use window::settings::WindowSettings;
+use window::{Monitor, VideoMode, Window, WindowEvent, WindowMode};
 
-pub struct App {
-    app_window: AppWindow,
-    _entry: ash::Entry,
+// The instance and the surface, destroyed when dropped, which in Renderer is after the device
+struct InstanceStuff {
     instance: ash::Instance,
-    _physical_device: vk::PhysicalDevice,
-    device: ash::Device,
+    surface_stuff: SurfaceStuff,
+}
+
+impl Drop for InstanceStuff {
+    fn drop(&mut self) {
+        unsafe {
+            self.surface_stuff
+                .surface_loader
+                .destroy_surface(self.surface_stuff.surface, None);
+            self.instance.destroy_instance(None);
+        }
+    }
+}
+
+// A grid of copies of one mesh, each spinning in place. Transforms are rebuilt from the base and
+// the angle every frame, so rounding error doesn't build up in them.
+struct StressTest {
+    batch: InstanceBatch,
+    bases: Vec<Matrix4<f32>>,
+    angles: Vec<f32>,
+}
+
+// Fields drop in declaration order after `drop` has run, so the ones owning device objects come
+// before the device, and the instance, window and loader after it
+pub struct Renderer {
+    physical_device: vk::PhysicalDevice,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
     _graphic_queue: vk::Queue,
     _present_queue: vk::Queue,
-    surface_stuff: SurfaceStuff,
     swapchain_stuff: SwapChainStuff,
-    swapchain_imageviews: Vec<vk::ImageView>,
-    graphics_pipeline_stuff: GraphicsPipelineStuff,
-    render_pass: vk::RenderPass,
-    framebuffers: Vec<vk::Framebuffer>,
-    command_pool: vk::CommandPool,
+    swapchain_imageviews: Vec<Owned<vk::ImageView>>,
+    depth_image: ImageStuff,
+    graphics_pipeline: Owned<vk::Pipeline>,
+    _pipeline_layout: Owned<vk::PipelineLayout>,
+    material_pipelines: MaterialPipelines,
+    _frame_set_layout: Owned<vk::DescriptorSetLayout>,
+    material_set_layout: Owned<vk::DescriptorSetLayout>,
+    _descriptor_pool: Owned<vk::DescriptorPool>,
+    frame_uniforms: FrameUniformStuff,
+    model: Option<Model>,
+    scene: Scene,
+    // Toggled at runtime
+    stress_test: Option<StressTest>,
+    indirect_draws: IndirectDraws,
+    bindless_textures: Option<BindlessTextures>,
+    // Records the forward pass on worker threads when enabled
+    parallel_recorder: Option<ParallelRecorder>,
+    camera: CameraController,
+    lights: Lights,
+    shadows: ShadowMaps,
+    environment: Environment,
+    post_process: PostProcess,
+    input: Input,
+    rendering_path: RenderingPath,
+    render_pass: Owned<vk::RenderPass>,
+    // Of the forward pass, on the render pass path
+    framebuffer: Owned<vk::Framebuffer>,
+    command_pool: Owned<vk::CommandPool>,
     command_buffers: Vec<vk::CommandBuffer>,
     sync_objects: SyncObjects,
     current_frame: usize,
+    transient_pools: Vec<TransientPool>,
+    screenshot: Screenshot,
+    dump_render_graph: bool,
+    frame_stats: FrameStats,
+    vsync_policy: VsyncPolicy,
+    frame_limiter: FrameLimiter,
+    // Set on resizes, out of date presents and present mode changes
+    swapchain_outdated: bool,
+    // Retired resources that frames in flight may still use
+    deletion_queue: DeletionQueue,
+    // Of the frame being recorded, counting from the first
+    frame_number: u64,
+    device: Arc<Device>,
+    instance_stuff: InstanceStuff,
+    window_settings: WindowSettings,
+    window: Box<dyn Window>,
+    _entry: ash::Entry,
 }
 
-impl App {
-    pub fn new() -> App {
-        let app_window = App::init_window();
-        let window = &app_window.window;
+impl Renderer {
+    pub fn new() -> Renderer {
+        let window_settings = WindowSettings::load(Path::new(WINDOW_SETTINGS_PATH));
+        let present_settings = PresentSettings::load(Path::new(PRESENT_SETTINGS_PATH));
+        let window = window::create("Hello this is window", &window_settings);
 
         let entry = unsafe { Entry::load() }.unwrap();
-        let instance = App::create_instance(&entry, &app_window);
-        let surface_stuff = App::create_surface(&entry, &instance, &window);
-        let physical_device = App::pick_physical_device(&instance, &surface_stuff);
-        let (device, indices) =
-            App::create_logical_device(&instance, &physical_device, &surface_stuff);
+        let colorspace_extension = color_space::check_swapchain_colorspace_support(&entry);
+        let instance = Renderer::create_instance(&entry, &*window, colorspace_extension);
+        let surface_stuff = Renderer::create_surface(&entry, &instance, &*window);
+        let physical_device = Renderer::pick_physical_device(&instance, &surface_stuff);
+        let memory_properties =
+            unsafe { instance.get_physical_device_memory_properties(physical_device) };
+        let rendering_path = Renderer::choose_rendering_path(&instance, &physical_device);
+        let indirect_path = indirect::choose_indirect_path(&instance, &physical_device);
+        let bindless = bindless::choose_bindless(&instance, &physical_device);
+        let (device, indices) = Renderer::create_logical_device(
+            &instance,
+            &physical_device,
+            &surface_stuff,
+            rendering_path,
+            indirect_path,
+            bindless,
+        );
+        let device = Device::new(&instance, device);
 
         let graphic_queue = unsafe { device.get_device_queue(indices.graphics_family.unwrap(), 0) };
         let present_queue = unsafe { device.get_device_queue(indices.present_family.unwrap(), 0) };
 
-        let queue_family = App::find_queue_family(&instance, &physical_device, &surface_stuff);
+        let queue_family = Renderer::find_queue_family(&instance, &physical_device, &surface_stuff);
 
-        let swapchain_stuff = App::create_swapchain(
+        let format_choice = color_space::choose_surface_format(
+            &Renderer::query_swapchain_support(&surface_stuff, &physical_device).formats,
+            COLOR_SPACE_POLICY,
+            colorspace_extension,
+        );
+        let swapchain_stuff = Renderer::create_swapchain(
             &instance,
             &surface_stuff,
             &physical_device,
             &device,
             &queue_family,
+            format_choice,
+            present_settings.vsync,
+            Renderer::framebuffer_extent(&*window),
+            vk::SwapchainKHR::null(),
+        );
+        let swapchain_imageviews = Renderer::create_image_view(&device, &swapchain_stuff);
+        let depth_image = texture::create_image(
+            &device,
+            &memory_properties,
+            swapchain_stuff.swapchain_extent,
+            1,
+            texture::find_depth_format(&instance, &physical_device),
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            "depth",
         );
-        let swapchain_imageviews = App::create_image_view(&device, &swapchain_stuff);
-
-        let render_pass =
-            graphics_pipeline::creat_render_pass(&device, swapchain_stuff.swapchain_format.clone());
-        let graphics_pipeline_stuff =
-            graphics_pipeline::create_graphics_pipeline(&device, render_pass.clone());
 
-        let framebuffers = App::create_frame_buffers(
+        let command_pool = Renderer::create_command_pool(&device, &queue_family);
+        let parallel_recorder = PARALLEL_RECORDING.then(|| {
+            // The main thread records the primary command buffer in the meantime
+            let threads = RECORDING_THREADS.unwrap_or_else(|| {
+                std::thread::available_parallelism().map_or(1, |cores| cores.get().max(2) - 1)
+            });
+            println!("Recording the forward pass on {} worker threads", threads);
+            ParallelRecorder::new(&device, queue_family.graphics_family.unwrap(), threads)
+        });
+        let command_buffers = Renderer::create_command_buffers(&device, command_pool);
+
+        let color_lut = color_lut::load(Path::new(COLOR_LUT_PATH)).unwrap_or_else(|e| {
+            println!(
+                "Not loading color lookup table, using the built-in look: {}",
+                e
+            );
+            color_lut::builtin_look(32)
+        });
+        let post_process = PostProcess::new(
             &device,
-            &swapchain_imageviews,
+            &memory_properties,
+            command_pool,
+            graphic_queue,
+            rendering_path,
             swapchain_stuff.swapchain_extent,
+            swapchain_stuff.swapchain_format,
+            swapchain_stuff.output_encoding,
+            &swapchain_imageviews,
+            &color_lut,
+        );
+
+        // The scene is rendered into the post-processing chain's HDR target. The dynamic
+        // rendering path has neither a render pass nor a framebuffer.
+        let (render_pass, framebuffer) = match rendering_path {
+            RenderingPath::RenderPass => {
+                let render_pass = graphics_pipeline::creat_render_pass(
+                    &device,
+                    SCENE_COLOR_FORMAT,
+                    depth_image.format,
+                );
+                let framebuffer = Renderer::create_frame_buffer(
+                    &device,
+                    *post_process.scene_color().view,
+                    *depth_image.view,
+                    swapchain_stuff.swapchain_extent,
+                    render_pass,
+                );
+                (render_pass, framebuffer)
+            }
+            RenderingPath::Dynamic => (vk::RenderPass::null(), vk::Framebuffer::null()),
+        };
+        let graphics_pipeline_stuff = graphics_pipeline::create_graphics_pipeline(
+            &device,
+            &PipelineDesc {
+                vert_shader: "triangle.vert",
+                frag_shader: "triangle.frag",
+                vertex_bindings: &[],
+                vertex_attributes: &[],
+                set_layouts: &[],
+                push_constant_ranges: &[],
+                render_pass,
+                color_format: SCENE_COLOR_FORMAT,
+                depth_format: depth_image.format,
+                depth_test: false,
+                depth_compare_op: Renderer::depth_compare_op(),
+                depth_bias: None,
+                blend: false,
+                cull_mode: vk::CullModeFlags::BACK,
+                front_face: vk::FrontFace::CLOCKWISE,
+            },
+        );
+
+        let frame_set_layout = descriptor::create_frame_set_layout(&device);
+        let material_set_layout = descriptor::create_material_set_layout(&device);
+        let descriptor_pool = descriptor::create_descriptor_pool(
+            &device,
+            &[
+                vk::DescriptorPoolSize {
+                    ty: vk::DescriptorType::UNIFORM_BUFFER,
+                    descriptor_count: MAX_FRAMES_IN_FLIGHT,
+                },
+                vk::DescriptorPoolSize {
+                    ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    descriptor_count: MAX_FRAMES_IN_FLIGHT * descriptor::FRAME_SET_IMAGES,
+                },
+            ],
+            MAX_FRAMES_IN_FLIGHT,
+            "frame descriptor pool",
+        );
+        let frame_uniforms = descriptor::create_frame_uniforms(
+            &device,
+            &memory_properties,
+            *descriptor_pool,
+            *frame_set_layout,
+        );
+        let shadows = ShadowMaps::new(
+            &instance,
+            &physical_device,
+            &device,
+            &memory_properties,
+            rendering_path,
+            *frame_set_layout,
+            render_pass,
+            SCENE_COLOR_FORMAT,
+            depth_image.format,
+        );
+        shadows.write_descriptors(&device, &frame_uniforms.descriptor_sets);
+        let indirect_draws = IndirectDraws::new(&device, indirect_path);
+        // The frame set's images count against the same per-stage limits
+        let bindless_textures = bindless.then(|| {
+            BindlessTextures::new(
+                &instance,
+                &physical_device,
+                &device,
+                descriptor::FRAME_SET_IMAGES,
+            )
+        });
+        let material_pipelines = MaterialPipelines::new(
+            &device,
+            &[
+                *frame_set_layout,
+                bindless_textures
+                    .as_ref()
+                    .map_or(*material_set_layout, BindlessTextures::set_layout),
+                indirect_draws.set_layout(),
+            ],
+            render_pass,
+            SCENE_COLOR_FORMAT,
+            depth_image.format,
+            Renderer::depth_compare_op(),
+            bindless,
+        );
+
+        let lights = Lights {
+            sun: DirectionalLight {
+                direction: Vector3::new(-0.4_f32, -1.0_f32, -0.6_f32),
+                color: [1.0_f32, 0.96_f32, 0.9_f32],
+                intensity: 3.0_f32,
+                casts_shadows: true,
+            },
+            spot: Some(SpotLight {
+                position: Point3::new(0.0_f32, 3.0_f32, 0.0_f32),
+                direction: -Vector3::unit_y(),
+                color: [1.0_f32, 0.8_f32, 0.6_f32],
+                intensity: 6.0_f32,
+                range: 10.0_f32,
+                inner_angle: 20.0_f32.to_radians(),
+                outer_angle: 28.0_f32.to_radians(),
+                casts_shadows: true,
+            }),
+            points: vec![],
+        };
+        let camera = CameraController::new(REVERSED_Z);
+
+        let environment_source = environment_source::load(Path::new(ENVIRONMENT_PATH))
+            .unwrap_or_else(|e| {
+                println!("Not loading environment, using a procedural sky: {}", e);
+                EnvironmentSource::Equirect(environment_source::procedural_sky(
+                    (-lights.sun.direction.normalize()).into(),
+                ))
+            });
+        let environment = Environment::new(
+            &device,
+            &memory_properties,
+            command_pool,
+            graphic_queue,
+            &environment_source,
+            *frame_set_layout,
             render_pass,
+            SCENE_COLOR_FORMAT,
+            depth_image.format,
         );
+        environment.write_descriptors(&device, &frame_uniforms.descriptor_sets);
 
-        let command_pool = App::create_command_pool(&device, &queue_family);
-        let command_buffers = App::create_command_buffers(&device, command_pool);
+        let input = Input::load(Path::new(INPUT_CONFIG_PATH));
 
-        let sync_objects = App::create_sync_objects(&device);
+        let sync_objects = Renderer::create_sync_objects(&device);
 
-        App {
-            _entry: entry,
-            app_window,
-            instance,
-            _physical_device: physical_device,
-            device,
+        let screenshot = Screenshot::new(
+            swapchain_stuff
+                .swapchain_usage
+                .contains(vk::ImageUsageFlags::TRANSFER_SRC),
+        );
+
+        Renderer {
+            physical_device,
+            memory_properties,
             _graphic_queue: graphic_queue,
             _present_queue: present_queue,
-            surface_stuff,
             swapchain_stuff,
-            swapchain_imageviews,
-            graphics_pipeline_stuff,
-            render_pass,
-            framebuffers,
-            command_pool,
+            swapchain_imageviews: device.own_all(swapchain_imageviews, "swapchain image view"),
+            depth_image,
+            graphics_pipeline: graphics_pipeline_stuff.graphics_pipeline,
+            _pipeline_layout: graphics_pipeline_stuff.pipeline_layout,
+            material_pipelines,
+            _frame_set_layout: frame_set_layout,
+            material_set_layout,
+            _descriptor_pool: descriptor_pool,
+            frame_uniforms,
+            // Without a model the triangle is drawn instead
+            model: None,
+            scene: Scene::new(),
+            stress_test: None,
+            indirect_draws,
+            bindless_textures,
+            parallel_recorder,
+            camera,
+            lights,
+            shadows,
+            environment,
+            post_process,
+            input,
+            rendering_path,
+            render_pass: device.own(render_pass, "forward render pass"),
+            framebuffer: device.own(framebuffer, "forward framebuffer"),
+            command_pool: device.own(command_pool, "command pool"),
             command_buffers,
             sync_objects,
             current_frame: 0,
+            transient_pools: (0..MAX_FRAMES_IN_FLIGHT)
+                .map(|_| TransientPool::new())
+                .collect(),
+            screenshot,
+            dump_render_graph: false,
+            frame_stats: FrameStats::default(),
+            vsync_policy: present_settings.vsync,
+            frame_limiter: FrameLimiter::new(present_settings.target_fps),
+            swapchain_outdated: false,
+            deletion_queue: DeletionQueue::default(),
+            frame_number: 0,
+            device,
+            instance_stuff: InstanceStuff {
+                instance,
+                surface_stuff,
+            },
+            window_settings,
+            window,
+            _entry: entry,
+        }
+    }
+
+    // Replaces the model and the scene with the glTF or OBJ file at `path`, keeping the old ones
+    // if it fails to load
+    pub fn load_model(&mut self, path: &Path) -> Result<(), String> {
+        let gltf_scene = match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("obj") => {
+                obj::load(path)?.into_scene()?
+            }
+            _ => gltf::load(path)?,
+        };
+        // The new model's materials go into the shared bindless set, which frames in flight may
+        // be reading
+        if self.bindless_textures.is_some() {
+            unsafe {
+                let _ = self.device.device_wait_idle();
+            }
+        }
+        let model = Model::from_gltf(
+            &self.device,
+            &self.memory_properties,
+            *self.command_pool,
+            self._graphic_queue,
+            *self.material_set_layout,
+            &TextureFormatSupport::query(&self.instance_stuff.instance, &self.physical_device),
+            self.bindless_textures.as_mut(),
+            &gltf_scene,
+        )?;
+        // Frames in flight may still draw the old model and the stress test made from its meshes
+        if let Some(old) = self.model.replace(model) {
+            if let Some(bindless_textures) = &mut self.bindless_textures {
+                old.release_bindless(bindless_textures);
+            }
+            self.deletion_queue.defer_drop(self.frame_number, old);
+        }
+        if let Some(stress_test) = self.stress_test.take() {
+            self.deletion_queue
+                .defer_drop(self.frame_number, stress_test);
+        }
+        self.scene = Scene::from_gltf(&gltf_scene);
+
+        Ok(())
+    }
+
+    pub fn model(&self) -> Option<&Model> {
+        self.model.as_ref()
+    }
+
+    pub fn scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    // World transforms are brought up to date before every frame
+    pub fn scene_mut(&mut self) -> &mut Scene {
+        &mut self.scene
+    }
+
+    pub fn camera_mut(&mut self) -> &mut CameraController {
+        &mut self.camera
+    }
+
+    pub fn lights_mut(&mut self) -> &mut Lights {
+        &mut self.lights
+    }
+
+    pub fn shadow_settings_mut(&mut self) -> &mut ShadowSettings {
+        &mut self.shadows.settings
+    }
+
+    pub fn input(&self) -> &Input {
+        &self.input
+    }
+
+    pub fn monitors(&mut self) -> Vec<Monitor> {
+        self.window.monitors()
+    }
+
+    pub fn window_mode(&self) -> WindowMode {
+        self.window.mode()
+    }
+
+    // Remembered for the next run, along with where the window was left
+    pub fn set_window_mode(
+        &mut self,
+        mode: WindowMode,
+        monitor: usize,
+        video_mode: Option<VideoMode>,
+    ) {
+        self.window.set_mode(mode, monitor, video_mode);
+        let settings = &mut self.window_settings;
+        settings.mode = self.window.mode();
+        settings.monitor = monitor;
+        settings.video_mode = video_mode;
+        if settings.mode != WindowMode::Windowed {
+            settings.fullscreen_mode = settings.mode;
         }
+        // The framebuffer size doesn't always change, but the surface may have
+        self.swapchain_outdated = true;
     }
-    fn create_instance(entry: &ash::Entry, app_window: &AppWindow) -> ash::Instance {
+
+    fn create_instance(
+        entry: &ash::Entry,
+        window: &dyn Window,
+        colorspace_extension: bool,
+    ) -> ash::Instance {
         if VALIDATION.enabled
             && !debug::check_validation_layer_support(entry, &VALIDATION.required_validation_layers)
         {
@@ -116,19 +582,21 @@ impl App {
             p_engine_name: engine_name.as_ptr(),
             application_version: vk::make_api_version(1, 1, 0, 0),
             engine_version: vk::make_api_version(1, 1, 0, 0),
-            api_version: vk::make_api_version(1, 1, 0, 0),
+            api_version: vk::make_api_version(0, 1, 3, 0),
             ..Default::default()
         };
 
         // Get Extension names
-        let glfw = &app_window.glfw;
-        let extension_names = glfw.get_required_instance_extensions().unwrap();
-        let cstr_ext_names: Vec<_> = extension_names
-            .iter()
-            .map(|x| CString::new(x.as_str()).unwrap())
-            .collect();
+        let cstr_ext_names = window.required_instance_extensions();
         let mut pp_ext_names: Vec<_> = cstr_ext_names.iter().map(|x| x.as_ptr()).collect();
-        pp_ext_names.push(ptr::null());
+        // Needed for the HDR color spaces
+        if colorspace_extension {
+            pp_ext_names.push(ash::ext::swapchain_colorspace::NAME.as_ptr());
+        }
+        // Object names and labels, see DebugUtils
+        if VALIDATION.enabled {
+            pp_ext_names.push(ash::ext::debug_utils::NAME.as_ptr());
+        }
 
         // Get Layers names
         let cstr_layer_names: Vec<_> = VALIDATION
@@ -144,7 +612,7 @@ impl App {
             flags: vk::InstanceCreateFlags::empty(),
             p_application_info: &app_info,
             pp_enabled_extension_names: pp_ext_names.as_ptr(),
-            enabled_extension_count: extension_names.len() as u32,
+            enabled_extension_count: pp_ext_names.len() as u32,
             pp_enabled_layer_names: if VALIDATION.enabled {
                 pp_layer_names.as_ptr()
             } else {
@@ -171,8 +639,11 @@ impl App {
         instance: &ash::Instance,
         physical_device: &vk::PhysicalDevice,
         surface_stuff: &SurfaceStuff,
+        rendering_path: RenderingPath,
+        indirect_path: IndirectPath,
+        bindless: bool,
     ) -> (ash::Device, QueueFamilyIndices) {
-        let indices = App::find_queue_family(instance, physical_device, surface_stuff);
+        let indices = Renderer::find_queue_family(instance, physical_device, surface_stuff);
         let mut unique_queue_families = HashSet::new();
         unique_queue_families.insert(indices.graphics_family);
         unique_queue_families.insert(indices.present_family);
@@ -192,7 +663,41 @@ impl App {
             queue_create_infos.push(queue_create_info);
         }
 
+        let indirect = if indirect_path == IndirectPath::Direct {
+            vk::FALSE
+        } else {
+            vk::TRUE
+        };
         let physical_device_features = vk::PhysicalDeviceFeatures {
+            multi_draw_indirect: indirect,
+            draw_indirect_first_instance: indirect,
+            ..Default::default()
+        };
+
+        let vulkan_13_features = vk::PhysicalDeviceVulkan13Features {
+            s_type: vk::StructureType::PHYSICAL_DEVICE_VULKAN_1_3_FEATURES,
+            dynamic_rendering: vk::TRUE,
+            synchronization2: vk::TRUE,
+            ..Default::default()
+        };
+        let vulkan_13_next = match rendering_path {
+            RenderingPath::Dynamic => &vulkan_13_features as *const _ as *mut c_void,
+            RenderingPath::RenderPass => ptr::null_mut(),
+        };
+        let descriptor_indexing = if bindless { vk::TRUE } else { vk::FALSE };
+        let vulkan_12_features = vk::PhysicalDeviceVulkan12Features {
+            s_type: vk::StructureType::PHYSICAL_DEVICE_VULKAN_1_2_FEATURES,
+            p_next: vulkan_13_next,
+            draw_indirect_count: if indirect_path == IndirectPath::GpuCulled {
+                vk::TRUE
+            } else {
+                vk::FALSE
+            },
+            runtime_descriptor_array: descriptor_indexing,
+            shader_sampled_image_array_non_uniform_indexing: descriptor_indexing,
+            descriptor_binding_partially_bound: descriptor_indexing,
+            descriptor_binding_sampled_image_update_after_bind: descriptor_indexing,
+            descriptor_binding_update_unused_while_pending: descriptor_indexing,
             ..Default::default()
         };
 
@@ -209,7 +714,12 @@ impl App {
 
         let device_create_info = vk::DeviceCreateInfo {
             s_type: vk::StructureType::DEVICE_CREATE_INFO,
-            p_next: ptr::null(),
+            // Only devices with Vulkan 1.2 may see its feature struct
+            p_next: if vulkan_12_features.draw_indirect_count == vk::TRUE || bindless {
+                &vulkan_12_features as *const _ as *const c_void
+            } else {
+                vulkan_13_next
+            },
             flags: vk::DeviceCreateFlags::empty(),
             queue_create_info_count: queue_create_infos.len() as u32,
             p_queue_create_infos: queue_create_infos.as_ptr(),
@@ -241,28 +751,31 @@ impl App {
     fn create_surface(
         entry: &ash::Entry,
         instance: &ash::Instance,
-        window: &glfw::Window,
+        window: &dyn Window,
     ) -> SurfaceStuff {
-        let surface = Box::new(vk::SurfaceKHR::null());
-        let p_surface = Box::into_raw(surface.clone());
-
-        let surface_loader = ash::khr::surface::Instance::new(&entry, &instance);
+        let surface_loader = ash::khr::surface::Instance::new(entry, instance);
 
-        match window.create_window_surface(instance.handle(), ptr::null(), p_surface) {
-            vk::Result::SUCCESS => SurfaceStuff {
-                surface: unsafe { *p_surface },
+        match window.create_surface(entry, instance) {
+            Ok(surface) => SurfaceStuff {
+                surface,
                 surface_loader,
             },
-            _ => panic!("Failed to create surface"),
+            Err(_) => panic!("Failed to create surface"),
         }
     }
 
+    // `old_swapchain` is the one being replaced, if any; it still has to be destroyed
+    #[allow(clippy::too_many_arguments)]
     fn create_swapchain(
         instance: &ash::Instance,
         surface_stuff: &SurfaceStuff,
         physical_device: &vk::PhysicalDevice,
-        device: &ash::Device,
+        device: &Device,
         queue_family: &QueueFamilyIndices,
+        format_choice: SurfaceFormatChoice,
+        vsync_policy: VsyncPolicy,
+        window_extent: vk::Extent2D,
+        old_swapchain: vk::SwapchainKHR,
     ) -> SwapChainStuff {
         let (image_sharing_mode, queue_family_index_count, queue_family_indices) =
             if queue_family.graphics_family != queue_family.present_family {
@@ -278,13 +791,23 @@ impl App {
                 (vk::SharingMode::EXCLUSIVE, 0, vec![])
             };
 
-        let swapchain_support = App::query_swapchain_support(surface_stuff, physical_device);
+        let swapchain_support = Renderer::query_swapchain_support(surface_stuff, physical_device);
 
-        let surface_format: vk::SurfaceFormatKHR =
-            App::choose_swap_surface_format(&swapchain_support.formats);
+        let surface_format = format_choice.surface_format;
         let present_mode: vk::PresentModeKHR =
-            App::choose_swap_present_mode(&swapchain_support.present_modes);
-        let extent: vk::Extent2D = App::choose_swap_extent(&swapchain_support.capabilities);
+            Renderer::choose_swap_present_mode(&swapchain_support.present_modes, vsync_policy);
+        let extent: vk::Extent2D =
+            Renderer::choose_swap_extent(&swapchain_support.capabilities, window_extent);
+
+        // Transfer source is only needed for screenshots, so don't require it
+        let mut image_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT;
+        if swapchain_support
+            .capabilities
+            .supported_usage_flags
+            .contains(vk::ImageUsageFlags::TRANSFER_SRC)
+        {
+            image_usage |= vk::ImageUsageFlags::TRANSFER_SRC;
+        }
 
         let mut image_count: u32 = swapchain_support.capabilities.min_image_count + 1;
         if swapchain_support.capabilities.max_image_count > 0
@@ -303,7 +826,7 @@ impl App {
             image_color_space: surface_format.color_space,
             image_extent: extent,
             image_array_layers: 1,
-            image_usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            image_usage,
             image_sharing_mode,
             queue_family_index_count,
             p_queue_family_indices: queue_family_indices.as_ptr(),
@@ -311,7 +834,7 @@ impl App {
             composite_alpha: vk::CompositeAlphaFlagsKHR::OPAQUE,
             present_mode,
             clipped: vk::TRUE,
-            old_swapchain: vk::SwapchainKHR::null(),
+            old_swapchain,
             _marker: std::marker::PhantomData,
         };
 
@@ -326,20 +849,26 @@ impl App {
                 .get_swapchain_images(swapchain)
                 .expect("Failed to get swapchain images")
         };
+        for (i, &image) in swapchain_images.iter().enumerate() {
+            device
+                .debug()
+                .set_name(image, &format!("swapchain image {}", i));
+        }
 
         SwapChainStuff {
             swapchain,
             swapchain_loader,
             swapchain_images,
             swapchain_format: surface_format.format,
+            swapchain_color_space: surface_format.color_space,
+            output_encoding: format_choice.encoding,
+            present_mode,
             swapchain_extent: extent,
+            swapchain_usage: image_usage,
         }
     }
 
-    fn create_image_view(
-        device: &ash::Device,
-        swapchain_stuff: &SwapChainStuff,
-    ) -> Vec<vk::ImageView> {
+    fn create_image_view(device: &Device, swapchain_stuff: &SwapChainStuff) -> Vec<vk::ImageView> {
         let mut swapchain_imageviews = vec![];
 
         for &image in swapchain_stuff.swapchain_images.iter() {
@@ -376,39 +905,33 @@ impl App {
         swapchain_imageviews
     }
 
-    fn create_frame_buffers(
-        device: &ash::Device,
-        swapchain_imageviews: &Vec<vk::ImageView>,
-        swapchain_extent: vk::Extent2D,
+    fn create_frame_buffer(
+        device: &Device,
+        color_view: vk::ImageView,
+        depth_view: vk::ImageView,
+        extent: vk::Extent2D,
         render_pass: vk::RenderPass,
-    ) -> Vec<vk::Framebuffer> {
-        swapchain_imageviews
-            .iter()
-            .map(|x| {
-                let frame_buffer_info = vk::FramebufferCreateInfo {
-                    s_type: vk::StructureType::FRAMEBUFFER_CREATE_INFO,
-                    render_pass,
-                    attachment_count: 1,
-                    p_attachments: x,
-                    width: swapchain_extent.width,
-                    height: swapchain_extent.height,
-                    layers: 1,
-                    ..Default::default()
-                };
+    ) -> vk::Framebuffer {
+        let attachments = [color_view, depth_view];
+        let frame_buffer_info = vk::FramebufferCreateInfo {
+            s_type: vk::StructureType::FRAMEBUFFER_CREATE_INFO,
+            render_pass,
+            attachment_count: attachments.len() as u32,
+            p_attachments: attachments.as_ptr(),
+            width: extent.width,
+            height: extent.height,
+            layers: 1,
+            ..Default::default()
+        };
 
-                unsafe {
-                    device
-                        .create_framebuffer(&frame_buffer_info, None)
-                        .expect("Failed to create frambuffer")
-                }
-            })
-            .collect()
+        unsafe {
+            device
+                .create_framebuffer(&frame_buffer_info, None)
+                .expect("Failed to create frambuffer")
+        }
     }
 
-    fn create_command_pool(
-        device: &ash::Device,
-        queue_family: &QueueFamilyIndices,
-    ) -> vk::CommandPool {
+    fn create_command_pool(device: &Device, queue_family: &QueueFamilyIndices) -> vk::CommandPool {
         let pool_info = vk::CommandPoolCreateInfo {
             s_type: vk::StructureType::COMMAND_POOL_CREATE_INFO,
             flags: vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
@@ -424,7 +947,7 @@ impl App {
     }
 
     fn create_command_buffers(
-        device: &ash::Device,
+        device: &Device,
         command_pool: vk::CommandPool,
     ) -> Vec<vk::CommandBuffer> {
         let alloc_info = vk::CommandBufferAllocateInfo {
@@ -435,14 +958,21 @@ impl App {
             ..Default::default()
         };
 
-        unsafe {
+        let command_buffers = unsafe {
             device
                 .allocate_command_buffers(&alloc_info)
                 .expect("Failed to allocate command buffers")
+        };
+        for (frame, &command_buffer) in command_buffers.iter().enumerate() {
+            device
+                .debug()
+                .set_name(command_buffer, &format!("frame command buffer {}", frame));
         }
+
+        command_buffers
     }
 
-    fn create_sync_objects(device: &ash::Device) -> SyncObjects {
+    fn create_sync_objects(device: &Device) -> SyncObjects {
         let semaphore_info = vk::SemaphoreCreateInfo {
             s_type: vk::StructureType::SEMAPHORE_CREATE_INFO,
             ..Default::default()
@@ -454,34 +984,31 @@ impl App {
             ..Default::default()
         };
 
-        unsafe {
-            let mut image_available_semaphores =
-                vec![vk::Semaphore::null(); MAX_FRAMES_IN_FLIGHT as usize];
-            let mut render_finished_semaphores =
-                vec![vk::Semaphore::null(); MAX_FRAMES_IN_FLIGHT as usize];
-            let mut in_flight_fences = vec![vk::Fence::null(); MAX_FRAMES_IN_FLIGHT as usize];
-
-            for i in 0..MAX_FRAMES_IN_FLIGHT as usize {
-                image_available_semaphores[i] = device
-                    .create_semaphore(&semaphore_info, None)
-                    .expect("Failed to create semaphore");
-                render_finished_semaphores[i] = device
-                    .create_semaphore(&semaphore_info, None)
-                    .expect("Failed to create semaphore");
-                in_flight_fences[i] = device
-                    .create_fence(&fence_info, None)
+        let semaphores = |name| {
+            (0..MAX_FRAMES_IN_FLIGHT)
+                .map(|_| {
+                    let semaphore = unsafe { device.create_semaphore(&semaphore_info, None) }
+                        .expect("Failed to create semaphore");
+                    device.own(semaphore, name)
+                })
+                .collect()
+        };
+        let in_flight_fences = (0..MAX_FRAMES_IN_FLIGHT)
+            .map(|_| {
+                let fence = unsafe { device.create_fence(&fence_info, None) }
                     .expect("Failed to create fence");
-            }
+                device.own(fence, "in flight fence")
+            })
+            .collect();
 
-            SyncObjects {
-                image_available_semaphores,
-                render_finished_semaphores,
-                in_flight_fences,
-            }
+        SyncObjects {
+            image_available_semaphores: semaphores("image available semaphore"),
+            render_finished_semaphores: semaphores("render finished semaphore"),
+            in_flight_fences,
         }
     }
 
-    fn record_command_buffer(&self, command_buffer: vk::CommandBuffer, image_index: u32) {
+    fn record_command_buffer(&mut self, command_buffer: vk::CommandBuffer, image_index: u32) {
         let begin_info = vk::CommandBufferBeginInfo {
             s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
             ..Default::default()
@@ -493,61 +1020,625 @@ impl App {
                 .expect("Failed to begin recording command buffer")
         };
 
-        let clear_color = vk::ClearValue {
-            color: vk::ClearColorValue {
-                float32: [0.0_f32, 0.0_f32, 0.0_f32, 1.0_f32],
+        let dump_render_graph = std::mem::take(&mut self.dump_render_graph);
+        let screenshot_buffer = if self.screenshot.is_requested() {
+            self.screenshot.prepare_capture(
+                &self.device,
+                &self.memory_properties,
+                self.current_frame,
+                &self.swapchain_stuff,
+            )
+        } else {
+            None
+        };
+        let extent = self.swapchain_stuff.swapchain_extent;
+        let aspect = extent.width as f32 / extent.height.max(1) as f32;
+        let mut uniforms = self.camera.camera.frame_uniforms(aspect);
+        self.lights.write_uniforms(&mut uniforms);
+        self.environment.write_uniforms(&mut uniforms);
+        self.shadows
+            .update(&self.camera.camera, aspect, &self.lights, &mut uniforms);
+        descriptor::update_frame_uniforms(&self.frame_uniforms, self.current_frame, &uniforms);
+
+        // Shadow casters may be outside the view, so only the forward pass uses the culled list
+        let all_draws = self.scene.draw_nodes();
+        let mut draws = all_draws.clone();
+        let frustum = Frustum::from_matrix(&Matrix4::from(uniforms.view_proj));
+        if let Some(model) = &self.model {
+            draws.retain(|draw| frustum.is_visible(model.mesh_bounds(draw.mesh), draw.transform));
+            self.frame_stats.objects_drawn = draws.len();
+            self.frame_stats.objects_culled = all_draws.len() - draws.len();
+            // The GPU culls its own copy of every object
+            let indirect_draws = match self.indirect_draws.path() {
+                IndirectPath::Direct => None,
+                IndirectPath::CpuBuilt => Some(&draws),
+                IndirectPath::GpuCulled => Some(&all_draws),
+            };
+            if let Some(indirect_draws) = indirect_draws {
+                self.indirect_draws.prepare(
+                    &self.device,
+                    &self.memory_properties,
+                    self.current_frame,
+                    model,
+                    indirect_draws,
+                );
+            }
+        }
+        self.frame_stats.instances_drawn = 0;
+        if let Some(StressTest { batch, .. }) = &mut self.stress_test {
+            batch.upload(&self.device, &self.memory_properties, self.current_frame);
+            self.frame_stats.instances_drawn = batch.instances.len();
+        }
+        let (all_draws, draws) = (&all_draws, &draws);
+        if let Some(recorder) = &self.parallel_recorder {
+            recorder.begin_frame(&self.device, self.current_frame);
+        }
+        let mut transient_pool = std::mem::take(&mut self.transient_pools[self.current_frame]);
+
+        let app = &*self;
+        let mut graph = RenderGraph::new();
+        let backbuffer = graph.import_image(
+            "swapchain",
+            app.swapchain_stuff.swapchain_images[image_index as usize],
+            *app.swapchain_imageviews[image_index as usize],
+            ImageDesc {
+                format: app.swapchain_stuff.swapchain_format,
+                extent: app.swapchain_stuff.swapchain_extent,
+            },
+            vk::ImageLayout::UNDEFINED,
+            Some(Access::Present),
+        );
+        let depth = graph.import_image(
+            "depth",
+            *app.depth_image.image,
+            *app.depth_image.view,
+            ImageDesc {
+                format: app.depth_image.format,
+                extent: app.depth_image.extent,
             },
+            vk::ImageLayout::UNDEFINED,
+            None,
+        );
+
+        let scene_color = app.post_process.import_scene_color(&mut graph);
+
+        let mut forward_uses = vec![
+            (scene_color, Access::ColorAttachment),
+            (depth, Access::DepthAttachment),
+        ];
+        if let Some(model) = &app.model {
+            let shadow_atlas = graph.import_image(
+                "shadow atlas",
+                *app.shadows.atlas.image,
+                *app.shadows.atlas.view,
+                ImageDesc {
+                    format: app.shadows.atlas.format,
+                    extent: app.shadows.atlas.extent,
+                },
+                vk::ImageLayout::UNDEFINED,
+                None,
+            );
+            graph.add_pass(
+                "shadows",
+                &[(shadow_atlas, Access::DepthAttachment)],
+                move |ctx| {
+                    app.shadows
+                        .record(ctx.device, ctx.command_buffer, model, all_draws)
+                },
+            );
+            forward_uses.push((shadow_atlas, Access::ShaderRead));
+            if let Some([commands, counts]) =
+                app.indirect_draws
+                    .add_cull_passes(&mut graph, app.current_frame, &frustum)
+            {
+                forward_uses.push((commands, Access::IndirectRead));
+                forward_uses.push((counts, Access::IndirectRead));
+            }
+        }
+
+        graph.add_pass("forward", &forward_uses, move |ctx| {
+            app.record_forward_pass(ctx, scene_color, depth, draws)
+        });
+        app.post_process.add_passes(
+            &mut graph,
+            app.current_frame,
+            scene_color,
+            backbuffer,
+            image_index as usize,
+        );
+
+        if let Some(buffer) = screenshot_buffer {
+            let readback =
+                graph.import_buffer("screenshot readback", buffer, Some(Access::HostRead));
+            graph.add_pass(
+                "screenshot",
+                &[
+                    (backbuffer, Access::TransferRead),
+                    (readback, Access::TransferWrite),
+                ],
+                move |ctx| {
+                    screenshot::cmd_copy_image_to_buffer(
+                        ctx.device,
+                        ctx.command_buffer,
+                        ctx.image(backbuffer),
+                        ctx.extent(backbuffer),
+                        ctx.buffer(readback),
+                    )
+                },
+            );
+        }
+
+        let graph = graph.compile(&app.device, &app.memory_properties, &mut transient_pool);
+        if dump_render_graph {
+            print!("{}", graph.dump());
+            match std::fs::write(RENDER_GRAPH_DUMP_PATH, graph.to_dot()) {
+                Ok(()) => println!("Wrote render graph to {}", RENDER_GRAPH_DUMP_PATH),
+                Err(e) => println!("Failed to write {}: {}", RENDER_GRAPH_DUMP_PATH, e),
+            }
+        }
+        graph.execute(
+            &app.device,
+            command_buffer,
+            &transient_pool,
+            app.rendering_path == RenderingPath::Dynamic,
+        );
+
+        self.transient_pools[self.current_frame] = transient_pool;
+
+        unsafe {
+            self.device
+                .end_command_buffer(command_buffer)
+                .expect("Failed to record command buffer");
         };
-        let renderpass_info = vk::RenderPassBeginInfo {
-            s_type: vk::StructureType::RENDER_PASS_BEGIN_INFO,
-            render_pass: self.render_pass,
-            framebuffer: self.framebuffers[image_index as usize],
-            render_area: vk::Rect2D {
-                offset: vk::Offset2D { x: 0, y: 0 },
-                extent: self.swapchain_stuff.swapchain_extent,
+    }
+
+    fn record_forward_pass(
+        &self,
+        ctx: &PassContext,
+        target: ResourceId,
+        depth: ResourceId,
+        draws: &[DrawNode],
+    ) {
+        let command_buffer = ctx.command_buffer;
+        let extent = ctx.extent(target);
+        let clear_values = [
+            vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: [0.0_f32, 0.0_f32, 0.0_f32, 1.0_f32],
+                },
             },
-            clear_value_count: 1,
-            p_clear_values: &clear_color,
-            ..Default::default()
+            vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    // The far plane, which reversed-Z puts at 0
+                    depth: if REVERSED_Z { 0.0_f32 } else { 1.0_f32 },
+                    stencil: 0,
+                },
+            },
+        ];
+
+        // A render pass instance holds either inline commands or secondary command buffers
+        let parallel = self.model.as_ref().zip(self.parallel_recorder.as_ref());
+        let (subpass_contents, rendering_flags) = match parallel {
+            Some(_) => (
+                vk::SubpassContents::SECONDARY_COMMAND_BUFFERS,
+                vk::RenderingFlags::CONTENTS_SECONDARY_COMMAND_BUFFERS,
+            ),
+            None => (vk::SubpassContents::INLINE, vk::RenderingFlags::empty()),
         };
+        match self.rendering_path {
+            RenderingPath::RenderPass => {
+                let renderpass_info = vk::RenderPassBeginInfo {
+                    s_type: vk::StructureType::RENDER_PASS_BEGIN_INFO,
+                    render_pass: *self.render_pass,
+                    framebuffer: *self.framebuffer,
+                    render_area: vk::Rect2D {
+                        offset: vk::Offset2D { x: 0, y: 0 },
+                        extent,
+                    },
+                    clear_value_count: clear_values.len() as u32,
+                    p_clear_values: clear_values.as_ptr(),
+                    ..Default::default()
+                };
 
-        unsafe {
-            self.device.cmd_begin_render_pass(
+                unsafe {
+                    self.device.cmd_begin_render_pass(
+                        command_buffer,
+                        &renderpass_info,
+                        subpass_contents,
+                    )
+                };
+            }
+            RenderingPath::Dynamic => dynamic_rendering::cmd_begin_rendering(
+                &self.device,
                 command_buffer,
-                &renderpass_info,
-                vk::SubpassContents::INLINE,
-            );
+                ctx.image_view(target),
+                ctx.image_view(depth),
+                extent,
+                &clear_values,
+                rendering_flags,
+            ),
+        }
 
-            self.device.cmd_bind_pipeline(
-                command_buffer,
-                vk::PipelineBindPoint::GRAPHICS,
-                self.graphics_pipeline_stuff.graphics_pipeline,
-            );
+        match parallel {
+            Some((model, recorder)) => {
+                // Direct opaque draws are spread over the workers; the rest uses state that
+                // isn't shared with them and is recorded here
+                let target = self.secondary_target();
+                let mut secondaries = vec![];
+                if self.indirect_draws.path() == IndirectPath::Direct {
+                    secondaries = self.record_opaque_chunks(
+                        recorder,
+                        self.current_frame,
+                        model,
+                        target,
+                        extent,
+                        draws,
+                    );
+                }
+                secondaries.push(recorder.record_here(
+                    &self.device,
+                    self.current_frame,
+                    target,
+                    |command_buffer| {
+                        Renderer::cmd_set_viewport_and_scissor(
+                            &self.device,
+                            command_buffer,
+                            extent,
+                        );
+                        model.bind(&self.device, command_buffer);
+                        if self.indirect_draws.path() != IndirectPath::Direct {
+                            self.record_opaque(command_buffer, model, draws);
+                        }
+                        self.record_forward_rest(command_buffer, model, extent, draws);
+                    },
+                ));
+                unsafe {
+                    self.device
+                        .cmd_execute_commands(command_buffer, &secondaries)
+                };
+            }
+            None => {
+                Renderer::cmd_set_viewport_and_scissor(&self.device, command_buffer, extent);
+                match &self.model {
+                    Some(model) => {
+                        model.bind(&self.device, command_buffer);
+                        self.record_opaque(command_buffer, model, draws);
+                        self.record_forward_rest(command_buffer, model, extent, draws);
+                    }
+                    None => unsafe {
+                        self.device.cmd_bind_pipeline(
+                            command_buffer,
+                            vk::PipelineBindPoint::GRAPHICS,
+                            *self.graphics_pipeline,
+                        );
+                        self.device.cmd_draw(command_buffer, 3, 1, 0, 0);
+                    },
+                }
+            }
+        }
+
+        unsafe {
+            match self.rendering_path {
+                RenderingPath::RenderPass => self.device.cmd_end_render_pass(command_buffer),
+                RenderingPath::Dynamic => self.device.cmd_end_rendering(command_buffer),
+            }
         };
+    }
 
+    // Dynamic state, which secondary command buffers don't inherit
+    fn cmd_set_viewport_and_scissor(
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        extent: vk::Extent2D,
+    ) {
         let viewport = vk::Viewport {
             x: 0.0_f32,
             y: 0.0_f32,
-            width: self.swapchain_stuff.swapchain_extent.width as f32,
-            height: self.swapchain_stuff.swapchain_extent.height as f32,
+            width: extent.width as f32,
+            height: extent.height as f32,
             min_depth: 0.0_f32,
             max_depth: 1.0_f32,
         };
 
         let scissor = vk::Rect2D {
             offset: vk::Offset2D { x: 0, y: 0 },
-            extent: self.swapchain_stuff.swapchain_extent,
+            extent,
         };
 
         unsafe {
-            self.device.cmd_set_viewport(command_buffer, 0, &[viewport]);
-            self.device.cmd_set_scissor(command_buffer, 0, &[scissor]);
-            self.device.cmd_draw(command_buffer, 3, 1, 0, 0);
-            self.device.cmd_end_render_pass(command_buffer);
-            self.device
-                .end_command_buffer(command_buffer)
-                .expect("Failed to record command buffer");
+            device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+            device.cmd_set_scissor(command_buffer, 0, &[scissor]);
+        };
+    }
+
+    // The forward pass as the secondary command buffers see it
+    fn secondary_target(&self) -> SecondaryTarget {
+        match self.rendering_path {
+            RenderingPath::RenderPass => SecondaryTarget::RenderPass {
+                render_pass: *self.render_pass,
+                framebuffer: *self.framebuffer,
+            },
+            RenderingPath::Dynamic => SecondaryTarget::Dynamic {
+                color_format: SCENE_COLOR_FORMAT,
+                depth_format: self.depth_image.format,
+            },
+        }
+    }
+
+    // Opaque scene geometry, drawn directly or indirectly. `model` has to be bound.
+    fn record_opaque(&self, command_buffer: vk::CommandBuffer, model: &Model, draws: &[DrawNode]) {
+        let frame_set = self.frame_uniforms.descriptor_sets[self.current_frame];
+        self.device.debug().begin_label(command_buffer, "opaque");
+        if self.indirect_draws.path() == IndirectPath::Direct {
+            model.draw(
+                &self.device,
+                command_buffer,
+                &self.material_pipelines,
+                frame_set,
+                draws,
+                self.camera.camera.position,
+                false,
+            );
+        } else {
+            self.indirect_draws.draw(
+                &self.device,
+                command_buffer,
+                &self.material_pipelines,
+                frame_set,
+                model,
+                self.current_frame,
+            );
+        }
+        self.device.debug().end_label(command_buffer);
+    }
+
+    // The direct opaque draws split into a chunk per worker, each recorded on its own. Only
+    // what's shared with the workers is captured, the rest of the app isn't Sync.
+    fn record_opaque_chunks(
+        &self,
+        recorder: &ParallelRecorder,
+        frame: usize,
+        model: &Model,
+        target: SecondaryTarget,
+        extent: vk::Extent2D,
+        draws: &[DrawNode],
+    ) -> Vec<vk::CommandBuffer> {
+        let chunk_size = draws.len().div_ceil(recorder.threads()).max(1);
+        let chunks: Vec<_> = draws.chunks(chunk_size).collect();
+        let device = &self.device;
+        let pipelines = &self.material_pipelines;
+        let frame_set = self.frame_uniforms.descriptor_sets[frame];
+        let eye = self.camera.camera.position;
+
+        recorder.record_chunks(
+            device,
+            frame,
+            target,
+            chunks.len(),
+            &|chunk, command_buffer| {
+                Renderer::cmd_set_viewport_and_scissor(device, command_buffer, extent);
+                model.bind(device, command_buffer);
+                model.draw(
+                    device,
+                    command_buffer,
+                    pipelines,
+                    frame_set,
+                    chunks[chunk],
+                    eye,
+                    false,
+                );
+            },
+        )
+    }
+
+    // Everything the forward pass draws after the opaque scene geometry. The sky fills what
+    // opaque geometry left at the far plane, and blended geometry has to go over it.
+    fn record_forward_rest(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        model: &Model,
+        extent: vk::Extent2D,
+        draws: &[DrawNode],
+    ) {
+        let frame_set = self.frame_uniforms.descriptor_sets[self.current_frame];
+        let debug = self.device.debug();
+        if let Some(StressTest { batch, .. }) = &self.stress_test {
+            debug.begin_label(command_buffer, "instanced opaque");
+            model.draw_instanced(
+                &self.device,
+                command_buffer,
+                &self.material_pipelines,
+                frame_set,
+                batch,
+                self.current_frame,
+                false,
+            );
+            debug.end_label(command_buffer);
+        }
+        debug.begin_label(command_buffer, "skybox");
+        self.environment
+            .record_skybox(&self.device, command_buffer, frame_set);
+        debug.end_label(command_buffer);
+        // Back to front after everything opaque
+        debug.begin_label(command_buffer, "blended");
+        model.draw(
+            &self.device,
+            command_buffer,
+            &self.material_pipelines,
+            frame_set,
+            draws,
+            self.camera.camera.position,
+            true,
+        );
+        if let Some(StressTest { batch, .. }) = &self.stress_test {
+            model.draw_instanced(
+                &self.device,
+                command_buffer,
+                &self.material_pipelines,
+                frame_set,
+                batch,
+                self.current_frame,
+                true,
+            );
+        }
+        debug.end_label(command_buffer);
+        self.shadows
+            .record_debug_overlay(&self.device, command_buffer, frame_set, extent);
+    }
+
+    // Times recording the direct opaque draws into secondary command buffers with 1, 2, 4...
+    // workers up to one per core. The scene's nodes are repeated to make the work measurable;
+    // nothing recorded is submitted.
+    fn run_recording_benchmark(&self) {
+        let Some(model) = &self.model else {
+            println!("Recording benchmark: no model loaded");
+            return;
         };
+        let scene_draws = self.scene.draw_nodes();
+        if scene_draws.is_empty() {
+            println!("Recording benchmark: the scene draws nothing");
+            return;
+        }
+        let draws: Vec<_> = scene_draws
+            .iter()
+            .cycle()
+            .take(scene_draws.len().max(RECORDING_BENCHMARK_DRAWS))
+            .cloned()
+            .collect();
+
+        let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+        let mut thread_counts: Vec<_> = std::iter::successors(Some(1_usize), |&n| Some(n * 2))
+            .take_while(|&n| n < cores)
+            .collect();
+        thread_counts.push(cores);
+
+        // The frame's pools are only borrowed while nothing is in flight
+        unsafe {
+            let _ = self.device.device_wait_idle();
+        }
+        let queue_family_index = Renderer::find_queue_family(
+            &self.instance_stuff.instance,
+            &self.physical_device,
+            &self.instance_stuff.surface_stuff,
+        )
+        .graphics_family
+        .unwrap();
+        let target = self.secondary_target();
+        let extent = self.swapchain_stuff.swapchain_extent;
+        println!(
+            "Recording benchmark: {} draws, best of {} runs",
+            draws.len(),
+            RECORDING_BENCHMARK_RUNS
+        );
+        let mut single_thread = None;
+        for threads in thread_counts {
+            let recorder = ParallelRecorder::new(&self.device, queue_family_index, threads);
+            let mut best = f64::MAX;
+            for _ in 0..RECORDING_BENCHMARK_RUNS {
+                recorder.begin_frame(&self.device, 0);
+                let start = Instant::now();
+                self.record_opaque_chunks(&recorder, 0, model, target, extent, &draws);
+                best = best.min(start.elapsed().as_secs_f64());
+            }
+
+            let single_thread = *single_thread.get_or_insert(best);
+            println!(
+                "  {:>2} threads: {:>8.3} ms, {:.2}x",
+                threads,
+                best * 1000.0_f64,
+                single_thread / best
+            );
+        }
+    }
+
+    // A grid of copies of the first mesh over the scene's footprint, tinted across the grid, to
+    // measure instanced drawing
+    fn create_stress_test(model: &Model, scene: &Scene) -> Option<StressTest> {
+        if model.mesh_count() == 0 {
+            return None;
+        }
+        let (min, max) = scene.bounds(|mesh| *model.mesh_bounds(mesh))?;
+        let side = (STRESS_TEST_INSTANCES as f32).cbrt().ceil() as usize;
+        let spacing = (max.x - min.x).max(max.z - min.z) / side as f32;
+        let bounds = model.mesh_bounds(0);
+        let size = (0..3)
+            .map(|axis| bounds.max[axis] - bounds.min[axis])
+            .fold(0.001_f32, f32::max);
+        let scale = spacing * 0.5_f32 / size;
+        let corner =
+            Vector3::new(min.x, min.y, min.z) + Vector3::new(0.5_f32, 1.0_f32, 0.5_f32) * spacing;
+
+        let bases: Vec<Matrix4<f32>> = (0..STRESS_TEST_INSTANCES)
+            .map(|i| {
+                let cell = [i % side, i / side % side, i / (side * side)];
+                let translation =
+                    corner + Vector3::new(cell[0] as f32, cell[1] as f32, cell[2] as f32) * spacing;
+                Matrix4::from_translation(translation)
+                    * Matrix4::from_scale(scale)
+                    * Matrix4::from_translation(-Vector3::from(bounds.center))
+            })
+            .collect();
+        let instances = bases
+            .iter()
+            .enumerate()
+            .map(|(i, &base)| {
+                let cell = [i % side, i / side % side, i / (side * side)];
+                let [x, y, z] = cell.map(|c| c as f32 / (side - 1).max(1) as f32);
+                InstanceData {
+                    model: base.into(),
+                    color: [
+                        0.25_f32 + 0.75_f32 * x,
+                        0.25_f32 + 0.75_f32 * y,
+                        0.25_f32 + 0.75_f32 * z,
+                        1.0_f32,
+                    ],
+                }
+            })
+            .collect();
+
+        Some(StressTest {
+            batch: InstanceBatch::new(0, None, instances),
+            angles: vec![0.0_f32; bases.len()],
+            bases,
+        })
+    }
+
+    fn update_scene(&mut self, dt: f32) {
+        self.scene.update_world_transforms();
+
+        // Every copy spins in place at one of a few rates, so all of them are rewritten each
+        // frame as a real workload would
+        if let (Some(stress_test), Some(model)) = (&mut self.stress_test, &self.model) {
+            let StressTest {
+                batch,
+                bases,
+                angles,
+            } = stress_test;
+            let center = Vector3::from(model.mesh_bounds(batch.mesh).center);
+            for (i, ((instance, base), angle)) in batch
+                .instances
+                .iter_mut()
+                .zip(bases.iter())
+                .zip(angles.iter_mut())
+                .enumerate()
+            {
+                let rate = 0.5_f32 + (i % 7) as f32 * 0.25_f32;
+                *angle = (*angle + dt * rate) % TAU;
+                // About the mesh's center, in its own space
+                let spin = Matrix4::from_translation(center)
+                    * Matrix4::from_angle_y(Rad(*angle))
+                    * Matrix4::from_translation(-center);
+                instance.model = (base * spin).into();
+            }
+        }
+    }
+
+    fn depth_compare_op() -> vk::CompareOp {
+        if REVERSED_Z {
+            vk::CompareOp::GREATER
+        } else {
+            vk::CompareOp::LESS
+        }
     }
 
     fn pick_physical_device(
@@ -566,7 +1657,7 @@ impl App {
 
         let mut result = None;
         for &physical_device in physical_devices.iter() {
-            if App::is_physical_device_suitable(instance, &physical_device, surface_stuff) {
+            if Renderer::is_physical_device_suitable(instance, &physical_device, surface_stuff) {
                 result = Some(physical_device);
             }
         }
@@ -582,10 +1673,12 @@ impl App {
         physical_device: &vk::PhysicalDevice,
         surface_stuff: &SurfaceStuff,
     ) -> bool {
-        let indices = App::find_queue_family(instance, physical_device, surface_stuff);
-        let extensions_supported = App::check_device_extension_support(instance, physical_device);
+        let indices = Renderer::find_queue_family(instance, physical_device, surface_stuff);
+        let extensions_supported =
+            Renderer::check_device_extension_support(instance, physical_device);
         let swapchain_adequate = if extensions_supported {
-            let swapchain_support = App::query_swapchain_support(surface_stuff, physical_device);
+            let swapchain_support =
+                Renderer::query_swapchain_support(surface_stuff, physical_device);
             !swapchain_support.formats.is_empty() && !swapchain_support.present_modes.is_empty()
         } else {
             false
@@ -594,6 +1687,22 @@ impl App {
         indices.is_complete() && extensions_supported && swapchain_adequate
     }
 
+    fn choose_rendering_path(
+        instance: &ash::Instance,
+        physical_device: &vk::PhysicalDevice,
+    ) -> RenderingPath {
+        let path = if PREFER_DYNAMIC_RENDERING
+            && dynamic_rendering::check_dynamic_rendering_support(instance, physical_device)
+        {
+            RenderingPath::Dynamic
+        } else {
+            RenderingPath::RenderPass
+        };
+        println!("Rendering path: {:?}", path);
+
+        path
+    }
+
     fn check_device_extension_support(
         instance: &ash::Instance,
         physical_device: &vk::PhysicalDevice,
@@ -652,43 +1761,38 @@ impl App {
         }
     }
 
-    fn choose_swap_surface_format(
-        available_formats: &Vec<vk::SurfaceFormatKHR>,
-    ) -> vk::SurfaceFormatKHR {
-        for available_format in available_formats.iter() {
-            if available_format.format == vk::Format::R8G8B8A8_SRGB
-                && available_format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
-            {
-                return available_format.clone();
-            }
-        }
-
-        available_formats.first().unwrap().clone()
-    }
-
+    // FIFO is the one mode every surface supports
     fn choose_swap_present_mode(
-        available_present_modes: &Vec<vk::PresentModeKHR>,
+        available_present_modes: &[vk::PresentModeKHR],
+        vsync_policy: VsyncPolicy,
     ) -> vk::PresentModeKHR {
-        for &mode in available_present_modes.iter() {
-            if mode == vk::PresentModeKHR::MAILBOX {
-                return mode;
-            }
+        let wanted = vsync_policy.present_mode();
+        if available_present_modes.contains(&wanted) {
+            return wanted;
         }
-        return vk::PresentModeKHR::FIFO;
+        println!(
+            "Present mode {:?} isn't supported by the surface; using FIFO",
+            wanted
+        );
+        vk::PresentModeKHR::FIFO
     }
 
-    fn choose_swap_extent(capabilities: &vk::SurfaceCapabilitiesKHR) -> vk::Extent2D {
+    // The surface decides, unless it leaves the size to the swapchain
+    fn choose_swap_extent(
+        capabilities: &vk::SurfaceCapabilitiesKHR,
+        window_extent: vk::Extent2D,
+    ) -> vk::Extent2D {
         if capabilities.current_extent.width != u32::max_value() {
             capabilities.current_extent
         } else {
             vk::Extent2D {
                 width: u32::clamp(
-                    WINDOW_WIDTH,
+                    window_extent.width,
                     capabilities.min_image_extent.width,
                     capabilities.max_image_extent.width,
                 ),
                 height: u32::clamp(
-                    WINDOW_HEIGHT,
+                    window_extent.height,
                     capabilities.min_image_extent.height,
                     capabilities.max_image_extent.height,
                 ),
@@ -696,6 +1800,98 @@ impl App {
         }
     }
 
+    fn framebuffer_extent(window: &dyn Window) -> vk::Extent2D {
+        let (width, height) = window.framebuffer_size();
+        vk::Extent2D { width, height }
+    }
+
+    // Replaces the swapchain and everything sized after it, keeping the surface format. A
+    // minimized window has nothing to present to, so this waits until it's restored.
+    fn recreate_swapchain(&mut self) {
+        let mut window_extent = Renderer::framebuffer_extent(&*self.window);
+        while window_extent.width == 0 || window_extent.height == 0 {
+            self.window.wait_events();
+            window_extent = Renderer::framebuffer_extent(&*self.window);
+        }
+
+        let queue_family = Renderer::find_queue_family(
+            &self.instance_stuff.instance,
+            &self.physical_device,
+            &self.instance_stuff.surface_stuff,
+        );
+        let format_choice = SurfaceFormatChoice {
+            surface_format: vk::SurfaceFormatKHR {
+                format: self.swapchain_stuff.swapchain_format,
+                color_space: self.swapchain_stuff.swapchain_color_space,
+            },
+            encoding: self.swapchain_stuff.output_encoding,
+        };
+        let swapchain_stuff = Renderer::create_swapchain(
+            &self.instance_stuff.instance,
+            &self.instance_stuff.surface_stuff,
+            &self.physical_device,
+            &self.device,
+            &queue_family,
+            format_choice,
+            self.vsync_policy,
+            window_extent,
+            self.swapchain_stuff.swapchain,
+        );
+        let old_swapchain_stuff = std::mem::replace(&mut self.swapchain_stuff, swapchain_stuff);
+        let swapchain_imageviews = Renderer::create_image_view(&self.device, &self.swapchain_stuff);
+
+        let extent = self.swapchain_stuff.swapchain_extent;
+        let depth_format = self.depth_image.format;
+        let depth_image = texture::create_image(
+            &self.device,
+            &self.memory_properties,
+            extent,
+            1,
+            depth_format,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            "depth",
+        );
+        let old_targets = self.post_process.resize(
+            &self.device,
+            &self.memory_properties,
+            extent,
+            &swapchain_imageviews,
+        );
+        let framebuffer = match *self.render_pass {
+            render_pass if render_pass == vk::RenderPass::null() => vk::Framebuffer::null(),
+            render_pass => Renderer::create_frame_buffer(
+                &self.device,
+                *self.post_process.scene_color().view,
+                *depth_image.view,
+                extent,
+                render_pass,
+            ),
+        };
+
+        // Everything sized after the old swapchain is retired with it, views before the swapchain
+        let retired = (
+            std::mem::replace(
+                &mut self.framebuffer,
+                self.device.own(framebuffer, "forward framebuffer"),
+            ),
+            old_targets,
+            std::mem::replace(&mut self.depth_image, depth_image),
+            std::mem::replace(
+                &mut self.swapchain_imageviews,
+                self.device
+                    .own_all(swapchain_imageviews, "swapchain image view"),
+            ),
+        );
+        self.deletion_queue.defer_drop(self.frame_number, retired);
+        self.deletion_queue
+            .defer(self.frame_number, move |_| unsafe {
+                old_swapchain_stuff
+                    .swapchain_loader
+                    .destroy_swapchain(old_swapchain_stuff.swapchain, None);
+            });
+        self.swapchain_outdated = false;
+    }
+
     fn find_queue_family(
         instance: &ash::Instance,
         physical_device: &vk::PhysicalDevice,
@@ -745,24 +1941,41 @@ impl App {
     fn draw_frame(&mut self) {
         unsafe {
             let _ = self.device.wait_for_fences(
-                &[self.sync_objects.in_flight_fences[self.current_frame]],
+                &[*self.sync_objects.in_flight_fences[self.current_frame]],
                 true,
                 u64::max_value(),
             );
 
-            let _ = self
-                .device
-                .reset_fences(&[self.sync_objects.in_flight_fences[self.current_frame]]);
+            self.screenshot.collect(&self.device, self.current_frame);
+            // The frame that last used this one's slot is done, and so is every one before it
+            self.deletion_queue.collect(
+                &self.device,
+                (self.frame_number + 1).saturating_sub(MAX_FRAMES_IN_FLIGHT as u64),
+            );
 
-            let Ok((image_index, _)) = self.swapchain_stuff.swapchain_loader.acquire_next_image(
+            // Suboptimal images can still be presented; the swapchain is replaced afterwards
+            let image_index = match self.swapchain_stuff.swapchain_loader.acquire_next_image(
                 self.swapchain_stuff.swapchain,
                 u64::max_value(),
-                self.sync_objects.image_available_semaphores[self.current_frame],
+                *self.sync_objects.image_available_semaphores[self.current_frame],
                 vk::Fence::null(),
-            ) else {
-                panic!("failed to acquire next images")
+            ) {
+                Ok((image_index, suboptimal)) => {
+                    self.swapchain_outdated |= suboptimal;
+                    image_index
+                }
+                Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                    self.swapchain_outdated = true;
+                    return;
+                }
+                Err(e) => panic!("Failed to acquire next image: {:?}", e),
             };
 
+            // Only once something will be submitted, or the next wait would never return
+            let _ = self
+                .device
+                .reset_fences(&[*self.sync_objects.in_flight_fences[self.current_frame]]);
+
             let _ = self.device.reset_command_buffer(
                 self.command_buffers[self.current_frame],
                 CommandBufferResetFlags::empty(),
@@ -774,9 +1987,9 @@ impl App {
             );
 
             let wait_semaphores =
-                [self.sync_objects.image_available_semaphores[self.current_frame]];
+                [*self.sync_objects.image_available_semaphores[self.current_frame]];
             let signal_semaphores =
-                [self.sync_objects.render_finished_semaphores[self.current_frame]];
+                [*self.sync_objects.render_finished_semaphores[self.current_frame]];
             let wait_stages = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
             let submit_info = vk::SubmitInfo {
                 s_type: vk::StructureType::SUBMIT_INFO,
@@ -790,13 +2003,16 @@ impl App {
                 ..Default::default()
             };
 
+            let debug = self.device.debug();
+            debug.begin_queue_label(self._graphic_queue, &format!("frame {}", self.frame_number));
             self.device
                 .queue_submit(
                     self._graphic_queue,
                     &[submit_info],
-                    self.sync_objects.in_flight_fences[self.current_frame],
+                    *self.sync_objects.in_flight_fences[self.current_frame],
                 )
                 .expect("Failed to submit draw command buffer");
+            debug.end_queue_label(self._graphic_queue);
 
             let swapchains = [self.swapchain_stuff.swapchain];
             let present_info = vk::PresentInfoKHR {
@@ -809,95 +2025,207 @@ impl App {
                 ..Default::default()
             };
 
-            self.swapchain_stuff
+            match self
+                .swapchain_stuff
                 .swapchain_loader
                 .queue_present(self._present_queue, &present_info)
-                .expect("Failed to present");
+            {
+                Ok(false) => {}
+                Ok(true) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => self.swapchain_outdated = true,
+                Err(e) => panic!("Failed to present: {:?}", e),
+            }
         };
 
         self.current_frame = (self.current_frame + 1) % MAX_FRAMES_IN_FLIGHT as usize;
+        self.frame_number += 1;
     }
 
-    fn init_window() -> AppWindow {
-        let mut glfw = glfw::init(glfw::fail_on_errors).unwrap();
-
-        glfw.window_hint(WindowHint::ClientApi(ClientApiHint::NoApi));
-
-        let (mut window, events) = glfw
-            .create_window(
-                WINDOW_WIDTH,
-                WINDOW_HEIGHT,
-                "Hello this is window",
-                glfw::WindowMode::Windowed,
-            )
-            .expect("Failed to create GLFW window.");
+    fn handle_window_event(&mut self, event: &WindowEvent) {
+        if let WindowEvent::FramebufferSize(..) = event {
+            self.swapchain_outdated = true;
+        }
+        self.input.handle_event(event);
+    }
 
-        window.set_key_polling(true);
+    fn handle_actions(&mut self) {
+        if self.input.pressed("quit") {
+            self.window.set_should_close(true);
+        }
+        if self.input.pressed("dump_render_graph") {
+            self.dump_render_graph = true;
+        }
+        if self.input.pressed("recording_benchmark") {
+            self.run_recording_benchmark();
+        }
+        if self.input.pressed("screenshot") {
+            self.screenshot.request();
+        }
+        if self.input.pressed("vsync") {
+            self.vsync_policy = self.vsync_policy.next();
+            self.swapchain_outdated = true;
+            println!("Vsync: {:?}", self.vsync_policy);
+        }
+        if self.input.pressed("frame_limit") {
+            self.frame_limiter.cycle_target();
+            match self.frame_limiter.target_fps() {
+                Some(fps) => println!("Frame limit: {} fps", fps),
+                None => println!("Frame limit: off"),
+            }
+        }
+        if self.input.pressed("stress_test") {
+            match self.stress_test.take() {
+                Some(stress_test) => {
+                    // Earlier frames may still be reading its buffers
+                    self.deletion_queue
+                        .defer_drop(self.frame_number, stress_test);
+                    println!("Stress test: off");
+                }
+                None => {
+                    self.stress_test = self
+                        .model
+                        .as_ref()
+                        .and_then(|model| Renderer::create_stress_test(model, &self.scene));
+                    match &self.stress_test {
+                        Some(StressTest { batch, .. }) => {
+                            println!("Stress test: {} instances", batch.instances.len())
+                        }
+                        None => println!("Stress test: no mesh in the scene to copy"),
+                    }
+                }
+            }
+        }
+        // Alt+Enter and Alt+M by default
+        if self.input.held("window_modifier") {
+            let settings = self.window_settings;
+            if self.input.pressed("fullscreen") {
+                let mode = match self.window.mode() {
+                    WindowMode::Windowed => settings.fullscreen_mode,
+                    _ => WindowMode::Windowed,
+                };
+                self.set_window_mode(mode, settings.monitor, settings.video_mode);
+                println!("Window mode: {:?}", self.window.mode());
+            }
+            if self.input.pressed("next_monitor") {
+                let monitors = self.window.monitors();
+                if !monitors.is_empty() {
+                    let index = (settings.monitor + 1) % monitors.len();
+                    // Video modes differ between monitors
+                    self.set_window_mode(self.window.mode(), index, None);
+                    let monitor = &monitors[index];
+                    println!(
+                        "Monitor {}: {} at {:?}, {} video modes",
+                        index,
+                        monitor.name,
+                        monitor.position,
+                        monitor.video_modes.len()
+                    );
+                }
+            }
+        }
+        if self.input.pressed("shadow_debug") {
+            let settings = &mut self.shadows.settings;
+            settings.debug_view = settings.debug_view.next();
+            println!("Shadow debug view: {:?}", settings.debug_view);
+        }
 
-        AppWindow {
-            window,
-            events,
-            glfw,
+        let settings = &mut self.post_process.settings;
+        if self.input.pressed("tonemapper") {
+            settings.tonemapper = settings.tonemapper.next();
+            println!("Tonemapper: {:?}", settings.tonemapper);
+        }
+        for (action, step) in [("exposure_up", 0.5_f32), ("exposure_down", -0.5_f32)] {
+            if self.input.pressed(action) {
+                settings.exposure += step;
+                println!("Exposure: {:+.1} EV", settings.exposure);
+            }
+        }
+        if self.input.pressed("bloom") {
+            settings.bloom = !settings.bloom;
+            println!("Bloom: {}", settings.bloom);
+        }
+        if self.input.pressed("color_grading") {
+            settings.color_grading = !settings.color_grading;
+            println!("Color grading: {}", settings.color_grading);
+        }
+        if self.input.pressed("fxaa") {
+            settings.fxaa = !settings.fxaa;
+            println!("FXAA: {}", settings.fxaa);
         }
     }
-    pub fn main_loop(&mut self) {
+
+    pub fn main_loop(&mut self, application: &mut dyn Application) {
         let mut frame_count: f64 = 0.0_f64;
-        while !self.app_window.window.should_close() {
-            self.app_window.glfw.poll_events();
-            for (_, event) in glfw::flush_messages(&self.app_window.events) {
-                handle_window_event(&mut self.app_window.window, event);
+        let start = Instant::now();
+        let mut last_time = 0.0_f64;
+        let mut last_report = last_time;
+        while !self.window.should_close() {
+            // Waiting before polling keeps the input the frame is built from fresh
+            self.frame_limiter.wait();
+            for event in self.window.poll_events() {
+                self.handle_window_event(&event);
+                application.on_event(self, &event);
             }
 
+            self.input.update(self.window.gamepad());
+            self.handle_actions();
+
+            let now = start.elapsed().as_secs_f64();
+            let dt = (now - last_time) as f32;
+            last_time = now;
+            self.camera.update(&self.input, dt);
+            application.update(self, dt);
+            self.update_scene(dt);
+
+            if self.swapchain_outdated {
+                self.recreate_swapchain();
+            }
+            application.render(self);
             self.draw_frame();
             frame_count += 1.0_f64;
-            let t = self.app_window.glfw.get_time();
-            println!("{}", frame_count / t);
+            let t = start.elapsed().as_secs_f64();
+            if t - last_report >= 1.0_f64 {
+                println!(
+                    "{:.1} fps ({:?}), {} objects drawn, {} culled, {} instances",
+                    frame_count / (t - last_report),
+                    self.swapchain_stuff.present_mode,
+                    self.frame_stats.objects_drawn,
+                    self.frame_stats.objects_culled,
+                    self.frame_stats.instances_drawn,
+                );
+                frame_count = 0.0_f64;
+                last_report = t;
+            }
         }
 
         unsafe {
             let _ = self.device.device_wait_idle();
         };
+
+        self.window_settings.placement = self.window.windowed_placement();
+        if let Err(e) = self.window_settings.save(Path::new(WINDOW_SETTINGS_PATH)) {
+            println!("Not saving window settings: {}", e);
+        }
+        let present_settings = PresentSettings {
+            vsync: self.vsync_policy,
+            target_fps: self.frame_limiter.target_fps(),
+        };
+        if let Err(e) = present_settings.save(Path::new(PRESENT_SETTINGS_PATH)) {
+            println!("Not saving present settings: {}", e);
+        }
     }
 }
 
-impl Drop for App {
+impl Drop for Renderer {
     fn drop(&mut self) {
+        self.screenshot.flush(&self.device);
+        self.deletion_queue.flush(&self.device);
+
+        // The views go before the swapchain; everything else owned goes with the fields
+        self.swapchain_imageviews.clear();
         unsafe {
-            for i in 0..MAX_FRAMES_IN_FLIGHT as usize {
-                self.device
-                    .destroy_semaphore(self.sync_objects.render_finished_semaphores[i], None);
-                self.device
-                    .destroy_semaphore(self.sync_objects.image_available_semaphores[i], None);
-                self.device
-                    .destroy_fence(self.sync_objects.in_flight_fences[i], None);
-            }
-            self.device.destroy_command_pool(self.command_pool, None);
-            for &framebuffer in self.framebuffers.iter() {
-                self.device.destroy_framebuffer(framebuffer, None);
-            }
-            self.device
-                .destroy_pipeline(self.graphics_pipeline_stuff.graphics_pipeline, None);
-            self.device
-                .destroy_pipeline_layout(self.graphics_pipeline_stuff.pipeline_layout, None);
-            self.device.destroy_render_pass(self.render_pass, None);
-            for &imageview in &self.swapchain_imageviews {
-                self.device.destroy_image_view(imageview, None);
-            }
             self.swapchain_stuff
                 .swapchain_loader
                 .destroy_swapchain(self.swapchain_stuff.swapchain, None);
-            self.surface_stuff
-                .surface_loader
-                .destroy_surface(self.surface_stuff.surface, None);
-            self.device.destroy_device(None);
-            self.instance.destroy_instance(None);
         }
     }
 }
-
-fn handle_window_event(window: &mut glfw::Window, event: glfw::WindowEvent) {
-    match event {
-        glfw::WindowEvent::Key(Key::Escape, _, Action::Press, _) => window.set_should_close(true),
-        _ => {}
-    }
-}
@@ -0,0 +1,673 @@
+use ash::vk;
+use cgmath::{InnerSpace, Matrix4, Point3, Transform};
+use std::collections::HashMap;
+
+use super::bindless::{BindlessTextures, SamplerHandle, TextureHandle};
+use super::buffer;
+use super::descriptor;
+use super::instancing::{InstanceBatch, INSTANCE_BINDING};
+use super::material::{
+    BindlessMaterial, MaterialPipelines, MaterialUniforms, PipelineKey, TextureSlot,
+    TransformSource, MATERIAL_UNIFORM_STRIDE, TEXTURE_SLOT_COUNT,
+};
+use super::resources::{Device, Owned};
+use super::scene::DrawNode;
+use super::texture::{self, TextureFormatSupport};
+use crate::assets::gltf::GltfScene;
+use crate::assets::material::{ImageData, Material, SamplerDesc};
+use crate::assets::mesh::Bounds;
+use crate::util::structures::{BufferStuff, ImageStuff};
+
+// The meshes, materials and textures of a glTF file uploaded to the GPU. Every primitive shares
+// one vertex and one index buffer. Where and how often meshes are drawn is up to the scene graph.
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct MeshPushConstants {
+    pub model: [[f32; 4]; 4],
+    // Only the bindless pipelines read it
+    pub material: u32,
+}
+
+// A range of the shared index buffer drawn with one material
+pub struct Primitive {
+    pub first_index: u32,
+    pub index_count: u32,
+    pub vertex_offset: i32,
+    pub material: usize,
+}
+
+pub struct Model {
+    vertex_buffer: BufferStuff,
+    index_buffer: BufferStuff,
+    primitives: Vec<Primitive>,
+    // Primitive indices for each mesh
+    meshes: Vec<Vec<usize>>,
+    // Object-space bounds of all primitives of each mesh
+    mesh_bounds: Vec<Bounds>,
+    _images: Vec<ImageStuff>,
+    _samplers: Vec<Owned<vk::Sampler>>,
+    _material_buffer: BufferStuff,
+    material_bindings: MaterialBindings,
+    material_keys: Vec<PipelineKey>,
+}
+
+// How draws find a material's factors and textures
+enum MaterialBindings {
+    // A descriptor set per material, with the factors in a uniform buffer
+    Sets {
+        _descriptor_pool: Owned<vk::DescriptorPool>,
+        sets: Vec<vk::DescriptorSet>,
+    },
+    // The shared bindless set, with a record per material in a storage buffer. The slots are
+    // the model's until they're released.
+    Bindless {
+        set: vk::DescriptorSet,
+        textures: Vec<TextureHandle>,
+        samplers: Vec<SamplerHandle>,
+    },
+}
+
+// One primitive of a scene node, the unit draws are sorted in
+struct PrimitiveDraw<'a> {
+    primitive: usize,
+    material: usize,
+    transform: &'a Matrix4<f32>,
+    key: PipelineKey,
+    // Squared distance from the eye, for sorting blended primitives back to front
+    distance: f32,
+}
+
+// The image and sampler of every texture slot of a material
+type SlotTextures = [(usize, vk::Sampler); TEXTURE_SLOT_COUNT];
+
+impl Model {
+    // With `bindless`, the textures and samplers are registered there and the materials go into
+    // its storage buffer, replacing those of any model loaded before
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_gltf(
+        device: &Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        material_set_layout: vk::DescriptorSetLayout,
+        format_support: &TextureFormatSupport,
+        bindless: Option<&mut BindlessTextures>,
+        scene: &GltfScene,
+    ) -> Result<Model, String> {
+        // Primitives without a material use a default one appended after the scene's
+        let default_material = scene.materials.len();
+        let mut materials = scene.materials.clone();
+        materials.push(Material::default());
+
+        let mut vertices = vec![];
+        let mut indices = vec![];
+        let mut primitives = vec![];
+        let mut meshes = vec![];
+        let mut mesh_bounds = vec![];
+        for mesh in scene.meshes.iter() {
+            let mut primitive_indices = vec![];
+            let mut bounds = Bounds::default();
+            for primitive in mesh.iter() {
+                primitive_indices.push(primitives.len());
+                primitives.push(Primitive {
+                    first_index: indices.len() as u32,
+                    index_count: primitive.indices.len() as u32,
+                    vertex_offset: vertices.len() as i32,
+                    material: primitive.material.unwrap_or(default_material),
+                });
+                bounds = bounds.union(&primitive.bounds);
+                vertices.extend_from_slice(&primitive.vertices);
+                indices.extend_from_slice(&primitive.indices);
+            }
+            meshes.push(primitive_indices);
+            mesh_bounds.push(bounds);
+        }
+        if indices.is_empty() {
+            return Err("scene has no triangle meshes to draw".to_string());
+        }
+
+        let vertex_buffer = buffer::create_device_local_buffer(
+            device,
+            memory_properties,
+            command_pool,
+            queue,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            as_bytes(&vertices),
+            "vertices",
+        );
+        let index_buffer = buffer::create_device_local_buffer(
+            device,
+            memory_properties,
+            command_pool,
+            queue,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            as_bytes(&indices),
+            "indices",
+        );
+
+        // Two 1x1 images go last for empty texture slots: white, which leaves the factors as they
+        // are, and a flat tangent-space normal
+        let white = ImageData {
+            width: 1,
+            height: 1,
+            pixels: vec![255; 4],
+            srgb: true,
+        };
+        let flat_normal = ImageData {
+            width: 1,
+            height: 1,
+            pixels: vec![128, 128, 255, 255],
+            srgb: false,
+        };
+        let images: Vec<ImageStuff> = scene
+            .images
+            .iter()
+            .chain([&white, &flat_normal])
+            .enumerate()
+            .map(|(index, image)| {
+                texture::create_texture(
+                    device,
+                    memory_properties,
+                    command_pool,
+                    queue,
+                    format_support,
+                    image,
+                    &format!("texture {}", index),
+                )
+            })
+            .collect();
+        let white_image = images.len() - 2;
+        let flat_normal_image = images.len() - 1;
+
+        let mut sampler_lookup: HashMap<SamplerDesc, Owned<vk::Sampler>> = HashMap::new();
+        let mut sampler_for = |desc: SamplerDesc| {
+            **sampler_lookup
+                .entry(desc)
+                .or_insert_with(|| texture::create_sampler(device, &desc, "material sampler"))
+        };
+
+        let slot_textures: Vec<SlotTextures> = materials
+            .iter()
+            .map(|material| {
+                TextureSlot::ALL.map(|slot| match slot.texture(material) {
+                    Some(texture) => (
+                        scene.textures[texture].image,
+                        sampler_for(scene.textures[texture].sampler),
+                    ),
+                    None if slot == TextureSlot::Normal => {
+                        (flat_normal_image, sampler_for(SamplerDesc::default()))
+                    }
+                    None => (white_image, sampler_for(SamplerDesc::default())),
+                })
+            })
+            .collect();
+        let samplers: Vec<Owned<vk::Sampler>> = sampler_lookup.into_values().collect();
+        let uniforms: Vec<MaterialUniforms> = materials.iter().map(MaterialUniforms::new).collect();
+
+        let (material_buffer, material_bindings) = match bindless {
+            Some(bindless) => {
+                let registered = register_bindless(
+                    device,
+                    bindless,
+                    &images,
+                    &samplers,
+                    &uniforms,
+                    &slot_textures,
+                );
+                let (records, material_bindings) = registered?;
+                let material_buffer = buffer::create_device_local_buffer(
+                    device,
+                    memory_properties,
+                    command_pool,
+                    queue,
+                    vk::BufferUsageFlags::STORAGE_BUFFER,
+                    as_bytes(&records),
+                    "bindless materials",
+                );
+                bindless.write_materials(device, *material_buffer.buffer);
+                (material_buffer, material_bindings)
+            }
+            None => {
+                let mut uniform_bytes = vec![0_u8; uniforms.len() * MATERIAL_UNIFORM_STRIDE];
+                for (uniforms, bytes) in uniforms
+                    .iter()
+                    .zip(uniform_bytes.chunks_exact_mut(MATERIAL_UNIFORM_STRIDE))
+                {
+                    let uniforms = as_bytes(std::slice::from_ref(uniforms));
+                    bytes[..uniforms.len()].copy_from_slice(uniforms);
+                }
+                let material_buffer = buffer::create_device_local_buffer(
+                    device,
+                    memory_properties,
+                    command_pool,
+                    queue,
+                    vk::BufferUsageFlags::UNIFORM_BUFFER,
+                    &uniform_bytes,
+                    "material uniforms",
+                );
+                let material_bindings = create_material_sets(
+                    device,
+                    material_set_layout,
+                    &material_buffer,
+                    &images,
+                    &slot_textures,
+                );
+                (material_buffer, material_bindings)
+            }
+        };
+
+        Ok(Model {
+            vertex_buffer,
+            index_buffer,
+            primitives,
+            meshes,
+            mesh_bounds,
+            _images: images,
+            _samplers: samplers,
+            _material_buffer: material_buffer,
+            material_bindings,
+            material_keys: materials.iter().map(PipelineKey::new).collect(),
+        })
+    }
+
+    pub fn mesh_count(&self) -> usize {
+        self.meshes.len()
+    }
+
+    pub fn material_count(&self) -> usize {
+        self.material_keys.len()
+    }
+
+    pub fn mesh_bounds(&self, mesh: usize) -> &Bounds {
+        &self.mesh_bounds[mesh]
+    }
+
+    pub fn mesh_primitives(&self, mesh: usize) -> impl Iterator<Item = &Primitive> {
+        self.meshes[mesh]
+            .iter()
+            .map(|&primitive| &self.primitives[primitive])
+    }
+
+    pub fn material_key(&self, material: usize) -> PipelineKey {
+        self.material_keys[material]
+    }
+
+    // The same set for every material when bindless
+    pub fn material_set(&self, material: usize) -> vk::DescriptorSet {
+        match &self.material_bindings {
+            MaterialBindings::Sets { sets, .. } => sets[material],
+            MaterialBindings::Bindless { set, .. } => *set,
+        }
+    }
+
+    pub fn bindless(&self) -> bool {
+        matches!(self.material_bindings, MaterialBindings::Bindless { .. })
+    }
+
+    // Once per command buffer before draw_mesh
+    pub fn bind(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[*self.vertex_buffer.buffer], &[0]);
+            device.cmd_bind_index_buffer(
+                command_buffer,
+                *self.index_buffer.buffer,
+                0,
+                vk::IndexType::UINT32,
+            );
+        }
+    }
+
+    // Draws the primitives of `draws` whose materials blend, or the ones that don't, with their
+    // material's pipeline and descriptor set. Opaque primitives are grouped by pipeline and
+    // material to save state changes, blended ones go back to front from `eye`. A node's material
+    // overrides those of its mesh's primitives.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        pipelines: &MaterialPipelines,
+        frame_set: vk::DescriptorSet,
+        draws: &[DrawNode],
+        eye: Point3<f32>,
+        blended: bool,
+    ) {
+        let mut primitive_draws = vec![];
+        for draw in draws.iter() {
+            let center = draw
+                .transform
+                .transform_point(Point3::from(self.mesh_bounds[draw.mesh].center));
+            for &primitive in self.meshes[draw.mesh].iter() {
+                let material = draw.material.unwrap_or(self.primitives[primitive].material);
+                if self.material_keys[material].blend != blended {
+                    continue;
+                }
+                primitive_draws.push(PrimitiveDraw {
+                    primitive,
+                    material,
+                    transform: draw.transform,
+                    key: self.material_keys[material],
+                    distance: (center - eye).magnitude2(),
+                });
+            }
+        }
+        if blended {
+            primitive_draws.sort_by(|a, b| b.distance.total_cmp(&a.distance));
+        } else {
+            primitive_draws.sort_by_key(|draw| (draw.key, draw.material));
+        }
+
+        let layout = pipelines.layout();
+        unsafe {
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                layout,
+                0,
+                &[frame_set],
+                &[],
+            )
+        };
+        let mut bound_key = None;
+        let mut bound_set = None;
+        for draw in primitive_draws.iter() {
+            let primitive = &self.primitives[draw.primitive];
+            let material_set = self.material_set(draw.material);
+            let push_constants = MeshPushConstants {
+                model: (*draw.transform).into(),
+                material: draw.material as u32,
+            };
+
+            unsafe {
+                if bound_key != Some(draw.key) {
+                    device.cmd_bind_pipeline(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        pipelines.pipeline(draw.key),
+                    );
+                    bound_key = Some(draw.key);
+                }
+                if bound_set != Some(material_set) {
+                    device.cmd_bind_descriptor_sets(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        layout,
+                        1,
+                        &[material_set],
+                        &[],
+                    );
+                    bound_set = Some(material_set);
+                }
+                device.cmd_push_constants(
+                    command_buffer,
+                    layout,
+                    vk::ShaderStageFlags::VERTEX,
+                    0,
+                    as_bytes(std::slice::from_ref(&push_constants)),
+                );
+                device.cmd_draw_indexed(
+                    command_buffer,
+                    primitive.index_count,
+                    1,
+                    primitive.first_index,
+                    primitive.vertex_offset,
+                    0,
+                );
+            }
+        }
+    }
+
+    // Draws every copy in `batch` with one call per primitive of its mesh, skipping primitives
+    // whose material doesn't match `blended`. Copies aren't culled or sorted; the batch has
+    // been uploaded for `frame`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_instanced(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        pipelines: &MaterialPipelines,
+        frame_set: vk::DescriptorSet,
+        batch: &InstanceBatch,
+        frame: usize,
+        blended: bool,
+    ) {
+        if batch.instances.is_empty() {
+            return;
+        }
+
+        let layout = pipelines.layout();
+        unsafe {
+            device.cmd_bind_vertex_buffers(
+                command_buffer,
+                INSTANCE_BINDING,
+                &[batch.buffer(frame)],
+                &[0],
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                layout,
+                0,
+                &[frame_set],
+                &[],
+            );
+        }
+        for &primitive in self.meshes[batch.mesh].iter() {
+            let primitive = &self.primitives[primitive];
+            let material = batch.material.unwrap_or(primitive.material);
+            let key = PipelineKey {
+                transforms: TransformSource::Instance,
+                ..self.material_keys[material]
+            };
+            if key.blend != blended {
+                continue;
+            }
+
+            unsafe {
+                device.cmd_bind_pipeline(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipelines.pipeline(key),
+                );
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    layout,
+                    1,
+                    &[self.material_set(material)],
+                    &[],
+                );
+                device.cmd_push_constants(
+                    command_buffer,
+                    layout,
+                    vk::ShaderStageFlags::VERTEX,
+                    std::mem::offset_of!(MeshPushConstants, material) as u32,
+                    &(material as u32).to_ne_bytes(),
+                );
+                device.cmd_draw_indexed(
+                    command_buffer,
+                    primitive.index_count,
+                    batch.instances.len() as u32,
+                    primitive.first_index,
+                    primitive.vertex_offset,
+                    0,
+                );
+            }
+        }
+    }
+
+    // Only the draw calls, for passes that bind their own descriptor sets and push constants
+    pub fn draw_mesh_geometry(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        mesh: usize,
+    ) {
+        for &primitive in self.meshes[mesh].iter() {
+            let primitive = &self.primitives[primitive];
+            unsafe {
+                device.cmd_draw_indexed(
+                    command_buffer,
+                    primitive.index_count,
+                    1,
+                    primitive.first_index,
+                    primitive.vertex_offset,
+                    0,
+                )
+            };
+        }
+    }
+
+    // Gives the bindless slots back once no frame in flight reads them. `bindless` has to be
+    // the set the model was loaded into; everything else goes when the model is dropped.
+    pub fn release_bindless(&self, bindless: &mut BindlessTextures) {
+        if let MaterialBindings::Bindless {
+            textures, samplers, ..
+        } = &self.material_bindings
+        {
+            for &handle in textures.iter() {
+                bindless.release_texture(handle);
+            }
+            for &handle in samplers.iter() {
+                bindless.release_sampler(handle);
+            }
+        }
+    }
+}
+
+// A set per material, its textures combined with their samplers
+fn create_material_sets(
+    device: &Device,
+    material_set_layout: vk::DescriptorSetLayout,
+    material_buffer: &BufferStuff,
+    images: &[ImageStuff],
+    slot_textures: &[SlotTextures],
+) -> MaterialBindings {
+    let descriptor_pool = descriptor::create_descriptor_pool(
+        device,
+        &[
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: slot_textures.len() as u32,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: (slot_textures.len() * TEXTURE_SLOT_COUNT) as u32,
+            },
+        ],
+        slot_textures.len() as u32,
+        "material pool",
+    );
+    let sets = descriptor::allocate_descriptor_sets(
+        device,
+        *descriptor_pool,
+        material_set_layout,
+        slot_textures.len(),
+    );
+    for (i, (textures, &descriptor_set)) in slot_textures.iter().zip(sets.iter()).enumerate() {
+        descriptor::write_uniform_buffer(
+            device,
+            descriptor_set,
+            0,
+            *material_buffer.buffer,
+            (i * MATERIAL_UNIFORM_STRIDE) as vk::DeviceSize,
+            std::mem::size_of::<MaterialUniforms>() as vk::DeviceSize,
+        );
+        for (slot, &(image, sampler)) in TextureSlot::ALL.iter().zip(textures.iter()) {
+            descriptor::write_combined_image_sampler(
+                device,
+                descriptor_set,
+                slot.binding(),
+                *images[image].view,
+                sampler,
+            );
+        }
+    }
+
+    MaterialBindings::Sets {
+        _descriptor_pool: descriptor_pool,
+        sets,
+    }
+}
+
+// Registers every image and sampler, then builds the material records from their handles.
+// Nothing stays registered when the bindless arrays run out of room.
+fn register_bindless(
+    device: &Device,
+    bindless: &mut BindlessTextures,
+    images: &[ImageStuff],
+    samplers: &[Owned<vk::Sampler>],
+    uniforms: &[MaterialUniforms],
+    slot_textures: &[SlotTextures],
+) -> Result<(Vec<BindlessMaterial>, MaterialBindings), String> {
+    let mut texture_handles = vec![];
+    let mut sampler_handles = vec![];
+    let mut registered = Ok(());
+    for image in images.iter() {
+        match bindless.register_texture(device, *image.view) {
+            Ok(handle) => texture_handles.push(handle),
+            Err(e) => {
+                registered = Err(e);
+                break;
+            }
+        }
+    }
+    if registered.is_ok() {
+        for sampler in samplers.iter() {
+            match bindless.register_sampler(device, **sampler) {
+                Ok(handle) => sampler_handles.push(handle),
+                Err(e) => {
+                    registered = Err(e);
+                    break;
+                }
+            }
+        }
+    }
+    if let Err(e) = registered {
+        for &handle in texture_handles.iter() {
+            bindless.release_texture(handle);
+        }
+        for &handle in sampler_handles.iter() {
+            bindless.release_sampler(handle);
+        }
+        return Err(e);
+    }
+
+    let sampler_handle = |sampler: vk::Sampler| {
+        let i = samplers.iter().position(|s| **s == sampler).unwrap();
+        sampler_handles[i].0
+    };
+    let records = uniforms
+        .iter()
+        .zip(slot_textures.iter())
+        .map(|(&uniforms, textures)| {
+            BindlessMaterial::new(
+                uniforms,
+                textures.map(|(image, _)| texture_handles[image].0),
+                textures.map(|(_, sampler)| sampler_handle(sampler)),
+            )
+        })
+        .collect();
+
+    Ok((
+        records,
+        MaterialBindings::Bindless {
+            set: bindless.set(),
+            textures: texture_handles,
+            samplers: sampler_handles,
+        },
+    ))
+}
+
+pub fn push_constant_range() -> vk::PushConstantRange {
+    vk::PushConstantRange {
+        stage_flags: vk::ShaderStageFlags::VERTEX,
+        offset: 0,
+        size: std::mem::size_of::<MeshPushConstants>() as u32,
+    }
+}
+
+pub fn as_bytes<T: Copy>(data: &[T]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, std::mem::size_of_val(data)) }
+}
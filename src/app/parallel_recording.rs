@@ -0,0 +1,230 @@
+use ash::vk;
+use std::ptr;
+use std::sync::Mutex;
+
+use super::resources::{Device, Owned};
+use crate::util::constants::MAX_FRAMES_IN_FLIGHT;
+use crate::util::worker_pool::WorkerPool;
+
+// Records the contents of a render pass into secondary command buffers on worker threads, for
+// the primary command buffer to execute in order. Command pools can only be used by one thread
+// at a time, so every worker, and the thread calling in, has its own pool per frame in flight;
+// each is reset as a whole when its frame comes round again.
+
+// What the secondary command buffers render into, which they have to declare when they begin
+#[derive(Clone, Copy)]
+pub enum SecondaryTarget {
+    RenderPass {
+        render_pass: vk::RenderPass,
+        framebuffer: vk::Framebuffer,
+    },
+    Dynamic {
+        color_format: vk::Format,
+        depth_format: vk::Format,
+    },
+}
+
+struct ThreadCommands {
+    pool: Owned<vk::CommandPool>,
+    buffers: Vec<vk::CommandBuffer>,
+    // Buffers handed out since the pool was last reset
+    used: usize,
+}
+
+impl ThreadCommands {
+    fn new(device: &Device, queue_family_index: u32, name: &str) -> ThreadCommands {
+        let pool_info = vk::CommandPoolCreateInfo {
+            s_type: vk::StructureType::COMMAND_POOL_CREATE_INFO,
+            flags: vk::CommandPoolCreateFlags::TRANSIENT,
+            queue_family_index,
+            ..Default::default()
+        };
+        let pool = unsafe {
+            device
+                .create_command_pool(&pool_info, None)
+                .expect("Failed to create command pool")
+        };
+
+        ThreadCommands {
+            pool: device.own(pool, name),
+            buffers: vec![],
+            used: 0,
+        }
+    }
+
+    // Allocates more buffers as needed; they stay allocated across resets
+    fn next(&mut self, device: &Device) -> vk::CommandBuffer {
+        if self.used == self.buffers.len() {
+            let alloc_info = vk::CommandBufferAllocateInfo {
+                s_type: vk::StructureType::COMMAND_BUFFER_ALLOCATE_INFO,
+                command_pool: *self.pool,
+                level: vk::CommandBufferLevel::SECONDARY,
+                command_buffer_count: 1,
+                ..Default::default()
+            };
+            let buffers = unsafe {
+                device
+                    .allocate_command_buffers(&alloc_info)
+                    .expect("Failed to allocate secondary command buffer")
+            };
+            self.buffers.extend(buffers);
+        }
+        self.used += 1;
+        self.buffers[self.used - 1]
+    }
+
+    fn reset(&mut self, device: &Device) {
+        unsafe {
+            device
+                .reset_command_pool(*self.pool, vk::CommandPoolResetFlags::empty())
+                .expect("Failed to reset command pool")
+        };
+        self.used = 0;
+    }
+}
+
+pub struct ParallelRecorder {
+    workers: WorkerPool,
+    // Per frame in flight, one per worker and a last one for the calling thread
+    frames: Vec<Vec<Mutex<ThreadCommands>>>,
+}
+
+impl ParallelRecorder {
+    pub fn new(device: &Device, queue_family_index: u32, threads: usize) -> ParallelRecorder {
+        let frames = (0..MAX_FRAMES_IN_FLIGHT)
+            .map(|frame| {
+                (0..=threads)
+                    .map(|thread| {
+                        let name = format!("recording pool {} frame {}", thread, frame);
+                        Mutex::new(ThreadCommands::new(device, queue_family_index, &name))
+                    })
+                    .collect()
+            })
+            .collect();
+
+        ParallelRecorder {
+            workers: WorkerPool::new(threads),
+            frames,
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.workers.threads()
+    }
+
+    // Once per frame after its fence has been waited on, before recording anything for it
+    pub fn begin_frame(&self, device: &Device, frame: usize) {
+        for commands in self.frames[frame].iter() {
+            commands.lock().unwrap().reset(device);
+        }
+    }
+
+    // Calls `record_chunk` for every chunk below `chunks` with a secondary command buffer of
+    // its own, spreading the chunks over the workers. Returns the buffers in chunk order.
+    pub fn record_chunks(
+        &self,
+        device: &Device,
+        frame: usize,
+        target: SecondaryTarget,
+        chunks: usize,
+        record_chunk: &(dyn Fn(usize, vk::CommandBuffer) + Sync),
+    ) -> Vec<vk::CommandBuffer> {
+        let recorded = Mutex::new(vec![vk::CommandBuffer::null(); chunks]);
+        let threads = self.threads();
+        let thread_commands = &self.frames[frame];
+        self.workers.run(&|worker| {
+            let mut commands = thread_commands[worker].lock().unwrap();
+            for chunk in (worker..chunks).step_by(threads) {
+                let command_buffer = commands.next(device);
+                begin_secondary(device, command_buffer, target);
+                record_chunk(chunk, command_buffer);
+                end_secondary(device, command_buffer);
+                recorded.lock().unwrap()[chunk] = command_buffer;
+            }
+        });
+
+        recorded.into_inner().unwrap()
+    }
+
+    // For commands that have to be recorded on the calling thread
+    pub fn record_here(
+        &self,
+        device: &Device,
+        frame: usize,
+        target: SecondaryTarget,
+        record: impl FnOnce(vk::CommandBuffer),
+    ) -> vk::CommandBuffer {
+        let command_buffer = self.frames[frame][self.threads()]
+            .lock()
+            .unwrap()
+            .next(device);
+        begin_secondary(device, command_buffer, target);
+        record(command_buffer);
+        end_secondary(device, command_buffer);
+
+        command_buffer
+    }
+}
+
+fn begin_secondary(device: &Device, command_buffer: vk::CommandBuffer, target: SecondaryTarget) {
+    let (render_pass, framebuffer, color_format, depth_format) = match target {
+        SecondaryTarget::RenderPass {
+            render_pass,
+            framebuffer,
+        } => (
+            render_pass,
+            framebuffer,
+            vk::Format::UNDEFINED,
+            vk::Format::UNDEFINED,
+        ),
+        SecondaryTarget::Dynamic {
+            color_format,
+            depth_format,
+        } => (
+            vk::RenderPass::null(),
+            vk::Framebuffer::null(),
+            color_format,
+            depth_format,
+        ),
+    };
+    let rendering_info = vk::CommandBufferInheritanceRenderingInfo {
+        s_type: vk::StructureType::COMMAND_BUFFER_INHERITANCE_RENDERING_INFO,
+        color_attachment_count: 1,
+        p_color_attachment_formats: &color_format,
+        depth_attachment_format: depth_format,
+        rasterization_samples: vk::SampleCountFlags::TYPE_1,
+        ..Default::default()
+    };
+    let inheritance_info = vk::CommandBufferInheritanceInfo {
+        s_type: vk::StructureType::COMMAND_BUFFER_INHERITANCE_INFO,
+        p_next: match target {
+            SecondaryTarget::RenderPass { .. } => ptr::null(),
+            SecondaryTarget::Dynamic { .. } => &rendering_info as *const _ as *const _,
+        },
+        render_pass,
+        subpass: 0,
+        framebuffer,
+        ..Default::default()
+    };
+    let begin_info = vk::CommandBufferBeginInfo {
+        s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
+        flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT
+            | vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE,
+        p_inheritance_info: &inheritance_info,
+        ..Default::default()
+    };
+
+    unsafe {
+        device
+            .begin_command_buffer(command_buffer, &begin_info)
+            .expect("Failed to begin secondary command buffer")
+    };
+}
+
+fn end_secondary(device: &Device, command_buffer: vk::CommandBuffer) {
+    unsafe {
+        device
+            .end_command_buffer(command_buffer)
+            .expect("Failed to record secondary command buffer")
+    };
+}
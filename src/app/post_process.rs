@@ -0,0 +1,694 @@
+use ash::vk;
+
+use super::descriptor;
+use super::dynamic_rendering;
+use super::graphics_pipeline;
+use super::model::as_bytes;
+use super::render_graph::{Access, ImageDesc, PassContext, RenderGraph, ResourceId};
+use super::resources::{Device, Owned};
+use super::texture;
+use crate::assets::color_lut::ColorLut;
+use crate::util::constants::MAX_FRAMES_IN_FLIGHT;
+use crate::util::structures::{
+    GraphicsPipelineStuff, ImageStuff, OutputEncoding, PipelineDesc, RenderingPath,
+};
+
+// The scene is rendered into an HDR target and reaches the swapchain through a chain of
+// full-screen passes. Bloom downsamples the bright parts of the image and blurs them back up;
+// the composite pass adds it, applies exposure, tonemaps to display range and grades through a
+// 3D lookup table; the output pass antialiases with FXAA while writing the swapchain image.
+// Every image between the scene and the swapchain is a render graph transient.
+
+pub const SCENE_COLOR_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+// Tonemapped and gamma encoded, with luma in alpha for FXAA. HDR output keeps values up to the
+// display's headroom scaled into 0..1, which needs more precision.
+const LDR_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+const HDR_OUTPUT_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+// Bloom starts at half resolution and halves this many times in total
+const BLOOM_LEVELS: usize = 6;
+
+// Match the FLAG_ constants in post.glsl
+const FLAG_THRESHOLD: u32 = 1;
+const FLAG_BLOOM: u32 = 1 << 1;
+const FLAG_COLOR_GRADING: u32 = 1 << 2;
+const FLAG_FXAA: u32 = 1 << 3;
+
+// Curves mapping scene radiance to display range, in the order tonemap.frag numbers them
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Tonemapper {
+    Clamp,
+    Reinhard,
+    AcesFilmic,
+    Uncharted2,
+}
+
+impl Tonemapper {
+    pub fn next(self) -> Tonemapper {
+        match self {
+            Tonemapper::Clamp => Tonemapper::Reinhard,
+            Tonemapper::Reinhard => Tonemapper::AcesFilmic,
+            Tonemapper::AcesFilmic => Tonemapper::Uncharted2,
+            Tonemapper::Uncharted2 => Tonemapper::Clamp,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PostSettings {
+    // In stops; 0 leaves the scene as lit
+    pub exposure: f32,
+    pub tonemapper: Tonemapper,
+    pub bloom: bool,
+    // Brightness where bloom starts, and how far below it it fades in
+    pub bloom_threshold: f32,
+    pub bloom_knee: f32,
+    pub bloom_intensity: f32,
+    pub color_grading: bool,
+    pub fxaa: bool,
+    // In nits, for HDR output: how bright SDR white is shown and how bright highlights may get
+    pub paper_white: f32,
+    pub peak_brightness: f32,
+}
+
+impl Default for PostSettings {
+    fn default() -> Self {
+        PostSettings {
+            exposure: 0.0_f32,
+            tonemapper: Tonemapper::AcesFilmic,
+            bloom: true,
+            bloom_threshold: 1.0_f32,
+            bloom_knee: 0.5_f32,
+            bloom_intensity: 0.3_f32,
+            color_grading: true,
+            fxaa: true,
+            // BT.2408's reference white, and what most HDR10 displays can at least reach
+            paper_white: 203.0_f32,
+            peak_brightness: 1000.0_f32,
+        }
+    }
+}
+
+// Mirrors the push constant block in post.glsl
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct PostPushConstants {
+    // Meaning depends on the pass, see the shaders
+    params: [f32; 4],
+    flags: u32,
+}
+
+// A full-screen pipeline and the render pass it draws in, null on the dynamic rendering path
+struct Stage {
+    pipeline: GraphicsPipelineStuff,
+    render_pass: vk::RenderPass,
+}
+
+// Everything sized after the swapchain, recreated along with it
+pub struct Targets {
+    output_framebuffers: Vec<Owned<vk::Framebuffer>>,
+    scene_color: ImageStuff,
+}
+
+// The sets of one frame in flight. They point at transient images, which can change from frame
+// to frame, so each pass writes its set as it's recorded.
+struct FrameSets {
+    downsample: Vec<vk::DescriptorSet>,
+    // One fewer than downsample, whose smallest level starts the way back up
+    upsample: Vec<vk::DescriptorSet>,
+    composite: vk::DescriptorSet,
+    output: vk::DescriptorSet,
+}
+
+pub struct PostProcess {
+    pub settings: PostSettings,
+    targets: Targets,
+    ldr_format: vk::Format,
+    output_encoding: OutputEncoding,
+    color_lut: ImageStuff,
+    sampler: Owned<vk::Sampler>,
+    frame_sets: Vec<FrameSets>,
+    downsample: Stage,
+    upsample: Stage,
+    composite: Stage,
+    output: Stage,
+    _descriptor_pool: Owned<vk::DescriptorPool>,
+    _set_layout: Owned<vk::DescriptorSetLayout>,
+    // Null on the dynamic rendering path
+    _render_passes: Vec<Owned<vk::RenderPass>>,
+}
+
+impl PostProcess {
+    // `output_views` are the swapchain images the chain ends in
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        rendering_path: RenderingPath,
+        extent: vk::Extent2D,
+        output_format: vk::Format,
+        output_encoding: OutputEncoding,
+        output_views: &[vk::ImageView],
+        color_lut: &ColorLut,
+    ) -> PostProcess {
+        let render_pass = |format, name: &str| match rendering_path {
+            RenderingPath::RenderPass => device.own(
+                graphics_pipeline::create_color_render_pass(device, format),
+                name,
+            ),
+            RenderingPath::Dynamic => device.own(vk::RenderPass::null(), name),
+        };
+        let hdr_render_pass = render_pass(SCENE_COLOR_FORMAT, "HDR render pass");
+        let ldr_format = if output_encoding.is_hdr() {
+            HDR_OUTPUT_FORMAT
+        } else {
+            LDR_FORMAT
+        };
+        let ldr_render_pass = render_pass(ldr_format, "LDR render pass");
+        let output_render_pass = render_pass(output_format, "output render pass");
+
+        let targets = create_targets(
+            device,
+            memory_properties,
+            extent,
+            *output_render_pass,
+            output_views,
+        );
+
+        let color_lut_image = texture::create_volume_texture(
+            device,
+            memory_properties,
+            command_pool,
+            queue,
+            color_lut.size,
+            &color_lut.texels,
+            "color LUT",
+        );
+        let sampler = create_clamped_sampler(device);
+
+        // Every pass samples at most the image it processes, a second image and the lookup table
+        let bindings: Vec<_> = (0..3)
+            .map(|binding| vk::DescriptorSetLayoutBinding {
+                binding,
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            })
+            .collect();
+        let set_layout =
+            descriptor::create_set_layout(device, &bindings, "post-process set layout");
+        let sets_per_frame = BLOOM_LEVELS + (BLOOM_LEVELS - 1) + 2;
+        let set_count = MAX_FRAMES_IN_FLIGHT * sets_per_frame as u32;
+        let descriptor_pool = descriptor::create_descriptor_pool(
+            device,
+            &[vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: set_count * bindings.len() as u32,
+            }],
+            set_count,
+            "post-process pool",
+        );
+        let sets = descriptor::allocate_descriptor_sets(
+            device,
+            *descriptor_pool,
+            *set_layout,
+            set_count as usize,
+        );
+        let frame_sets = sets
+            .chunks(sets_per_frame)
+            .map(|sets| FrameSets {
+                downsample: sets[..BLOOM_LEVELS].to_vec(),
+                upsample: sets[BLOOM_LEVELS..sets_per_frame - 2].to_vec(),
+                composite: sets[sets_per_frame - 2],
+                output: sets[sets_per_frame - 1],
+            })
+            .collect();
+
+        let stage = |frag_shader: &str, render_pass: vk::RenderPass, color_format| Stage {
+            pipeline: graphics_pipeline::create_graphics_pipeline(
+                device,
+                &PipelineDesc {
+                    vert_shader: "post.vert",
+                    frag_shader,
+                    vertex_bindings: &[],
+                    vertex_attributes: &[],
+                    set_layouts: &[*set_layout],
+                    push_constant_ranges: &[vk::PushConstantRange {
+                        stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                        offset: 0,
+                        size: std::mem::size_of::<PostPushConstants>() as u32,
+                    }],
+                    render_pass,
+                    color_format,
+                    depth_format: vk::Format::UNDEFINED,
+                    depth_test: false,
+                    depth_compare_op: vk::CompareOp::ALWAYS,
+                    depth_bias: None,
+                    blend: false,
+                    cull_mode: vk::CullModeFlags::NONE,
+                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                },
+            ),
+            render_pass,
+        };
+
+        PostProcess {
+            settings: PostSettings::default(),
+            downsample: stage(
+                "bloom_downsample.frag",
+                *hdr_render_pass,
+                SCENE_COLOR_FORMAT,
+            ),
+            upsample: stage("bloom_upsample.frag", *hdr_render_pass, SCENE_COLOR_FORMAT),
+            composite: stage("tonemap.frag", *ldr_render_pass, ldr_format),
+            output: stage("post_output.frag", *output_render_pass, output_format),
+            targets,
+            ldr_format,
+            output_encoding,
+            color_lut: color_lut_image,
+            sampler,
+            frame_sets,
+            _descriptor_pool: descriptor_pool,
+            _set_layout: set_layout,
+            _render_passes: vec![hdr_render_pass, ldr_render_pass, output_render_pass],
+        }
+    }
+
+    // Recreates the targets for a new swapchain of the same format. Returns the old targets,
+    // which frames in flight may still render to.
+    pub fn resize(
+        &mut self,
+        device: &Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        extent: vk::Extent2D,
+        output_views: &[vk::ImageView],
+    ) -> Targets {
+        let targets = create_targets(
+            device,
+            memory_properties,
+            extent,
+            self.output.render_pass,
+            output_views,
+        );
+
+        std::mem::replace(&mut self.targets, targets)
+    }
+
+    // The HDR target the scene is rendered into
+    pub fn scene_color(&self) -> &ImageStuff {
+        &self.targets.scene_color
+    }
+
+    pub fn import_scene_color(&self, graph: &mut RenderGraph) -> ResourceId {
+        import(graph, "scene color", &self.targets.scene_color)
+    }
+
+    // Adds the passes from `scene_color`, once an earlier pass has rendered it, to `output`,
+    // the swapchain image `output_index`, recording into the descriptor sets of `frame`
+    pub fn add_passes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        frame: usize,
+        scene_color: ResourceId,
+        output: ResourceId,
+        output_index: usize,
+    ) {
+        let settings = self.settings;
+        let sets = &self.frame_sets[frame];
+        let extent = self.targets.scene_color.extent;
+        let bloom_desc = |level: usize| ImageDesc {
+            format: SCENE_COLOR_FORMAT,
+            extent: vk::Extent2D {
+                width: (extent.width >> (level + 1)).max(1),
+                height: (extent.height >> (level + 1)).max(1),
+            },
+        };
+        let bloom_down: Vec<ResourceId> = (0..BLOOM_LEVELS)
+            .map(|level| graph.create_image(&format!("bloom down {}", level), bloom_desc(level)))
+            .collect();
+        let bloom_up: Vec<ResourceId> = (0..BLOOM_LEVELS - 1)
+            .map(|level| graph.create_image(&format!("bloom up {}", level), bloom_desc(level)))
+            .collect();
+        let ldr = graph.create_image(
+            "ldr color",
+            ImageDesc {
+                format: self.ldr_format,
+                extent,
+            },
+        );
+
+        if settings.bloom {
+            for level in 0..BLOOM_LEVELS {
+                let (source, target) = match level {
+                    0 => (scene_color, bloom_down[0]),
+                    _ => (bloom_down[level - 1], bloom_down[level]),
+                };
+                let push_constants = PostPushConstants {
+                    params: [
+                        settings.bloom_threshold,
+                        settings.bloom_knee,
+                        0.0_f32,
+                        0.0_f32,
+                    ],
+                    flags: if level == 0 { FLAG_THRESHOLD } else { 0 },
+                };
+                graph.add_pass(
+                    &format!("bloom downsample {}", level),
+                    &[
+                        (source, Access::ShaderRead),
+                        (target, Access::ColorAttachment),
+                    ],
+                    move |ctx| {
+                        self.record_pass(
+                            ctx,
+                            &self.downsample,
+                            target,
+                            None,
+                            sets.downsample[level],
+                            &[ctx.image_view(source)],
+                            push_constants,
+                        )
+                    },
+                );
+            }
+            for level in (0..bloom_up.len()).rev() {
+                let lower = *bloom_up.get(level + 1).unwrap_or(&bloom_down[level + 1]);
+                let down = bloom_down[level];
+                let target = bloom_up[level];
+                let push_constants = PostPushConstants {
+                    params: [0.0_f32; 4],
+                    flags: 0,
+                };
+                graph.add_pass(
+                    &format!("bloom upsample {}", level),
+                    &[
+                        (down, Access::ShaderRead),
+                        (lower, Access::ShaderRead),
+                        (target, Access::ColorAttachment),
+                    ],
+                    move |ctx| {
+                        self.record_pass(
+                            ctx,
+                            &self.upsample,
+                            target,
+                            None,
+                            sets.upsample[level],
+                            &[ctx.image_view(down), ctx.image_view(lower)],
+                            push_constants,
+                        )
+                    },
+                );
+            }
+        }
+
+        // The composite set always holds the bloom image, so it is read (and put in a layout
+        // fit for sampling) even when bloom is off, nothing draws it and the shader skips it
+        let bloom = bloom_up[0];
+        let mut flags = 0;
+        if settings.bloom {
+            flags |= FLAG_BLOOM;
+        }
+        if settings.color_grading {
+            flags |= FLAG_COLOR_GRADING;
+        }
+        // How much brighter than paper white the output can go
+        let headroom = if self.output_encoding.is_hdr() {
+            (settings.peak_brightness / settings.paper_white).max(1.0_f32)
+        } else {
+            1.0_f32
+        };
+        let push_constants = PostPushConstants {
+            params: [
+                settings.exposure.exp2(),
+                settings.bloom_intensity / BLOOM_LEVELS as f32,
+                settings.tonemapper as u32 as f32,
+                headroom,
+            ],
+            flags,
+        };
+        graph.add_pass(
+            "tonemap",
+            &[
+                (scene_color, Access::ShaderRead),
+                (bloom, Access::ShaderRead),
+                (ldr, Access::ColorAttachment),
+            ],
+            move |ctx| {
+                self.record_pass(
+                    ctx,
+                    &self.composite,
+                    ldr,
+                    None,
+                    sets.composite,
+                    &[
+                        ctx.image_view(scene_color),
+                        ctx.image_view(bloom),
+                        *self.color_lut.view,
+                    ],
+                    push_constants,
+                )
+            },
+        );
+
+        let mut flags = 0;
+        if settings.fxaa {
+            flags |= FLAG_FXAA;
+        }
+        let push_constants = PostPushConstants {
+            params: [
+                settings.paper_white,
+                headroom,
+                self.output_encoding as u32 as f32,
+                0.0_f32,
+            ],
+            flags,
+        };
+        graph.add_pass(
+            "post output",
+            &[(ldr, Access::ShaderRead), (output, Access::ColorAttachment)],
+            move |ctx| {
+                self.record_pass(
+                    ctx,
+                    &self.output,
+                    output,
+                    Some(*self.targets.output_framebuffers[output_index]),
+                    sets.output,
+                    &[ctx.image_view(ldr)],
+                    push_constants,
+                )
+            },
+        );
+    }
+
+    // `framebuffer` is None for a transient `target`, which gets one from the graph. `sources`
+    // are the views `descriptor_set` is written with, binding by binding.
+    #[allow(clippy::too_many_arguments)]
+    fn record_pass(
+        &self,
+        ctx: &PassContext,
+        stage: &Stage,
+        target: ResourceId,
+        framebuffer: Option<vk::Framebuffer>,
+        descriptor_set: vk::DescriptorSet,
+        sources: &[vk::ImageView],
+        push_constants: PostPushConstants,
+    ) {
+        let device = ctx.device;
+        let command_buffer = ctx.command_buffer;
+        let extent = ctx.extent(target);
+
+        // This frame's fence has been waited on, so the set isn't in use yet
+        for (binding, &view) in sources.iter().enumerate() {
+            descriptor::write_combined_image_sampler(
+                device,
+                descriptor_set,
+                binding as u32,
+                view,
+                *self.sampler,
+            );
+        }
+
+        unsafe {
+            if stage.render_pass == vk::RenderPass::null() {
+                dynamic_rendering::cmd_begin_color_rendering(
+                    device,
+                    command_buffer,
+                    ctx.image_view(target),
+                    extent,
+                );
+            } else {
+                let render_pass_info = vk::RenderPassBeginInfo {
+                    s_type: vk::StructureType::RENDER_PASS_BEGIN_INFO,
+                    render_pass: stage.render_pass,
+                    framebuffer: framebuffer
+                        .unwrap_or_else(|| ctx.framebuffer(target, stage.render_pass)),
+                    render_area: vk::Rect2D {
+                        offset: vk::Offset2D { x: 0, y: 0 },
+                        extent,
+                    },
+                    ..Default::default()
+                };
+                device.cmd_begin_render_pass(
+                    command_buffer,
+                    &render_pass_info,
+                    vk::SubpassContents::INLINE,
+                );
+            }
+
+            device.cmd_set_viewport(
+                command_buffer,
+                0,
+                &[vk::Viewport {
+                    x: 0.0_f32,
+                    y: 0.0_f32,
+                    width: extent.width as f32,
+                    height: extent.height as f32,
+                    min_depth: 0.0_f32,
+                    max_depth: 1.0_f32,
+                }],
+            );
+            device.cmd_set_scissor(
+                command_buffer,
+                0,
+                &[vk::Rect2D {
+                    offset: vk::Offset2D { x: 0, y: 0 },
+                    extent,
+                }],
+            );
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                *stage.pipeline.graphics_pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                *stage.pipeline.pipeline_layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                *stage.pipeline.pipeline_layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                as_bytes(std::slice::from_ref(&push_constants)),
+            );
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+
+            if stage.render_pass == vk::RenderPass::null() {
+                device.cmd_end_rendering(command_buffer);
+            } else {
+                device.cmd_end_render_pass(command_buffer);
+            }
+        }
+    }
+}
+
+// `output_render_pass` is null on the dynamic rendering path
+fn create_targets(
+    device: &Device,
+    memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    extent: vk::Extent2D,
+    output_render_pass: vk::RenderPass,
+    output_views: &[vk::ImageView],
+) -> Targets {
+    Targets {
+        output_framebuffers: output_views
+            .iter()
+            .enumerate()
+            .map(|(i, &view)| {
+                create_framebuffer(
+                    device,
+                    output_render_pass,
+                    view,
+                    extent,
+                    &format!("output framebuffer {}", i),
+                )
+            })
+            .collect(),
+        scene_color: texture::create_image(
+            device,
+            memory_properties,
+            extent,
+            1,
+            SCENE_COLOR_FORMAT,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            "scene color",
+        ),
+    }
+}
+
+// Rewritten every frame, so previous contents are never needed
+fn import(graph: &mut RenderGraph, name: &str, image: &ImageStuff) -> ResourceId {
+    graph.import_image(
+        name,
+        *image.image,
+        *image.view,
+        ImageDesc {
+            format: image.format,
+            extent: image.extent,
+        },
+        vk::ImageLayout::UNDEFINED,
+        None,
+    )
+}
+
+// Null without a render pass, for dynamic rendering
+fn create_framebuffer(
+    device: &Device,
+    render_pass: vk::RenderPass,
+    view: vk::ImageView,
+    extent: vk::Extent2D,
+    name: &str,
+) -> Owned<vk::Framebuffer> {
+    if render_pass == vk::RenderPass::null() {
+        return device.own(vk::Framebuffer::null(), name);
+    }
+
+    let framebuffer_info = vk::FramebufferCreateInfo {
+        s_type: vk::StructureType::FRAMEBUFFER_CREATE_INFO,
+        render_pass,
+        attachment_count: 1,
+        p_attachments: &view,
+        width: extent.width,
+        height: extent.height,
+        layers: 1,
+        ..Default::default()
+    };
+
+    let framebuffer = unsafe {
+        device
+            .create_framebuffer(&framebuffer_info, None)
+            .expect("Failed to create post-process framebuffer")
+    };
+
+    device.own(framebuffer, name)
+}
+
+// Bilinear and clamped on every axis, including the lookup table's third
+fn create_clamped_sampler(device: &Device) -> Owned<vk::Sampler> {
+    let sampler_info = vk::SamplerCreateInfo {
+        s_type: vk::StructureType::SAMPLER_CREATE_INFO,
+        mag_filter: vk::Filter::LINEAR,
+        min_filter: vk::Filter::LINEAR,
+        mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+        address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        max_lod: 0.0_f32,
+        border_color: vk::BorderColor::FLOAT_OPAQUE_BLACK,
+        ..Default::default()
+    };
+
+    let sampler = unsafe {
+        device
+            .create_sampler(&sampler_info, None)
+            .expect("Failed to create post-process sampler")
+    };
+
+    device.own(sampler, "post-process clamped")
+}
@@ -0,0 +1,145 @@
+use crate::util::constants::{TARGET_FPS, VSYNC_POLICY};
+use crate::util::structures::VsyncPolicy;
+use crate::util::tools::parse_key_values;
+use std::fs;
+use std::path::Path;
+
+// The present mode and the frame limit. Read at startup, falling back to the defaults in
+// constants.rs for anything the file leaves out, and written on exit so the runtime toggles
+// stick, in the same `<key> = <value>` format as the window settings.
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PresentSettings {
+    pub vsync: VsyncPolicy,
+    // None runs unlimited
+    pub target_fps: Option<f64>,
+}
+
+impl Default for PresentSettings {
+    fn default() -> PresentSettings {
+        PresentSettings {
+            vsync: VSYNC_POLICY,
+            target_fps: TARGET_FPS,
+        }
+    }
+}
+
+impl PresentSettings {
+    // Falls back to the defaults when the file is missing or invalid
+    pub fn load(path: &Path) -> PresentSettings {
+        if !path.exists() {
+            return PresentSettings::default();
+        }
+        fs::read_to_string(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+            .and_then(|source| parse(&source).map_err(|e| format!("{}:{}", path.display(), e)))
+            .unwrap_or_else(|e| {
+                println!("Using default present settings: {}", e);
+                PresentSettings::default()
+            })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let mut source = String::from("# Written on exit\n");
+        source += &format!("vsync = {}\n", vsync_name(self.vsync));
+        match self.target_fps {
+            Some(fps) => source += &format!("target_fps = {}\n", fps),
+            None => source += "target_fps = off\n",
+        }
+
+        fs::write(path, source).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+pub fn parse(source: &str) -> Result<PresentSettings, String> {
+    let mut settings = PresentSettings::default();
+
+    parse_key_values(source, |key, value| {
+        let invalid = || format!("invalid {} '{}'", key, value);
+
+        match key {
+            "vsync" => settings.vsync = parse_vsync(value).ok_or_else(invalid)?,
+            "target_fps" => {
+                settings.target_fps = match value {
+                    "off" => None,
+                    _ => Some(
+                        value
+                            .parse::<f64>()
+                            .ok()
+                            .filter(|&fps| fps > 0.0_f64)
+                            .ok_or_else(invalid)?,
+                    ),
+                }
+            }
+            key => return Err(format!("unknown setting '{}'", key)),
+        }
+        Ok(())
+    })?;
+
+    Ok(settings)
+}
+
+fn vsync_name(vsync: VsyncPolicy) -> &'static str {
+    match vsync {
+        VsyncPolicy::Fifo => "fifo",
+        VsyncPolicy::FifoRelaxed => "fifo_relaxed",
+        VsyncPolicy::Mailbox => "mailbox",
+        VsyncPolicy::Immediate => "immediate",
+    }
+}
+
+fn parse_vsync(name: &str) -> Option<VsyncPolicy> {
+    match name {
+        "fifo" => Some(VsyncPolicy::Fifo),
+        "fifo_relaxed" => Some(VsyncPolicy::FifoRelaxed),
+        "mailbox" => Some(VsyncPolicy::Mailbox),
+        "immediate" => Some(VsyncPolicy::Immediate),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_keys_keep_the_defaults() {
+        assert_eq!(parse("# nothing set\n"), Ok(PresentSettings::default()));
+        assert_eq!(
+            parse("vsync = fifo_relaxed\n").unwrap().target_fps,
+            TARGET_FPS
+        );
+    }
+
+    #[test]
+    fn vsync_and_target_fps_are_read() {
+        assert_eq!(
+            parse("vsync = immediate\ntarget_fps = 144\n"),
+            Ok(PresentSettings {
+                vsync: VsyncPolicy::Immediate,
+                target_fps: Some(144.0_f64),
+            })
+        );
+        assert_eq!(parse("target_fps = off\n").unwrap().target_fps, None);
+    }
+
+    #[test]
+    fn bad_values_name_the_line() {
+        assert_eq!(
+            parse("vsync = fifo\ntarget_fps = 0\n"),
+            Err("2: invalid target_fps '0'".to_string())
+        );
+        assert_eq!(
+            parse("vsync = adaptive\n"),
+            Err("1: invalid vsync 'adaptive'".to_string())
+        );
+        assert_eq!(
+            parse("refresh = 60\n"),
+            Err("1: unknown setting 'refresh'".to_string())
+        );
+        assert_eq!(
+            parse("vsync fifo\n"),
+            Err("1: expected '<key> = <value>', got 'vsync fifo'".to_string())
+        );
+    }
+}
@@ -0,0 +1,1300 @@
+use ash::vk;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+use super::buffer;
+use super::resources::{Device, Owned};
+use super::texture;
+
+// Passes declare which resources they use and how; compiling the graph culls passes that don't
+// contribute to an imported resource, orders the remaining ones, assigns transient images from a
+// per-frame pool and works out every layout transition and pipeline barrier. A pool image is
+// handed to a later transient once its previous one is dead, but only when their description and
+// usage are identical; nothing is memory-aliased.
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ResourceId(usize);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Access {
+    ColorAttachment,
+    DepthAttachment,
+    // Sampled in fragment shaders
+    ShaderRead,
+    TransferRead,
+    TransferWrite,
+    // Storage buffers and images written (and read) by compute shaders
+    ComputeWrite,
+    // Draw commands and counts of indirect draws; buffers only
+    IndirectRead,
+    HostRead,
+    Present,
+}
+
+struct AccessState {
+    layout: vk::ImageLayout,
+    stage: vk::PipelineStageFlags2,
+    access: vk::AccessFlags2,
+    usage: vk::ImageUsageFlags,
+    write: bool,
+}
+
+impl Access {
+    fn state(self) -> AccessState {
+        match self {
+            Access::ColorAttachment => AccessState {
+                layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                stage: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                access: vk::AccessFlags2::COLOR_ATTACHMENT_READ
+                    | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+                usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
+                write: true,
+            },
+            Access::DepthAttachment => AccessState {
+                layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                stage: vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+                access: vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                usage: vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+                write: true,
+            },
+            Access::ShaderRead => AccessState {
+                layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                stage: vk::PipelineStageFlags2::FRAGMENT_SHADER,
+                access: vk::AccessFlags2::SHADER_READ,
+                usage: vk::ImageUsageFlags::SAMPLED,
+                write: false,
+            },
+            Access::TransferRead => AccessState {
+                layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                stage: vk::PipelineStageFlags2::TRANSFER,
+                access: vk::AccessFlags2::TRANSFER_READ,
+                usage: vk::ImageUsageFlags::TRANSFER_SRC,
+                write: false,
+            },
+            Access::TransferWrite => AccessState {
+                layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                stage: vk::PipelineStageFlags2::TRANSFER,
+                access: vk::AccessFlags2::TRANSFER_WRITE,
+                usage: vk::ImageUsageFlags::TRANSFER_DST,
+                write: true,
+            },
+            Access::ComputeWrite => AccessState {
+                layout: vk::ImageLayout::GENERAL,
+                stage: vk::PipelineStageFlags2::COMPUTE_SHADER,
+                access: vk::AccessFlags2::SHADER_STORAGE_READ
+                    | vk::AccessFlags2::SHADER_STORAGE_WRITE,
+                usage: vk::ImageUsageFlags::STORAGE,
+                write: true,
+            },
+            Access::IndirectRead => AccessState {
+                layout: vk::ImageLayout::UNDEFINED,
+                stage: vk::PipelineStageFlags2::DRAW_INDIRECT,
+                access: vk::AccessFlags2::INDIRECT_COMMAND_READ,
+                usage: vk::ImageUsageFlags::empty(),
+                write: false,
+            },
+            Access::HostRead => AccessState {
+                layout: vk::ImageLayout::GENERAL,
+                stage: vk::PipelineStageFlags2::HOST,
+                access: vk::AccessFlags2::HOST_READ,
+                usage: vk::ImageUsageFlags::empty(),
+                write: false,
+            },
+            Access::Present => AccessState {
+                layout: vk::ImageLayout::PRESENT_SRC_KHR,
+                stage: vk::PipelineStageFlags2::BOTTOM_OF_PIPE,
+                access: vk::AccessFlags2::NONE,
+                usage: vk::ImageUsageFlags::empty(),
+                write: false,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ImageDesc {
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+}
+
+enum ResourceKind {
+    ImportedImage {
+        image: vk::Image,
+        view: vk::ImageView,
+        desc: ImageDesc,
+        initial_layout: vk::ImageLayout,
+        final_access: Option<Access>,
+    },
+    TransientImage {
+        desc: ImageDesc,
+    },
+    ImportedBuffer {
+        buffer: vk::Buffer,
+        final_access: Option<Access>,
+    },
+}
+
+struct Resource {
+    name: String,
+    kind: ResourceKind,
+}
+
+impl Resource {
+    fn is_imported(&self) -> bool {
+        !matches!(self.kind, ResourceKind::TransientImage { .. })
+    }
+
+    fn is_buffer(&self) -> bool {
+        matches!(self.kind, ResourceKind::ImportedBuffer { .. })
+    }
+}
+
+pub struct PassContext<'g> {
+    pub device: &'g Device,
+    pub command_buffer: vk::CommandBuffer,
+    images: Vec<(vk::Image, vk::ImageView, ImageDesc)>,
+    buffers: Vec<vk::Buffer>,
+    transients: Vec<Option<&'g TransientImage>>,
+}
+
+impl PassContext<'_> {
+    pub fn image(&self, id: ResourceId) -> vk::Image {
+        self.images[id.0].0
+    }
+
+    pub fn image_view(&self, id: ResourceId) -> vk::ImageView {
+        self.images[id.0].1
+    }
+
+    pub fn extent(&self, id: ResourceId) -> vk::Extent2D {
+        self.images[id.0].2.extent
+    }
+
+    pub fn buffer(&self, id: ResourceId) -> vk::Buffer {
+        self.buffers[id.0]
+    }
+
+    // A framebuffer for drawing to transient image `id` alone in `render_pass`, made the first
+    // time it's asked for and kept with the pool image
+    pub fn framebuffer(&self, id: ResourceId, render_pass: vk::RenderPass) -> vk::Framebuffer {
+        let image = self.transients[id.0].expect("Only transient images have graph framebuffers");
+        let mut framebuffers = image.framebuffers.borrow_mut();
+        if let Some((_, framebuffer)) = framebuffers.iter().find(|(pass, _)| *pass == render_pass) {
+            return **framebuffer;
+        }
+
+        let framebuffer_info = vk::FramebufferCreateInfo {
+            s_type: vk::StructureType::FRAMEBUFFER_CREATE_INFO,
+            render_pass,
+            attachment_count: 1,
+            p_attachments: &*image.view,
+            width: image.desc.extent.width,
+            height: image.desc.extent.height,
+            layers: 1,
+            ..Default::default()
+        };
+        let framebuffer = unsafe {
+            self.device
+                .create_framebuffer(&framebuffer_info, None)
+                .expect("Failed to create transient framebuffer")
+        };
+        let name = format!("{} framebuffer", image.name);
+        framebuffers.push((render_pass, self.device.own(framebuffer, &name)));
+
+        framebuffer
+    }
+}
+
+type PassFn<'a> = Box<dyn FnOnce(&PassContext) + 'a>;
+
+struct Pass<'a> {
+    name: String,
+    uses: Vec<(ResourceId, Access)>,
+    execute: PassFn<'a>,
+}
+
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    resources: Vec<Resource>,
+    passes: Vec<Pass<'a>>,
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> RenderGraph<'a> {
+        RenderGraph {
+            resources: vec![],
+            passes: vec![],
+        }
+    }
+
+    pub fn import_image(
+        &mut self,
+        name: &str,
+        image: vk::Image,
+        view: vk::ImageView,
+        desc: ImageDesc,
+        initial_layout: vk::ImageLayout,
+        final_access: Option<Access>,
+    ) -> ResourceId {
+        self.add_resource(
+            name,
+            ResourceKind::ImportedImage {
+                image,
+                view,
+                desc,
+                initial_layout,
+                final_access,
+            },
+        )
+    }
+
+    pub fn import_buffer(
+        &mut self,
+        name: &str,
+        buffer: vk::Buffer,
+        final_access: Option<Access>,
+    ) -> ResourceId {
+        self.add_resource(
+            name,
+            ResourceKind::ImportedBuffer {
+                buffer,
+                final_access,
+            },
+        )
+    }
+
+    // Transient images only live for the duration of the graph and may reuse the image of an
+    // identical transient whose lifetime doesn't overlap
+    pub fn create_image(&mut self, name: &str, desc: ImageDesc) -> ResourceId {
+        self.add_resource(name, ResourceKind::TransientImage { desc })
+    }
+
+    pub fn add_pass(
+        &mut self,
+        name: &str,
+        uses: &[(ResourceId, Access)],
+        execute: impl FnOnce(&PassContext) + 'a,
+    ) {
+        self.passes.push(Pass {
+            name: name.to_string(),
+            uses: uses.to_vec(),
+            execute: Box::new(execute),
+        });
+    }
+
+    fn add_resource(&mut self, name: &str, kind: ResourceKind) -> ResourceId {
+        self.resources.push(Resource {
+            name: name.to_string(),
+            kind,
+        });
+        ResourceId(self.resources.len() - 1)
+    }
+
+    pub fn compile(
+        self,
+        device: &Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        pool: &mut TransientPool,
+    ) -> CompiledGraph<'a> {
+        let dependencies = self.dependencies();
+        let alive = self.live_passes(&dependencies);
+        let order = topological_order(&dependencies, &alive);
+        let physical = self.assign_transients(device, memory_properties, pool, &order);
+        let (barriers, final_barriers) = self.barriers(&order);
+
+        CompiledGraph {
+            graph: self,
+            order,
+            alive,
+            barriers,
+            final_barriers,
+            physical,
+        }
+    }
+
+    // dependencies[p] holds the passes that must run before pass p: the last writer of every
+    // resource p uses, and for writes also every reader since that writer
+    fn dependencies(&self) -> Vec<Vec<usize>> {
+        let mut last_writer: Vec<Option<usize>> = vec![None; self.resources.len()];
+        let mut readers: Vec<Vec<usize>> = vec![vec![]; self.resources.len()];
+        let mut dependencies = vec![vec![]; self.passes.len()];
+
+        for (p, pass) in self.passes.iter().enumerate() {
+            for &(id, access) in pass.uses.iter() {
+                if let Some(writer) = last_writer[id.0] {
+                    dependencies[p].push(writer);
+                }
+                if access.state().write {
+                    dependencies[p].append(&mut readers[id.0]);
+                    last_writer[id.0] = Some(p);
+                } else {
+                    readers[id.0].push(p);
+                }
+            }
+            dependencies[p].retain(|&d| d != p);
+            dependencies[p].sort_unstable();
+            dependencies[p].dedup();
+        }
+
+        dependencies
+    }
+
+    // A pass survives if it writes an imported resource or feeds a pass that survives
+    fn live_passes(&self, dependencies: &[Vec<usize>]) -> Vec<bool> {
+        let mut alive = vec![false; self.passes.len()];
+        let mut queue: VecDeque<usize> = self
+            .passes
+            .iter()
+            .enumerate()
+            .filter(|(_, pass)| {
+                pass.uses
+                    .iter()
+                    .any(|&(id, access)| access.state().write && self.resources[id.0].is_imported())
+            })
+            .map(|(p, _)| p)
+            .collect();
+
+        while let Some(p) = queue.pop_front() {
+            if alive[p] {
+                continue;
+            }
+            alive[p] = true;
+            queue.extend(dependencies[p].iter().copied());
+        }
+
+        alive
+    }
+
+    fn assign_transients(
+        &self,
+        device: &Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        pool: &mut TransientPool,
+        order: &[usize],
+    ) -> Vec<Option<usize>> {
+        let existing: Vec<_> = pool
+            .images
+            .iter()
+            .map(|image| (image.desc, image.usage))
+            .collect();
+        let plan = self.plan_transients(order, &existing);
+        for (desc, usage) in plan.new_images {
+            // Named by slot, as several resources may share one
+            let name = format!("transient #{}", pool.images.len());
+            pool.images.push(create_transient_image(
+                device,
+                memory_properties,
+                desc,
+                usage,
+                &name,
+            ));
+        }
+
+        // The pool belongs to a single frame in flight whose fence has been waited on, so images
+        // this frame no longer needs (e.g. after a resize) can go right away
+        let mut remap = vec![None; pool.images.len()];
+        let mut kept = 0;
+        for (i, &used) in plan.used.iter().enumerate() {
+            if used {
+                remap[i] = Some(kept);
+                kept += 1;
+            }
+        }
+        let mut i = 0;
+        pool.images.retain(|_| {
+            let keep = remap[i].is_some();
+            i += 1;
+            keep
+        });
+
+        plan.physical
+            .into_iter()
+            .map(|slot| slot.and_then(|s| remap[s]))
+            .collect()
+    }
+
+    // Greedily hands every live transient a pool slot, reusing one as soon as its previous tenant
+    // is dead. `pool` holds the description and usage of the images already in the pool.
+    fn plan_transients(
+        &self,
+        order: &[usize],
+        pool: &[(ImageDesc, vk::ImageUsageFlags)],
+    ) -> TransientPlan {
+        // Lifetime (first and last position in `order`) and required usage of every transient
+        let mut lifetimes: Vec<Option<(usize, usize)>> = vec![None; self.resources.len()];
+        let mut usages = vec![vk::ImageUsageFlags::empty(); self.resources.len()];
+        for (position, &p) in order.iter().enumerate() {
+            for &(id, access) in self.passes[p].uses.iter() {
+                let lifetime = lifetimes[id.0].get_or_insert((position, position));
+                lifetime.1 = position;
+                usages[id.0] |= access.state().usage;
+            }
+        }
+
+        let mut transients: Vec<usize> = (0..self.resources.len())
+            .filter(|&r| !self.resources[r].is_imported() && lifetimes[r].is_some())
+            .collect();
+        transients.sort_by_key(|&r| lifetimes[r].unwrap().0);
+
+        let mut slots = pool.to_vec();
+        let mut busy_until: Vec<Option<usize>> = vec![None; slots.len()];
+        let mut physical = vec![None; self.resources.len()];
+        for r in transients {
+            let ResourceKind::TransientImage { desc } = self.resources[r].kind else {
+                unreachable!()
+            };
+            let (first, last) = lifetimes[r].unwrap();
+
+            let slot = (0..slots.len()).find(|&i| {
+                slots[i] == (desc, usages[r]) && busy_until[i].is_none_or(|until| until < first)
+            });
+            let slot = slot.unwrap_or_else(|| {
+                slots.push((desc, usages[r]));
+                busy_until.push(None);
+                slots.len() - 1
+            });
+
+            busy_until[slot] = Some(last);
+            physical[r] = Some(slot);
+        }
+
+        TransientPlan {
+            physical,
+            new_images: slots.split_off(pool.len()),
+            used: busy_until.iter().map(Option::is_some).collect(),
+        }
+    }
+
+    fn barriers(&self, order: &[usize]) -> (Vec<Vec<Barrier>>, Vec<Barrier>) {
+        let mut states: Vec<ResourceState> = self
+            .resources
+            .iter()
+            .map(|resource| match resource.kind {
+                ResourceKind::ImportedImage { initial_layout, .. } => ResourceState {
+                    layout: initial_layout,
+                    ..ResourceState::new()
+                },
+                _ => ResourceState::new(),
+            })
+            .collect();
+
+        let mut barriers = vec![];
+        for &p in order.iter() {
+            let mut pass_barriers = vec![];
+            for &(id, access) in self.passes[p].uses.iter() {
+                let is_buffer = self.resources[id.0].is_buffer();
+                if let Some(barrier) = states[id.0].transition(id, access, is_buffer) {
+                    pass_barriers.push(barrier);
+                }
+            }
+            barriers.push(pass_barriers);
+        }
+
+        let mut final_barriers = vec![];
+        for (r, resource) in self.resources.iter().enumerate() {
+            let final_access = match resource.kind {
+                ResourceKind::ImportedImage { final_access, .. } => final_access,
+                ResourceKind::ImportedBuffer { final_access, .. } => final_access,
+                ResourceKind::TransientImage { .. } => None,
+            };
+            if let Some(access) = final_access {
+                let barrier = states[r].transition(ResourceId(r), access, resource.is_buffer());
+                final_barriers.extend(barrier);
+            }
+        }
+
+        (barriers, final_barriers)
+    }
+}
+
+struct TransientPlan {
+    // Pool slot of every resource; None for imported resources and unused transients
+    physical: Vec<Option<usize>>,
+    // Images to add to the pool, taking the slots after the existing ones
+    new_images: Vec<(ImageDesc, vk::ImageUsageFlags)>,
+    // Whether each slot, existing or new, is used this frame
+    used: Vec<bool>,
+}
+
+struct Barrier {
+    resource: ResourceId,
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+    src_stage: vk::PipelineStageFlags2,
+    src_access: vk::AccessFlags2,
+    dst_stage: vk::PipelineStageFlags2,
+    dst_access: vk::AccessFlags2,
+}
+
+struct ResourceState {
+    layout: vk::ImageLayout,
+    // Last write (or layout transition) and the stages it has already been made visible to
+    write_stage: vk::PipelineStageFlags2,
+    write_access: vk::AccessFlags2,
+    read_stages: vk::PipelineStageFlags2,
+}
+
+impl ResourceState {
+    // Resources enter the graph with unknown prior use: imported images may be waited on by a
+    // semaphore at any stage and transient images may be reused from a transient earlier in the frame
+    fn new() -> ResourceState {
+        ResourceState {
+            layout: vk::ImageLayout::UNDEFINED,
+            write_stage: vk::PipelineStageFlags2::ALL_COMMANDS,
+            write_access: vk::AccessFlags2::NONE,
+            read_stages: vk::PipelineStageFlags2::NONE,
+        }
+    }
+
+    // Moves the resource into the state required by `access`, returning the barrier needed
+    // to get there
+    fn transition(
+        &mut self,
+        resource: ResourceId,
+        access: Access,
+        is_buffer: bool,
+    ) -> Option<Barrier> {
+        let next = access.state();
+        let new_layout = if is_buffer {
+            vk::ImageLayout::UNDEFINED
+        } else {
+            next.layout
+        };
+
+        if !next.write && self.layout == new_layout {
+            // Another read: only wait for the last write if this stage hasn't seen it yet
+            if self.read_stages.contains(next.stage) {
+                return None;
+            }
+            self.read_stages |= next.stage;
+            return Some(Barrier {
+                resource,
+                old_layout: self.layout,
+                new_layout,
+                src_stage: self.write_stage,
+                src_access: self.write_access,
+                dst_stage: next.stage,
+                dst_access: next.access,
+            });
+        }
+
+        // Writes and layout transitions wait for the last write and every read since
+        let barrier = Barrier {
+            resource,
+            old_layout: self.layout,
+            new_layout,
+            src_stage: self.write_stage | self.read_stages,
+            src_access: self.write_access,
+            dst_stage: next.stage,
+            dst_access: next.access,
+        };
+
+        self.layout = new_layout;
+        self.write_stage = next.stage;
+        self.write_access = if next.write {
+            next.access
+        } else {
+            vk::AccessFlags2::NONE
+        };
+        self.read_stages = if next.write {
+            vk::PipelineStageFlags2::NONE
+        } else {
+            next.stage
+        };
+
+        Some(barrier)
+    }
+}
+
+pub struct CompiledGraph<'a> {
+    graph: RenderGraph<'a>,
+    order: Vec<usize>,
+    alive: Vec<bool>,
+    barriers: Vec<Vec<Barrier>>,
+    final_barriers: Vec<Barrier>,
+    physical: Vec<Option<usize>>,
+}
+
+impl CompiledGraph<'_> {
+    // Barriers are recorded with synchronization2 when it is enabled on the device
+    pub fn execute(
+        self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        pool: &TransientPool,
+        synchronization2: bool,
+    ) {
+        let mut images = vec![];
+        let mut buffers = vec![];
+        let transients = self
+            .physical
+            .iter()
+            .map(|slot| slot.map(|s| &pool.images[s]))
+            .collect();
+        for (r, resource) in self.graph.resources.iter().enumerate() {
+            match resource.kind {
+                ResourceKind::ImportedImage {
+                    image, view, desc, ..
+                } => images.push((image, view, desc)),
+                ResourceKind::TransientImage { desc } => match self.physical[r] {
+                    Some(slot) => {
+                        images.push((*pool.images[slot].image, *pool.images[slot].view, desc))
+                    }
+                    None => images.push((vk::Image::null(), vk::ImageView::null(), desc)),
+                },
+                ResourceKind::ImportedBuffer { .. } => images.push((
+                    vk::Image::null(),
+                    vk::ImageView::null(),
+                    ImageDesc {
+                        format: vk::Format::UNDEFINED,
+                        extent: vk::Extent2D::default(),
+                    },
+                )),
+            }
+            buffers.push(match resource.kind {
+                ResourceKind::ImportedBuffer { buffer, .. } => buffer,
+                _ => vk::Buffer::null(),
+            });
+        }
+
+        let context = PassContext {
+            device,
+            command_buffer,
+            images,
+            buffers,
+            transients,
+        };
+
+        let mut passes: Vec<Option<Pass>> = self.graph.passes.into_iter().map(Some).collect();
+        for (position, &p) in self.order.iter().enumerate() {
+            record_barriers(&context, &self.barriers[position], synchronization2);
+            let pass = passes[p].take().unwrap();
+            // A labelled section per pass, which capture tools show as a tree
+            device.debug().begin_label(command_buffer, &pass.name);
+            (pass.execute)(&context);
+            device.debug().end_label(command_buffer);
+        }
+        record_barriers(&context, &self.final_barriers, synchronization2);
+    }
+
+    pub fn dump(&self) -> String {
+        let mut out = String::new();
+        let culled = self.alive.iter().filter(|alive| !**alive).count();
+        let _ = writeln!(
+            out,
+            "Render graph: {} passes ({} culled), {} resources",
+            self.graph.passes.len(),
+            culled,
+            self.graph.resources.len()
+        );
+
+        for (position, &p) in self.order.iter().enumerate() {
+            let pass = &self.graph.passes[p];
+            let _ = writeln!(out, "  [{}] {}", position, pass.name);
+            for &(id, access) in pass.uses.iter() {
+                let _ = writeln!(
+                    out,
+                    "        {} {} ({:?})",
+                    if access.state().write {
+                        "write"
+                    } else {
+                        "read "
+                    },
+                    self.resource_label(id),
+                    access
+                );
+            }
+            for barrier in self.barriers[position].iter() {
+                let _ = writeln!(out, "        barrier {}", self.barrier_label(barrier));
+            }
+        }
+
+        for barrier in self.final_barriers.iter() {
+            let _ = writeln!(out, "  final barrier {}", self.barrier_label(barrier));
+        }
+        for (p, pass) in self.graph.passes.iter().enumerate() {
+            if !self.alive[p] {
+                let _ = writeln!(out, "  culled {}", pass.name);
+            }
+        }
+
+        out
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph render_graph {\n  rankdir=LR;\n");
+
+        for (r, resource) in self.graph.resources.iter().enumerate() {
+            let style = if resource.is_imported() {
+                "style=bold"
+            } else {
+                "style=dashed"
+            };
+            let _ = writeln!(
+                out,
+                "  r{} [shape=ellipse, {}, label=\"{}\"];",
+                r,
+                style,
+                self.resource_label(ResourceId(r))
+            );
+        }
+
+        for (p, pass) in self.graph.passes.iter().enumerate() {
+            let position = self.order.iter().position(|&o| o == p);
+            let (label, style) = match position {
+                Some(position) => (format!("{}: {}", position, pass.name), "style=filled"),
+                None => (format!("{} (culled)", pass.name), "style=dotted"),
+            };
+            let _ = writeln!(out, "  p{} [shape=box, {}, label=\"{}\"];", p, style, label);
+
+            for &(id, access) in pass.uses.iter() {
+                if access.state().write {
+                    let _ = writeln!(out, "  p{} -> r{} [label=\"{:?}\"];", p, id.0, access);
+                } else {
+                    let _ = writeln!(out, "  r{} -> p{} [label=\"{:?}\"];", id.0, p, access);
+                }
+            }
+        }
+
+        out.push_str("}\n");
+        out
+    }
+
+    fn resource_label(&self, id: ResourceId) -> String {
+        let resource = &self.graph.resources[id.0];
+        match self.physical[id.0] {
+            Some(slot) => format!("{} (transient #{})", resource.name, slot),
+            None => resource.name.clone(),
+        }
+    }
+
+    fn barrier_label(&self, barrier: &Barrier) -> String {
+        if self.graph.resources[barrier.resource.0].is_buffer() {
+            format!(
+                "{} {:?} -> {:?}",
+                self.resource_label(barrier.resource),
+                barrier.src_stage,
+                barrier.dst_stage
+            )
+        } else {
+            format!(
+                "{} {:?} -> {:?}",
+                self.resource_label(barrier.resource),
+                barrier.old_layout,
+                barrier.new_layout
+            )
+        }
+    }
+}
+
+// Kahn's algorithm over the live passes, breaking ties by declaration order
+fn topological_order(dependencies: &[Vec<usize>], alive: &[bool]) -> Vec<usize> {
+    let mut remaining: Vec<usize> = dependencies
+        .iter()
+        .map(|deps| deps.iter().filter(|&&d| alive[d]).count())
+        .collect();
+    let mut ready: Vec<usize> = (0..dependencies.len())
+        .filter(|&p| alive[p] && remaining[p] == 0)
+        .collect();
+    let mut order = vec![];
+
+    while let Some(p) = ready.iter().copied().min() {
+        ready.retain(|&r| r != p);
+        order.push(p);
+        for (q, deps) in dependencies.iter().enumerate() {
+            if alive[q] && deps.contains(&p) {
+                remaining[q] -= 1;
+                if remaining[q] == 0 {
+                    ready.push(q);
+                }
+            }
+        }
+    }
+
+    order
+}
+
+fn record_barriers(context: &PassContext, barriers: &[Barrier], synchronization2: bool) {
+    if barriers.is_empty() {
+        return;
+    }
+
+    let subresource_range = |format: vk::Format| vk::ImageSubresourceRange {
+        aspect_mask: aspect_mask(format),
+        base_mip_level: 0,
+        level_count: vk::REMAINING_MIP_LEVELS,
+        base_array_layer: 0,
+        layer_count: vk::REMAINING_ARRAY_LAYERS,
+    };
+    let is_buffer = |barrier: &Barrier| context.buffers[barrier.resource.0] != vk::Buffer::null();
+
+    if synchronization2 {
+        let image_barriers: Vec<_> = barriers
+            .iter()
+            .filter(|barrier| !is_buffer(barrier))
+            .map(|barrier| {
+                let (image, _, desc) = context.images[barrier.resource.0];
+                vk::ImageMemoryBarrier2 {
+                    s_type: vk::StructureType::IMAGE_MEMORY_BARRIER_2,
+                    src_stage_mask: barrier.src_stage,
+                    src_access_mask: barrier.src_access,
+                    dst_stage_mask: barrier.dst_stage,
+                    dst_access_mask: barrier.dst_access,
+                    old_layout: barrier.old_layout,
+                    new_layout: barrier.new_layout,
+                    src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                    dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                    image,
+                    subresource_range: subresource_range(desc.format),
+                    ..Default::default()
+                }
+            })
+            .collect();
+        let buffer_barriers: Vec<_> = barriers
+            .iter()
+            .filter(|barrier| is_buffer(barrier))
+            .map(|barrier| vk::BufferMemoryBarrier2 {
+                s_type: vk::StructureType::BUFFER_MEMORY_BARRIER_2,
+                src_stage_mask: barrier.src_stage,
+                src_access_mask: barrier.src_access,
+                dst_stage_mask: barrier.dst_stage,
+                dst_access_mask: barrier.dst_access,
+                src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                buffer: context.buffers[barrier.resource.0],
+                offset: 0,
+                size: vk::WHOLE_SIZE,
+                ..Default::default()
+            })
+            .collect();
+
+        let dependency_info = vk::DependencyInfo {
+            s_type: vk::StructureType::DEPENDENCY_INFO,
+            image_memory_barrier_count: image_barriers.len() as u32,
+            p_image_memory_barriers: image_barriers.as_ptr(),
+            buffer_memory_barrier_count: buffer_barriers.len() as u32,
+            p_buffer_memory_barriers: buffer_barriers.as_ptr(),
+            ..Default::default()
+        };
+
+        unsafe {
+            context
+                .device
+                .cmd_pipeline_barrier2(context.command_buffer, &dependency_info)
+        };
+    } else {
+        // Every stage bit the graph uses has the same value in the legacy enum; access bits go
+        // through legacy_access
+        let mut src_stage = vk::PipelineStageFlags::empty();
+        let mut dst_stage = vk::PipelineStageFlags::empty();
+        let mut image_barriers = vec![];
+        let mut buffer_barriers = vec![];
+
+        for barrier in barriers.iter() {
+            src_stage |= vk::PipelineStageFlags::from_raw(barrier.src_stage.as_raw() as u32);
+            dst_stage |= vk::PipelineStageFlags::from_raw(barrier.dst_stage.as_raw() as u32);
+            let src_access_mask = legacy_access(barrier.src_access);
+            let dst_access_mask = legacy_access(barrier.dst_access);
+
+            if is_buffer(barrier) {
+                buffer_barriers.push(vk::BufferMemoryBarrier {
+                    s_type: vk::StructureType::BUFFER_MEMORY_BARRIER,
+                    src_access_mask,
+                    dst_access_mask,
+                    src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                    dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                    buffer: context.buffers[barrier.resource.0],
+                    offset: 0,
+                    size: vk::WHOLE_SIZE,
+                    ..Default::default()
+                });
+            } else {
+                let (image, _, desc) = context.images[barrier.resource.0];
+                image_barriers.push(vk::ImageMemoryBarrier {
+                    s_type: vk::StructureType::IMAGE_MEMORY_BARRIER,
+                    src_access_mask,
+                    dst_access_mask,
+                    old_layout: barrier.old_layout,
+                    new_layout: barrier.new_layout,
+                    src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                    dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                    image,
+                    subresource_range: subresource_range(desc.format),
+                    ..Default::default()
+                });
+            }
+        }
+
+        if src_stage.is_empty() {
+            src_stage = vk::PipelineStageFlags::TOP_OF_PIPE;
+        }
+        if dst_stage.is_empty() {
+            dst_stage = vk::PipelineStageFlags::BOTTOM_OF_PIPE;
+        }
+
+        unsafe {
+            context.device.cmd_pipeline_barrier(
+                context.command_buffer,
+                src_stage,
+                dst_stage,
+                vk::DependencyFlags::empty(),
+                &[],
+                &buffer_barriers,
+                &image_barriers,
+            )
+        };
+    }
+}
+
+// The storage read and write bits only exist in the sync2 enum and sit above bit 31, so they're
+// widened to the shader read and write bits they're part of. The rest have the same values.
+fn legacy_access(access: vk::AccessFlags2) -> vk::AccessFlags {
+    let mut legacy = vk::AccessFlags::from_raw(access.as_raw() as u32);
+    if access.contains(vk::AccessFlags2::SHADER_STORAGE_READ) {
+        legacy |= vk::AccessFlags::SHADER_READ;
+    }
+    if access.contains(vk::AccessFlags2::SHADER_STORAGE_WRITE) {
+        legacy |= vk::AccessFlags::SHADER_WRITE;
+    }
+
+    legacy
+}
+
+pub fn aspect_mask(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM | vk::Format::D32_SFLOAT | vk::Format::X8_D24_UNORM_PACK32 => {
+            vk::ImageAspectFlags::DEPTH
+        }
+        vk::Format::D16_UNORM_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        _ => vk::ImageAspectFlags::COLOR,
+    }
+}
+
+pub struct TransientImage {
+    name: String,
+    desc: ImageDesc,
+    usage: vk::ImageUsageFlags,
+    // Made on demand by passes on the render pass path; they go before the view they use
+    framebuffers: RefCell<Vec<(vk::RenderPass, Owned<vk::Framebuffer>)>>,
+    view: Owned<vk::ImageView>,
+    image: Owned<vk::Image>,
+    _memory: Owned<vk::DeviceMemory>,
+}
+
+// One pool per frame in flight, so transients are never shared with a frame the GPU may still
+// be working on
+#[derive(Default)]
+pub struct TransientPool {
+    images: Vec<TransientImage>,
+}
+
+impl TransientPool {
+    pub fn new() -> TransientPool {
+        TransientPool { images: vec![] }
+    }
+}
+
+fn create_transient_image(
+    device: &Device,
+    memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    desc: ImageDesc,
+    usage: vk::ImageUsageFlags,
+    name: &str,
+) -> TransientImage {
+    let image_info = vk::ImageCreateInfo {
+        s_type: vk::StructureType::IMAGE_CREATE_INFO,
+        image_type: vk::ImageType::TYPE_2D,
+        format: desc.format,
+        extent: vk::Extent3D {
+            width: desc.extent.width,
+            height: desc.extent.height,
+            depth: 1,
+        },
+        mip_levels: 1,
+        array_layers: 1,
+        samples: vk::SampleCountFlags::TYPE_1,
+        tiling: vk::ImageTiling::OPTIMAL,
+        usage,
+        sharing_mode: vk::SharingMode::EXCLUSIVE,
+        initial_layout: vk::ImageLayout::UNDEFINED,
+        ..Default::default()
+    };
+
+    let image = unsafe {
+        device
+            .create_image(&image_info, None)
+            .expect("Failed to create transient image")
+    };
+
+    let requirements = unsafe { device.get_image_memory_requirements(image) };
+    let alloc_info = vk::MemoryAllocateInfo {
+        s_type: vk::StructureType::MEMORY_ALLOCATE_INFO,
+        allocation_size: requirements.size,
+        memory_type_index: buffer::find_memory_type(
+            memory_properties,
+            requirements.memory_type_bits,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        ),
+        ..Default::default()
+    };
+
+    let memory = unsafe {
+        let memory = device
+            .allocate_memory(&alloc_info, None)
+            .expect("Failed to allocate transient image memory");
+        device
+            .bind_image_memory(image, memory, 0)
+            .expect("Failed to bind transient image memory");
+        memory
+    };
+
+    let view_info = vk::ImageViewCreateInfo {
+        s_type: vk::StructureType::IMAGE_VIEW_CREATE_INFO,
+        image,
+        view_type: vk::ImageViewType::TYPE_2D,
+        format: desc.format,
+        subresource_range: vk::ImageSubresourceRange {
+            aspect_mask: aspect_mask(desc.format),
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        },
+        ..Default::default()
+    };
+
+    let view = unsafe {
+        device
+            .create_image_view(&view_info, None)
+            .expect("Failed to create transient image view")
+    };
+    let (view, image, memory) = texture::own_image(device, view, image, memory, name);
+
+    TransientImage {
+        name: name.to_string(),
+        desc,
+        usage,
+        framebuffers: RefCell::new(vec![]),
+        view,
+        image,
+        _memory: memory,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESC: ImageDesc = ImageDesc {
+        format: vk::Format::R16G16B16A16_SFLOAT,
+        extent: vk::Extent2D {
+            width: 64,
+            height: 64,
+        },
+    };
+
+    fn import_output(graph: &mut RenderGraph) -> ResourceId {
+        graph.import_image(
+            "output",
+            vk::Image::null(),
+            vk::ImageView::null(),
+            DESC,
+            vk::ImageLayout::UNDEFINED,
+            Some(Access::Present),
+        )
+    }
+
+    #[test]
+    fn reads_after_a_write_change_layout_once_per_stage() {
+        let id = ResourceId(0);
+        let mut state = ResourceState::new();
+
+        let write = state
+            .transition(id, Access::ColorAttachment, false)
+            .unwrap();
+        assert_eq!(write.old_layout, vk::ImageLayout::UNDEFINED);
+        assert_eq!(write.new_layout, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+
+        let read = state.transition(id, Access::ShaderRead, false).unwrap();
+        assert_eq!(read.old_layout, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        assert_eq!(read.new_layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        assert_eq!(
+            read.src_stage,
+            vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT
+        );
+        assert!(read
+            .src_access
+            .contains(vk::AccessFlags2::COLOR_ATTACHMENT_WRITE));
+        assert_eq!(read.dst_stage, vk::PipelineStageFlags2::FRAGMENT_SHADER);
+
+        // The layout change already made the write visible to fragment shaders
+        assert!(state.transition(id, Access::ShaderRead, false).is_none());
+    }
+
+    #[test]
+    fn writes_after_reads_wait_for_the_readers() {
+        let id = ResourceId(0);
+        let mut state = ResourceState::new();
+        state.transition(id, Access::ColorAttachment, false);
+        state.transition(id, Access::ShaderRead, false);
+
+        let write = state
+            .transition(id, Access::ColorAttachment, false)
+            .unwrap();
+        assert_eq!(write.old_layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        assert_eq!(write.new_layout, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        assert!(write
+            .src_stage
+            .contains(vk::PipelineStageFlags2::FRAGMENT_SHADER));
+        // Reads leave nothing to make available
+        assert_eq!(write.src_access, vk::AccessFlags2::NONE);
+    }
+
+    #[test]
+    fn buffers_only_get_memory_barriers() {
+        let id = ResourceId(0);
+        let mut state = ResourceState::new();
+        state.transition(id, Access::ComputeWrite, true);
+
+        let indirect = state.transition(id, Access::IndirectRead, true).unwrap();
+        assert_eq!(indirect.old_layout, vk::ImageLayout::UNDEFINED);
+        assert_eq!(indirect.new_layout, vk::ImageLayout::UNDEFINED);
+        assert_eq!(indirect.src_stage, vk::PipelineStageFlags2::COMPUTE_SHADER);
+        assert_eq!(indirect.dst_stage, vk::PipelineStageFlags2::DRAW_INDIRECT);
+        assert!(state.transition(id, Access::IndirectRead, true).is_none());
+
+        // A read at a new stage still waits for the write, not for the earlier read
+        let copy = state.transition(id, Access::TransferRead, true).unwrap();
+        assert_eq!(copy.src_stage, vk::PipelineStageFlags2::COMPUTE_SHADER);
+        assert_eq!(copy.dst_stage, vk::PipelineStageFlags2::TRANSFER);
+    }
+
+    #[test]
+    fn order_follows_dependencies_then_declaration() {
+        let alive = [true; 4];
+        assert_eq!(
+            topological_order(&[vec![], vec![0], vec![0], vec![1, 2]], &alive),
+            [0, 1, 2, 3]
+        );
+        assert_eq!(
+            topological_order(&[vec![2], vec![], vec![1], vec![]], &alive),
+            [1, 2, 0, 3]
+        );
+        // Dead passes are left out and don't hold back their dependents
+        assert_eq!(
+            topological_order(&[vec![], vec![0], vec![1]], &[true, false, true]),
+            [0, 2]
+        );
+    }
+
+    #[test]
+    fn passes_not_reaching_an_import_are_culled() {
+        let mut graph = RenderGraph::new();
+        let output = import_output(&mut graph);
+        let scene = graph.create_image("scene", DESC);
+        let debug = graph.create_image("debug", DESC);
+        graph.add_pass("draw", &[(scene, Access::ColorAttachment)], |_| {});
+        graph.add_pass(
+            "debug view",
+            &[
+                (scene, Access::ShaderRead),
+                (debug, Access::ColorAttachment),
+            ],
+            |_| {},
+        );
+        graph.add_pass(
+            "composite",
+            &[
+                (scene, Access::ShaderRead),
+                (output, Access::ColorAttachment),
+            ],
+            |_| {},
+        );
+        graph.add_pass("redraw", &[(scene, Access::ColorAttachment)], |_| {});
+
+        let dependencies = graph.dependencies();
+        assert_eq!(dependencies, [vec![], vec![0], vec![0], vec![0, 1, 2]]);
+        assert_eq!(graph.live_passes(&dependencies), [true, false, true, false]);
+    }
+
+    #[test]
+    fn transients_share_images_once_dead() {
+        let mut graph = RenderGraph::new();
+        let output = import_output(&mut graph);
+        let a = graph.create_image("a", DESC);
+        let b = graph.create_image("b", DESC);
+        let c = graph.create_image("c", DESC);
+        let unused = graph.create_image("unused", DESC);
+        graph.add_pass("a", &[(a, Access::ColorAttachment)], |_| {});
+        graph.add_pass(
+            "b",
+            &[(a, Access::ShaderRead), (b, Access::ColorAttachment)],
+            |_| {},
+        );
+        graph.add_pass(
+            "c",
+            &[(b, Access::ShaderRead), (c, Access::ColorAttachment)],
+            |_| {},
+        );
+        graph.add_pass(
+            "output",
+            &[(c, Access::ShaderRead), (output, Access::ColorAttachment)],
+            |_| {},
+        );
+
+        // a is dead after "b", so c takes its image; b overlaps both
+        let plan = graph.plan_transients(&[0, 1, 2, 3], &[]);
+        assert_eq!(plan.physical[output.0], None);
+        assert_eq!(plan.physical[a.0], Some(0));
+        assert_eq!(plan.physical[b.0], Some(1));
+        assert_eq!(plan.physical[c.0], Some(0));
+        assert_eq!(plan.physical[unused.0], None);
+        assert_eq!(plan.new_images.len(), 2);
+        assert_eq!(plan.used, [true, true]);
+    }
+
+    #[test]
+    fn transients_reuse_matching_pool_images_only() {
+        let mut graph = RenderGraph::new();
+        let output = import_output(&mut graph);
+        let half = ImageDesc {
+            extent: vk::Extent2D {
+                width: 32,
+                height: 32,
+            },
+            ..DESC
+        };
+        let small = graph.create_image("small", half);
+        let full = graph.create_image("full", DESC);
+        graph.add_pass("small", &[(small, Access::ColorAttachment)], |_| {});
+        graph.add_pass(
+            "full",
+            &[(small, Access::ShaderRead), (full, Access::ColorAttachment)],
+            |_| {},
+        );
+        graph.add_pass(
+            "output",
+            &[
+                (full, Access::ShaderRead),
+                (output, Access::ColorAttachment),
+            ],
+            |_| {},
+        );
+
+        let usage = vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED;
+        let pool = [(DESC, vk::ImageUsageFlags::COLOR_ATTACHMENT), (DESC, usage)];
+        // Small is dead before full is first used but their descriptions differ
+        let plan = graph.plan_transients(&[0, 1, 2], &pool);
+        assert_eq!(plan.physical[full.0], Some(1));
+        assert_eq!(plan.physical[small.0], Some(2));
+        assert_eq!(plan.new_images, [(half, usage)]);
+        assert_eq!(plan.used, [false, true, true]);
+    }
+}
@@ -0,0 +1,239 @@
+use ash::vk::{self, Handle};
+use std::collections::{BTreeMap, VecDeque};
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::util::constants::VALIDATION;
+use crate::util::debug::DebugUtils;
+
+// Ownership of device-level Vulkan objects. `Owned` destroys its handle when dropped, so an
+// owner's objects go in the order its fields are declared; `Scoped` does the same for
+// temporaries that don't outlive a function. The device itself is destroyed when the last
+// `Arc<Device>` goes, which has to be after everything made from it: owned objects only hold a
+// weak reference, and any still alive by then are listed in a leak report and never destroyed.
+// Objects that frames in flight may still use go through a `DeletionQueue` instead. Owned
+// objects are also given their name for debugging tools.
+
+// A device-level handle and how to destroy it
+pub trait DeviceObject: Handle + Copy {
+    const KIND: &'static str;
+
+    /// # Safety
+    /// The GPU must be done with the object, and nothing may use it afterwards
+    unsafe fn destroy(self, device: &ash::Device);
+}
+
+macro_rules! device_objects {
+    ($($handle:ty => $destroy:ident, $kind:literal;)*) => {
+        $(
+            impl DeviceObject for $handle {
+                const KIND: &'static str = $kind;
+
+                unsafe fn destroy(self, device: &ash::Device) {
+                    device.$destroy(self, None)
+                }
+            }
+        )*
+    };
+}
+
+device_objects! {
+    vk::Buffer => destroy_buffer, "buffer";
+    vk::DeviceMemory => free_memory, "memory";
+    vk::Image => destroy_image, "image";
+    vk::ImageView => destroy_image_view, "image view";
+    vk::Sampler => destroy_sampler, "sampler";
+    vk::ShaderModule => destroy_shader_module, "shader module";
+    vk::Pipeline => destroy_pipeline, "pipeline";
+    vk::PipelineLayout => destroy_pipeline_layout, "pipeline layout";
+    vk::DescriptorSetLayout => destroy_descriptor_set_layout, "descriptor set layout";
+    vk::DescriptorPool => destroy_descriptor_pool, "descriptor pool";
+    vk::RenderPass => destroy_render_pass, "render pass";
+    vk::Framebuffer => destroy_framebuffer, "framebuffer";
+    vk::CommandPool => destroy_command_pool, "command pool";
+    vk::Semaphore => destroy_semaphore, "semaphore";
+    vk::Fence => destroy_fence, "fence";
+}
+
+// The logical device and the objects owned through it
+pub struct Device {
+    raw: ash::Device,
+    this: Weak<Device>,
+    debug: DebugUtils,
+    // Kind and name of every live owned object by raw handle
+    live: Mutex<BTreeMap<u64, (&'static str, String)>>,
+}
+
+impl Device {
+    pub fn new(instance: &ash::Instance, raw: ash::Device) -> Arc<Device> {
+        Arc::new_cyclic(|this| Device {
+            debug: DebugUtils::new(instance, &raw),
+            raw,
+            this: this.clone(),
+            live: Mutex::default(),
+        })
+    }
+
+    // Object names and labels; no-ops without validation
+    pub fn debug(&self) -> &DebugUtils {
+        &self.debug
+    }
+
+    // Takes ownership of `handle`, naming it for debugging tools and the leak report. Null
+    // handles are allowed and destroy to nothing.
+    pub fn own<T: DeviceObject>(&self, handle: T, name: &str) -> Owned<T> {
+        self.debug.set_name(handle, name);
+        if !handle.is_null() {
+            self.live
+                .lock()
+                .unwrap()
+                .insert(handle.as_raw(), (T::KIND, name.to_string()));
+        }
+
+        Owned {
+            handle,
+            device: self.this.clone(),
+        }
+    }
+
+    pub fn own_all<T: DeviceObject>(&self, handles: Vec<T>, name: &str) -> Vec<Owned<T>> {
+        handles
+            .into_iter()
+            .enumerate()
+            .map(|(i, handle)| self.own(handle, &format!("{} {}", name, i)))
+            .collect()
+    }
+
+    fn report_leaks(&self) {
+        let live = self.live.lock().unwrap();
+        if live.is_empty() {
+            return;
+        }
+        println!(
+            "{} device objects still alive at device destruction:",
+            live.len()
+        );
+        for (handle, (kind, name)) in live.iter() {
+            println!("  {} {:#x} \"{}\"", kind, handle, name);
+        }
+    }
+}
+
+impl Deref for Device {
+    type Target = ash::Device;
+
+    fn deref(&self) -> &ash::Device {
+        &self.raw
+    }
+}
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        if VALIDATION.enabled {
+            self.report_leaks();
+        }
+        unsafe { self.raw.destroy_device(None) };
+    }
+}
+
+pub struct Owned<T: DeviceObject> {
+    handle: T,
+    device: Weak<Device>,
+}
+
+impl<T: DeviceObject> Owned<T> {
+    // Gives up ownership without destroying the handle
+    pub fn into_raw(self) -> T {
+        if let Some(device) = self.device.upgrade() {
+            device.live.lock().unwrap().remove(&self.handle.as_raw());
+        }
+        let handle = self.handle;
+        std::mem::forget(self);
+        handle
+    }
+}
+
+impl<T: DeviceObject> Deref for Owned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.handle
+    }
+}
+
+impl<T: DeviceObject> Drop for Owned<T> {
+    fn drop(&mut self) {
+        // Gone means destroyed already, and this object was reported as leaked
+        if let Some(device) = self.device.upgrade() {
+            device.live.lock().unwrap().remove(&self.handle.as_raw());
+            unsafe { self.handle.destroy(&device) };
+        }
+    }
+}
+
+// Destroys a handle at the end of the scope it's made in
+pub struct Scoped<'d, T: DeviceObject> {
+    handle: T,
+    device: &'d ash::Device,
+}
+
+impl<'d, T: DeviceObject> Scoped<'d, T> {
+    pub fn new(device: &'d ash::Device, handle: T) -> Scoped<'d, T> {
+        Scoped { handle, device }
+    }
+}
+
+impl<T: DeviceObject> Deref for Scoped<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.handle
+    }
+}
+
+impl<T: DeviceObject> Drop for Scoped<'_, T> {
+    fn drop(&mut self) {
+        unsafe { self.handle.destroy(self.device) };
+    }
+}
+
+type Deletion = Box<dyn FnOnce(&Device)>;
+
+// Resources retired while earlier frames may still read them, freed once those frames are done.
+// Frames are numbered in submission order, and a frame counts as done once its fence has been
+// waited on.
+#[derive(Default)]
+pub struct DeletionQueue {
+    // Oldest first, by the frame being recorded when they were retired
+    pending: VecDeque<(u64, Deletion)>,
+}
+
+impl DeletionQueue {
+    // `frame` is the one about to be recorded, which can't use the resource anymore
+    pub fn defer(&mut self, frame: u64, delete: impl FnOnce(&Device) + 'static) {
+        self.pending.push_back((frame, Box::new(delete)));
+    }
+
+    // For owned objects, which destroy themselves when dropped
+    pub fn defer_drop<T: 'static>(&mut self, frame: u64, value: T) {
+        self.defer(frame, move |_| drop(value));
+    }
+
+    // `done_before`: every frame numbered below it is done
+    pub fn collect(&mut self, device: &Device, done_before: u64) {
+        while let Some((frame, _)) = self.pending.front() {
+            if *frame > done_before {
+                break;
+            }
+            let (_, delete) = self.pending.pop_front().unwrap();
+            delete(device);
+        }
+    }
+
+    // Everything, once the device is idle
+    pub fn flush(&mut self, device: &Device) {
+        for (_, delete) in self.pending.drain(..) {
+            delete(device);
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use cgmath::{Matrix4, Point3, Quaternion, SquareMatrix, Transform as _, Vector3};
+
+use crate::assets::gltf::GltfScene;
+use crate::assets::mesh::Bounds;
+
+// Nodes live in one Vec and refer to each other by index. Each node keeps a local transform and
+// a cached world matrix; changing a local transform only flags that node, and the next
+// update_world_transforms recomputes it together with everything below it.
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct NodeId(usize);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform {
+            translation: Vector3::new(0.0_f32, 0.0_f32, 0.0_f32),
+            rotation: Quaternion::new(1.0_f32, 0.0_f32, 0.0_f32, 0.0_f32),
+            scale: Vector3::new(1.0_f32, 1.0_f32, 1.0_f32),
+        }
+    }
+}
+
+impl Transform {
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+pub struct SceneNode {
+    pub name: String,
+    local: Transform,
+    world: Matrix4<f32>,
+    dirty: bool,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    // Index into the model's meshes
+    pub mesh: Option<usize>,
+    // Replaces the material of every primitive in the mesh when set
+    pub material: Option<usize>,
+    // Hiding a node hides its whole subtree
+    pub visible: bool,
+}
+
+impl SceneNode {
+    pub fn local_transform(&self) -> &Transform {
+        &self.local
+    }
+
+    // Only up to date after update_world_transforms
+    pub fn world_transform(&self) -> &Matrix4<f32> {
+        &self.world
+    }
+
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+}
+
+// A node with a mesh that should be drawn this frame
+#[derive(Clone, Copy)]
+pub struct DrawNode<'a> {
+    pub mesh: usize,
+    pub material: Option<usize>,
+    pub transform: &'a Matrix4<f32>,
+}
+
+#[derive(Default)]
+pub struct Scene {
+    nodes: Vec<SceneNode>,
+    roots: Vec<NodeId>,
+}
+
+impl Scene {
+    pub fn new() -> Scene {
+        Scene::default()
+    }
+
+    // Mirrors the glTF node hierarchy; mesh indices match the order Model::from_gltf uploads in
+    pub fn from_gltf(gltf: &GltfScene) -> Scene {
+        let mut scene = Scene::new();
+        let mut stack: Vec<(usize, Option<NodeId>)> =
+            gltf.roots.iter().rev().map(|&root| (root, None)).collect();
+
+        while let Some((index, parent)) = stack.pop() {
+            let node = &gltf.nodes[index];
+            let [x, y, z, w] = node.rotation;
+            let id = scene.add_node(
+                &node.name,
+                parent,
+                Transform {
+                    translation: Vector3::from(node.translation),
+                    rotation: Quaternion::new(w, x, y, z),
+                    scale: Vector3::from(node.scale),
+                },
+            );
+            scene.nodes[id.0].mesh = node.mesh;
+            for &child in node.children.iter().rev() {
+                stack.push((child, Some(id)));
+            }
+        }
+        scene.update_world_transforms();
+
+        scene
+    }
+
+    pub fn add_node(&mut self, name: &str, parent: Option<NodeId>, local: Transform) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(SceneNode {
+            name: name.to_string(),
+            local,
+            world: Matrix4::identity(),
+            dirty: true,
+            parent,
+            children: vec![],
+            mesh: None,
+            material: None,
+            visible: true,
+        });
+        match parent {
+            Some(parent) => self.nodes[parent.0].children.push(id),
+            None => self.roots.push(id),
+        }
+
+        id
+    }
+
+    pub fn node(&self, id: NodeId) -> &SceneNode {
+        &self.nodes[id.0]
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> &mut SceneNode {
+        &mut self.nodes[id.0]
+    }
+
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    pub fn set_local_transform(&mut self, id: NodeId, local: Transform) {
+        let node = &mut self.nodes[id.0];
+        node.local = local;
+        node.dirty = true;
+    }
+
+    // Keeps the node's local transform, so it moves along with its new parent
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) {
+        let mut ancestor = parent;
+        while let Some(a) = ancestor {
+            assert!(
+                a != id,
+                "Node {} can't be parented to its own subtree",
+                id.0
+            );
+            ancestor = self.nodes[a.0].parent;
+        }
+
+        match self.nodes[id.0].parent {
+            Some(old) => self.nodes[old.0].children.retain(|&child| child != id),
+            None => self.roots.retain(|&root| root != id),
+        }
+        match parent {
+            Some(new) => self.nodes[new.0].children.push(id),
+            None => self.roots.push(id),
+        }
+        let node = &mut self.nodes[id.0];
+        node.parent = parent;
+        node.dirty = true;
+    }
+
+    // Recomputes dirty nodes and every descendant of one, leaving clean subtrees alone
+    pub fn update_world_transforms(&mut self) {
+        let mut stack: Vec<(NodeId, Matrix4<f32>, bool)> = self
+            .roots
+            .iter()
+            .map(|&root| (root, Matrix4::identity(), false))
+            .collect();
+
+        while let Some((id, parent_world, parent_changed)) = stack.pop() {
+            let node = &mut self.nodes[id.0];
+            let changed = node.dirty || parent_changed;
+            if changed {
+                node.world = parent_world * node.local.matrix();
+                node.dirty = false;
+            }
+            let world = node.world;
+            for &child in node.children.iter() {
+                stack.push((child, world, changed));
+            }
+        }
+    }
+
+    // Every mesh node whose subtree isn't hidden, in no particular order
+    pub fn draw_nodes(&self) -> Vec<DrawNode<'_>> {
+        let mut draws = vec![];
+        let mut stack: Vec<NodeId> = self.roots.clone();
+
+        while let Some(id) = stack.pop() {
+            let node = &self.nodes[id.0];
+            if !node.visible {
+                continue;
+            }
+            if let Some(mesh) = node.mesh {
+                draws.push(DrawNode {
+                    mesh,
+                    material: node.material,
+                    transform: &node.world,
+                });
+            }
+            stack.extend_from_slice(&node.children);
+        }
+
+        draws
+    }
+
+    // World-space box around the visible meshes, given each mesh's local bounds
+    pub fn bounds(
+        &self,
+        mesh_bounds: impl Fn(usize) -> Bounds,
+    ) -> Option<(Point3<f32>, Point3<f32>)> {
+        let mut result: Option<(Point3<f32>, Point3<f32>)> = None;
+        for draw in self.draw_nodes() {
+            let bounds = mesh_bounds(draw.mesh);
+            if bounds.is_empty() {
+                continue;
+            }
+            let (min, max) = (bounds.min, bounds.max);
+            for corner in 0..8 {
+                let local = Point3::new(
+                    if corner & 1 == 0 { min[0] } else { max[0] },
+                    if corner & 2 == 0 { min[1] } else { max[1] },
+                    if corner & 4 == 0 { min[2] } else { max[2] },
+                );
+                let p = draw.transform.transform_point(local);
+                result = Some(match result {
+                    Some((lo, hi)) => (
+                        Point3::new(lo.x.min(p.x), lo.y.min(p.y), lo.z.min(p.z)),
+                        Point3::new(hi.x.max(p.x), hi.y.max(p.y), hi.z.max(p.z)),
+                    ),
+                    None => (p, p),
+                });
+            }
+        }
+
+        result
+    }
+}
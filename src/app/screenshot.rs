@@ -0,0 +1,222 @@
+use ash::vk;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::thread::JoinHandle;
+use std::{ptr, thread};
+
+use super::buffer;
+use super::resources::Device;
+use crate::util::constants::{MAX_FRAMES_IN_FLIGHT, SCREENSHOT_DIR};
+use crate::util::structures::{BufferStuff, SwapChainStuff};
+
+struct Capture {
+    buffer_stuff: BufferStuff,
+    format: vk::Format,
+    extent: vk::Extent2D,
+}
+
+// Captures are copied into a per-frame readback buffer at the end of the frame's render graph
+// and only read back once that frame's fence has been waited on again, so taking a screenshot
+// never stalls the GPU. PNG encoding happens on a worker thread.
+pub struct Screenshot {
+    supported: bool,
+    requested: bool,
+    captures: Vec<Option<Capture>>,
+    writers: Vec<JoinHandle<()>>,
+}
+
+impl Screenshot {
+    pub fn new(supported: bool) -> Screenshot {
+        Screenshot {
+            supported,
+            requested: false,
+            captures: (0..MAX_FRAMES_IN_FLIGHT).map(|_| None).collect(),
+            writers: vec![],
+        }
+    }
+
+    pub fn request(&mut self) {
+        if self.supported {
+            self.requested = true;
+        } else {
+            println!(
+                "Screenshots are not supported: swapchain images cannot be used as transfer source"
+            );
+        }
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested
+    }
+
+    // Allocates the readback buffer for `frame`; the copy itself is a render graph pass
+    pub fn prepare_capture(
+        &mut self,
+        device: &Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        frame: usize,
+        swapchain_stuff: &SwapChainStuff,
+    ) -> Option<vk::Buffer> {
+        self.requested = false;
+
+        let format = swapchain_stuff.swapchain_format;
+        let extent = swapchain_stuff.swapchain_extent;
+        if !is_supported_format(format) {
+            println!(
+                "Screenshot skipped: unsupported swapchain format {:?}",
+                format
+            );
+            return None;
+        }
+
+        let size = (extent.width * extent.height * 4) as vk::DeviceSize;
+        let buffer_stuff = buffer::create_buffer(
+            device,
+            memory_properties,
+            size,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            &format!("screenshot readback {}", frame),
+        );
+        let buffer = *buffer_stuff.buffer;
+
+        // Replaces any stale capture, whose frame is done
+        self.captures[frame] = Some(Capture {
+            buffer_stuff,
+            format,
+            extent,
+        });
+
+        Some(buffer)
+    }
+
+    // Call once the in-flight fence of `frame` has been waited on
+    pub fn collect(&mut self, device: &Device, frame: usize) {
+        self.writers.retain(|writer| !writer.is_finished());
+
+        let Some(capture) = self.captures[frame].take() else {
+            return;
+        };
+
+        let mut pixels = vec![0_u8; capture.buffer_stuff.size as usize];
+        unsafe {
+            let data = device
+                .map_memory(
+                    *capture.buffer_stuff.memory,
+                    0,
+                    capture.buffer_stuff.size,
+                    vk::MemoryMapFlags::empty(),
+                )
+                .expect("Failed to map screenshot memory");
+            ptr::copy_nonoverlapping(data as *const u8, pixels.as_mut_ptr(), pixels.len());
+            device.unmap_memory(*capture.buffer_stuff.memory);
+        };
+        drop(capture.buffer_stuff);
+
+        let path = screenshot_path();
+        let writer = thread::spawn(move || {
+            to_rgba8(capture.format, &mut pixels);
+            match write_png(&path, capture.extent, &pixels) {
+                Ok(()) => println!("Saved screenshot to {}", path.display()),
+                Err(e) => println!("Failed to save screenshot to {}: {}", path.display(), e),
+            }
+        });
+        self.writers.push(writer);
+    }
+
+    // Saves the captures still waiting to be read back. Device must be idle.
+    pub fn flush(&mut self, device: &Device) {
+        for frame in 0..self.captures.len() {
+            self.collect(device, frame);
+        }
+        for writer in self.writers.drain(..) {
+            let _ = writer.join();
+        }
+    }
+}
+
+pub fn cmd_copy_image_to_buffer(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    extent: vk::Extent2D,
+    buffer: vk::Buffer,
+) {
+    let region = vk::BufferImageCopy {
+        buffer_offset: 0,
+        buffer_row_length: 0,
+        buffer_image_height: 0,
+        image_subresource: vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1,
+        },
+        image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+        image_extent: vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        },
+    };
+
+    unsafe {
+        device.cmd_copy_image_to_buffer(
+            command_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            buffer,
+            &[region],
+        )
+    };
+}
+
+fn is_supported_format(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::B8G8R8A8_SRGB
+            | vk::Format::B8G8R8A8_UNORM
+            | vk::Format::R8G8B8A8_SRGB
+            | vk::Format::R8G8B8A8_UNORM
+            | vk::Format::A8B8G8R8_SRGB_PACK32
+            | vk::Format::A8B8G8R8_UNORM_PACK32
+    )
+}
+
+// The swapchain holds display-referred values whether the format is *_SRGB (hardware encoded)
+// or *_UNORM with an SRGB_NONLINEAR color space (shader encoded), so the bytes are written
+// unchanged and tagged as sRGB. Only the channel order and the alpha need fixing up.
+fn to_rgba8(format: vk::Format, pixels: &mut [u8]) {
+    let bgra = matches!(
+        format,
+        vk::Format::B8G8R8A8_SRGB | vk::Format::B8G8R8A8_UNORM
+    );
+
+    for pixel in pixels.chunks_exact_mut(4) {
+        if bgra {
+            pixel.swap(0, 2);
+        }
+        pixel[3] = 255;
+    }
+}
+
+fn screenshot_path() -> PathBuf {
+    let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S-%3f");
+    Path::new(SCREENSHOT_DIR).join(format!("screenshot-{}.png", timestamp))
+}
+
+fn write_png(path: &Path, extent: vk::Extent2D, pixels: &[u8]) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+
+    let file = File::create(path).map_err(|e| e.to_string())?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), extent.width, extent.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    writer.write_image_data(pixels).map_err(|e| e.to_string())
+}
@@ -0,0 +1,642 @@
+use ash::vk;
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Transform, Vector3, Vector4};
+
+use super::camera::{Camera, Projection};
+use super::culling::Frustum;
+use super::descriptor::{self, FrameUniforms};
+use super::dynamic_rendering;
+use super::graphics_pipeline;
+use super::light::Lights;
+use super::model::{self, Model};
+use super::resources::{Device, Owned};
+use super::scene::DrawNode;
+use super::texture;
+use crate::assets::mesh::Vertex;
+use crate::util::constants::{SHADOW_CASCADES, SHADOW_MAP_SIZE};
+use crate::util::structures::{
+    DepthBias, GraphicsPipelineStuff, ImageStuff, PipelineDesc, RenderingPath,
+};
+
+// The sun's cascades and the spot light's shadow map are tiles of one depth atlas, all rendered
+// in a single pass with a viewport per tile. Shadow maps use regular depth (near 0, far 1)
+// whatever REVERSED_Z says, and the lit shader samples them through a comparison sampler with
+// PCF on top.
+
+const TILE_COUNT: usize = SHADOW_CASCADES + 1;
+const SPOT_TILE: usize = SHADOW_CASCADES;
+
+// Scaled by the format's minimum resolvable difference (constant) and the depth slope
+const DEPTH_BIAS: DepthBias = DepthBias {
+    constant_factor: 1.25_f32,
+    slope_factor: 1.75_f32,
+    clamp: 0.0_f32,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ShadowDebugView {
+    Off,
+    // Tints the scene by the cascade each pixel samples
+    Cascades,
+    // Draws the atlas over the corner of the screen
+    Atlas,
+}
+
+impl ShadowDebugView {
+    pub fn next(self) -> ShadowDebugView {
+        match self {
+            ShadowDebugView::Off => ShadowDebugView::Cascades,
+            ShadowDebugView::Cascades => ShadowDebugView::Atlas,
+            ShadowDebugView::Atlas => ShadowDebugView::Off,
+        }
+    }
+}
+
+pub struct ShadowSettings {
+    // Cascades cover the view from the near plane out to this distance
+    pub max_distance: f32,
+    // 0 places cascade splits evenly, 1 logarithmically
+    pub split_lambda: f32,
+    // How far towards the sun casters outside the view still throw shadows into it
+    pub caster_distance: f32,
+    pub pcf_radius: u32,
+    pub debug_view: ShadowDebugView,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> ShadowSettings {
+        ShadowSettings {
+            max_distance: 50.0_f32,
+            split_lambda: 0.75_f32,
+            caster_distance: 20.0_f32,
+            pcf_radius: 1,
+            debug_view: ShadowDebugView::Off,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct ShadowPushConstants {
+    light_view_proj: [[f32; 4]; 4],
+    model: [[f32; 4]; 4],
+}
+
+#[derive(Clone, Copy)]
+struct ShadowView {
+    view_proj: Matrix4<f32>,
+    active: bool,
+}
+
+pub struct ShadowMaps {
+    // Both null on the dynamic rendering path. The framebuffer goes before the atlas it renders to.
+    framebuffer: Owned<vk::Framebuffer>,
+    render_pass: Owned<vk::RenderPass>,
+    pub atlas: ImageStuff,
+    compare_sampler: Owned<vk::Sampler>,
+    depth_sampler: Owned<vk::Sampler>,
+    pipeline: GraphicsPipelineStuff,
+    debug_pipeline: GraphicsPipelineStuff,
+    views: [ShadowView; TILE_COUNT],
+    pub settings: ShadowSettings,
+}
+
+impl ShadowMaps {
+    // `color_render_pass`, `color_format` and `depth_format` describe the pass the debug overlay
+    // is drawn in
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        instance: &ash::Instance,
+        physical_device: &vk::PhysicalDevice,
+        device: &Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        rendering_path: RenderingPath,
+        frame_set_layout: vk::DescriptorSetLayout,
+        color_render_pass: vk::RenderPass,
+        color_format: vk::Format,
+        depth_format: vk::Format,
+    ) -> ShadowMaps {
+        let (format, linear_filter) = find_shadow_format(instance, physical_device);
+        let atlas = texture::create_image(
+            device,
+            memory_properties,
+            atlas_extent(),
+            1,
+            format,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            "shadow atlas",
+        );
+
+        // Linear filtering with comparison averages four depth tests for free
+        let filter = if linear_filter {
+            vk::Filter::LINEAR
+        } else {
+            vk::Filter::NEAREST
+        };
+        let compare_sampler = create_shadow_sampler(device, filter, true, "shadow compare");
+        let depth_sampler =
+            create_shadow_sampler(device, vk::Filter::NEAREST, false, "shadow depth");
+
+        let (render_pass, framebuffer) = match rendering_path {
+            RenderingPath::RenderPass => {
+                let render_pass = graphics_pipeline::create_depth_only_render_pass(device, format);
+                let framebuffer_info = vk::FramebufferCreateInfo {
+                    s_type: vk::StructureType::FRAMEBUFFER_CREATE_INFO,
+                    render_pass,
+                    attachment_count: 1,
+                    p_attachments: &*atlas.view,
+                    width: atlas.extent.width,
+                    height: atlas.extent.height,
+                    layers: 1,
+                    ..Default::default()
+                };
+                let framebuffer = unsafe {
+                    device
+                        .create_framebuffer(&framebuffer_info, None)
+                        .expect("Failed to create shadow framebuffer")
+                };
+                (render_pass, framebuffer)
+            }
+            RenderingPath::Dynamic => (vk::RenderPass::null(), vk::Framebuffer::null()),
+        };
+        let render_pass = device.own(render_pass, "shadow render pass");
+        let framebuffer = device.own(framebuffer, "shadow framebuffer");
+
+        let pipeline = graphics_pipeline::create_graphics_pipeline(
+            device,
+            &PipelineDesc {
+                vert_shader: "shadow.vert",
+                frag_shader: "shadow.frag",
+                vertex_bindings: &Vertex::binding_descriptions(),
+                vertex_attributes: &Vertex::attribute_descriptions(),
+                set_layouts: &[],
+                push_constant_ranges: &[vk::PushConstantRange {
+                    stage_flags: vk::ShaderStageFlags::VERTEX,
+                    offset: 0,
+                    size: std::mem::size_of::<ShadowPushConstants>() as u32,
+                }],
+                render_pass: *render_pass,
+                color_format: vk::Format::UNDEFINED,
+                depth_format: format,
+                depth_test: true,
+                depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+                depth_bias: Some(DEPTH_BIAS),
+                blend: false,
+                // Light projections don't agree on winding, and thin geometry needs both sides
+                cull_mode: vk::CullModeFlags::NONE,
+                front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            },
+        );
+        let debug_pipeline = graphics_pipeline::create_graphics_pipeline(
+            device,
+            &PipelineDesc {
+                vert_shader: "shadow_debug.vert",
+                frag_shader: "shadow_debug.frag",
+                vertex_bindings: &[],
+                vertex_attributes: &[],
+                set_layouts: &[frame_set_layout],
+                push_constant_ranges: &[vk::PushConstantRange {
+                    stage_flags: vk::ShaderStageFlags::VERTEX,
+                    offset: 0,
+                    size: std::mem::size_of::<[f32; 4]>() as u32,
+                }],
+                render_pass: color_render_pass,
+                color_format,
+                depth_format,
+                depth_test: false,
+                depth_compare_op: vk::CompareOp::ALWAYS,
+                depth_bias: None,
+                blend: false,
+                cull_mode: vk::CullModeFlags::NONE,
+                front_face: vk::FrontFace::CLOCKWISE,
+            },
+        );
+
+        ShadowMaps {
+            framebuffer,
+            render_pass,
+            atlas,
+            compare_sampler,
+            depth_sampler,
+            pipeline,
+            debug_pipeline,
+            views: [ShadowView {
+                view_proj: Matrix4::from_scale(1.0_f32),
+                active: false,
+            }; TILE_COUNT],
+            settings: ShadowSettings::default(),
+        }
+    }
+
+    // The atlas has to be in SHADER_READ_ONLY_OPTIMAL wherever these sets are used
+    pub fn write_descriptors(&self, device: &Device, frame_sets: &[vk::DescriptorSet]) {
+        for &descriptor_set in frame_sets.iter() {
+            descriptor::write_combined_image_sampler(
+                device,
+                descriptor_set,
+                1,
+                *self.atlas.view,
+                *self.compare_sampler,
+            );
+            descriptor::write_combined_image_sampler(
+                device,
+                descriptor_set,
+                2,
+                *self.atlas.view,
+                *self.depth_sampler,
+            );
+        }
+    }
+
+    // Fits the light projections to this frame's camera and fills in the shadow uniforms
+    pub fn update(
+        &mut self,
+        camera: &Camera,
+        aspect: f32,
+        lights: &Lights,
+        uniforms: &mut FrameUniforms,
+    ) {
+        let (near, far) = camera.projection.near_far();
+        let far = far.min(self.settings.max_distance).max(near);
+        let mut splits = [far; 4];
+        for (i, split) in splits.iter_mut().enumerate().take(SHADOW_CASCADES) {
+            // Blend between logarithmic and uniform splits ("practical" split scheme)
+            let t = (i + 1) as f32 / SHADOW_CASCADES as f32;
+            let logarithmic = near * (far / near).powf(t);
+            let uniform = near + (far - near) * t;
+            *split = self.settings.split_lambda * logarithmic
+                + (1.0_f32 - self.settings.split_lambda) * uniform;
+        }
+
+        let sun_active = lights.sun.casts_shadows;
+        let direction = lights.sun.direction.normalize();
+        let mut start = near;
+        for (cascade, &end) in splits.iter().enumerate().take(SHADOW_CASCADES) {
+            let corners = slice_corners(camera, aspect, start, end);
+            self.views[cascade] = ShadowView {
+                view_proj: cascade_matrix(direction, &corners, self.settings.caster_distance),
+                active: sun_active,
+            };
+            start = end;
+        }
+
+        self.views[SPOT_TILE] = match &lights.spot {
+            Some(spot) if spot.casts_shadows => {
+                let direction = spot.direction.normalize();
+                let view = Matrix4::look_to_rh(spot.position, direction, up_for(direction));
+                let projection = Projection::Perspective {
+                    // A little wider than the cone, so PCF near its edge stays inside the map
+                    fov_y_degrees: (spot.outer_angle * 2.0_f32).to_degrees().min(170.0_f32)
+                        + 2.0_f32,
+                    near: (spot.range * 0.01_f32).max(0.01_f32),
+                    far: spot.range,
+                };
+                ShadowView {
+                    view_proj: projection.matrix(1.0_f32, false) * view,
+                    active: true,
+                }
+            }
+            _ => ShadowView {
+                view_proj: Matrix4::from_scale(1.0_f32),
+                active: false,
+            },
+        };
+
+        for (cascade, view) in self.views.iter().enumerate().take(SHADOW_CASCADES) {
+            uniforms.cascade_view_proj[cascade] = view.view_proj.into();
+        }
+        uniforms.spot_view_proj = self.views[SPOT_TILE].view_proj.into();
+        uniforms.cascade_splits = splits;
+        let atlas = atlas_extent();
+        for (tile, uv) in uniforms.shadow_tiles.iter_mut().enumerate() {
+            let rect = tile_rect(tile);
+            *uv = [
+                rect.offset.x as f32 / atlas.width as f32,
+                rect.offset.y as f32 / atlas.height as f32,
+                rect.extent.width as f32 / atlas.width as f32,
+                rect.extent.height as f32 / atlas.height as f32,
+            ];
+        }
+        let flags = (sun_active as u32) | ((self.views[SPOT_TILE].active as u32) << 1);
+        uniforms.shadow_params = [
+            1.0_f32 / atlas.width as f32,
+            self.settings.pcf_radius as f32,
+            self.settings.debug_view as u32 as f32,
+            flags as f32,
+        ];
+    }
+
+    // Renders every active shadow map. `draws` should be all of the scene's draws, since
+    // objects outside the camera's view can still cast shadows into it.
+    pub fn record(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        model: &Model,
+        draws: &[DrawNode],
+    ) {
+        let clear_value = vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: 1.0_f32,
+                stencil: 0,
+            },
+        };
+        if *self.render_pass == vk::RenderPass::null() {
+            dynamic_rendering::cmd_begin_depth_rendering(
+                device,
+                command_buffer,
+                *self.atlas.view,
+                self.atlas.extent,
+                clear_value,
+            );
+        } else {
+            let render_pass_info = vk::RenderPassBeginInfo {
+                s_type: vk::StructureType::RENDER_PASS_BEGIN_INFO,
+                render_pass: *self.render_pass,
+                framebuffer: *self.framebuffer,
+                render_area: vk::Rect2D {
+                    offset: vk::Offset2D { x: 0, y: 0 },
+                    extent: self.atlas.extent,
+                },
+                clear_value_count: 1,
+                p_clear_values: &clear_value,
+                ..Default::default()
+            };
+            unsafe {
+                device.cmd_begin_render_pass(
+                    command_buffer,
+                    &render_pass_info,
+                    vk::SubpassContents::INLINE,
+                )
+            };
+        }
+
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                *self.pipeline.graphics_pipeline,
+            )
+        };
+        model.bind(device, command_buffer);
+
+        for (tile, view) in self.views.iter().enumerate() {
+            if !view.active {
+                continue;
+            }
+
+            let rect = tile_rect(tile);
+            let viewport = vk::Viewport {
+                x: rect.offset.x as f32,
+                y: rect.offset.y as f32,
+                width: rect.extent.width as f32,
+                height: rect.extent.height as f32,
+                min_depth: 0.0_f32,
+                max_depth: 1.0_f32,
+            };
+            unsafe {
+                device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+                device.cmd_set_scissor(command_buffer, 0, &[rect]);
+            }
+
+            let frustum = Frustum::from_matrix(&view.view_proj);
+            for draw in draws.iter() {
+                if !frustum.is_visible(model.mesh_bounds(draw.mesh), draw.transform) {
+                    continue;
+                }
+                let push_constants = ShadowPushConstants {
+                    light_view_proj: view.view_proj.into(),
+                    model: (*draw.transform).into(),
+                };
+                unsafe {
+                    device.cmd_push_constants(
+                        command_buffer,
+                        *self.pipeline.pipeline_layout,
+                        vk::ShaderStageFlags::VERTEX,
+                        0,
+                        model::as_bytes(std::slice::from_ref(&push_constants)),
+                    )
+                };
+                model.draw_mesh_geometry(device, command_buffer, draw.mesh);
+            }
+        }
+
+        unsafe {
+            if *self.render_pass == vk::RenderPass::null() {
+                device.cmd_end_rendering(command_buffer);
+            } else {
+                device.cmd_end_render_pass(command_buffer);
+            }
+        }
+    }
+
+    // Draws the atlas into the bottom right corner when that debug view is on. Has to be
+    // recorded inside a pass that samples the atlas.
+    pub fn record_debug_overlay(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        frame_set: vk::DescriptorSet,
+        extent: vk::Extent2D,
+    ) {
+        if self.settings.debug_view != ShadowDebugView::Atlas {
+            return;
+        }
+
+        // Normalized device coordinates with y pointing down, keeping the atlas' aspect ratio
+        let height = 0.8_f32;
+        let width = height * self.atlas.extent.width as f32 / self.atlas.extent.height as f32
+            * extent.height as f32
+            / extent.width.max(1) as f32;
+        let margin = 0.02_f32;
+        let rect: [f32; 4] = [
+            1.0_f32 - margin - width,
+            1.0_f32 - margin - height,
+            1.0_f32 - margin,
+            1.0_f32 - margin,
+        ];
+
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                *self.debug_pipeline.graphics_pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                *self.debug_pipeline.pipeline_layout,
+                0,
+                &[frame_set],
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                *self.debug_pipeline.pipeline_layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                model::as_bytes(&rect),
+            );
+            device.cmd_draw(command_buffer, 6, 1, 0, 0);
+        }
+    }
+}
+
+// Tiles fill a square-ish grid of SHADOW_MAP_SIZE cells
+fn atlas_columns() -> u32 {
+    (TILE_COUNT as f32).sqrt().ceil() as u32
+}
+
+fn atlas_extent() -> vk::Extent2D {
+    let columns = atlas_columns();
+    let rows = (TILE_COUNT as u32).div_ceil(columns);
+    vk::Extent2D {
+        width: columns * SHADOW_MAP_SIZE,
+        height: rows * SHADOW_MAP_SIZE,
+    }
+}
+
+fn tile_rect(tile: usize) -> vk::Rect2D {
+    let columns = atlas_columns();
+    vk::Rect2D {
+        offset: vk::Offset2D {
+            x: ((tile as u32 % columns) * SHADOW_MAP_SIZE) as i32,
+            y: ((tile as u32 / columns) * SHADOW_MAP_SIZE) as i32,
+        },
+        extent: vk::Extent2D {
+            width: SHADOW_MAP_SIZE,
+            height: SHADOW_MAP_SIZE,
+        },
+    }
+}
+
+// The format has to be renderable as depth and sampleable; the flag says whether comparison
+// results can be filtered linearly
+fn find_shadow_format(
+    instance: &ash::Instance,
+    physical_device: &vk::PhysicalDevice,
+) -> (vk::Format, bool) {
+    let required =
+        vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT | vk::FormatFeatureFlags::SAMPLED_IMAGE;
+
+    for format in [vk::Format::D32_SFLOAT, vk::Format::D16_UNORM] {
+        let features =
+            unsafe { instance.get_physical_device_format_properties(*physical_device, format) }
+                .optimal_tiling_features;
+        if features.contains(required) {
+            return (
+                format,
+                features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR),
+            );
+        }
+    }
+
+    panic!("Failed to find a supported shadow map format")
+}
+
+fn create_shadow_sampler(
+    device: &Device,
+    filter: vk::Filter,
+    compare: bool,
+    name: &str,
+) -> Owned<vk::Sampler> {
+    let sampler_info = vk::SamplerCreateInfo {
+        s_type: vk::StructureType::SAMPLER_CREATE_INFO,
+        mag_filter: filter,
+        min_filter: filter,
+        mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+        address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        // Passes (lit) when the fragment is no farther from the light than the stored depth
+        compare_enable: compare as vk::Bool32,
+        compare_op: vk::CompareOp::LESS_OR_EQUAL,
+        min_lod: 0.0_f32,
+        max_lod: 0.0_f32,
+        ..Default::default()
+    };
+
+    let sampler = unsafe {
+        device
+            .create_sampler(&sampler_info, None)
+            .expect("Failed to create shadow sampler")
+    };
+
+    device.own(sampler, name)
+}
+
+fn up_for(direction: Vector3<f32>) -> Vector3<f32> {
+    if direction.y.abs() > 0.99_f32 {
+        Vector3::unit_z()
+    } else {
+        Vector3::unit_y()
+    }
+}
+
+// World-space corners of the part of the view between two view distances
+fn slice_corners(camera: &Camera, aspect: f32, start: f32, end: f32) -> [Point3<f32>; 8] {
+    let forward = camera.forward();
+    let right = camera.right();
+    let up = right.cross(forward).normalize();
+
+    let mut corners = [Point3::origin(); 8];
+    for (i, &distance) in [start, end].iter().enumerate() {
+        let half_height = match camera.projection {
+            Projection::Perspective { fov_y_degrees, .. } => {
+                distance * (fov_y_degrees.to_radians() * 0.5_f32).tan()
+            }
+            Projection::Orthographic { height, .. } => height * 0.5_f32,
+        };
+        let half_width = half_height * aspect;
+        let center = camera.position + forward * distance;
+        for (j, (x, y)) in [(-1.0_f32, -1.0_f32), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)]
+            .into_iter()
+            .enumerate()
+        {
+            corners[i * 4 + j] = center + right * (x * half_width) + up * (y * half_height);
+        }
+    }
+
+    corners
+}
+
+// Orthographic light projection around the slice's bounding sphere. The sphere keeps the
+// projection's size constant as the camera turns, and snapping its center to whole texels keeps
+// shadow edges from shimmering as the camera moves.
+fn cascade_matrix(
+    direction: Vector3<f32>,
+    corners: &[Point3<f32>; 8],
+    caster_distance: f32,
+) -> Matrix4<f32> {
+    let center = Point3::centroid(corners);
+    let radius = corners
+        .iter()
+        .map(|&corner| (corner - center).magnitude())
+        .fold(0.0_f32, f32::max);
+    let radius = ((radius * 16.0_f32).ceil() / 16.0_f32).max(0.001_f32);
+
+    let light_view = Matrix4::look_to_rh(Point3::origin(), direction, up_for(direction));
+    let center = light_view.transform_point(center);
+    let texel = 2.0_f32 * radius / SHADOW_MAP_SIZE as f32;
+    let x = (center.x / texel).floor() * texel;
+    let y = (center.y / texel).floor() * texel;
+
+    // The view looks down -z, so the slice spans -center.z ± radius in depth
+    let near = -center.z - radius - caster_distance;
+    let far = -center.z + radius;
+    orthographic(x - radius, x + radius, y - radius, y + radius, near, far) * light_view
+}
+
+// Vulkan clip space with regular depth
+fn orthographic(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> Matrix4<f32> {
+    Matrix4::from_cols(
+        Vector4::new(2.0_f32 / (right - left), 0.0_f32, 0.0_f32, 0.0_f32),
+        Vector4::new(0.0_f32, 2.0_f32 / (top - bottom), 0.0_f32, 0.0_f32),
+        Vector4::new(0.0_f32, 0.0_f32, -1.0_f32 / (far - near), 0.0_f32),
+        Vector4::new(
+            -(right + left) / (right - left),
+            -(top + bottom) / (top - bottom),
+            -near / (far - near),
+            1.0_f32,
+        ),
+    )
+}
@@ -0,0 +1,755 @@
+use ash::vk;
+
+use super::buffer;
+use super::commands;
+use super::render_graph::aspect_mask;
+use super::resources::{Device, Owned};
+use crate::assets::material::{ImageData, SamplerDesc};
+use crate::util::structures::ImageStuff;
+
+pub fn create_image(
+    device: &Device,
+    memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    extent: vk::Extent2D,
+    mip_levels: u32,
+    format: vk::Format,
+    usage: vk::ImageUsageFlags,
+    name: &str,
+) -> ImageStuff {
+    let image_info = vk::ImageCreateInfo {
+        s_type: vk::StructureType::IMAGE_CREATE_INFO,
+        image_type: vk::ImageType::TYPE_2D,
+        format,
+        extent: vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        },
+        mip_levels,
+        array_layers: 1,
+        samples: vk::SampleCountFlags::TYPE_1,
+        tiling: vk::ImageTiling::OPTIMAL,
+        usage,
+        sharing_mode: vk::SharingMode::EXCLUSIVE,
+        initial_layout: vk::ImageLayout::UNDEFINED,
+        ..Default::default()
+    };
+
+    let image = unsafe {
+        device
+            .create_image(&image_info, None)
+            .expect("Failed to create image")
+    };
+    let memory = allocate_image_memory(device, memory_properties, image);
+    let view = create_image_view(
+        device,
+        image,
+        vk::ImageViewType::TYPE_2D,
+        format,
+        0..mip_levels,
+        1,
+    );
+    let (view, image, memory) = own_image(device, view, image, memory, name);
+
+    ImageStuff {
+        view,
+        image,
+        memory,
+        format,
+        extent,
+        mip_levels,
+    }
+}
+
+// Owns the image named `name`, and its memory and view named after it
+pub fn own_image(
+    device: &Device,
+    view: vk::ImageView,
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+    name: &str,
+) -> (
+    Owned<vk::ImageView>,
+    Owned<vk::Image>,
+    Owned<vk::DeviceMemory>,
+) {
+    (
+        device.own(view, &format!("{} view", name)),
+        device.own(image, name),
+        device.own(memory, &format!("{} memory", name)),
+    )
+}
+
+// Six square layers, viewed as a cube
+pub fn create_cube_image(
+    device: &Device,
+    memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    size: u32,
+    mip_levels: u32,
+    format: vk::Format,
+    usage: vk::ImageUsageFlags,
+    name: &str,
+) -> ImageStuff {
+    let image_info = vk::ImageCreateInfo {
+        s_type: vk::StructureType::IMAGE_CREATE_INFO,
+        flags: vk::ImageCreateFlags::CUBE_COMPATIBLE,
+        image_type: vk::ImageType::TYPE_2D,
+        format,
+        extent: vk::Extent3D {
+            width: size,
+            height: size,
+            depth: 1,
+        },
+        mip_levels,
+        array_layers: 6,
+        samples: vk::SampleCountFlags::TYPE_1,
+        tiling: vk::ImageTiling::OPTIMAL,
+        usage,
+        sharing_mode: vk::SharingMode::EXCLUSIVE,
+        initial_layout: vk::ImageLayout::UNDEFINED,
+        ..Default::default()
+    };
+
+    let image = unsafe {
+        device
+            .create_image(&image_info, None)
+            .expect("Failed to create cube image")
+    };
+    let memory = allocate_image_memory(device, memory_properties, image);
+    let view = create_image_view(
+        device,
+        image,
+        vk::ImageViewType::CUBE,
+        format,
+        0..mip_levels,
+        6,
+    );
+    let (view, image, memory) = own_image(device, view, image, memory, name);
+
+    ImageStuff {
+        view,
+        image,
+        memory,
+        format,
+        extent: vk::Extent2D {
+            width: size,
+            height: size,
+        },
+        mip_levels,
+    }
+}
+
+// A `size`³ volume from tightly packed RGBA8 texels, x varying fastest, e.g. a color lookup table
+pub fn create_volume_texture(
+    device: &Device,
+    memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    command_pool: vk::CommandPool,
+    queue: vk::Queue,
+    size: u32,
+    texels: &[u8],
+    name: &str,
+) -> ImageStuff {
+    let format = vk::Format::R8G8B8A8_UNORM;
+    let image_info = vk::ImageCreateInfo {
+        s_type: vk::StructureType::IMAGE_CREATE_INFO,
+        image_type: vk::ImageType::TYPE_3D,
+        format,
+        extent: vk::Extent3D {
+            width: size,
+            height: size,
+            depth: size,
+        },
+        mip_levels: 1,
+        array_layers: 1,
+        samples: vk::SampleCountFlags::TYPE_1,
+        tiling: vk::ImageTiling::OPTIMAL,
+        usage: vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+        sharing_mode: vk::SharingMode::EXCLUSIVE,
+        initial_layout: vk::ImageLayout::UNDEFINED,
+        ..Default::default()
+    };
+
+    let image = unsafe {
+        device
+            .create_image(&image_info, None)
+            .expect("Failed to create volume image")
+    };
+    let memory = allocate_image_memory(device, memory_properties, image);
+    let view = create_image_view(device, image, vk::ImageViewType::TYPE_3D, format, 0..1, 1);
+    let (view, image, memory) = own_image(device, view, image, memory, name);
+    let staging = buffer::create_staging_buffer(device, memory_properties, texels);
+
+    let command_buffer = commands::begin_single_time_commands(device, command_pool);
+    cmd_transition_image(
+        device,
+        command_buffer,
+        *image,
+        1,
+        vk::ImageLayout::UNDEFINED,
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+    );
+    let region = vk::BufferImageCopy {
+        buffer_offset: 0,
+        buffer_row_length: 0,
+        buffer_image_height: 0,
+        image_subresource: vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1,
+        },
+        image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+        image_extent: image_info.extent,
+    };
+    unsafe {
+        device.cmd_copy_buffer_to_image(
+            command_buffer,
+            *staging.buffer,
+            *image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[region],
+        )
+    };
+    cmd_transition_image(
+        device,
+        command_buffer,
+        *image,
+        1,
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    );
+    commands::end_single_time_commands(device, command_pool, queue, command_buffer);
+
+    drop(staging);
+
+    ImageStuff {
+        view,
+        image,
+        memory,
+        format,
+        extent: vk::Extent2D {
+            width: size,
+            height: size,
+        },
+        mip_levels: 1,
+    }
+}
+
+fn allocate_image_memory(
+    device: &Device,
+    memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    image: vk::Image,
+) -> vk::DeviceMemory {
+    let requirements = unsafe { device.get_image_memory_requirements(image) };
+    let alloc_info = vk::MemoryAllocateInfo {
+        s_type: vk::StructureType::MEMORY_ALLOCATE_INFO,
+        allocation_size: requirements.size,
+        memory_type_index: buffer::find_memory_type(
+            memory_properties,
+            requirements.memory_type_bits,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        ),
+        ..Default::default()
+    };
+
+    unsafe {
+        let memory = device
+            .allocate_memory(&alloc_info, None)
+            .expect("Failed to allocate image memory");
+        device
+            .bind_image_memory(image, memory, 0)
+            .expect("Failed to bind image memory");
+        memory
+    }
+}
+
+// Views `layer_count` layers starting at the first of the mip levels in `mips`
+pub fn create_image_view(
+    device: &Device,
+    image: vk::Image,
+    view_type: vk::ImageViewType,
+    format: vk::Format,
+    mips: std::ops::Range<u32>,
+    layer_count: u32,
+) -> vk::ImageView {
+    let view_info = vk::ImageViewCreateInfo {
+        s_type: vk::StructureType::IMAGE_VIEW_CREATE_INFO,
+        image,
+        view_type,
+        format,
+        subresource_range: vk::ImageSubresourceRange {
+            aspect_mask: aspect_mask(format),
+            base_mip_level: mips.start,
+            level_count: mips.end - mips.start,
+            base_array_layer: 0,
+            layer_count,
+        },
+        ..Default::default()
+    };
+
+    unsafe {
+        device
+            .create_image_view(&view_info, None)
+            .expect("Failed to create image view")
+    }
+}
+
+pub fn find_depth_format(
+    instance: &ash::Instance,
+    physical_device: &vk::PhysicalDevice,
+) -> vk::Format {
+    let candidates = [
+        vk::Format::D32_SFLOAT,
+        vk::Format::D32_SFLOAT_S8_UINT,
+        vk::Format::D24_UNORM_S8_UINT,
+    ];
+
+    for &format in candidates.iter() {
+        let properties =
+            unsafe { instance.get_physical_device_format_properties(*physical_device, format) };
+        if properties
+            .optimal_tiling_features
+            .contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+        {
+            return format;
+        }
+    }
+
+    panic!("Failed to find a supported depth format")
+}
+
+// Formats uploaded textures use, and whether their optimal tiling supports linear filtering in
+// blits. Mip chains for formats that don't are built on the CPU instead.
+#[derive(Clone, Copy, Debug)]
+pub struct TextureFormatSupport {
+    pub srgb_linear_blit: bool,
+    pub unorm_linear_blit: bool,
+}
+
+impl TextureFormatSupport {
+    pub fn query(instance: &ash::Instance, physical_device: &vk::PhysicalDevice) -> Self {
+        let linear_blit = |format| {
+            let properties =
+                unsafe { instance.get_physical_device_format_properties(*physical_device, format) };
+            properties.optimal_tiling_features.contains(
+                vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR
+                    | vk::FormatFeatureFlags::BLIT_SRC
+                    | vk::FormatFeatureFlags::BLIT_DST,
+            )
+        };
+
+        TextureFormatSupport {
+            srgb_linear_blit: linear_blit(vk::Format::R8G8B8A8_SRGB),
+            unorm_linear_blit: linear_blit(vk::Format::R8G8B8A8_UNORM),
+        }
+    }
+
+    fn linear_blit(&self, format: vk::Format) -> bool {
+        match format {
+            vk::Format::R8G8B8A8_SRGB => self.srgb_linear_blit,
+            _ => self.unorm_linear_blit,
+        }
+    }
+}
+
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+// Uploads tightly packed RGBA8 pixels with a full mip chain and leaves every level in
+// SHADER_READ_ONLY_OPTIMAL
+pub fn create_texture(
+    device: &Device,
+    memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    command_pool: vk::CommandPool,
+    queue: vk::Queue,
+    format_support: &TextureFormatSupport,
+    image_data: &ImageData,
+    name: &str,
+) -> ImageStuff {
+    let format = if image_data.srgb {
+        vk::Format::R8G8B8A8_SRGB
+    } else {
+        vk::Format::R8G8B8A8_UNORM
+    };
+    let extent = vk::Extent2D {
+        width: image_data.width,
+        height: image_data.height,
+    };
+    let mip_levels = mip_level_count(extent.width, extent.height);
+    let blit = mip_levels > 1 && format_support.linear_blit(format);
+
+    // Without linear blits every level is uploaded from the CPU
+    let levels = if blit || mip_levels == 1 {
+        vec![image_data.pixels.clone()]
+    } else {
+        generate_mipmaps_cpu(image_data, mip_levels)
+    };
+    let staging = buffer::create_staging_buffer(device, memory_properties, &levels.concat());
+
+    let image_stuff = create_image(
+        device,
+        memory_properties,
+        extent,
+        mip_levels,
+        format,
+        vk::ImageUsageFlags::TRANSFER_SRC
+            | vk::ImageUsageFlags::TRANSFER_DST
+            | vk::ImageUsageFlags::SAMPLED,
+        name,
+    );
+
+    let command_buffer = commands::begin_single_time_commands(device, command_pool);
+    cmd_transition_image(
+        device,
+        command_buffer,
+        *image_stuff.image,
+        image_stuff.mip_levels,
+        vk::ImageLayout::UNDEFINED,
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+    );
+
+    let mut buffer_offset = 0;
+    let regions: Vec<_> = levels
+        .iter()
+        .enumerate()
+        .map(|(level, pixels)| {
+            let region = vk::BufferImageCopy {
+                buffer_offset,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: level as u32,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                },
+                image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+                image_extent: vk::Extent3D {
+                    width: (extent.width >> level).max(1),
+                    height: (extent.height >> level).max(1),
+                    depth: 1,
+                },
+            };
+            buffer_offset += pixels.len() as vk::DeviceSize;
+            region
+        })
+        .collect();
+    unsafe {
+        device.cmd_copy_buffer_to_image(
+            command_buffer,
+            *staging.buffer,
+            *image_stuff.image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &regions,
+        )
+    };
+
+    if blit {
+        cmd_generate_mipmaps(device, command_buffer, &image_stuff, 1);
+    } else {
+        cmd_transition_image(
+            device,
+            command_buffer,
+            *image_stuff.image,
+            image_stuff.mip_levels,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+    }
+    commands::end_single_time_commands(device, command_pool, queue, command_buffer);
+
+    drop(staging);
+
+    image_stuff
+}
+
+// Each level is blitted from the one above it, which is then done being read and moves to
+// SHADER_READ_ONLY_OPTIMAL for fragment and compute shaders. Expects all levels of all
+// `layer_count` layers in TRANSFER_DST_OPTIMAL with level 0 filled.
+pub fn cmd_generate_mipmaps(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    image_stuff: &ImageStuff,
+    layer_count: u32,
+) {
+    let level_barrier =
+        |level: u32,
+         old_layout: vk::ImageLayout,
+         new_layout: vk::ImageLayout,
+         src_access_mask: vk::AccessFlags,
+         dst_access_mask: vk::AccessFlags| vk::ImageMemoryBarrier {
+            s_type: vk::StructureType::IMAGE_MEMORY_BARRIER,
+            src_access_mask,
+            dst_access_mask,
+            old_layout,
+            new_layout,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            image: *image_stuff.image,
+            subresource_range: vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: level,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count,
+            },
+            ..Default::default()
+        };
+
+    let mip_extent = |level: u32| vk::Offset3D {
+        x: (image_stuff.extent.width >> level).max(1) as i32,
+        y: (image_stuff.extent.height >> level).max(1) as i32,
+        z: 1,
+    };
+
+    for level in 1..image_stuff.mip_levels {
+        let to_source = level_barrier(
+            level - 1,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::TRANSFER_READ,
+        );
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[to_source],
+            )
+        };
+
+        let subresource = |mip_level| vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level,
+            base_array_layer: 0,
+            layer_count,
+        };
+        let region = vk::ImageBlit {
+            src_subresource: subresource(level - 1),
+            src_offsets: [vk::Offset3D { x: 0, y: 0, z: 0 }, mip_extent(level - 1)],
+            dst_subresource: subresource(level),
+            dst_offsets: [vk::Offset3D { x: 0, y: 0, z: 0 }, mip_extent(level)],
+        };
+        unsafe {
+            device.cmd_blit_image(
+                command_buffer,
+                *image_stuff.image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                *image_stuff.image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[region],
+                vk::Filter::LINEAR,
+            )
+        };
+
+        let to_shader_read = level_barrier(
+            level - 1,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::AccessFlags::TRANSFER_READ,
+            vk::AccessFlags::SHADER_READ,
+        );
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[to_shader_read],
+            )
+        };
+    }
+
+    // The last level is only ever written
+    let last = level_barrier(
+        image_stuff.mip_levels - 1,
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        vk::AccessFlags::TRANSFER_WRITE,
+        vk::AccessFlags::SHADER_READ,
+    );
+    unsafe {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[last],
+        )
+    };
+}
+
+// 2x2 box filter per level, averaging sRGB images in linear space. Odd sizes clamp the last
+// row/column instead of dropping it.
+fn generate_mipmaps_cpu(image_data: &ImageData, mip_levels: u32) -> Vec<Vec<u8>> {
+    let to_linear = |value: u8| -> f32 {
+        let c = value as f32 / 255.0_f32;
+        if !image_data.srgb {
+            c
+        } else if c <= 0.04045_f32 {
+            c / 12.92_f32
+        } else {
+            ((c + 0.055_f32) / 1.055_f32).powf(2.4_f32)
+        }
+    };
+    let from_linear = |c: f32| -> u8 {
+        let c = if !image_data.srgb {
+            c
+        } else if c <= 0.0031308_f32 {
+            c * 12.92_f32
+        } else {
+            1.055_f32 * c.powf(1.0_f32 / 2.4_f32) - 0.055_f32
+        };
+        (c.clamp(0.0_f32, 1.0_f32) * 255.0_f32).round() as u8
+    };
+
+    let mut levels = vec![image_data.pixels.clone()];
+    let (mut width, mut height) = (image_data.width as usize, image_data.height as usize);
+    for _ in 1..mip_levels {
+        let previous = levels.last().unwrap();
+        let (next_width, next_height) = ((width / 2).max(1), (height / 2).max(1));
+        let mut next = Vec::with_capacity(next_width * next_height * 4);
+
+        for y in 0..next_height {
+            for x in 0..next_width {
+                let texels = [
+                    (2 * x, 2 * y),
+                    ((2 * x + 1).min(width - 1), 2 * y),
+                    (2 * x, (2 * y + 1).min(height - 1)),
+                    ((2 * x + 1).min(width - 1), (2 * y + 1).min(height - 1)),
+                ];
+                for channel in 0..4 {
+                    let sum: f32 = texels
+                        .iter()
+                        .map(|&(tx, ty)| previous[(ty * width + tx) * 4 + channel])
+                        .map(|value| {
+                            // Alpha is always linear
+                            if channel == 3 {
+                                value as f32 / 255.0_f32
+                            } else {
+                                to_linear(value)
+                            }
+                        })
+                        .sum();
+                    let average = sum / 4.0_f32;
+                    next.push(if channel == 3 {
+                        (average * 255.0_f32).round() as u8
+                    } else {
+                        from_linear(average)
+                    });
+                }
+            }
+        }
+
+        levels.push(next);
+        (width, height) = (next_width, next_height);
+    }
+
+    levels
+}
+
+// Load-time layout transitions, outside the render graph
+fn cmd_transition_image(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    mip_levels: u32,
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+) {
+    let (src_access_mask, src_stage) = match old_layout {
+        vk::ImageLayout::UNDEFINED => (
+            vk::AccessFlags::empty(),
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+        ),
+        _ => (
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::PipelineStageFlags::TRANSFER,
+        ),
+    };
+    let (dst_access_mask, dst_stage) = match new_layout {
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => (
+            vk::AccessFlags::SHADER_READ,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+        ),
+        _ => (
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::PipelineStageFlags::TRANSFER,
+        ),
+    };
+
+    let barrier = vk::ImageMemoryBarrier {
+        s_type: vk::StructureType::IMAGE_MEMORY_BARRIER,
+        src_access_mask,
+        dst_access_mask,
+        old_layout,
+        new_layout,
+        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        image,
+        subresource_range: vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: mip_levels,
+            base_array_layer: 0,
+            layer_count: 1,
+        },
+        ..Default::default()
+    };
+
+    unsafe {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            src_stage,
+            dst_stage,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[barrier],
+        )
+    };
+}
+
+pub fn create_sampler(device: &Device, desc: &SamplerDesc, name: &str) -> Owned<vk::Sampler> {
+    let sampler_info = vk::SamplerCreateInfo {
+        s_type: vk::StructureType::SAMPLER_CREATE_INFO,
+        mag_filter: desc.mag_filter,
+        min_filter: desc.min_filter,
+        mipmap_mode: desc.mipmap_mode,
+        address_mode_u: desc.address_mode_u,
+        address_mode_v: desc.address_mode_v,
+        address_mode_w: vk::SamplerAddressMode::REPEAT,
+        mip_lod_bias: 0.0_f32,
+        anisotropy_enable: vk::FALSE,
+        max_anisotropy: 1.0_f32,
+        compare_enable: vk::FALSE,
+        compare_op: vk::CompareOp::ALWAYS,
+        // The full chain is clamped to the image's own mip count by the view
+        min_lod: 0.0_f32,
+        max_lod: if desc.mipmaps {
+            vk::LOD_CLAMP_NONE
+        } else {
+            0.0_f32
+        },
+        border_color: vk::BorderColor::INT_OPAQUE_BLACK,
+        unnormalized_coordinates: vk::FALSE,
+        ..Default::default()
+    };
+
+    let sampler = unsafe {
+        device
+            .create_sampler(&sampler_info, None)
+            .expect("Failed to create sampler")
+    };
+
+    device.own(sampler, name)
+}
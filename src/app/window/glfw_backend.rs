@@ -0,0 +1,361 @@
+use super::{
+    Action, GamepadAxis, GamepadButton, GamepadState, Key, Monitor, MouseButton, Placement,
+    VideoMode, Window, WindowEvent, WindowMode,
+};
+use ash::vk;
+use glfw::{ClientApiHint, JoystickId, WindowHint};
+use std::ffi::CString;
+use std::ptr;
+
+// GLFW delivers events through a channel filled by `poll_events`, and is the only backend that
+// reads gamepads.
+
+const GAMEPAD_BUTTONS: [(glfw::GamepadButton, GamepadButton); 15] = [
+    (glfw::GamepadButton::ButtonA, GamepadButton::A),
+    (glfw::GamepadButton::ButtonB, GamepadButton::B),
+    (glfw::GamepadButton::ButtonX, GamepadButton::X),
+    (glfw::GamepadButton::ButtonY, GamepadButton::Y),
+    (
+        glfw::GamepadButton::ButtonLeftBumper,
+        GamepadButton::LeftBumper,
+    ),
+    (
+        glfw::GamepadButton::ButtonRightBumper,
+        GamepadButton::RightBumper,
+    ),
+    (glfw::GamepadButton::ButtonBack, GamepadButton::Back),
+    (glfw::GamepadButton::ButtonStart, GamepadButton::Start),
+    (glfw::GamepadButton::ButtonGuide, GamepadButton::Guide),
+    (
+        glfw::GamepadButton::ButtonLeftThumb,
+        GamepadButton::LeftThumb,
+    ),
+    (
+        glfw::GamepadButton::ButtonRightThumb,
+        GamepadButton::RightThumb,
+    ),
+    (glfw::GamepadButton::ButtonDpadUp, GamepadButton::DpadUp),
+    (
+        glfw::GamepadButton::ButtonDpadRight,
+        GamepadButton::DpadRight,
+    ),
+    (glfw::GamepadButton::ButtonDpadDown, GamepadButton::DpadDown),
+    (glfw::GamepadButton::ButtonDpadLeft, GamepadButton::DpadLeft),
+];
+const GAMEPAD_AXES: [(glfw::GamepadAxis, GamepadAxis); 6] = [
+    (glfw::GamepadAxis::AxisLeftX, GamepadAxis::LeftX),
+    (glfw::GamepadAxis::AxisLeftY, GamepadAxis::LeftY),
+    (glfw::GamepadAxis::AxisRightX, GamepadAxis::RightX),
+    (glfw::GamepadAxis::AxisRightY, GamepadAxis::RightY),
+    (glfw::GamepadAxis::AxisLeftTrigger, GamepadAxis::LeftTrigger),
+    (
+        glfw::GamepadAxis::AxisRightTrigger,
+        GamepadAxis::RightTrigger,
+    ),
+];
+
+pub struct GlfwWindow {
+    window: glfw::PWindow,
+    events: glfw::GlfwReceiver<(f64, glfw::WindowEvent)>,
+    glfw: glfw::Glfw,
+    mode: WindowMode,
+    // Where the window goes back to when leaving fullscreen
+    windowed: (i32, i32, u32, u32),
+}
+
+impl GlfwWindow {
+    pub fn new(title: &str, placement: Placement) -> GlfwWindow {
+        let mut glfw = glfw::init(glfw::fail_on_errors).unwrap();
+
+        glfw.window_hint(WindowHint::ClientApi(ClientApiHint::NoApi));
+
+        let (width, height) = placement.size;
+        let (mut window, events) = glfw
+            .create_window(width, height, title, glfw::WindowMode::Windowed)
+            .expect("Failed to create GLFW window.");
+        if let Some((x, y)) = placement.position {
+            window.set_pos(x, y);
+        }
+
+        window.set_key_polling(true);
+        window.set_mouse_button_polling(true);
+        window.set_cursor_pos_polling(true);
+        window.set_scroll_polling(true);
+        window.set_focus_polling(true);
+        window.set_framebuffer_size_polling(true);
+
+        let mut glfw_window = GlfwWindow {
+            window,
+            events,
+            glfw,
+            mode: WindowMode::Windowed,
+            windowed: (0, 0, width, height),
+        };
+        glfw_window.windowed = glfw_window.current_rect();
+
+        glfw_window
+    }
+
+    fn current_rect(&self) -> (i32, i32, u32, u32) {
+        let (x, y) = self.window.get_pos();
+        let (width, height) = self.window.get_size();
+        (x, y, width.max(1) as u32, height.max(1) as u32)
+    }
+}
+
+impl Window for GlfwWindow {
+    fn required_instance_extensions(&self) -> Vec<CString> {
+        self.glfw
+            .get_required_instance_extensions()
+            .unwrap()
+            .iter()
+            .map(|x| CString::new(x.as_str()).unwrap())
+            .collect()
+    }
+
+    fn create_surface(
+        &self,
+        _entry: &ash::Entry,
+        instance: &ash::Instance,
+    ) -> Result<vk::SurfaceKHR, vk::Result> {
+        let mut surface = vk::SurfaceKHR::null();
+        let p_surface: *mut vk::SurfaceKHR = &mut surface;
+
+        match self
+            .window
+            .create_window_surface(instance.handle(), ptr::null(), p_surface)
+        {
+            vk::Result::SUCCESS => Ok(surface),
+            result => Err(result),
+        }
+    }
+
+    fn framebuffer_size(&self) -> (u32, u32) {
+        let (width, height) = self.window.get_framebuffer_size();
+        (width.max(0) as u32, height.max(0) as u32)
+    }
+
+    fn poll_events(&mut self) -> Vec<WindowEvent> {
+        self.glfw.poll_events();
+        glfw::flush_messages(&self.events)
+            .filter_map(|(_, event)| convert_event(event))
+            .collect()
+    }
+
+    fn wait_events(&mut self) {
+        self.glfw.wait_events();
+    }
+
+    fn should_close(&self) -> bool {
+        self.window.should_close()
+    }
+
+    fn set_should_close(&mut self, value: bool) {
+        self.window.set_should_close(value);
+    }
+
+    fn monitors(&mut self) -> Vec<Monitor> {
+        self.glfw.with_connected_monitors(|_, monitors| {
+            monitors
+                .iter()
+                .map(|monitor| {
+                    let mut video_modes: Vec<_> = monitor
+                        .get_video_modes()
+                        .into_iter()
+                        .map(convert_video_mode)
+                        .collect();
+                    video_modes.sort();
+                    video_modes.dedup();
+                    Monitor {
+                        name: monitor.get_name().unwrap_or_default(),
+                        position: monitor.get_pos(),
+                        current_mode: monitor.get_video_mode().map(convert_video_mode),
+                        video_modes,
+                    }
+                })
+                .collect()
+        })
+    }
+
+    fn mode(&self) -> WindowMode {
+        self.mode
+    }
+
+    fn set_mode(&mut self, mode: WindowMode, monitor: usize, video_mode: Option<VideoMode>) {
+        if self.mode == WindowMode::Windowed {
+            self.windowed = self.current_rect();
+        }
+
+        let windowed = self.windowed;
+        let window = &mut self.window;
+        self.mode = self.glfw.with_connected_monitors(|_, monitors| {
+            let Some(monitor) = monitors.get(monitor).or(monitors.first()) else {
+                return WindowMode::Windowed;
+            };
+            let (x, y) = monitor.get_pos();
+            let current = monitor.get_video_mode();
+            match (mode, current) {
+                (WindowMode::Borderless, Some(current)) => {
+                    window.set_decorated(false);
+                    window.set_monitor(
+                        glfw::WindowMode::Windowed,
+                        x,
+                        y,
+                        current.width,
+                        current.height,
+                        None,
+                    );
+                    WindowMode::Borderless
+                }
+                (WindowMode::Exclusive, Some(current)) => {
+                    let target = video_mode
+                        .filter(|&target| {
+                            monitor
+                                .get_video_modes()
+                                .into_iter()
+                                .any(|mode| convert_video_mode(mode) == target)
+                        })
+                        .unwrap_or(convert_video_mode(current));
+                    window.set_decorated(true);
+                    window.set_monitor(
+                        glfw::WindowMode::FullScreen(monitor),
+                        0,
+                        0,
+                        target.width,
+                        target.height,
+                        Some(target.refresh_rate),
+                    );
+                    WindowMode::Exclusive
+                }
+                _ => {
+                    let (x, y, width, height) = windowed;
+                    window.set_decorated(true);
+                    window.set_monitor(glfw::WindowMode::Windowed, x, y, width, height, None);
+                    WindowMode::Windowed
+                }
+            }
+        });
+    }
+
+    fn windowed_placement(&self) -> Placement {
+        let (x, y, width, height) = match self.mode {
+            WindowMode::Windowed => self.current_rect(),
+            _ => self.windowed,
+        };
+        Placement {
+            position: Some((x, y)),
+            size: (width, height),
+        }
+    }
+
+    // Uses the first connected joystick with a gamepad mapping
+    fn gamepad(&self) -> Option<GamepadState> {
+        let state = (0..16)
+            .filter_map(JoystickId::from_i32)
+            .map(|id| self.glfw.get_joystick(id))
+            .filter(|joystick| joystick.is_present() && joystick.is_gamepad())
+            .find_map(|joystick| joystick.get_gamepad_state())?;
+
+        Some(GamepadState {
+            buttons: GAMEPAD_BUTTONS
+                .iter()
+                .filter(|(button, _)| state.get_button_state(*button) == glfw::Action::Press)
+                .map(|&(_, button)| button)
+                .collect(),
+            axes: GAMEPAD_AXES
+                .iter()
+                .map(|&(axis, ours)| (ours, state.get_axis(axis)))
+                .collect(),
+        })
+    }
+}
+
+fn convert_event(event: glfw::WindowEvent) -> Option<WindowEvent> {
+    let action = |action: glfw::Action| match action {
+        glfw::Action::Press => Some(Action::Press),
+        glfw::Action::Release => Some(Action::Release),
+        glfw::Action::Repeat => None,
+    };
+
+    match event {
+        glfw::WindowEvent::Key(key, _, a, _) => {
+            Some(WindowEvent::Key(convert_key(key)?, action(a)?))
+        }
+        glfw::WindowEvent::MouseButton(button, a, _) => {
+            let button = match button as i32 {
+                0 => MouseButton::Left,
+                1 => MouseButton::Right,
+                2 => MouseButton::Middle,
+                n => MouseButton::Other(n as u8 + 1),
+            };
+            Some(WindowEvent::MouseButton(button, action(a)?))
+        }
+        glfw::WindowEvent::CursorPos(x, y) => Some(WindowEvent::CursorPos(x, y)),
+        glfw::WindowEvent::Scroll(x, y) => Some(WindowEvent::Scroll(x, y)),
+        glfw::WindowEvent::Focus(focused) => Some(WindowEvent::Focus(focused)),
+        glfw::WindowEvent::FramebufferSize(width, height) => Some(WindowEvent::FramebufferSize(
+            width.max(0) as u32,
+            height.max(0) as u32,
+        )),
+        _ => None,
+    }
+}
+
+fn convert_video_mode(mode: glfw::VidMode) -> VideoMode {
+    VideoMode {
+        width: mode.width,
+        height: mode.height,
+        refresh_rate: mode.refresh_rate,
+    }
+}
+
+fn convert_key(key: glfw::Key) -> Option<Key> {
+    use glfw::Key as G;
+
+    // GLFW's letter and digit keys are their ASCII codes and its function keys are consecutive
+    let code = key as i32;
+    let offset = |first: G| (code - first as i32) as usize;
+    match code {
+        c if (G::A as i32..=G::Z as i32).contains(&c) => return Some(Key::LETTERS[offset(G::A)]),
+        c if (G::Num0 as i32..=G::Num9 as i32).contains(&c) => {
+            return Some(Key::DIGITS[offset(G::Num0)])
+        }
+        c if (G::F1 as i32..=G::F12 as i32).contains(&c) => {
+            return Some(Key::FUNCTION_KEYS[offset(G::F1)])
+        }
+        _ => {}
+    }
+
+    let key = match key {
+        G::Space => Key::Space,
+        G::Escape => Key::Escape,
+        G::Enter => Key::Enter,
+        G::Tab => Key::Tab,
+        G::Backspace => Key::Backspace,
+        G::Insert => Key::Insert,
+        G::Delete => Key::Delete,
+        G::Home => Key::Home,
+        G::End => Key::End,
+        G::PageUp => Key::PageUp,
+        G::PageDown => Key::PageDown,
+        G::Up => Key::Up,
+        G::Down => Key::Down,
+        G::Left => Key::Left,
+        G::Right => Key::Right,
+        G::LeftShift => Key::LeftShift,
+        G::RightShift => Key::RightShift,
+        G::LeftControl => Key::LeftControl,
+        G::RightControl => Key::RightControl,
+        G::LeftAlt => Key::LeftAlt,
+        G::RightAlt => Key::RightAlt,
+        G::Minus => Key::Minus,
+        G::Equal => Key::Equal,
+        G::Comma => Key::Comma,
+        G::Period => Key::Period,
+        G::Slash => Key::Slash,
+        G::GraveAccent => Key::GraveAccent,
+        _ => return None,
+    };
+
+    Some(key)
+}
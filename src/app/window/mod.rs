@@ -0,0 +1,305 @@
+#[cfg(all(feature = "glfw", not(feature = "winit")))]
+mod glfw_backend;
+pub mod settings;
+#[cfg(feature = "winit")]
+mod winit_backend;
+
+use ash::vk;
+use settings::WindowSettings;
+use std::ffi::CString;
+
+// The renderer only sees a window through the `Window` trait: the instance extensions it needs,
+// a surface, its framebuffer size and a stream of backend-neutral events. Which backend creates
+// it is a cargo feature; `glfw` is the default and `winit` takes over when it's enabled.
+//
+// Borderless fullscreen is an undecorated window covering a monitor. Exclusive fullscreen is
+// whatever the backend's platform mode switch gives; VK_EXT_full_screen_exclusive isn't used.
+
+#[cfg(not(any(feature = "glfw", feature = "winit")))]
+compile_error!("Enable a window backend feature, either \"glfw\" or \"winit\"");
+
+pub trait Window {
+    fn required_instance_extensions(&self) -> Vec<CString>;
+
+    fn create_surface(
+        &self,
+        entry: &ash::Entry,
+        instance: &ash::Instance,
+    ) -> Result<vk::SurfaceKHR, vk::Result>;
+
+    // In pixels, 0 while minimized
+    fn framebuffer_size(&self) -> (u32, u32);
+
+    // Everything that happened since the last call, without blocking
+    fn poll_events(&mut self) -> Vec<WindowEvent>;
+
+    // Blocks until something happens; the events are left for the next `poll_events`
+    fn wait_events(&mut self);
+
+    fn should_close(&self) -> bool;
+
+    fn set_should_close(&mut self, value: bool);
+
+    fn monitors(&mut self) -> Vec<Monitor>;
+
+    fn mode(&self) -> WindowMode;
+
+    // Fullscreen modes go to `monitor`, or the first one if it's gone. Exclusive fullscreen
+    // switches to `video_mode` if the monitor has it and keeps the current mode otherwise.
+    fn set_mode(&mut self, mode: WindowMode, monitor: usize, video_mode: Option<VideoMode>);
+
+    // Where the window is or was last while windowed, to restore it to
+    fn windowed_placement(&self) -> Placement;
+
+    // The first connected gamepad, for backends that can read them
+    fn gamepad(&self) -> Option<GamepadState> {
+        None
+    }
+}
+
+// Opens the window where `settings` left it
+pub fn create(title: &str, settings: &WindowSettings) -> Box<dyn Window> {
+    #[cfg(feature = "winit")]
+    let mut window = Box::new(winit_backend::WinitWindow::new(title, settings.placement));
+    #[cfg(not(feature = "winit"))]
+    let mut window = Box::new(glfw_backend::GlfwWindow::new(title, settings.placement));
+
+    if settings.mode != WindowMode::Windowed {
+        window.set_mode(settings.mode, settings.monitor, settings.video_mode);
+    }
+
+    window
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WindowMode {
+    Windowed,
+    Borderless,
+    Exclusive,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct VideoMode {
+    pub width: u32,
+    pub height: u32,
+    // In Hz
+    pub refresh_rate: u32,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Monitor {
+    pub name: String,
+    pub position: (i32, i32),
+    pub current_mode: Option<VideoMode>,
+    // Sorted, without duplicates
+    pub video_modes: Vec<VideoMode>,
+}
+
+// In screen coordinates
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Placement {
+    // None lets the system place the window
+    pub position: Option<(i32, i32)>,
+    pub size: (u32, u32),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WindowEvent {
+    Key(Key, Action),
+    MouseButton(MouseButton, Action),
+    CursorPos(f64, f64),
+    // In lines, positive is up and right
+    Scroll(f64, f64),
+    Focus(bool),
+    FramebufferSize(u32, u32),
+}
+
+// Key repeats aren't reported
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Press,
+    Release,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    // Numbered from 4
+    Other(u8),
+}
+
+// The keys bindings can name, by physical position on a US layout
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Key {
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+    G,
+    H,
+    I,
+    J,
+    K,
+    L,
+    M,
+    N,
+    O,
+    P,
+    Q,
+    R,
+    S,
+    T,
+    U,
+    V,
+    W,
+    X,
+    Y,
+    Z,
+    Num0,
+    Num1,
+    Num2,
+    Num3,
+    Num4,
+    Num5,
+    Num6,
+    Num7,
+    Num8,
+    Num9,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    Space,
+    Escape,
+    Enter,
+    Tab,
+    Backspace,
+    Insert,
+    Delete,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Up,
+    Down,
+    Left,
+    Right,
+    LeftShift,
+    RightShift,
+    LeftControl,
+    RightControl,
+    LeftAlt,
+    RightAlt,
+    Minus,
+    Equal,
+    Comma,
+    Period,
+    Slash,
+    GraveAccent,
+}
+
+impl Key {
+    pub const LETTERS: [Key; 26] = [
+        Key::A,
+        Key::B,
+        Key::C,
+        Key::D,
+        Key::E,
+        Key::F,
+        Key::G,
+        Key::H,
+        Key::I,
+        Key::J,
+        Key::K,
+        Key::L,
+        Key::M,
+        Key::N,
+        Key::O,
+        Key::P,
+        Key::Q,
+        Key::R,
+        Key::S,
+        Key::T,
+        Key::U,
+        Key::V,
+        Key::W,
+        Key::X,
+        Key::Y,
+        Key::Z,
+    ];
+    pub const DIGITS: [Key; 10] = [
+        Key::Num0,
+        Key::Num1,
+        Key::Num2,
+        Key::Num3,
+        Key::Num4,
+        Key::Num5,
+        Key::Num6,
+        Key::Num7,
+        Key::Num8,
+        Key::Num9,
+    ];
+    pub const FUNCTION_KEYS: [Key; 12] = [
+        Key::F1,
+        Key::F2,
+        Key::F3,
+        Key::F4,
+        Key::F5,
+        Key::F6,
+        Key::F7,
+        Key::F8,
+        Key::F9,
+        Key::F10,
+        Key::F11,
+        Key::F12,
+    ];
+}
+
+// Named after the Xbox layout
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum GamepadButton {
+    A,
+    B,
+    X,
+    Y,
+    LeftBumper,
+    RightBumper,
+    Back,
+    Start,
+    Guide,
+    LeftThumb,
+    RightThumb,
+    DpadUp,
+    DpadRight,
+    DpadDown,
+    DpadLeft,
+}
+
+// -1..1, with stick Y axes pointing down and triggers resting at -1
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum GamepadAxis {
+    LeftX,
+    LeftY,
+    RightX,
+    RightY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct GamepadState {
+    pub buttons: Vec<GamepadButton>,
+    pub axes: Vec<(GamepadAxis, f32)>,
+}
@@ -0,0 +1,144 @@
+use super::{Placement, VideoMode, WindowMode};
+use crate::util::constants::{WINDOW_HEIGHT, WINDOW_WIDTH};
+use crate::util::tools::parse_key_values;
+use std::fs;
+use std::path::Path;
+
+// How the window was last left: its mode, the monitor and video mode fullscreen goes to, and
+// where it sat while windowed. Written on exit and read back on the next run, in the same
+// `<key> = <value>` format as the input bindings.
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct WindowSettings {
+    pub mode: WindowMode,
+    // What toggling out of windowed mode switches to
+    pub fullscreen_mode: WindowMode,
+    pub monitor: usize,
+    // None keeps the monitor's current mode
+    pub video_mode: Option<VideoMode>,
+    pub placement: Placement,
+}
+
+impl Default for WindowSettings {
+    fn default() -> WindowSettings {
+        WindowSettings {
+            mode: WindowMode::Windowed,
+            fullscreen_mode: WindowMode::Borderless,
+            monitor: 0,
+            video_mode: None,
+            placement: Placement {
+                position: None,
+                size: (WINDOW_WIDTH, WINDOW_HEIGHT),
+            },
+        }
+    }
+}
+
+impl WindowSettings {
+    // Falls back to the defaults when the file is missing or invalid
+    pub fn load(path: &Path) -> WindowSettings {
+        if !path.exists() {
+            return WindowSettings::default();
+        }
+        fs::read_to_string(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+            .and_then(|source| parse(&source).map_err(|e| format!("{}:{}", path.display(), e)))
+            .unwrap_or_else(|e| {
+                println!("Using default window settings: {}", e);
+                WindowSettings::default()
+            })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let mut source = String::from("# Written on exit\n");
+        source += &format!("mode = {}\n", mode_name(self.mode));
+        source += &format!("fullscreen_mode = {}\n", mode_name(self.fullscreen_mode));
+        source += &format!("monitor = {}\n", self.monitor);
+        match self.video_mode {
+            Some(mode) => {
+                source += &format!(
+                    "video_mode = {}x{}@{}\n",
+                    mode.width, mode.height, mode.refresh_rate
+                )
+            }
+            None => source += "video_mode = current\n",
+        }
+        if let Some((x, y)) = self.placement.position {
+            source += &format!("position = {}, {}\n", x, y);
+        }
+        let (width, height) = self.placement.size;
+        source += &format!("size = {}, {}\n", width, height);
+
+        fs::write(path, source).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+pub fn parse(source: &str) -> Result<WindowSettings, String> {
+    let mut settings = WindowSettings::default();
+
+    parse_key_values(source, |key, value| {
+        let invalid = || format!("invalid {} '{}'", key, value);
+
+        match key {
+            "mode" => settings.mode = parse_mode(value).ok_or_else(invalid)?,
+            "fullscreen_mode" => {
+                settings.fullscreen_mode = parse_mode(value)
+                    .filter(|&mode| mode != WindowMode::Windowed)
+                    .ok_or_else(invalid)?
+            }
+            "monitor" => settings.monitor = value.parse().map_err(|_| invalid())?,
+            "video_mode" => {
+                settings.video_mode = match value {
+                    "current" => None,
+                    _ => Some(parse_video_mode(value).ok_or_else(invalid)?),
+                }
+            }
+            "position" => {
+                settings.placement.position = Some(parse_pair(value).ok_or_else(invalid)?)
+            }
+            "size" => {
+                settings.placement.size = parse_pair(value)
+                    .filter(|&(width, height)| width > 0 && height > 0)
+                    .ok_or_else(invalid)?
+            }
+            key => return Err(format!("unknown setting '{}'", key)),
+        }
+        Ok(())
+    })?;
+
+    Ok(settings)
+}
+
+fn mode_name(mode: WindowMode) -> &'static str {
+    match mode {
+        WindowMode::Windowed => "windowed",
+        WindowMode::Borderless => "borderless",
+        WindowMode::Exclusive => "exclusive",
+    }
+}
+
+fn parse_mode(name: &str) -> Option<WindowMode> {
+    match name {
+        "windowed" => Some(WindowMode::Windowed),
+        "borderless" => Some(WindowMode::Borderless),
+        "exclusive" => Some(WindowMode::Exclusive),
+        _ => None,
+    }
+}
+
+// <width>x<height>@<refresh rate>
+fn parse_video_mode(value: &str) -> Option<VideoMode> {
+    let (size, refresh_rate) = value.split_once('@')?;
+    let (width, height) = size.split_once('x')?;
+
+    Some(VideoMode {
+        width: width.trim().parse().ok()?,
+        height: height.trim().parse().ok()?,
+        refresh_rate: refresh_rate.trim().parse().ok()?,
+    })
+}
+
+fn parse_pair<T: std::str::FromStr>(value: &str) -> Option<(T, T)> {
+    let (a, b) = value.split_once(',')?;
+    Some((a.trim().parse().ok()?, b.trim().parse().ok()?))
+}
@@ -0,0 +1,391 @@
+use super::{
+    Action, Key, Monitor, MouseButton, Placement, VideoMode, Window, WindowEvent, WindowMode,
+};
+use ash::vk;
+use std::ffi::{CStr, CString};
+use std::time::Duration;
+use winit::application::ApplicationHandler;
+use winit::dpi::{LogicalPosition, LogicalSize};
+use winit::event::{ElementState, MouseScrollDelta};
+use winit::event_loop::{ActiveEventLoop, EventLoop};
+use winit::keyboard::{KeyCode, PhysicalKey};
+use winit::monitor::{MonitorHandle, VideoModeHandle};
+use winit::platform::pump_events::{EventLoopExtPumpEvents, PumpStatus};
+use winit::raw_window_handle::{HasDisplayHandle, HasWindowHandle};
+use winit::window::{Fullscreen, WindowId};
+
+// winit wants to own the main loop, so its event loop is pumped from `poll_events` instead, which
+// keeps the renderer's loop the same for every backend. winit can't read gamepads.
+
+// Touchpads scroll in pixels, mouse wheels in lines
+const SCROLL_PIXELS_PER_LINE: f64 = 20.0_f64;
+
+pub struct WinitWindow {
+    event_loop: EventLoop<()>,
+    state: State,
+    mode: WindowMode,
+    // Where the window goes back to when leaving fullscreen
+    windowed: Placement,
+}
+
+// What the event loop calls back into while it's pumped
+struct State {
+    title: String,
+    placement: Placement,
+    window: Option<winit::window::Window>,
+    events: Vec<WindowEvent>,
+    should_close: bool,
+}
+
+impl WinitWindow {
+    pub fn new(title: &str, placement: Placement) -> WinitWindow {
+        let mut event_loop = EventLoop::new().expect("Failed to create winit event loop.");
+        let mut state = State {
+            title: title.to_string(),
+            placement,
+            window: None,
+            events: Vec::new(),
+            should_close: false,
+        };
+        // Windows can only be created once the event loop has resumed
+        while state.window.is_none() {
+            if let PumpStatus::Exit(_) =
+                event_loop.pump_app_events(Some(Duration::ZERO), &mut state)
+            {
+                panic!("Failed to create winit window.");
+            }
+        }
+
+        let mut winit_window = WinitWindow {
+            event_loop,
+            state,
+            mode: WindowMode::Windowed,
+            windowed: placement,
+        };
+        winit_window.windowed = winit_window.current_placement();
+
+        winit_window
+    }
+
+    // In logical pixels, like GLFW's screen coordinates
+    fn current_placement(&self) -> Placement {
+        let window = self.window();
+        let scale = window.scale_factor();
+        let size: LogicalSize<u32> = window.inner_size().to_logical(scale);
+        Placement {
+            position: window
+                .outer_position()
+                .ok()
+                .map(|position| position.to_logical::<i32>(scale).into()),
+            size: (size.width.max(1), size.height.max(1)),
+        }
+    }
+
+    fn window(&self) -> &winit::window::Window {
+        self.state.window.as_ref().unwrap()
+    }
+
+    fn pump(&mut self, timeout: Option<Duration>) {
+        if let PumpStatus::Exit(_) = self.event_loop.pump_app_events(timeout, &mut self.state) {
+            self.state.should_close = true;
+        }
+    }
+}
+
+impl Window for WinitWindow {
+    fn required_instance_extensions(&self) -> Vec<CString> {
+        let display = self.window().display_handle().unwrap().as_raw();
+        ash_window::enumerate_required_extensions(display)
+            .expect("Unsupported windowing system")
+            .iter()
+            .map(|&name| unsafe { CStr::from_ptr(name) }.to_owned())
+            .collect()
+    }
+
+    fn create_surface(
+        &self,
+        entry: &ash::Entry,
+        instance: &ash::Instance,
+    ) -> Result<vk::SurfaceKHR, vk::Result> {
+        let window = self.window();
+        unsafe {
+            ash_window::create_surface(
+                entry,
+                instance,
+                window.display_handle().unwrap().as_raw(),
+                window.window_handle().unwrap().as_raw(),
+                None,
+            )
+        }
+    }
+
+    fn framebuffer_size(&self) -> (u32, u32) {
+        let size = self.window().inner_size();
+        (size.width, size.height)
+    }
+
+    fn poll_events(&mut self) -> Vec<WindowEvent> {
+        self.pump(Some(Duration::ZERO));
+        std::mem::take(&mut self.state.events)
+    }
+
+    fn wait_events(&mut self) {
+        self.pump(None);
+    }
+
+    fn should_close(&self) -> bool {
+        self.state.should_close
+    }
+
+    fn set_should_close(&mut self, value: bool) {
+        self.state.should_close = value;
+    }
+
+    fn monitors(&mut self) -> Vec<Monitor> {
+        self.window()
+            .available_monitors()
+            .map(|monitor| {
+                let mut video_modes: Vec<_> =
+                    monitor.video_modes().map(convert_video_mode).collect();
+                video_modes.sort();
+                video_modes.dedup();
+                Monitor {
+                    name: monitor.name().unwrap_or_default(),
+                    position: monitor.position().into(),
+                    current_mode: current_video_mode(&monitor).map(convert_video_mode),
+                    video_modes,
+                }
+            })
+            .collect()
+    }
+
+    fn mode(&self) -> WindowMode {
+        self.mode
+    }
+
+    fn set_mode(&mut self, mode: WindowMode, monitor: usize, video_mode: Option<VideoMode>) {
+        if self.mode == WindowMode::Windowed {
+            self.windowed = self.current_placement();
+        }
+
+        let window = self.state.window.as_ref().unwrap();
+        let monitor = window
+            .available_monitors()
+            .nth(monitor)
+            .or_else(|| window.available_monitors().next());
+        let fullscreen = match (mode, monitor) {
+            (WindowMode::Borderless, Some(monitor)) => Some(Fullscreen::Borderless(Some(monitor))),
+            (WindowMode::Exclusive, Some(monitor)) => video_mode
+                .and_then(|target| {
+                    monitor
+                        .video_modes()
+                        .find(|mode| convert_video_mode(mode.clone()) == target)
+                })
+                .or_else(|| current_video_mode(&monitor))
+                .map(Fullscreen::Exclusive),
+            _ => None,
+        };
+
+        self.mode = match &fullscreen {
+            Some(Fullscreen::Borderless(_)) => WindowMode::Borderless,
+            Some(Fullscreen::Exclusive(_)) => WindowMode::Exclusive,
+            None => WindowMode::Windowed,
+        };
+        window.set_fullscreen(fullscreen);
+        if self.mode == WindowMode::Windowed {
+            let (width, height) = self.windowed.size;
+            let _ = window.request_inner_size(LogicalSize::new(width, height));
+            if let Some((x, y)) = self.windowed.position {
+                window.set_outer_position(LogicalPosition::new(x, y));
+            }
+        }
+    }
+
+    fn windowed_placement(&self) -> Placement {
+        match self.mode {
+            WindowMode::Windowed => self.current_placement(),
+            _ => self.windowed,
+        }
+    }
+}
+
+impl ApplicationHandler for State {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if self.window.is_none() {
+            let (width, height) = self.placement.size;
+            let mut attributes = winit::window::Window::default_attributes()
+                .with_title(self.title.as_str())
+                .with_inner_size(LogicalSize::new(width, height));
+            if let Some((x, y)) = self.placement.position {
+                attributes = attributes.with_position(LogicalPosition::new(x, y));
+            }
+            self.window = Some(
+                event_loop
+                    .create_window(attributes)
+                    .expect("Failed to create winit window."),
+            );
+        }
+    }
+
+    fn window_event(
+        &mut self,
+        _event_loop: &ActiveEventLoop,
+        _window_id: WindowId,
+        event: winit::event::WindowEvent,
+    ) {
+        use winit::event::WindowEvent as W;
+
+        let action = |state: ElementState| match state {
+            ElementState::Pressed => Action::Press,
+            ElementState::Released => Action::Release,
+        };
+        let event = match event {
+            W::CloseRequested => {
+                self.should_close = true;
+                return;
+            }
+            W::KeyboardInput { event, .. } if !event.repeat => match event.physical_key {
+                PhysicalKey::Code(code) => match convert_key(code) {
+                    Some(key) => WindowEvent::Key(key, action(event.state)),
+                    None => return,
+                },
+                PhysicalKey::Unidentified(_) => return,
+            },
+            W::MouseInput { state, button, .. } => {
+                let button = match button {
+                    winit::event::MouseButton::Left => MouseButton::Left,
+                    winit::event::MouseButton::Right => MouseButton::Right,
+                    winit::event::MouseButton::Middle => MouseButton::Middle,
+                    winit::event::MouseButton::Back => MouseButton::Other(4),
+                    winit::event::MouseButton::Forward => MouseButton::Other(5),
+                    winit::event::MouseButton::Other(n) => MouseButton::Other(n as u8),
+                };
+                WindowEvent::MouseButton(button, action(state))
+            }
+            W::CursorMoved { position, .. } => WindowEvent::CursorPos(position.x, position.y),
+            W::MouseWheel { delta, .. } => match delta {
+                MouseScrollDelta::LineDelta(x, y) => WindowEvent::Scroll(x as f64, y as f64),
+                MouseScrollDelta::PixelDelta(offset) => WindowEvent::Scroll(
+                    offset.x / SCROLL_PIXELS_PER_LINE,
+                    offset.y / SCROLL_PIXELS_PER_LINE,
+                ),
+            },
+            W::Focused(focused) => WindowEvent::Focus(focused),
+            W::Resized(size) => WindowEvent::FramebufferSize(size.width, size.height),
+            _ => return,
+        };
+        self.events.push(event);
+    }
+}
+
+fn convert_video_mode(mode: VideoModeHandle) -> VideoMode {
+    VideoMode {
+        width: mode.size().width,
+        height: mode.size().height,
+        refresh_rate: (mode.refresh_rate_millihertz() + 500) / 1000,
+    }
+}
+
+// winit only reports the current size and refresh rate, so this finds the mode matching them,
+// or the largest and fastest one
+fn current_video_mode(monitor: &MonitorHandle) -> Option<VideoModeHandle> {
+    let size = monitor.size();
+    let refresh_rate = monitor.refresh_rate_millihertz();
+    monitor
+        .video_modes()
+        .find(|mode| {
+            mode.size() == size
+                && refresh_rate.is_none_or(|rate| mode.refresh_rate_millihertz() == rate)
+        })
+        .or_else(|| {
+            monitor.video_modes().max_by_key(|mode| {
+                (
+                    mode.size().width * mode.size().height,
+                    mode.refresh_rate_millihertz(),
+                )
+            })
+        })
+}
+
+fn convert_key(code: KeyCode) -> Option<Key> {
+    let key = match code {
+        KeyCode::KeyA => Key::A,
+        KeyCode::KeyB => Key::B,
+        KeyCode::KeyC => Key::C,
+        KeyCode::KeyD => Key::D,
+        KeyCode::KeyE => Key::E,
+        KeyCode::KeyF => Key::F,
+        KeyCode::KeyG => Key::G,
+        KeyCode::KeyH => Key::H,
+        KeyCode::KeyI => Key::I,
+        KeyCode::KeyJ => Key::J,
+        KeyCode::KeyK => Key::K,
+        KeyCode::KeyL => Key::L,
+        KeyCode::KeyM => Key::M,
+        KeyCode::KeyN => Key::N,
+        KeyCode::KeyO => Key::O,
+        KeyCode::KeyP => Key::P,
+        KeyCode::KeyQ => Key::Q,
+        KeyCode::KeyR => Key::R,
+        KeyCode::KeyS => Key::S,
+        KeyCode::KeyT => Key::T,
+        KeyCode::KeyU => Key::U,
+        KeyCode::KeyV => Key::V,
+        KeyCode::KeyW => Key::W,
+        KeyCode::KeyX => Key::X,
+        KeyCode::KeyY => Key::Y,
+        KeyCode::KeyZ => Key::Z,
+        KeyCode::Digit0 => Key::Num0,
+        KeyCode::Digit1 => Key::Num1,
+        KeyCode::Digit2 => Key::Num2,
+        KeyCode::Digit3 => Key::Num3,
+        KeyCode::Digit4 => Key::Num4,
+        KeyCode::Digit5 => Key::Num5,
+        KeyCode::Digit6 => Key::Num6,
+        KeyCode::Digit7 => Key::Num7,
+        KeyCode::Digit8 => Key::Num8,
+        KeyCode::Digit9 => Key::Num9,
+        KeyCode::F1 => Key::F1,
+        KeyCode::F2 => Key::F2,
+        KeyCode::F3 => Key::F3,
+        KeyCode::F4 => Key::F4,
+        KeyCode::F5 => Key::F5,
+        KeyCode::F6 => Key::F6,
+        KeyCode::F7 => Key::F7,
+        KeyCode::F8 => Key::F8,
+        KeyCode::F9 => Key::F9,
+        KeyCode::F10 => Key::F10,
+        KeyCode::F11 => Key::F11,
+        KeyCode::F12 => Key::F12,
+        KeyCode::Space => Key::Space,
+        KeyCode::Escape => Key::Escape,
+        KeyCode::Enter => Key::Enter,
+        KeyCode::Tab => Key::Tab,
+        KeyCode::Backspace => Key::Backspace,
+        KeyCode::Insert => Key::Insert,
+        KeyCode::Delete => Key::Delete,
+        KeyCode::Home => Key::Home,
+        KeyCode::End => Key::End,
+        KeyCode::PageUp => Key::PageUp,
+        KeyCode::PageDown => Key::PageDown,
+        KeyCode::ArrowUp => Key::Up,
+        KeyCode::ArrowDown => Key::Down,
+        KeyCode::ArrowLeft => Key::Left,
+        KeyCode::ArrowRight => Key::Right,
+        KeyCode::ShiftLeft => Key::LeftShift,
+        KeyCode::ShiftRight => Key::RightShift,
+        KeyCode::ControlLeft => Key::LeftControl,
+        KeyCode::ControlRight => Key::RightControl,
+        KeyCode::AltLeft => Key::LeftAlt,
+        KeyCode::AltRight => Key::RightAlt,
+        KeyCode::Minus => Key::Minus,
+        KeyCode::Equal => Key::Equal,
+        KeyCode::Comma => Key::Comma,
+        KeyCode::Period => Key::Period,
+        KeyCode::Slash => Key::Slash,
+        KeyCode::Backquote => Key::GraveAccent,
+        _ => return None,
+    };
+
+    Some(key)
+}
@@ -0,0 +1,84 @@
+use std::fs::File;
+use std::path::Path;
+
+// 3D color lookup tables for grading. On disk they are the usual horizontal strip: `size` slices
+// of `size`x`size` side by side, red increasing to the right within a slice, green downwards and
+// blue from slice to slice. Both input and output are gamma encoded.
+
+pub struct ColorLut {
+    pub size: u32,
+    // RGBA8, red varying fastest, then green, then blue
+    pub texels: Vec<u8>,
+}
+
+pub fn load(path: &Path) -> Result<ColorLut, String> {
+    let error = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+
+    let file = File::open(path).map_err(|e| error(&e))?;
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| error(&e))?;
+    let mut buffer = vec![0_u8; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(|e| error(&e))?;
+
+    let size = info.height;
+    if size < 2 || info.width != size * size {
+        return Err(format!(
+            "{}: a {}x{} image isn't a strip of square slices",
+            path.display(),
+            info.width,
+            info.height
+        ));
+    }
+
+    let channels = info.color_type.samples();
+    let size = size as usize;
+    let mut texels = Vec::with_capacity(size * size * size * 4);
+    for blue in 0..size {
+        for green in 0..size {
+            for red in 0..size {
+                let x = blue * size + red;
+                let offset = (green * info.width as usize + x) * channels;
+                let texel = &buffer[offset..offset + channels];
+                match channels {
+                    1 | 2 => texels.extend([texel[0], texel[0], texel[0], 255]),
+                    _ => texels.extend([texel[0], texel[1], texel[2], 255]),
+                }
+            }
+        }
+    }
+
+    Ok(ColorLut {
+        size: size as u32,
+        texels,
+    })
+}
+
+// A mild filmic look for when there's no table to load: a little more contrast and saturation,
+// with warm highlights and cool shadows
+pub fn builtin_look(size: u32) -> ColorLut {
+    let mut texels = Vec::with_capacity((size * size * size * 4) as usize);
+    let scale = 1.0_f32 / (size - 1) as f32;
+    for blue in 0..size {
+        for green in 0..size {
+            for red in 0..size {
+                let color = [red, green, blue].map(|c| c as f32 * scale);
+                let luma = 0.2126_f32 * color[0] + 0.7152_f32 * color[1] + 0.0722_f32 * color[2];
+                let s_curve = |c: f32| c + (c * c * (3.0_f32 - 2.0_f32 * c) - c) * 0.35_f32;
+                let tint = [
+                    1.0_f32 + 0.06_f32 * (luma - 0.5_f32),
+                    1.0_f32,
+                    1.0_f32 - 0.08_f32 * (luma - 0.5_f32),
+                ];
+                let graded = [0, 1, 2].map(|i| {
+                    let saturated = luma + (color[i] - luma) * 1.15_f32;
+                    (s_curve(saturated.clamp(0.0_f32, 1.0_f32)) * tint[i]).clamp(0.0_f32, 1.0_f32)
+                });
+                texels.extend(graded.map(|c| (c * 255.0_f32 + 0.5_f32) as u8));
+                texels.push(255);
+            }
+        }
+    }
+
+    ColorLut { size, texels }
+}
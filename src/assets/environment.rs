@@ -0,0 +1,139 @@
+use std::fs::File;
+use std::path::Path;
+
+use super::hdr::{self, HdrImage};
+
+// Distant lighting around the scene, either a single equirectangular panorama or the six faces
+// of a cube map
+
+pub enum EnvironmentSource {
+    Equirect(HdrImage),
+    // +X, -X, +Y, -Y, +Z, -Z, in Vulkan's cube face orientation
+    Faces(Vec<HdrImage>),
+}
+
+const FACE_NAMES: [&str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+
+// A .hdr file is a panorama. A directory holds the faces as px/nx/py/ny/pz/nz, each either a
+// .hdr or an sRGB .png.
+pub fn load(path: &Path) -> Result<EnvironmentSource, String> {
+    if !path.is_dir() {
+        return hdr::load(path).map(EnvironmentSource::Equirect);
+    }
+
+    let mut faces: Vec<HdrImage> = vec![];
+    for name in FACE_NAMES {
+        let hdr_path = path.join(format!("{}.hdr", name));
+        let face = if hdr_path.exists() {
+            hdr::load(&hdr_path)?
+        } else {
+            load_png(&path.join(format!("{}.png", name)))?
+        };
+        if face.width != face.height {
+            return Err(format!(
+                "{}: cube face {} isn't square",
+                path.display(),
+                name
+            ));
+        }
+        if let Some(first) = faces.first() {
+            if face.width != first.width {
+                return Err(format!(
+                    "{}: cube faces differ in size ({} is {}, {} is {})",
+                    path.display(),
+                    name,
+                    face.width,
+                    FACE_NAMES[0],
+                    first.width
+                ));
+            }
+        }
+        faces.push(face);
+    }
+
+    Ok(EnvironmentSource::Faces(faces))
+}
+
+fn load_png(path: &Path) -> Result<HdrImage, String> {
+    let error = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+
+    let file = File::open(path).map_err(|e| error(&e))?;
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| error(&e))?;
+    let mut buffer = vec![0_u8; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(|e| error(&e))?;
+
+    let channels = info.color_type.samples();
+    let to_linear = |value: u8| {
+        let c = value as f32 / 255.0_f32;
+        if c <= 0.04045_f32 {
+            c / 12.92_f32
+        } else {
+            ((c + 0.055_f32) / 1.055_f32).powf(2.4_f32)
+        }
+    };
+    let pixels = buffer[..info.buffer_size()]
+        .chunks_exact(channels)
+        .map(|texel| match channels {
+            1 | 2 => [to_linear(texel[0]); 3],
+            _ => [
+                to_linear(texel[0]),
+                to_linear(texel[1]),
+                to_linear(texel[2]),
+            ],
+        })
+        .collect();
+
+    Ok(HdrImage {
+        width: info.width,
+        height: info.height,
+        pixels,
+    })
+}
+
+// A panorama of a clear sky with a sun disk where `to_sun` points, for when there's no
+// environment map to load
+pub fn procedural_sky(to_sun: [f32; 3]) -> HdrImage {
+    let (width, height) = (512_usize, 256_usize);
+    let zenith = [0.15_f32, 0.3_f32, 0.65_f32];
+    let horizon = [0.7_f32, 0.75_f32, 0.8_f32];
+    let ground = [0.12_f32, 0.1_f32, 0.08_f32];
+    let sun_color = [60.0_f32, 55.0_f32, 48.0_f32];
+    let sun_cos = 1.5_f32.to_radians().cos();
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        // Same mapping the conversion shader samples with: +Y at the top row
+        let theta = (y as f32 + 0.5_f32) / height as f32 * std::f32::consts::PI;
+        for x in 0..width {
+            let phi = ((x as f32 + 0.5_f32) / width as f32 - 0.5_f32) * std::f32::consts::TAU;
+            let direction = [
+                theta.sin() * phi.cos(),
+                theta.cos(),
+                theta.sin() * phi.sin(),
+            ];
+
+            let up = direction[1];
+            let mut color = if up >= 0.0_f32 {
+                let t = up.powf(0.5_f32);
+                [0, 1, 2].map(|i| horizon[i] + (zenith[i] - horizon[i]) * t)
+            } else {
+                let t = (-up * 8.0_f32).min(1.0_f32);
+                [0, 1, 2].map(|i| horizon[i] + (ground[i] - horizon[i]) * t)
+            };
+            let cos_sun =
+                direction[0] * to_sun[0] + direction[1] * to_sun[1] + direction[2] * to_sun[2];
+            if cos_sun > sun_cos {
+                color = sun_color;
+            }
+            pixels.push(color);
+        }
+    }
+
+    HdrImage {
+        width: width as u32,
+        height: height as u32,
+        pixels,
+    }
+}
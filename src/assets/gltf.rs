@@ -0,0 +1,304 @@
+use ash::vk;
+use std::path::Path;
+
+use super::material::{AlphaMode, ImageData, Material, SamplerDesc, Texture};
+use super::mesh::{Bounds, Mesh, Vertex};
+
+// glTF 2.0 import for both .gltf (+ .bin / data URIs) and binary .glb files
+
+pub struct Node {
+    pub name: String,
+    // Local transform relative to the parent; rotation is a quaternion as [x, y, z, w]
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+    pub children: Vec<usize>,
+    pub mesh: Option<usize>,
+}
+
+pub struct GltfScene {
+    // One entry per glTF mesh, holding a Mesh per primitive
+    pub meshes: Vec<Vec<Mesh>>,
+    pub nodes: Vec<Node>,
+    pub roots: Vec<usize>,
+    pub materials: Vec<Material>,
+    pub textures: Vec<Texture>,
+    pub images: Vec<ImageData>,
+}
+
+pub fn load(path: &Path) -> Result<GltfScene, String> {
+    let (document, buffers, images) =
+        ::gltf::import(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    let mut meshes = vec![];
+    for mesh in document.meshes() {
+        let mut primitives = vec![];
+        for (i, primitive) in mesh.primitives().enumerate() {
+            let name = format!("{}#{}", mesh.name().unwrap_or("mesh"), i);
+            if primitive.mode() != ::gltf::mesh::Mode::Triangles {
+                println!("glTF {}: skipping {:?} primitive", name, primitive.mode());
+                continue;
+            }
+            primitives.push(load_primitive(&primitive, &buffers, name)?);
+        }
+        meshes.push(primitives);
+    }
+
+    let nodes = document
+        .nodes()
+        .map(|node| {
+            let (translation, rotation, scale) = node.transform().decomposed();
+            Node {
+                name: node.name().unwrap_or("node").to_string(),
+                translation,
+                rotation,
+                scale,
+                children: node.children().map(|child| child.index()).collect(),
+                mesh: node.mesh().map(|mesh| mesh.index()),
+            }
+        })
+        .collect();
+
+    let roots = match document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        Some(scene) => scene.nodes().map(|node| node.index()).collect(),
+        None => vec![],
+    };
+
+    let materials: Vec<Material> = document.materials().map(|m| load_material(&m)).collect();
+
+    let textures = document
+        .textures()
+        .map(|texture| Texture {
+            image: texture.source().index(),
+            sampler: load_sampler(&texture.sampler()),
+        })
+        .collect::<Vec<_>>();
+
+    // Images are sRGB when sampled as color by any material
+    let mut srgb = vec![false; images.len()];
+    for material in materials.iter() {
+        for texture in [material.base_color_texture, material.emissive_texture]
+            .into_iter()
+            .flatten()
+        {
+            srgb[textures[texture].image] = true;
+        }
+    }
+
+    let images = images
+        .into_iter()
+        .zip(srgb)
+        .map(|(data, srgb)| to_rgba8(data, srgb))
+        .collect::<Result<Vec<_>, String>>()
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    Ok(GltfScene {
+        meshes,
+        nodes,
+        roots,
+        materials,
+        textures,
+        images,
+    })
+}
+
+fn load_primitive(
+    primitive: &::gltf::Primitive,
+    buffers: &[::gltf::buffer::Data],
+    name: String,
+) -> Result<Mesh, String> {
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+    let positions: Vec<[f32; 3]> = reader
+        .read_positions()
+        .ok_or_else(|| format!("{} has no positions", name))?
+        .collect();
+    let mut vertices: Vec<Vertex> = positions
+        .iter()
+        .map(|&pos| Vertex {
+            pos,
+            ..Default::default()
+        })
+        .collect();
+
+    let has_normals = match reader.read_normals() {
+        Some(normals) => {
+            for (vertex, normal) in vertices.iter_mut().zip(normals) {
+                vertex.normal = normal;
+            }
+            true
+        }
+        None => false,
+    };
+    if let Some(tex_coords) = reader.read_tex_coords(0) {
+        for (vertex, tex_coord) in vertices.iter_mut().zip(tex_coords.into_f32()) {
+            vertex.tex_coord = tex_coord;
+        }
+    }
+    if let Some(colors) = reader.read_colors(0) {
+        for (vertex, color) in vertices.iter_mut().zip(colors.into_rgb_f32()) {
+            vertex.color = color;
+        }
+    }
+
+    let has_tangents = match reader.read_tangents() {
+        Some(tangents) => {
+            for (vertex, tangent) in vertices.iter_mut().zip(tangents) {
+                vertex.tangent = tangent;
+            }
+            true
+        }
+        None => false,
+    };
+
+    let indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..vertices.len() as u32).collect(),
+    };
+
+    let mut mesh = Mesh {
+        name,
+        vertices,
+        indices,
+        material: primitive.material().index(),
+        bounds: Bounds::default(),
+    };
+    if !has_normals {
+        mesh.compute_normals();
+    }
+    // glTF asks for MikkTSpace here; per-triangle gradients are close enough for most assets
+    if !has_tangents {
+        mesh.compute_tangents();
+    }
+    mesh.compute_bounds();
+
+    Ok(mesh)
+}
+
+fn load_material(material: &::gltf::Material) -> Material {
+    let pbr = material.pbr_metallic_roughness();
+
+    Material {
+        name: material.name().unwrap_or("material").to_string(),
+        base_color_factor: pbr.base_color_factor(),
+        base_color_texture: pbr.base_color_texture().map(|t| t.texture().index()),
+        metallic_factor: pbr.metallic_factor(),
+        roughness_factor: pbr.roughness_factor(),
+        metallic_roughness_texture: pbr
+            .metallic_roughness_texture()
+            .map(|t| t.texture().index()),
+        normal_texture: material.normal_texture().map(|t| t.texture().index()),
+        normal_scale: material.normal_texture().map_or(1.0_f32, |t| t.scale()),
+        occlusion_texture: material.occlusion_texture().map(|t| t.texture().index()),
+        occlusion_strength: material
+            .occlusion_texture()
+            .map_or(1.0_f32, |t| t.strength()),
+        emissive_factor: material.emissive_factor(),
+        emissive_texture: material.emissive_texture().map(|t| t.texture().index()),
+        alpha_mode: match material.alpha_mode() {
+            ::gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            ::gltf::material::AlphaMode::Mask => AlphaMode::Mask,
+            ::gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+        },
+        alpha_cutoff: material.alpha_cutoff().unwrap_or(0.5_f32),
+        double_sided: material.double_sided(),
+    }
+}
+
+fn load_sampler(sampler: &::gltf::texture::Sampler) -> SamplerDesc {
+    use ::gltf::texture::{MagFilter, MinFilter, WrappingMode};
+
+    let address_mode = |mode: WrappingMode| match mode {
+        WrappingMode::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        WrappingMode::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+        WrappingMode::Repeat => vk::SamplerAddressMode::REPEAT,
+    };
+
+    let (min_filter, mipmap_mode, mipmaps) = match sampler.min_filter() {
+        Some(MinFilter::Nearest) => (vk::Filter::NEAREST, vk::SamplerMipmapMode::NEAREST, false),
+        Some(MinFilter::Linear) => (vk::Filter::LINEAR, vk::SamplerMipmapMode::NEAREST, false),
+        Some(MinFilter::NearestMipmapNearest) => {
+            (vk::Filter::NEAREST, vk::SamplerMipmapMode::NEAREST, true)
+        }
+        Some(MinFilter::NearestMipmapLinear) => {
+            (vk::Filter::NEAREST, vk::SamplerMipmapMode::LINEAR, true)
+        }
+        Some(MinFilter::LinearMipmapNearest) => {
+            (vk::Filter::LINEAR, vk::SamplerMipmapMode::NEAREST, true)
+        }
+        _ => (vk::Filter::LINEAR, vk::SamplerMipmapMode::LINEAR, true),
+    };
+
+    SamplerDesc {
+        mag_filter: match sampler.mag_filter() {
+            Some(MagFilter::Nearest) => vk::Filter::NEAREST,
+            _ => vk::Filter::LINEAR,
+        },
+        min_filter,
+        mipmap_mode,
+        mipmaps,
+        address_mode_u: address_mode(sampler.wrap_s()),
+        address_mode_v: address_mode(sampler.wrap_t()),
+    }
+}
+
+fn to_rgba8(data: ::gltf::image::Data, srgb: bool) -> Result<ImageData, String> {
+    use ::gltf::image::Format;
+
+    let texels = (data.width * data.height) as usize;
+    let mut pixels = Vec::with_capacity(texels * 4);
+
+    // 16 bit channels keep their high byte, float channels are clamped to [0, 1]
+    let (channels, bytes_per_channel) = match data.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+    if data.pixels.len() < texels * channels * bytes_per_channel {
+        return Err(format!("truncated {:?} image", data.format));
+    }
+
+    for texel in data
+        .pixels
+        .chunks_exact(channels * bytes_per_channel)
+        .take(texels)
+    {
+        let channel = |c: usize| -> u8 {
+            let bytes = &texel[c * bytes_per_channel..(c + 1) * bytes_per_channel];
+            match bytes_per_channel {
+                1 => bytes[0],
+                2 => (u16::from_ne_bytes([bytes[0], bytes[1]]) >> 8) as u8,
+                _ => {
+                    let value = f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                    (value.clamp(0.0_f32, 1.0_f32) * 255.0_f32).round() as u8
+                }
+            }
+        };
+
+        let rgba = match channels {
+            1 => [channel(0), channel(0), channel(0), 255],
+            // Two channels are grayscale and alpha
+            2 => [channel(0), channel(0), channel(0), channel(1)],
+            3 => [channel(0), channel(1), channel(2), 255],
+            _ => [channel(0), channel(1), channel(2), channel(3)],
+        };
+        pixels.extend_from_slice(&rgba);
+    }
+
+    Ok(ImageData {
+        width: data.width,
+        height: data.height,
+        pixels,
+        srgb,
+    })
+}
@@ -0,0 +1,139 @@
+use std::fs;
+use std::path::Path;
+
+// Radiance RGBE (.hdr) images, flat or with the run-length encoded scanlines most tools write
+
+// Linear RGB radiance, rows top to bottom
+pub struct HdrImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[f32; 3]>,
+}
+
+pub fn load(path: &Path) -> Result<HdrImage, String> {
+    let bytes = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse(&bytes).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+pub fn parse(bytes: &[u8]) -> Result<HdrImage, String> {
+    let mut position = 0;
+    let mut next_line = || -> Result<&[u8], String> {
+        let rest = &bytes[position..];
+        let end = rest
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or("truncated header")?;
+        position += end + 1;
+        Ok(&rest[..end])
+    };
+
+    let magic = next_line()?;
+    if !magic.starts_with(b"#?") {
+        return Err("not a Radiance HDR file".to_string());
+    }
+    loop {
+        let line = next_line()?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix(b"FORMAT=") {
+            if format != b"32-bit_rle_rgbe" {
+                return Err(format!(
+                    "unsupported format {}",
+                    String::from_utf8_lossy(format)
+                ));
+            }
+        }
+    }
+
+    // Only the standard orientation, rows top to bottom and columns left to right
+    let resolution = String::from_utf8_lossy(next_line()?).to_string();
+    let (height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => (
+            height.parse::<usize>().map_err(|e| e.to_string())?,
+            width.parse::<usize>().map_err(|e| e.to_string())?,
+        ),
+        _ => return Err(format!("unsupported resolution line \"{}\"", resolution)),
+    };
+
+    let data = &bytes[position..];
+    let mut offset = 0;
+    let mut pixels = Vec::with_capacity(width * height);
+    let mut scanline = vec![[0_u8; 4]; width];
+    for _ in 0..height {
+        offset += read_scanline(&data[offset..], &mut scanline)?;
+        pixels.extend(scanline.iter().map(|&rgbe| to_rgb(rgbe)));
+    }
+
+    Ok(HdrImage {
+        width: width as u32,
+        height: height as u32,
+        pixels,
+    })
+}
+
+// Returns how many bytes the scanline took
+fn read_scanline(data: &[u8], scanline: &mut [[u8; 4]]) -> Result<usize, String> {
+    let width = scanline.len();
+    let truncated = || "truncated pixel data".to_string();
+
+    // Run-length encoded scanlines start with 2, 2 and the width; each channel is then stored
+    // on its own as runs (count above 128) and literal spans
+    let encoded = (8..0x8000).contains(&width) && data.len() >= 4 && data[0] == 2 && data[1] == 2;
+    if !encoded {
+        let flat = data.get(..width * 4).ok_or_else(truncated)?;
+        for (texel, rgbe) in scanline.iter_mut().zip(flat.chunks_exact(4)) {
+            texel.copy_from_slice(rgbe);
+        }
+        return Ok(width * 4);
+    }
+    if ((data[2] as usize) << 8 | data[3] as usize) != width {
+        return Err("scanline width doesn't match the image".to_string());
+    }
+
+    let mut offset = 4;
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = *data.get(offset).ok_or_else(truncated)? as usize;
+            offset += 1;
+            if count > 128 {
+                let run = count - 128;
+                let value = *data.get(offset).ok_or_else(truncated)?;
+                offset += 1;
+                if x + run > width {
+                    return Err("run overflows the scanline".to_string());
+                }
+                for texel in scanline[x..x + run].iter_mut() {
+                    texel[channel] = value;
+                }
+                x += run;
+            } else {
+                if count == 0 || x + count > width {
+                    return Err("bad literal span".to_string());
+                }
+                let values = data.get(offset..offset + count).ok_or_else(truncated)?;
+                offset += count;
+                for (texel, &value) in scanline[x..x + count].iter_mut().zip(values) {
+                    texel[channel] = value;
+                }
+                x += count;
+            }
+        }
+    }
+
+    Ok(offset)
+}
+
+fn to_rgb(rgbe: [u8; 4]) -> [f32; 3] {
+    if rgbe[3] == 0 {
+        return [0.0_f32; 3];
+    }
+    // Mantissas are stored as value / 256 of the shared exponent, biased by 128
+    let scale = 2.0_f32.powi(rgbe[3] as i32 - 136);
+    [
+        (rgbe[0] as f32 + 0.5_f32) * scale,
+        (rgbe[1] as f32 + 0.5_f32) * scale,
+        (rgbe[2] as f32 + 0.5_f32) * scale,
+    ]
+}
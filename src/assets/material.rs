@@ -0,0 +1,89 @@
+use ash::vk;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AlphaMode {
+    Opaque,
+    Mask,
+    Blend,
+}
+
+// glTF-style metallic-roughness material; textures index into the owning scene's texture list
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub name: String,
+    pub base_color_factor: [f32; 4],
+    pub base_color_texture: Option<usize>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub metallic_roughness_texture: Option<usize>,
+    pub normal_texture: Option<usize>,
+    pub normal_scale: f32,
+    pub occlusion_texture: Option<usize>,
+    pub occlusion_strength: f32,
+    pub emissive_factor: [f32; 3],
+    pub emissive_texture: Option<usize>,
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32,
+    pub double_sided: bool,
+}
+
+impl Default for Material {
+    fn default() -> Material {
+        Material {
+            name: "default".to_string(),
+            base_color_factor: [1.0_f32; 4],
+            base_color_texture: None,
+            metallic_factor: 1.0_f32,
+            roughness_factor: 1.0_f32,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0_f32,
+            occlusion_texture: None,
+            occlusion_strength: 1.0_f32,
+            emissive_factor: [0.0_f32; 3],
+            emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5_f32,
+            double_sided: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SamplerDesc {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    // False samples only the base level, like glTF's NEAREST/LINEAR minification filters
+    pub mipmaps: bool,
+    pub address_mode_u: vk::SamplerAddressMode,
+    pub address_mode_v: vk::SamplerAddressMode,
+}
+
+impl Default for SamplerDesc {
+    fn default() -> SamplerDesc {
+        SamplerDesc {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            mipmaps: true,
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Texture {
+    pub image: usize,
+    pub sampler: SamplerDesc,
+}
+
+// Tightly packed RGBA8 pixels. Color textures (base color, emissive) are sRGB encoded, data
+// textures (normal, metallic-roughness, occlusion) are linear.
+pub struct ImageData {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+    pub srgb: bool,
+}
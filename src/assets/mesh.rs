@@ -0,0 +1,268 @@
+use ash::vk;
+use std::mem::offset_of;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vertex {
+    pub pos: [f32; 3],
+    pub normal: [f32; 3],
+    pub tex_coord: [f32; 2],
+    pub color: [f32; 3],
+    // xyz along increasing u, w the handedness of the bitangent (glTF convention)
+    pub tangent: [f32; 4],
+}
+
+impl Default for Vertex {
+    fn default() -> Vertex {
+        Vertex {
+            pos: [0.0_f32; 3],
+            normal: [0.0_f32; 3],
+            tex_coord: [0.0_f32; 2],
+            color: [1.0_f32; 3],
+            tangent: [1.0_f32, 0.0_f32, 0.0_f32, 1.0_f32],
+        }
+    }
+}
+
+impl Vertex {
+    pub fn binding_descriptions() -> [vk::VertexInputBindingDescription; 1] {
+        [vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: std::mem::size_of::<Vertex>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }]
+    }
+
+    pub fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 5] {
+        [
+            vk::VertexInputAttributeDescription {
+                location: 0,
+                binding: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: offset_of!(Vertex, pos) as u32,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 1,
+                binding: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: offset_of!(Vertex, normal) as u32,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 2,
+                binding: 0,
+                format: vk::Format::R32G32_SFLOAT,
+                offset: offset_of!(Vertex, tex_coord) as u32,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 3,
+                binding: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: offset_of!(Vertex, color) as u32,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 4,
+                binding: 0,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                offset: offset_of!(Vertex, tangent) as u32,
+            },
+        ]
+    }
+}
+
+// CPU-side indexed triangle list, laid out the way it is uploaded
+pub struct Mesh {
+    pub name: String,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub material: Option<usize>,
+    pub bounds: Bounds,
+}
+
+// Object-space bounding box plus a sphere centered on it, for culling
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bounds {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    pub center: [f32; 3],
+    pub radius: f32,
+}
+
+impl Default for Bounds {
+    // Empty, so the union with anything is that thing
+    fn default() -> Bounds {
+        Bounds {
+            min: [f32::MAX; 3],
+            max: [f32::MIN; 3],
+            center: [0.0_f32; 3],
+            radius: 0.0_f32,
+        }
+    }
+}
+
+impl Bounds {
+    pub fn from_vertices(vertices: &[Vertex]) -> Bounds {
+        let mut bounds = Bounds::default();
+        for vertex in vertices.iter() {
+            for axis in 0..3 {
+                bounds.min[axis] = bounds.min[axis].min(vertex.pos[axis]);
+                bounds.max[axis] = bounds.max[axis].max(vertex.pos[axis]);
+            }
+        }
+        if bounds.is_empty() {
+            return bounds;
+        }
+
+        // Tighter than half the box diagonal for anything that isn't box-shaped
+        bounds.center = [0, 1, 2].map(|axis| (bounds.min[axis] + bounds.max[axis]) * 0.5_f32);
+        bounds.radius = vertices
+            .iter()
+            .map(|vertex| length(sub(vertex.pos, bounds.center)))
+            .fold(0.0_f32, f32::max);
+
+        bounds
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min[0] > self.max[0]
+    }
+
+    pub fn union(&self, other: &Bounds) -> Bounds {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+
+        let min = [0, 1, 2].map(|axis| self.min[axis].min(other.min[axis]));
+        let max = [0, 1, 2].map(|axis| self.max[axis].max(other.max[axis]));
+        let center = [0, 1, 2].map(|axis| (min[axis] + max[axis]) * 0.5_f32);
+        let radius = [self, other]
+            .iter()
+            .map(|b| length(sub(b.center, center)) + b.radius)
+            .fold(0.0_f32, f32::max);
+
+        Bounds {
+            min,
+            max,
+            center,
+            radius,
+        }
+    }
+}
+
+impl Mesh {
+    // Smooth normals weighted by triangle area, for sources that don't provide any
+    pub fn compute_normals(&mut self) {
+        let mut normals = vec![[0.0_f32; 3]; self.vertices.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| self.vertices[triangle[i] as usize].pos);
+            let normal = cross(sub(b, a), sub(c, a));
+            for &index in triangle.iter() {
+                let n = &mut normals[index as usize];
+                *n = [n[0] + normal[0], n[1] + normal[1], n[2] + normal[2]];
+            }
+        }
+
+        for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+            vertex.normal = normalize(normal);
+        }
+    }
+
+    // Per-vertex tangents from the texture coordinate gradients of the surrounding triangles,
+    // orthogonalized against the normal. Needs normals first.
+    pub fn compute_tangents(&mut self) {
+        let mut tangents = vec![[0.0_f32; 3]; self.vertices.len()];
+        let mut bitangents = vec![[0.0_f32; 3]; self.vertices.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| &self.vertices[triangle[i] as usize]);
+            let (e1, e2) = (sub(b.pos, a.pos), sub(c.pos, a.pos));
+            let (du1, dv1) = (
+                b.tex_coord[0] - a.tex_coord[0],
+                b.tex_coord[1] - a.tex_coord[1],
+            );
+            let (du2, dv2) = (
+                c.tex_coord[0] - a.tex_coord[0],
+                c.tex_coord[1] - a.tex_coord[1],
+            );
+            let det = du1 * dv2 - du2 * dv1;
+            if det.abs() < f32::EPSILON {
+                continue;
+            }
+            let r = 1.0_f32 / det;
+            let tangent = [0, 1, 2].map(|i| (e1[i] * dv2 - e2[i] * dv1) * r);
+            let bitangent = [0, 1, 2].map(|i| (e2[i] * du1 - e1[i] * du2) * r);
+            for &index in triangle.iter() {
+                let (t, b) = (
+                    &mut tangents[index as usize],
+                    &mut bitangents[index as usize],
+                );
+                *t = [t[0] + tangent[0], t[1] + tangent[1], t[2] + tangent[2]];
+                *b = [
+                    b[0] + bitangent[0],
+                    b[1] + bitangent[1],
+                    b[2] + bitangent[2],
+                ];
+            }
+        }
+
+        for ((vertex, t), b) in self.vertices.iter_mut().zip(tangents).zip(bitangents) {
+            let n = vertex.normal;
+            let n_dot_t = dot(n, t);
+            let mut tangent = normalize([
+                t[0] - n[0] * n_dot_t,
+                t[1] - n[1] * n_dot_t,
+                t[2] - n[2] * n_dot_t,
+            ]);
+            // Untextured vertices get any vector perpendicular to the normal
+            if length(t) < f32::EPSILON {
+                let axis = if n[0].abs() < 0.9_f32 {
+                    [1.0_f32, 0.0_f32, 0.0_f32]
+                } else {
+                    [0.0_f32, 1.0_f32, 0.0_f32]
+                };
+                tangent = normalize(cross(cross(n, axis), n));
+            }
+            let handedness = if dot(cross(n, tangent), b) < 0.0_f32 {
+                -1.0_f32
+            } else {
+                1.0_f32
+            };
+            vertex.tangent = [tangent[0], tangent[1], tangent[2], handedness];
+        }
+    }
+
+    // Loaders call this once the vertices are final
+    pub fn compute_bounds(&mut self) {
+        self.bounds = Bounds::from_vertices(&self.vertices);
+    }
+}
+
+pub fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+pub fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub fn length(v: [f32; 3]) -> f32 {
+    (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt()
+}
+
+pub fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = length(v);
+    if length > f32::EPSILON {
+        [v[0] / length, v[1] / length, v[2] / length]
+    } else {
+        [0.0_f32, 0.0_f32, 1.0_f32]
+    }
+}
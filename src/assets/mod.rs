@@ -0,0 +1,7 @@
+pub mod color_lut;
+pub mod environment;
+pub mod gltf;
+pub mod hdr;
+pub mod material;
+pub mod mesh;
+pub mod obj;
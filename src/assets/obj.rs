@@ -0,0 +1,706 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use super::gltf::{GltfScene, Node};
+use super::material::{self, AlphaMode, ImageData, SamplerDesc, Texture};
+use super::mesh::{Bounds, Mesh, Vertex};
+
+// Wavefront OBJ/MTL. Every `o`, `g` or `usemtl` starts a new mesh; each mesh gets its own
+// deduplicated vertex list so it can be uploaded and drawn on its own.
+
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub name: String,
+    pub ambient: [f32; 3],
+    pub diffuse: [f32; 3],
+    pub specular: [f32; 3],
+    pub emissive: [f32; 3],
+    pub shininess: f32,
+    pub dissolve: f32,
+    // PBR extension (Pr/Pm)
+    pub roughness: Option<f32>,
+    pub metallic: Option<f32>,
+    pub diffuse_texture: Option<PathBuf>,
+    pub normal_texture: Option<PathBuf>,
+    pub specular_texture: Option<PathBuf>,
+    pub emissive_texture: Option<PathBuf>,
+    pub alpha_texture: Option<PathBuf>,
+}
+
+impl Material {
+    fn new(name: &str) -> Material {
+        Material {
+            name: name.to_string(),
+            ambient: [0.0_f32; 3],
+            diffuse: [1.0_f32; 3],
+            specular: [0.0_f32; 3],
+            emissive: [0.0_f32; 3],
+            shininess: 0.0_f32,
+            dissolve: 1.0_f32,
+            roughness: None,
+            metallic: None,
+            diffuse_texture: None,
+            normal_texture: None,
+            specular_texture: None,
+            emissive_texture: None,
+            alpha_texture: None,
+        }
+    }
+}
+
+pub struct ObjModel {
+    pub meshes: Vec<Mesh>,
+    pub materials: Vec<Material>,
+}
+
+impl ObjModel {
+    // Every mesh becomes a root node of its own and the Phong materials are mapped onto
+    // metallic-roughness. Only PNG textures are loaded.
+    pub fn into_scene(self) -> Result<GltfScene, String> {
+        let mut textures: Vec<Texture> = vec![];
+        let mut images: Vec<ImageData> = vec![];
+        let mut loaded: HashMap<(PathBuf, bool), usize> = HashMap::new();
+        let mut texture = |path: &Option<PathBuf>, srgb: bool| -> Result<Option<usize>, String> {
+            let Some(path) = path else {
+                return Ok(None);
+            };
+            if let Some(&index) = loaded.get(&(path.clone(), srgb)) {
+                return Ok(Some(index));
+            }
+            if !path
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+            {
+                println!(
+                    "OBJ: skipping texture {}, only PNG is supported",
+                    path.display()
+                );
+                return Ok(None);
+            }
+            images.push(load_png(path, srgb)?);
+            textures.push(Texture {
+                image: images.len() - 1,
+                sampler: SamplerDesc::default(),
+            });
+            loaded.insert((path.clone(), srgb), textures.len() - 1);
+            Ok(Some(textures.len() - 1))
+        };
+
+        let materials = self
+            .materials
+            .iter()
+            .map(|m| {
+                Ok(material::Material {
+                    name: m.name.clone(),
+                    base_color_factor: [m.diffuse[0], m.diffuse[1], m.diffuse[2], m.dissolve],
+                    base_color_texture: texture(&m.diffuse_texture, true)?,
+                    metallic_factor: m.metallic.unwrap_or(0.0_f32),
+                    // The usual mapping from a Blinn-Phong exponent
+                    roughness_factor: m
+                        .roughness
+                        .unwrap_or_else(|| (2.0_f32 / (m.shininess + 2.0_f32)).sqrt()),
+                    normal_texture: texture(&m.normal_texture, false)?,
+                    emissive_factor: m.emissive,
+                    emissive_texture: texture(&m.emissive_texture, true)?,
+                    alpha_mode: if m.dissolve < 1.0_f32 {
+                        AlphaMode::Blend
+                    } else {
+                        AlphaMode::Opaque
+                    },
+                    ..Default::default()
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let nodes = self
+            .meshes
+            .iter()
+            .enumerate()
+            .map(|(i, mesh)| Node {
+                name: mesh.name.clone(),
+                translation: [0.0_f32; 3],
+                rotation: [0.0_f32, 0.0_f32, 0.0_f32, 1.0_f32],
+                scale: [1.0_f32; 3],
+                children: vec![],
+                mesh: Some(i),
+            })
+            .collect::<Vec<_>>();
+
+        Ok(GltfScene {
+            roots: (0..nodes.len()).collect(),
+            meshes: self.meshes.into_iter().map(|mesh| vec![mesh]).collect(),
+            nodes,
+            materials,
+            textures,
+            images,
+        })
+    }
+}
+
+pub fn load(path: &Path) -> Result<ObjModel, String> {
+    let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let base_dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+
+    parse(&source, |mtllib| {
+        let mtl_path = base_dir.join(mtllib);
+        let mtl_source =
+            fs::read_to_string(&mtl_path).map_err(|e| format!("{}: {}", mtl_path.display(), e))?;
+        // Texture paths are relative to the .mtl file, not the .obj
+        parse_mtl(&mtl_source, mtl_path.parent().unwrap_or(Path::new("")))
+            .map_err(|e| format!("{}:{}", mtl_path.display(), e))
+    })
+    .map_err(|e| format!("{}:{}", path.display(), e))
+}
+
+// `load_mtl` resolves `mtllib` statements; errors are reported as "line: message"
+pub fn parse(
+    source: &str,
+    mut load_mtl: impl FnMut(&str) -> Result<Vec<Material>, String>,
+) -> Result<ObjModel, String> {
+    let mut positions: Vec<[f32; 3]> = vec![];
+    let mut colors: Vec<[f32; 3]> = vec![];
+    let mut tex_coords: Vec<[f32; 2]> = vec![];
+    let mut normals: Vec<[f32; 3]> = vec![];
+
+    let mut materials: Vec<Material> = vec![];
+    let mut builders: Vec<MeshBuilder> = vec![];
+    let mut current = MeshBuilder::new("default", None);
+
+    for (line_number, line) in logical_lines(source) {
+        let error = |message: String| format!("{}: {}", line_number, message);
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => {
+                let values = parse_floats(&args, 3, 7).map_err(error)?;
+                positions.push([values[0], values[1], values[2]]);
+                // Vertex colors are a common extension: "v x y z r g b"
+                colors.push(if values.len() >= 6 {
+                    let rgb = &values[values.len() - 3..];
+                    [rgb[0], rgb[1], rgb[2]]
+                } else {
+                    [1.0_f32; 3]
+                });
+            }
+            "vt" => {
+                let values = parse_floats(&args, 1, 3).map_err(error)?;
+                // OBJ puts the texture origin at the bottom left, Vulkan at the top left
+                tex_coords.push([values[0], 1.0_f32 - values.get(1).copied().unwrap_or(0.0)]);
+            }
+            "vn" => {
+                let values = parse_floats(&args, 3, 3).map_err(error)?;
+                normals.push([values[0], values[1], values[2]]);
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(error(format!(
+                        "face needs at least 3 vertices, got {}",
+                        args.len()
+                    )));
+                }
+                let corners = args
+                    .iter()
+                    .map(|arg| {
+                        parse_face_vertex(arg, positions.len(), tex_coords.len(), normals.len())
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(error)?;
+
+                let polygon: Vec<[f32; 3]> = corners.iter().map(|c| positions[c.0]).collect();
+                for triangle in triangulate(&polygon) {
+                    for i in triangle {
+                        current.push(corners[i], &positions, &colors, &tex_coords, &normals);
+                    }
+                }
+            }
+            "o" | "g" => {
+                let name = if args.is_empty() {
+                    "default".to_string()
+                } else {
+                    args.join(" ")
+                };
+                let material = current.mesh.material;
+                builders.push(std::mem::replace(
+                    &mut current,
+                    MeshBuilder::new(&name, material),
+                ));
+            }
+            "usemtl" => {
+                let name = args.join(" ");
+                let material = materials.iter().position(|m| m.name == name);
+                if material.is_none() {
+                    println!("OBJ line {}: unknown material {}", line_number, name);
+                }
+                let mesh_name = current.mesh.name.clone();
+                builders.push(std::mem::replace(
+                    &mut current,
+                    MeshBuilder::new(&mesh_name, material),
+                ));
+            }
+            "mtllib" => {
+                for library in args.iter() {
+                    materials.extend(load_mtl(library).map_err(error)?);
+                }
+            }
+            // Smoothing groups, lines, points and free-form geometry are not supported
+            "s" | "l" | "p" | "cstype" | "deg" | "curv" | "surf" | "parm" | "end" => {}
+            _ => println!("OBJ line {}: ignoring {}", line_number, keyword),
+        }
+    }
+    builders.push(current);
+
+    let meshes = builders
+        .into_iter()
+        .filter(|builder| !builder.mesh.indices.is_empty())
+        .map(|mut builder| {
+            if !builder.has_normals {
+                builder.mesh.compute_normals();
+            }
+            builder.mesh.compute_tangents();
+            builder.mesh.compute_bounds();
+            builder.mesh
+        })
+        .collect();
+
+    Ok(ObjModel { meshes, materials })
+}
+
+// Texture paths are resolved relative to `base_dir`
+pub fn parse_mtl(source: &str, base_dir: &Path) -> Result<Vec<Material>, String> {
+    let mut materials: Vec<Material> = vec![];
+
+    for (line_number, line) in logical_lines(source) {
+        let error = |message: String| format!("{}: {}", line_number, message);
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            materials.push(Material::new(&args.join(" ")));
+            continue;
+        }
+        let Some(material) = materials.last_mut() else {
+            return Err(error(format!("{} before newmtl", keyword)));
+        };
+
+        let color = |args: &[&str]| -> Result<[f32; 3], String> {
+            let values = parse_floats(args, 1, 3).map_err(error)?;
+            // A single value means grey
+            Ok(match values.len() {
+                1 => [values[0]; 3],
+                _ => [values[0], values[1], values[2]],
+            })
+        };
+        let scalar = |args: &[&str]| -> Result<f32, String> {
+            Ok(parse_floats(args, 1, 1).map_err(error)?[0])
+        };
+        // Texture options (-bm, -s, ...) come before the file name
+        let texture = |args: &[&str]| -> Result<PathBuf, String> {
+            match args.last() {
+                Some(file) => Ok(base_dir.join(file.replace('\\', "/"))),
+                None => Err(error("missing texture file name".to_string())),
+            }
+        };
+
+        match keyword {
+            "Ka" => material.ambient = color(&args)?,
+            "Kd" => material.diffuse = color(&args)?,
+            "Ks" => material.specular = color(&args)?,
+            "Ke" => material.emissive = color(&args)?,
+            "Ns" => material.shininess = scalar(&args)?,
+            "d" => material.dissolve = scalar(&args)?,
+            "Tr" => material.dissolve = 1.0_f32 - scalar(&args)?,
+            "Pr" => material.roughness = Some(scalar(&args)?),
+            "Pm" => material.metallic = Some(scalar(&args)?),
+            "map_Kd" => material.diffuse_texture = Some(texture(&args)?),
+            "map_Bump" | "map_bump" | "bump" | "norm" => {
+                material.normal_texture = Some(texture(&args)?)
+            }
+            "map_Ks" => material.specular_texture = Some(texture(&args)?),
+            "map_Ke" => material.emissive_texture = Some(texture(&args)?),
+            "map_d" => material.alpha_texture = Some(texture(&args)?),
+            _ => {}
+        }
+    }
+
+    Ok(materials)
+}
+
+fn load_png(path: &Path, srgb: bool) -> Result<ImageData, String> {
+    let error = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+
+    let file = File::open(path).map_err(|e| error(&e))?;
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| error(&e))?;
+    let mut buffer = vec![0_u8; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(|e| error(&e))?;
+
+    let channels = info.color_type.samples();
+    let pixels = buffer[..info.buffer_size()]
+        .chunks_exact(channels)
+        .flat_map(|texel| match channels {
+            1 => [texel[0], texel[0], texel[0], 255],
+            2 => [texel[0], texel[0], texel[0], texel[1]],
+            3 => [texel[0], texel[1], texel[2], 255],
+            _ => [texel[0], texel[1], texel[2], texel[3]],
+        })
+        .collect();
+
+    Ok(ImageData {
+        width: info.width,
+        height: info.height,
+        pixels,
+        srgb,
+    })
+}
+
+// Strips comments and joins lines continued with a trailing backslash
+fn logical_lines(source: &str) -> Vec<(usize, String)> {
+    let mut lines = vec![];
+    let mut pending: Option<(usize, String)> = None;
+
+    for (i, raw) in source.lines().enumerate() {
+        let line = raw.split('#').next().unwrap_or("").trim_end();
+        let (start, mut text) = pending.take().unwrap_or((i + 1, String::new()));
+
+        match line.strip_suffix('\\') {
+            Some(continued) => {
+                text.push_str(continued);
+                text.push(' ');
+                pending = Some((start, text));
+            }
+            None => {
+                text.push_str(line);
+                lines.push((start, text));
+            }
+        }
+    }
+    lines.extend(pending);
+
+    lines
+}
+
+fn parse_floats(args: &[&str], min: usize, max: usize) -> Result<Vec<f32>, String> {
+    if args.len() < min || args.len() > max {
+        return Err(format!(
+            "expected {} to {} values, got {}",
+            min,
+            max,
+            args.len()
+        ));
+    }
+
+    args.iter()
+        .map(|arg| {
+            arg.parse::<f32>()
+                .map_err(|_| format!("invalid number {}", arg))
+        })
+        .collect()
+}
+
+// Resolves one "v", "v/vt", "v//vn" or "v/vt/vn" face vertex into zero-based indices.
+// Negative indices count back from the most recently defined element.
+fn parse_face_vertex(
+    arg: &str,
+    position_count: usize,
+    tex_coord_count: usize,
+    normal_count: usize,
+) -> Result<(usize, Option<usize>, Option<usize>), String> {
+    let resolve = |text: &str, count: usize| -> Result<usize, String> {
+        let index: i64 = text
+            .parse()
+            .map_err(|_| format!("invalid index {} in {}", text, arg))?;
+        let resolved = match index {
+            0 => return Err(format!("index 0 in {}", arg)),
+            i if i > 0 => i - 1,
+            i => count as i64 + i,
+        };
+        if resolved < 0 || resolved >= count as i64 {
+            return Err(format!("index {} out of range in {}", index, arg));
+        }
+        Ok(resolved as usize)
+    };
+
+    let mut parts = arg.split('/');
+    let position = resolve(parts.next().unwrap_or(""), position_count)?;
+    let tex_coord = match parts.next() {
+        Some("") | None => None,
+        Some(text) => Some(resolve(text, tex_coord_count)?),
+    };
+    let normal = match parts.next() {
+        Some("") | None => None,
+        Some(text) => Some(resolve(text, normal_count)?),
+    };
+
+    Ok((position, tex_coord, normal))
+}
+
+struct MeshBuilder {
+    mesh: Mesh,
+    unique: HashMap<(usize, Option<usize>, Option<usize>), u32>,
+    has_normals: bool,
+}
+
+impl MeshBuilder {
+    fn new(name: &str, material: Option<usize>) -> MeshBuilder {
+        MeshBuilder {
+            mesh: Mesh {
+                name: name.to_string(),
+                vertices: vec![],
+                indices: vec![],
+                material,
+                bounds: Bounds::default(),
+            },
+            unique: HashMap::new(),
+            has_normals: true,
+        }
+    }
+
+    fn push(
+        &mut self,
+        corner: (usize, Option<usize>, Option<usize>),
+        positions: &[[f32; 3]],
+        colors: &[[f32; 3]],
+        tex_coords: &[[f32; 2]],
+        normals: &[[f32; 3]],
+    ) {
+        let (position, tex_coord, normal) = corner;
+        self.has_normals &= normal.is_some();
+
+        let vertices = &mut self.mesh.vertices;
+        let index = *self.unique.entry(corner).or_insert_with(|| {
+            vertices.push(Vertex {
+                pos: positions[position],
+                normal: normal.map_or([0.0_f32; 3], |n| normals[n]),
+                tex_coord: tex_coord.map_or([0.0_f32; 2], |t| tex_coords[t]),
+                color: colors[position],
+                ..Default::default()
+            });
+            (vertices.len() - 1) as u32
+        });
+        self.mesh.indices.push(index);
+    }
+}
+
+// Ear clipping in the polygon's dominant plane, so concave faces come out right. Degenerate
+// input falls back to a fan.
+fn triangulate(polygon: &[[f32; 3]]) -> Vec<[usize; 3]> {
+    let n = polygon.len();
+    let fan = |remaining: &[usize]| -> Vec<[usize; 3]> {
+        (1..remaining.len().saturating_sub(1))
+            .map(|i| [remaining[0], remaining[i], remaining[i + 1]])
+            .collect()
+    };
+    if n == 3 {
+        return vec![[0, 1, 2]];
+    }
+
+    // Newell's method gives a robust normal even for concave polygons
+    let mut normal = [0.0_f32; 3];
+    for i in 0..n {
+        let (a, b) = (polygon[i], polygon[(i + 1) % n]);
+        normal[0] += (a[1] - b[1]) * (a[2] + b[2]);
+        normal[1] += (a[2] - b[2]) * (a[0] + b[0]);
+        normal[2] += (a[0] - b[0]) * (a[1] + b[1]);
+    }
+    let axis = (0..3)
+        .max_by(|&a, &b| normal[a].abs().total_cmp(&normal[b].abs()))
+        .unwrap();
+    if normal[axis].abs() <= f32::EPSILON {
+        return fan(&(0..n).collect::<Vec<_>>());
+    }
+
+    // Project onto the plane perpendicular to the dominant axis, keeping counter-clockwise order
+    let (u, v) = match axis {
+        0 => (1, 2),
+        1 => (2, 0),
+        _ => (0, 1),
+    };
+    let sign = normal[axis].signum();
+    let points: Vec<[f32; 2]> = polygon.iter().map(|p| [p[u], p[v] * sign]).collect();
+    let area2 = |a: [f32; 2], b: [f32; 2], c: [f32; 2]| {
+        (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+    };
+
+    let mut remaining: Vec<usize> = (0..n).collect();
+    let mut triangles = vec![];
+    let mut misses = 0;
+    let mut i = 0;
+
+    while remaining.len() > 3 {
+        if misses >= remaining.len() {
+            triangles.extend(fan(&remaining));
+            return triangles;
+        }
+
+        let count = remaining.len();
+        let (prev, curr, next) = (
+            remaining[(i + count - 1) % count],
+            remaining[i % count],
+            remaining[(i + 1) % count],
+        );
+        let (a, b, c) = (points[prev], points[curr], points[next]);
+
+        let is_ear = area2(a, b, c) > 0.0_f32
+            && remaining.iter().all(|&other| {
+                if other == prev || other == curr || other == next {
+                    return true;
+                }
+                let p = points[other];
+                area2(a, b, p) < 0.0_f32 || area2(b, c, p) < 0.0_f32 || area2(c, a, p) < 0.0_f32
+            });
+
+        if is_ear {
+            triangles.push([prev, curr, next]);
+            remaining.remove(i % count);
+            misses = 0;
+        } else {
+            i += 1;
+            misses += 1;
+        }
+        i %= remaining.len();
+    }
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+
+    triangles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    fn mesh<'a>(model: &'a ObjModel, name: &str) -> &'a Mesh {
+        model
+            .meshes
+            .iter()
+            .find(|mesh| mesh.name == name)
+            .unwrap_or_else(|| panic!("no mesh named {}", name))
+    }
+
+    // Twice the signed area of each triangle, seen from +Z
+    fn triangle_areas(mesh: &Mesh) -> Vec<f32> {
+        mesh.indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                let [a, b, c] = [0, 1, 2].map(|i| mesh.vertices[triangle[i] as usize].pos);
+                (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn quad_is_split_into_two_triangles() {
+        let model = load(&fixture("polygons.obj")).unwrap();
+        let quad = mesh(&model, "quad");
+
+        assert_eq!(quad.vertices.len(), 4);
+        assert_eq!(quad.indices.len(), 6);
+        assert_eq!(quad.material, Some(0));
+        assert!(triangle_areas(quad).iter().all(|&area| area > 0.0_f32));
+    }
+
+    #[test]
+    fn concave_polygon_is_triangulated_inside_its_outline() {
+        let model = load(&fixture("polygons.obj")).unwrap();
+        let l_shape = mesh(&model, "l_shape");
+        let areas = triangle_areas(l_shape);
+
+        assert_eq!(l_shape.vertices.len(), 6);
+        assert_eq!(areas.len(), 4);
+        // A fan from the first corner would make a clockwise triangle across the notch
+        assert!(areas.iter().all(|&area| area > 0.0_f32));
+        assert!((areas.iter().sum::<f32>() - 6.0_f32).abs() < 1e-5_f32);
+        assert_eq!(l_shape.material, Some(1));
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_last_element() {
+        let model = load(&fixture("negative_indices.obj")).unwrap();
+        let absolute = mesh(&model, "absolute");
+        let relative = mesh(&model, "relative");
+
+        assert_eq!(absolute.indices, relative.indices);
+        for (a, r) in absolute.vertices.iter().zip(relative.vertices.iter()) {
+            assert_eq!(a.pos, r.pos);
+            assert_eq!(a.tex_coord, r.tex_coord);
+        }
+        // Flipped for Vulkan's top-left origin
+        let corner = relative
+            .vertices
+            .iter()
+            .find(|vertex| vertex.pos == [1.0_f32, 1.0_f32, 0.0_f32])
+            .unwrap();
+        assert_eq!(corner.tex_coord, [1.0_f32, 0.0_f32]);
+    }
+
+    #[test]
+    fn identical_corners_share_a_vertex() {
+        let model = load(&fixture("shared_vertices.obj")).unwrap();
+
+        let shared = mesh(&model, "shared");
+        assert_eq!(shared.vertices.len(), 4);
+        assert_eq!(shared.indices, vec![0, 1, 2, 0, 2, 3]);
+
+        // Same position, different texture coordinate
+        let split = mesh(&model, "split");
+        assert_eq!(split.vertices.len(), 5);
+        assert_eq!(split.indices, vec![0, 1, 2, 3, 2, 4]);
+    }
+
+    #[test]
+    fn mtl_statements_fill_in_materials() {
+        let model = load(&fixture("polygons.obj")).unwrap();
+        let [red, textured] = &model.materials[..] else {
+            panic!("expected 2 materials, got {}", model.materials.len());
+        };
+
+        assert_eq!(red.name, "red");
+        assert_eq!(red.diffuse, [1.0_f32, 0.0_f32, 0.0_f32]);
+        // A single value means grey
+        assert_eq!(red.specular, [0.5_f32; 3]);
+        assert_eq!(red.shininess, 100.0_f32);
+        assert_eq!(red.dissolve, 1.0_f32);
+        assert_eq!(red.diffuse_texture, None);
+
+        assert_eq!(textured.dissolve, 0.5_f32);
+        assert_eq!(textured.roughness, Some(0.25_f32));
+        assert_eq!(textured.metallic, Some(1.0_f32));
+        // Options are skipped and paths are relative to the .mtl file
+        assert_eq!(
+            textured.diffuse_texture,
+            Some(fixture("textures/albedo.png"))
+        );
+        assert_eq!(textured.normal_texture, Some(fixture("normal.png")));
+    }
+
+    #[test]
+    fn mtl_textures_resolve_against_the_mtl_file() {
+        let model = load(&fixture("nested.obj")).unwrap();
+
+        assert_eq!(
+            model.materials[0].diffuse_texture,
+            Some(fixture("materials/textures/brick.png"))
+        );
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let result = parse("v 0 0 0\nv 1 0 0\n\nf 1 2 3\n", |_| Ok(vec![]));
+
+        assert_eq!(result.err().unwrap(), "4: index 3 out of range in 3");
+    }
+}
@@ -0,0 +1,6 @@
+pub mod app;
+pub mod assets;
+pub mod util;
+
+pub use app::application::{run, Application};
+pub use app::Renderer;
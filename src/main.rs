@@ -1,9 +1,161 @@
-use app::App;
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Quaternion, Rad, Rotation3, Vector3};
+use std::f32::consts::FRAC_PI_2;
+use std::path::Path;
+use vulkan_tutorial_rust::app::light::{PointLight, SpotLight};
+use vulkan_tutorial_rust::app::scene::{NodeId, Scene, Transform};
+use vulkan_tutorial_rust::assets::mesh::Bounds;
+use vulkan_tutorial_rust::util::constants::MODEL_PATH;
+use vulkan_tutorial_rust::{Application, Renderer};
 
-pub mod app;
-pub mod util;
+// The model viewer: loads the configured glTF file, dresses it with a ground slab, spinning
+// satellites and lights around it, and frames the camera on it.
+
+#[derive(Default)]
+struct Viewer {
+    spinner: Option<NodeId>,
+}
+
+impl Application for Viewer {
+    fn init(&mut self, renderer: &mut Renderer) {
+        // Without a model the triangle is drawn instead
+        if let Err(e) = renderer.load_model(Path::new(MODEL_PATH)) {
+            println!("Not loading model: {}", e);
+            return;
+        }
+
+        // The satellites and the ground are copies of the first mesh
+        let first_mesh = match renderer.model() {
+            Some(model) if model.mesh_count() > 0 => Some(*model.mesh_bounds(0)),
+            _ => None,
+        };
+        if let (Some(_), Some((min, max))) = (first_mesh, scene_bounds(renderer)) {
+            self.spinner = Some(add_satellites(
+                renderer.scene_mut(),
+                Point3::midpoint(min, max),
+                (max - min).magnitude() * 0.5_f32,
+            ));
+        }
+        // A unit sphere at the origin stands in when nothing in the scene draws
+        let (center, radius, floor) = match scene_bounds(renderer) {
+            Some((min, max)) => (
+                Point3::midpoint(min, max),
+                (max - min).magnitude() * 0.5_f32,
+                min.y,
+            ),
+            None => (Point3::origin(), 1.0_f32, -1.0_f32),
+        };
+        renderer.camera_mut().frame_bounds(center, radius);
+        if let Some(first_mesh) = &first_mesh {
+            add_ground(renderer.scene_mut(), first_mesh, center, radius, floor);
+        }
+
+        let shadows = renderer.shadow_settings_mut();
+        shadows.max_distance = radius * 12.0_f32;
+        shadows.caster_distance = radius * 4.0_f32;
+
+        let lights = renderer.lights_mut();
+        let spot_position = center + Vector3::new(1.5_f32, 2.5_f32, 1.5_f32) * radius;
+        if let Some(spot) = lights.spot {
+            lights.spot = Some(SpotLight {
+                position: spot_position,
+                direction: center - spot_position,
+                range: radius * 8.0_f32,
+                ..spot
+            });
+        }
+        // A warm and a cool light on opposite sides, low enough to graze the ground
+        for (offset, color) in [
+            (
+                Vector3::new(-1.2_f32, 0.3_f32, 0.8_f32),
+                [1.0_f32, 0.5_f32, 0.2_f32],
+            ),
+            (
+                Vector3::new(1.0_f32, 0.4_f32, -1.2_f32),
+                [0.3_f32, 0.5_f32, 1.0_f32],
+            ),
+        ] {
+            lights.points.push(PointLight {
+                position: center + offset * radius,
+                color,
+                intensity: 4.0_f32,
+                range: radius * 3.0_f32,
+            });
+        }
+    }
+
+    fn update(&mut self, renderer: &mut Renderer, dt: f32) {
+        if let Some(spinner) = self.spinner {
+            let scene = renderer.scene_mut();
+            let mut transform = *scene.node(spinner).local_transform();
+            transform.rotation = Quaternion::from_angle_y(Rad(dt * 0.5_f32)) * transform.rotation;
+            scene.set_local_transform(spinner, transform);
+        }
+    }
+}
+
+// World-space bounds of everything in the scene that draws a mesh of the model
+fn scene_bounds(renderer: &Renderer) -> Option<(Point3<f32>, Point3<f32>)> {
+    let model = renderer.model()?;
+    renderer.scene().bounds(|mesh| *model.mesh_bounds(mesh))
+}
+
+// A pivot spinning above the model with smaller copies of its first mesh around it
+fn add_satellites(scene: &mut Scene, center: Point3<f32>, radius: f32) -> NodeId {
+    let pivot = scene.add_node(
+        "satellites",
+        None,
+        Transform {
+            translation: center.to_vec() + Vector3::unit_y() * radius * 1.5_f32,
+            ..Default::default()
+        },
+    );
+    for i in 0..4 {
+        let angle = Rad(i as f32 * FRAC_PI_2);
+        let satellite = scene.add_node(
+            &format!("satellite{}", i),
+            Some(pivot),
+            Transform {
+                translation: Vector3::new(angle.0.cos(), 0.0_f32, angle.0.sin()) * radius * 1.5_f32,
+                rotation: Quaternion::from_angle_y(angle),
+                scale: Vector3::new(0.3_f32, 0.3_f32, 0.3_f32),
+            },
+        );
+        scene.node_mut(satellite).mesh = Some(0);
+    }
+    scene.update_world_transforms();
+
+    pivot
+}
+
+// A flat slab under the scene to catch shadows, made from the first mesh
+fn add_ground(scene: &mut Scene, bounds: &Bounds, center: Point3<f32>, radius: f32, floor: f32) {
+    let size = [0, 1, 2].map(|axis| (bounds.max[axis] - bounds.min[axis]).max(0.001_f32));
+    let scale = Vector3::new(
+        radius * 8.0_f32 / size[0],
+        radius * 0.05_f32 / size[1],
+        radius * 8.0_f32 / size[2],
+    );
+    // Puts the top of the slab at the floor, centered under the scene
+    let translation = Vector3::new(center.x, floor, center.z)
+        - Vector3::new(
+            bounds.center[0] * scale.x,
+            bounds.max[1] * scale.y,
+            bounds.center[2] * scale.z,
+        );
+
+    let ground = scene.add_node(
+        "ground",
+        None,
+        Transform {
+            translation,
+            scale,
+            ..Default::default()
+        },
+    );
+    scene.node_mut(ground).mesh = Some(0);
+    scene.update_world_transforms();
+}
 
 fn main() {
-    let mut app = App::new();
-    app.main_loop();
+    vulkan_tutorial_rust::run(Viewer::default());
 }
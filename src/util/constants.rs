@@ -1,8 +1,50 @@
-use super::structures::{DeviceExtension, ValidationInfo};
+use super::structures::{ColorSpacePolicy, DeviceExtension, ValidationInfo, VsyncPolicy};
 
 pub const WINDOW_WIDTH: u32 = 800;
 pub const WINDOW_HEIGHT: u32 = 600;
 pub const MAX_FRAMES_IN_FLIGHT: u32 = 2;
+pub const PREFER_DYNAMIC_RENDERING: bool = true;
+// Cull and build draw commands in a compute pass when drawIndirectCount is supported
+pub const PREFER_GPU_CULLING: bool = true;
+// Index textures from one descriptor set when descriptor indexing is supported
+pub const PREFER_BINDLESS: bool = true;
+// HDR policies fall back to SDR when the display or driver can't do them
+pub const COLOR_SPACE_POLICY: ColorSpacePolicy = ColorSpacePolicy::SdrSrgb;
+// The present mode when the present settings don't name one; switchable at runtime
+pub const VSYNC_POLICY: VsyncPolicy = VsyncPolicy::Mailbox;
+// Frames per second the CPU is held to, on top of whatever vsync does, when the present settings
+// don't give one; None to run unlimited
+pub const TARGET_FPS: Option<f64> = None;
+// Far plane at depth 0, for better precision with floating point depth buffers
+pub const REVERSED_Z: bool = true;
+pub const SCREENSHOT_DIR: &str = "screenshots";
+pub const RENDER_GRAPH_DUMP_PATH: &str = "render_graph.dot";
+pub const MODEL_PATH: &str = "models/cube.gltf";
+// An equirectangular .hdr, or a directory of px/nx/py/ny/pz/nz cube faces. A procedural sky is
+// used when it can't be loaded.
+pub const ENVIRONMENT_PATH: &str = "models/environment.hdr";
+pub const INPUT_CONFIG_PATH: &str = "config/input.cfg";
+// Window mode, monitor and placement, written on exit and restored on the next run
+pub const WINDOW_SETTINGS_PATH: &str = "window.cfg";
+// Vsync policy and frame limit, written on exit and restored on the next run
+pub const PRESENT_SETTINGS_PATH: &str = "present.cfg";
+// A strip of color lookup table slices for grading; a built-in look is used when it can't be
+// loaded
+pub const COLOR_LUT_PATH: &str = "models/color_lut.png";
+// Size of one shadow map tile; the atlas holds a tile per cascade plus one for a spot light
+pub const SHADOW_MAP_SIZE: u32 = 2048;
+pub const SHADOW_CASCADES: usize = 3;
+// Copies of the first mesh the instancing stress test draws
+pub const STRESS_TEST_INSTANCES: usize = 10_000;
+// Record the forward pass into secondary command buffers on worker threads
+pub const PARALLEL_RECORDING: bool = true;
+// None leaves one core to the main thread
+pub const RECORDING_THREADS: Option<usize> = None;
+// Draws the recording benchmark records at each thread count, repeating the scene's nodes
+pub const RECORDING_BENCHMARK_DRAWS: usize = 50_000;
+pub const RECORDING_BENCHMARK_RUNS: usize = 10;
+// Point lights beyond this many are ignored by the lit shader
+pub const MAX_POINT_LIGHTS: usize = 4;
 
 pub const VALIDATION: ValidationInfo = ValidationInfo {
     enabled: true,
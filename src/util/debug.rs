@@ -1,3 +1,8 @@
+use ash::vk::{self, Handle};
+use std::ffi::{CStr, CString};
+
+use super::constants::VALIDATION;
+
 pub fn check_validation_layer_support(
     entry: &ash::Entry,
     required_validation_layers: &[&'static str],
@@ -24,3 +29,82 @@ pub fn check_validation_layer_support(
 
     false
 }
+
+// Object names and command buffer and queue labels through VK_EXT_debug_utils, which tools like
+// RenderDoc and the validation messages show in place of raw handles. The extension is only
+// enabled along with validation; otherwise every call returns straight away, and as VALIDATION
+// is a constant the calls compile down to nothing.
+pub struct DebugUtils {
+    loader: Option<ash::ext::debug_utils::Device>,
+}
+
+impl DebugUtils {
+    // The instance must have been created with the extension when validation is on
+    pub fn new(instance: &ash::Instance, device: &ash::Device) -> DebugUtils {
+        DebugUtils {
+            loader: VALIDATION
+                .enabled
+                .then(|| ash::ext::debug_utils::Device::new(instance, device)),
+        }
+    }
+
+    pub fn set_name<T: Handle + Copy>(&self, handle: T, name: &str) {
+        let Some(loader) = self.loader() else {
+            return;
+        };
+        if handle.is_null() {
+            return;
+        }
+        let name = CString::new(name).unwrap();
+        let name_info = vk::DebugUtilsObjectNameInfoEXT {
+            s_type: vk::StructureType::DEBUG_UTILS_OBJECT_NAME_INFO_EXT,
+            object_type: T::TYPE,
+            object_handle: handle.as_raw(),
+            p_object_name: name.as_ptr(),
+            ..Default::default()
+        };
+        let _ = unsafe { loader.set_debug_utils_object_name(&name_info) };
+    }
+
+    // Labels nest, and each begin needs an end in the same command buffer
+    pub fn begin_label(&self, command_buffer: vk::CommandBuffer, name: &str) {
+        if let Some(loader) = self.loader() {
+            let name = CString::new(name).unwrap();
+            unsafe { loader.cmd_begin_debug_utils_label(command_buffer, &label(&name)) };
+        }
+    }
+
+    pub fn end_label(&self, command_buffer: vk::CommandBuffer) {
+        if let Some(loader) = self.loader() {
+            unsafe { loader.cmd_end_debug_utils_label(command_buffer) };
+        }
+    }
+
+    pub fn begin_queue_label(&self, queue: vk::Queue, name: &str) {
+        if let Some(loader) = self.loader() {
+            let name = CString::new(name).unwrap();
+            unsafe { loader.queue_begin_debug_utils_label(queue, &label(&name)) };
+        }
+    }
+
+    pub fn end_queue_label(&self, queue: vk::Queue) {
+        if let Some(loader) = self.loader() {
+            unsafe { loader.queue_end_debug_utils_label(queue) };
+        }
+    }
+
+    fn loader(&self) -> Option<&ash::ext::debug_utils::Device> {
+        if !VALIDATION.enabled {
+            return None;
+        }
+        self.loader.as_ref()
+    }
+}
+
+fn label(name: &CStr) -> vk::DebugUtilsLabelEXT<'_> {
+    vk::DebugUtilsLabelEXT {
+        s_type: vk::StructureType::DEBUG_UTILS_LABEL_EXT,
+        p_label_name: name.as_ptr(),
+        ..Default::default()
+    }
+}
@@ -2,3 +2,4 @@ pub mod constants;
 pub mod debug;
 pub mod structures;
 pub mod tools;
+pub mod worker_pool;
@@ -1,5 +1,7 @@
 use ash::vk;
-use glfw::WindowEvent;
+use std::ffi::c_void;
+
+use crate::app::resources::Owned;
 
 pub struct ValidationInfo {
     pub enabled: bool,
@@ -10,12 +12,6 @@ pub struct DeviceExtension {
     pub names: [&'static str; 1],
 }
 
-pub struct AppWindow {
-    pub window: glfw::PWindow,
-    pub events: glfw::GlfwReceiver<(f64, WindowEvent)>,
-    pub glfw: glfw::Glfw,
-}
-
 pub struct SurfaceStuff {
     pub surface: vk::SurfaceKHR,
     pub surface_loader: ash::khr::surface::Instance,
@@ -35,7 +31,11 @@ pub struct SwapChainStuff {
     pub swapchain_loader: ash::khr::swapchain::Device,
     pub swapchain: vk::SwapchainKHR,
     pub swapchain_format: vk::Format,
+    pub swapchain_color_space: vk::ColorSpaceKHR,
+    pub output_encoding: OutputEncoding,
+    pub present_mode: vk::PresentModeKHR,
     pub swapchain_extent: vk::Extent2D,
+    pub swapchain_usage: vk::ImageUsageFlags,
     pub swapchain_images: Vec<vk::Image>,
 }
 
@@ -45,13 +45,161 @@ pub struct SwapChainSupportDetails {
     pub present_modes: Vec<vk::PresentModeKHR>,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RenderingPath {
+    RenderPass,
+    Dynamic,
+}
+
+// How opaque scene geometry is submitted
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum IndirectPath {
+    // A draw call per primitive with its transform in push constants
+    Direct,
+    // Culled on the CPU, which writes the indirect commands
+    CpuBuilt,
+    // Culled by a compute pass that writes the commands and their counts
+    GpuCulled,
+}
+
+// What kind of swapchain to ask for. The HDR policies need VK_EXT_swapchain_colorspace and a
+// display that offers them, and fall back to SDR otherwise.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorSpacePolicy {
+    // An *_SRGB format that encodes on write
+    SdrSrgb,
+    // A *_UNORM format with sRGB gamma applied in the shader
+    SdrUnorm,
+    // 10-bit BT.2020 with the ST 2084 (PQ) transfer function
+    Hdr10,
+    // Linear BT.709 in half floats, 1.0 being 80 nits and beyond it brighter or wider gamut
+    ScRgb,
+}
+
+// How the last post-processing pass has to encode what it writes to the swapchain, in the order
+// post_output.frag numbers them
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OutputEncoding {
+    // Linear, the format applies the sRGB curve
+    SrgbFormat,
+    SrgbShader,
+    Pq,
+    ScRgbLinear,
+}
+
+impl OutputEncoding {
+    pub fn is_hdr(self) -> bool {
+        matches!(self, OutputEncoding::Pq | OutputEncoding::ScRgbLinear)
+    }
+}
+
+// How presentation waits for vertical blank. Only FIFO is guaranteed; the others fall back to it
+// when the surface doesn't offer them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VsyncPolicy {
+    // Waits for every vblank, never tears
+    Fifo,
+    // Waits, but presents immediately and tears when a frame is late
+    FifoRelaxed,
+    // Doesn't block; the newest frame replaces the queued one at vblank
+    Mailbox,
+    // No waiting at all, tearing
+    Immediate,
+}
+
+impl VsyncPolicy {
+    pub fn next(self) -> VsyncPolicy {
+        match self {
+            VsyncPolicy::Fifo => VsyncPolicy::FifoRelaxed,
+            VsyncPolicy::FifoRelaxed => VsyncPolicy::Mailbox,
+            VsyncPolicy::Mailbox => VsyncPolicy::Immediate,
+            VsyncPolicy::Immediate => VsyncPolicy::Fifo,
+        }
+    }
+
+    pub fn present_mode(self) -> vk::PresentModeKHR {
+        match self {
+            VsyncPolicy::Fifo => vk::PresentModeKHR::FIFO,
+            VsyncPolicy::FifoRelaxed => vk::PresentModeKHR::FIFO_RELAXED,
+            VsyncPolicy::Mailbox => vk::PresentModeKHR::MAILBOX,
+            VsyncPolicy::Immediate => vk::PresentModeKHR::IMMEDIATE,
+        }
+    }
+}
+
+pub struct PipelineDesc<'a> {
+    pub vert_shader: &'a str,
+    pub frag_shader: &'a str,
+    pub vertex_bindings: &'a [vk::VertexInputBindingDescription],
+    pub vertex_attributes: &'a [vk::VertexInputAttributeDescription],
+    pub set_layouts: &'a [vk::DescriptorSetLayout],
+    pub push_constant_ranges: &'a [vk::PushConstantRange],
+    // Null for dynamic rendering, which then uses the attachment formats below
+    pub render_pass: vk::RenderPass,
+    // UNDEFINED for depth-only pipelines
+    pub color_format: vk::Format,
+    pub depth_format: vk::Format,
+    pub depth_test: bool,
+    pub depth_compare_op: vk::CompareOp,
+    pub depth_bias: Option<DepthBias>,
+    // Alpha blending over the target; blended pipelines test depth but don't write it
+    pub blend: bool,
+    pub cull_mode: vk::CullModeFlags,
+    pub front_face: vk::FrontFace,
+}
+
+// Rasterizer depth offset, in the units of vkCmdSetDepthBias
+#[derive(Clone, Copy, Debug)]
+pub struct DepthBias {
+    pub constant_factor: f32,
+    pub slope_factor: f32,
+    pub clamp: f32,
+}
+
 pub struct GraphicsPipelineStuff {
-    pub graphics_pipeline: vk::Pipeline,
-    pub pipeline_layout: vk::PipelineLayout,
+    pub graphics_pipeline: Owned<vk::Pipeline>,
+    pub pipeline_layout: Owned<vk::PipelineLayout>,
+}
+
+pub struct ComputePipelineStuff {
+    pub compute_pipeline: Owned<vk::Pipeline>,
+    pub pipeline_layout: Owned<vk::PipelineLayout>,
+}
+
+// Counters for the most recently recorded frame
+#[derive(Clone, Copy, Default, Debug)]
+pub struct FrameStats {
+    pub objects_drawn: usize,
+    pub objects_culled: usize,
+    // Copies drawn by instanced batches, which aren't culled
+    pub instances_drawn: usize,
 }
 
 pub struct SyncObjects {
-    pub image_available_semaphores: Vec<vk::Semaphore>,
-    pub render_finished_semaphores: Vec<vk::Semaphore>,
-    pub in_flight_fences: Vec<vk::Fence>,
+    pub image_available_semaphores: Vec<Owned<vk::Semaphore>>,
+    pub render_finished_semaphores: Vec<Owned<vk::Semaphore>>,
+    pub in_flight_fences: Vec<Owned<vk::Fence>>,
+}
+
+pub struct BufferStuff {
+    pub buffer: Owned<vk::Buffer>,
+    pub memory: Owned<vk::DeviceMemory>,
+    pub size: vk::DeviceSize,
+}
+
+// Fields drop in order, so the view goes before the image and the image before its memory
+pub struct ImageStuff {
+    pub view: Owned<vk::ImageView>,
+    pub image: Owned<vk::Image>,
+    pub memory: Owned<vk::DeviceMemory>,
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    pub mip_levels: u32,
+}
+
+// The buffers stay mapped until their memory is freed
+pub struct FrameUniformStuff {
+    pub buffers: Vec<BufferStuff>,
+    pub mapped: Vec<*mut c_void>,
+    pub descriptor_sets: Vec<vk::DescriptorSet>,
 }
@@ -29,3 +29,25 @@ pub fn vec_string_to_pp(v: &Vec<String>) -> *const *const i8 {
 
     ptr
 }
+
+// Hands each `<key> = <value>` line of a settings file to `apply` with the key and value
+// trimmed. Comments start with '#'. Errors from `apply` get the line number prepended.
+pub fn parse_key_values(
+    source: &str,
+    mut apply: impl FnMut(&str, &str) -> Result<(), String>,
+) -> Result<(), String> {
+    for (index, line) in source.lines().enumerate() {
+        let error = |message: String| format!("{}: {}", index + 1, message);
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(error(format!("expected '<key> = <value>', got '{}'", line)));
+        };
+        apply(key.trim(), value.trim()).map_err(error)?;
+    }
+
+    Ok(())
+}
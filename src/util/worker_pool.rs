@@ -0,0 +1,93 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+// Long-lived threads that run one borrowed closure each per call to `run`, which blocks until
+// every worker has returned, so the closure may borrow from the caller's stack like with
+// thread::scope but without spawning threads every frame. A worker always gets the same index,
+// which lets callers keep per-thread state such as command pools.
+
+// A closure borrowed for the duration of one `run`
+struct Task(*const (dyn Fn(usize) + Sync));
+
+// The pointee is Sync and outlives every use, see `run`
+unsafe impl Send for Task {}
+
+struct Worker {
+    tasks: Option<Sender<Task>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+pub struct WorkerPool {
+    workers: Vec<Worker>,
+    // Whether each worker finished its task without panicking
+    done: Receiver<bool>,
+}
+
+impl WorkerPool {
+    pub fn new(threads: usize) -> WorkerPool {
+        let (done_sender, done) = mpsc::channel();
+        let workers = (0..threads)
+            .map(|index| {
+                let (tasks, task_receiver) = mpsc::channel::<Task>();
+                let done_sender = done_sender.clone();
+                let thread = thread::Builder::new()
+                    .name(format!("worker {}", index))
+                    .spawn(move || {
+                        for task in task_receiver {
+                            let task = unsafe { &*task.0 };
+                            let ok = panic::catch_unwind(AssertUnwindSafe(|| task(index))).is_ok();
+                            let _ = done_sender.send(ok);
+                        }
+                    })
+                    .expect("Failed to spawn worker thread");
+                Worker {
+                    tasks: Some(tasks),
+                    thread: Some(thread),
+                }
+            })
+            .collect();
+
+        WorkerPool { workers, done }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    // Calls `task` once on every worker with the worker's index and waits for all of them
+    pub fn run(&self, task: &(dyn Fn(usize) + Sync)) {
+        // Erasing the lifetime is sound because nothing returns before every worker reported
+        // back, and no worker touches the task after that
+        let task: *const (dyn Fn(usize) + Sync + 'static) = unsafe { std::mem::transmute(task) };
+        for worker in self.workers.iter() {
+            worker
+                .tasks
+                .as_ref()
+                .unwrap()
+                .send(Task(task))
+                .expect("Worker thread exited");
+        }
+        let mut ok = true;
+        for _ in self.workers.iter() {
+            ok &= self.done.recv().expect("Worker thread exited");
+        }
+        if !ok {
+            panic!("A worker thread panicked");
+        }
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        // Closing the channels ends the workers' loops
+        for worker in self.workers.iter_mut() {
+            worker.tasks.take();
+        }
+        for worker in self.workers.iter_mut() {
+            if let Some(thread) = worker.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}